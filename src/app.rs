@@ -1,6 +1,7 @@
 use iced::widget::{
     button,
     center,
+    checkbox,
     column,
     scrollable,
     text_input,
@@ -8,45 +9,215 @@ use iced::widget::{
     responsive,
     row,
     container,
+    pick_list,
+    combo_box,
 };
-use iced::{Element, Length, Task, Theme};
+use iced::futures::channel::oneshot;
+use iced::{keyboard, time, Element, Font, Length, Subscription, Task, Theme};
 
+use iced_aw::date_picker::Date;
+use iced_aw::helpers::date_picker;
 use iced_aw::widget::{Tabs, TabLabel};
 use iced_table::table;
 use rusqlite::Connection;
+use chrono::{Days, Local, NaiveDate};
+use std::fs;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
-use crate::order::{Order, OrderForm};
-use crate::order::table::{OrderColumn, OrderColumnKind};
-use crate::helpers::{field_error, required_input_label};
+use crate::order::{LateFee, Order, OrderForm, OrderQuery, UndoAction};
+use crate::order::form::{parse_date, DateLimits};
+use crate::order::query::{OrderSortField, SortDirection, StatusFilter};
+use crate::order::table::{OrderColumn, OrderColumnKind, OrderRow};
+use crate::order::export::export_csv_file;
+use crate::order::import::ImportPreview;
+use crate::dashboard::{Dashboard, DashboardFigure};
+use crate::customer::{Customer, CustomerForm};
+use crate::customer::table::{CustomerColumn, CustomerColumnKind};
+use crate::item::{format_price, Item, ItemForm};
+use crate::item::table::{ItemColumn, ItemColumnKind};
+use crate::packing::{PackingPlan, PACKING_PLAN_DAYS};
+use crate::payment::{describe_balance, ChargesForm, Payment, PaymentForm, PaymentMethod};
+use crate::raffle::{RaffleDraw, RaffleDrawForm};
+use crate::raffle::table::{RaffleDrawColumn, RaffleDrawColumnKind};
+use crate::backup::{self, BackupFile};
+use crate::docket;
+use crate::reminder::{self, OverdueOrder, ReminderMethod};
+use crate::email::{self, EmailStatus, OutgoingEmail, SendSummary, SentBatch};
+use crate::email::smtp::{SmtpForm, SmtpSecurity};
+use crate::config::{Config, Profile};
+use crate::database::init_db;
+use crate::settings::{SettingsForm, BACKUP_INTERVAL_MINUTES, EMAIL_MAX_ATTEMPTS, SETTINGS};
+use crate::helpers::{confirm_dialog, error_banner, field_error, required_input_label, Form};
+use crate::error::Error;
+
+/// How many actions can be undone.
+const UNDO_LIMIT: usize = 20;
+
+/// How often to look for reminders to email, and send the ones that are due.
+const EMAIL_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Used to represent the current tab the program is on.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum TabId {
+    Dashboard,
     Orders,
     AddOrder,
+    Customers,
+    Items,
+    Packing,
+    Overdue,
+    Email,
     Raffle,
+    ImportExport,
+    Backups,
+    Settings,
 }
 
 /// Represents an event sent by the UI to the app
 #[derive(Clone, Debug)]
 pub enum Message {
     TabSelected(TabId),
+    ShowDashboardOrders(DashboardFigure),
     CustomerNameChanged(String),
     ReceiptNumberChanged(String),
-    ItemHiredChanged(String),
-    HowManyChanged(String),
+    NextReceiptNumber,
+    ConfirmReceiptReuse,
+    ItemHiredChanged(usize, String),
+    HowManyChanged(usize, String),
+    AddOrderLine,
+    RemoveOrderLine(usize),
     HiredOnChanged(String),
     ReturnOnChanged(String),
+    OpenDatePicker(&'static str),
+    CloseDatePicker,
+    DatePicked(&'static str, Date),
     AddOrder,
+    EditOrder(i32),
+    SaveOrder,
+    MarkOrderOut(i32),
+    MarkOrderReturned(i32),
+    CancelOrder(i32),
+    ToggleOrderLines(i32),
     DeleteOrder(i32),
+    ToggleDeletedOrders,
+    RestoreOrder(i32),
+    SaveDocket(i32),
+    OpenDocket(PathBuf),
+    Undo,
+
+    ViewPayments(i32),
+    ClosePayments,
+    PaymentAmountChanged(String),
+    PaymentRefundToggled(bool),
+    PaymentMethodSelected(PaymentMethod),
+    PaymentDateChanged(String),
+    PaymentNoteChanged(String),
+    RecordPayment,
+    DeletePayment(i32),
+    DepositChanged(String),
+    DamageChargeChanged(String),
+    SaveCharges,
+    WaiveReasonChanged(String),
+    WaiveLateFee,
+
+    CustomerFormNameChanged(String),
+    CustomerPhoneChanged(String),
+    CustomerEmailChanged(String),
+    CustomerAddressChanged(String),
+    CustomerNotesChanged(String),
+    SaveCustomer,
+    EditCustomer(i32),
+    CancelEditCustomer,
+    DeleteCustomer(i32),
+    ViewCustomer(i32),
+    CloseCustomer,
+    NewOrderForCustomer(i32),
+
+    ItemNameChanged(String),
+    ItemStockChanged(String),
+    ItemBoxCapacityChanged(String),
+    ItemHirePriceChanged(String),
+    SaveItem,
+    EditItem(i32),
+    CancelEditItem,
+    DeleteItem(i32),
+
+    SearchChanged(String),
+    FilterFromChanged(String),
+    FilterToChanged(String),
+    StatusFilterSelected(StatusFilter),
+    ItemFilterSelected(String),
+    ClearOrderFilters,
+    SortOrders(OrderSortField),
+
+    PackingFromChanged(String),
+    PackingPathChanged(String),
+    SavePackingPlan,
+
+    ReminderMethodSelected(ReminderMethod),
+    WriteReminders,
+
+    SmtpFieldChanged(&'static str, String),
+    SmtpSecuritySelected(SmtpSecurity),
+    SaveSmtpSettings,
+    EmailTimer,
+    SendEmailsNow,
+    // The emails were sent in the background, by hand if the bool is true
+    EmailsSent(bool, SentBatch),
+    RetryEmail(i32),
+
+    PrizeNameChanged(String),
+    RaffleWinnersChanged(String),
+    DrawRaffle,
+    VerifyRaffleDraw(i32),
+
+    CsvPathChanged(String),
+    ExportOrders,
+    PreviewImport,
+    ImportOrders,
+    CancelImport,
+
+    BackupTimer,
+    BackUpNow,
+    RestoreBackup(PathBuf),
+
+    SettingChanged(&'static str, String),
+    SaveSettings,
+
+    ProfileSelected(String),
+    NewProfileNameChanged(String),
+    NewProfileDbChanged(String),
+    AddProfile,
+
+    DismissError,
+
+    // Asks the user the question before doing the action, such as a delete
+    AskToConfirm(String, Box<Message>),
+    Confirm,
+    CancelConfirm,
 
     SyncOrderTableHeader(scrollable::AbsoluteOffset),
     OrderTableResizing(usize, f32),
     OrderTableResized,
 
+    SyncCustomerTableHeader(scrollable::AbsoluteOffset),
+    CustomerTableResizing(usize, f32),
+    CustomerTableResized,
+
+    SyncItemTableHeader(scrollable::AbsoluteOffset),
+    ItemTableResizing(usize, f32),
+    ItemTableResized,
+
     SyncRaffleTableHeader(scrollable::AbsoluteOffset),
     RaffleTableResizing(usize, f32),
     RaffleTableResized,
+
+    SyncRaffleDrawTableHeader(scrollable::AbsoluteOffset),
+    RaffleDrawTableResizing(usize, f32),
+    RaffleDrawTableResized,
 }
 
 /// Stores the state and methods of the app
@@ -57,61 +228,311 @@ pub struct App {
     // What tab is active
     active_tab: TabId,
 
-    // The orders in the table
+    // The last thing that went wrong, shown in a banner until dismissed
+    error: Option<String>,
+
+    // The figures on the dashboard, or None if they couldn't be worked out
+    dashboard: Option<Dashboard>,
+
+    // The question being asked before an action is done, and the action
+    confirm: Option<(String, Message)>,
+
+    // What can be undone, most recent last, and what the last undo did
+    undo_stack: Vec<UndoAction>,
+    undo_message: Option<String>,
+
+    // Where the last docket was saved, or why it couldn't be
+    docket: Option<Result<PathBuf, String>>,
+
+    // The orders in the table, and the search, filters and sorting used to get them
     orders: Vec<Order>,
+    order_query: OrderQuery,
+    // The figure on the dashboard the filters were set from, if any
+    dashboard_filter: Option<DashboardFigure>,
+    filter_from: String,
+    filter_to: String,
+    hired_item_names: Vec<String>,
+
+    // The rows of the orders table, with the lines of the expanded orders under them
+    order_rows: Vec<OrderRow>,
+    expanded_orders: HashSet<i32>,
+
+    // The orders that were deleted but can still be restored, and whether
+    // they are being shown in place of the table
+    deleted_orders: Vec<Order>,
+    show_deleted_orders: bool,
+
+    // The order whose payments are being looked at in place of the table with
+    // its late fee, the forms to record a payment, change its charges and
+    // waive the late fee, and why saving one of them last failed
+    viewed_payments: Option<(Order, Vec<Payment>, Option<LateFee>)>,
+    payment_form: PaymentForm,
+    charges_form: ChargesForm,
+    waive_reason: String,
+    payment_form_error: Option<String>,
+
+    // The customers, their names for the order form to suggest, the form to add
+    // or edit a customer, why saving it last failed and the customer whose
+    // hires are being looked at
+    customers: Vec<Customer>,
+    customer_names: combo_box::State<String>,
+    customer_form: CustomerForm,
+    customer_form_error: Option<String>,
+    viewed_customer: Option<(Customer, Vec<Order>)>,
+
+    // The item catalogue, the form to add or edit an item and why saving it last failed
+    items: Vec<Item>,
+    item_names: Vec<String>,
+    item_form: ItemForm,
+    item_form_error: Option<String>,
+
+    // The first day of the packing plan, the plan itself, the file to save it
+    // to and the outcome of saving it
+    packing_from: String,
+    packing_plan: Option<PackingPlan>,
+    packing_path: String,
+    packing_message: Option<String>,
+
+    // The orders that are late back, how to remind their customers and what
+    // the last reminders written were
+    overdue: Vec<OverdueOrder>,
+    reminder_method: ReminderMethod,
+    reminder_message: Option<String>,
+
+    // The email settings form, the reminder emails that are queued or were
+    // sent, how many tries each gets, and what the last save or send did
+    smtp_form: SmtpForm,
+    emails: Vec<OutgoingEmail>,
+    email_max_attempts: i64,
+    email_message: Option<String>,
+    // Whether emails are being sent in the background, and why the last
+    // automatic send failed, so the same failure isn't shown every time
+    sending_emails: bool,
+    email_error: Option<String>,
 
-    // The form for the add order screen
+    // The rows of the raffle table, which ignores the order filters
+    raffle_rows: Vec<OrderRow>,
+
+    // The form to draw the raffle, the past draws and the outcome of the last action
+    raffle_form: RaffleDrawForm,
+    raffle_draws: Vec<RaffleDraw>,
+    raffle_message: Option<String>,
+
+    // The file to import or export, the import waiting to be confirmed and the
+    // outcome of the last action
+    csv_path: String,
+    import_preview: Option<ImportPreview>,
+    csv_message: Option<String>,
+
+    // The backups of the database, what the last backup or restore did, and
+    // how often to back up automatically
+    backups: Vec<BackupFile>,
+    backup_message: Option<String>,
+    backup_interval_minutes: i64,
+
+    // The form on the settings tab, and whether saving it worked
+    settings_form: SettingsForm,
+    settings_message: Option<String>,
+
+    // The config file and the profile whose database is open, which are None
+    // if the database wasn't opened from a profile, and the form to add one
+    config_path: Option<PathBuf>,
+    config: Config,
+    profile: Option<Profile>,
+    new_profile_name: String,
+    new_profile_db: String,
+    profile_message: Option<String>,
+
+    // The form for the add order screen, why saving it last failed, and what
+    // needs confirming before it can be saved
     order_form: OrderForm,
+    order_form_error: Option<String>,
+    order_form_confirm: Option<String>,
+    // Which date field of the order form has its calendar open
+    open_date_picker: Option<&'static str>,
 
     // Infomation for the order table
     order_table_header: scrollable::Id,
     order_table_body: scrollable::Id,
     order_table_columns: Vec<OrderColumn>,
 
+    // Infomation for the customers table
+    customer_table_header: scrollable::Id,
+    customer_table_body: scrollable::Id,
+    customer_table_columns: Vec<CustomerColumn>,
+
+    // Infomation for the item catalogue table
+    item_table_header: scrollable::Id,
+    item_table_body: scrollable::Id,
+    item_table_columns: Vec<ItemColumn>,
+
     // Infomation for the raffle table
     raffle_table_header: scrollable::Id,
     raffle_table_body: scrollable::Id,
     raffle_table_columns: Vec<OrderColumn>,
+
+    // Infomation for the past raffle draws table
+    raffle_draw_table_header: scrollable::Id,
+    raffle_draw_table_body: scrollable::Id,
+    raffle_draw_table_columns: Vec<RaffleDrawColumn>,
 }
 
 impl App {
     /// Creates the app and inits the database.
     pub fn new(db_connection: Connection) -> (Self, Task<Message>) {
-	let orders = Order::get_all(&db_connection);
-	
-	let app = Self {
+	let mut app = Self {
             db_connection,
-            active_tab: TabId::Orders,
-	    orders,
+	    active_tab: TabId::Dashboard,
+	    error: None,
+	    confirm: None,
+	    undo_stack: Vec::new(),
+	    undo_message: None,
+	    docket: None,
+	    orders: Vec::new(),
+	    order_query: OrderQuery::default(),
+	    dashboard_filter: None,
+	    dashboard: None,
+	    filter_from: String::new(),
+	    filter_to: String::new(),
+	    hired_item_names: Vec::new(),
+	    order_rows: Vec::new(),
+	    expanded_orders: HashSet::new(),
+	    deleted_orders: Vec::new(),
+	    show_deleted_orders: false,
+	    viewed_payments: None,
+	    payment_form: PaymentForm::new(Local::now().date_naive()),
+	    charges_form: ChargesForm::default(),
+	    waive_reason: String::new(),
+	    payment_form_error: None,
+	    customers: Vec::new(),
+	    customer_names: combo_box::State::default(),
+	    customer_form: CustomerForm::default(),
+	    customer_form_error: None,
+	    viewed_customer: None,
+	    items: Vec::new(),
+	    item_names: Vec::new(),
+	    item_form: ItemForm::default(),
+	    item_form_error: None,
+	    packing_from: Local::now().date_naive().format("%Y-%m-%d").to_string(),
+	    packing_plan: None,
+	    packing_path: String::new(),
+	    packing_message: None,
+	    overdue: Vec::new(),
+	    reminder_method: ReminderMethod::EmailFile,
+	    reminder_message: None,
+	    smtp_form: SmtpForm::default(),
+	    emails: Vec::new(),
+	    email_max_attempts: EMAIL_MAX_ATTEMPTS.default,
+	    email_message: None,
+	    sending_emails: false,
+	    email_error: None,
+	    raffle_rows: Vec::new(),
+	    raffle_form: RaffleDrawForm::default(),
+	    raffle_draws: Vec::new(),
+	    raffle_message: None,
+	    csv_path: String::new(),
+	    import_preview: None,
+	    csv_message: None,
+	    backups: Vec::new(),
+	    backup_message: None,
+	    backup_interval_minutes: BACKUP_INTERVAL_MINUTES.default,
+	    settings_form: SettingsForm::default(),
+	    settings_message: None,
+	    config_path: None,
+	    config: Config::default(),
+	    profile: None,
+	    new_profile_name: String::new(),
+	    new_profile_db: String::new(),
+	    profile_message: None,
 	    order_form: OrderForm::default(),
+	    order_form_error: None,
+	    order_form_confirm: None,
+	    open_date_picker: None,
 
 	    order_table_header: scrollable::Id::unique(),
 	    order_table_body: scrollable::Id::unique(),
 	    order_table_columns: vec![
-		OrderColumn::new(OrderColumnKind::CustomerName),
-		OrderColumn::new(OrderColumnKind::RecieptNumber),
-		OrderColumn::new(OrderColumnKind::ItemHired),
-		OrderColumn::new(OrderColumnKind::HowMany),
-		OrderColumn::new(OrderColumnKind::HiredOn),
-		OrderColumn::new(OrderColumnKind::ReturnOn),
-		OrderColumn::new(OrderColumnKind::BoxesNeeded),
+		OrderColumn::new(OrderColumnKind::Expand),
+		OrderColumn::new(OrderColumnKind::CustomerName).sortable(),
+		OrderColumn::new(OrderColumnKind::RecieptNumber).sortable(),
+		OrderColumn::new(OrderColumnKind::ItemHired).sortable(),
+		OrderColumn::new(OrderColumnKind::HowMany).sortable(),
+		OrderColumn::new(OrderColumnKind::HiredOn).sortable(),
+		OrderColumn::new(OrderColumnKind::ReturnOn).sortable(),
+		OrderColumn::new(OrderColumnKind::BoxesNeeded).sortable(),
+		OrderColumn::new(OrderColumnKind::Status).sortable(),
+		OrderColumn::new(OrderColumnKind::StatusAction),
+		OrderColumn::new(OrderColumnKind::Paid),
+		OrderColumn::new(OrderColumnKind::Docket),
+		OrderColumn::new(OrderColumnKind::Edit),
 		OrderColumn::new(OrderColumnKind::Delete),
 	    ],
 
+	    customer_table_header: scrollable::Id::unique(),
+	    customer_table_body: scrollable::Id::unique(),
+	    customer_table_columns: vec![
+		CustomerColumn::new(CustomerColumnKind::Name),
+		CustomerColumn::new(CustomerColumnKind::Phone),
+		CustomerColumn::new(CustomerColumnKind::Email),
+		CustomerColumn::new(CustomerColumnKind::View),
+		CustomerColumn::new(CustomerColumnKind::Edit),
+		CustomerColumn::new(CustomerColumnKind::Delete),
+	    ],
+
+	    item_table_header: scrollable::Id::unique(),
+	    item_table_body: scrollable::Id::unique(),
+	    item_table_columns: vec![
+		ItemColumn::new(ItemColumnKind::Name),
+		ItemColumn::new(ItemColumnKind::TotalStock),
+		ItemColumn::new(ItemColumnKind::BoxCapacity),
+		ItemColumn::new(ItemColumnKind::HirePrice),
+		ItemColumn::new(ItemColumnKind::Edit),
+		ItemColumn::new(ItemColumnKind::Delete),
+	    ],
+
 	    raffle_table_header: scrollable::Id::unique(),
 	    raffle_table_body: scrollable::Id::unique(),
 	    raffle_table_columns: vec![
 		OrderColumn::new(OrderColumnKind::CustomerName),
 		OrderColumn::new(OrderColumnKind::RaffleNumber),
 	    ],
+
+	    raffle_draw_table_header: scrollable::Id::unique(),
+	    raffle_draw_table_body: scrollable::Id::unique(),
+	    raffle_draw_table_columns: vec![
+		RaffleDrawColumn::new(RaffleDrawColumnKind::DrawnAt),
+		RaffleDrawColumn::new(RaffleDrawColumnKind::PrizeName),
+		RaffleDrawColumn::new(RaffleDrawColumnKind::Winner),
+		RaffleDrawColumn::new(RaffleDrawColumnKind::RaffleNumber),
+		RaffleDrawColumn::new(RaffleDrawColumnKind::Seed),
+		RaffleDrawColumn::new(RaffleDrawColumnKind::Verify),
+	    ],
         };
+
+	app.refresh_orders();
+	app.set_order_form(OrderForm::default());
+	app.refresh_backup_interval();
 	
         (app,Task::none())
     }
 
+    /// Lets the profiles in the config file be switched between, with
+    /// `profile` being the one that is open. `error` is shown if it isn't the
+    /// one that was asked for.
+    pub fn with_profile(mut self, config_path: PathBuf, config: Config, profile: Profile, error: Option<String>) -> Self {
+	self.config_path = Some(config_path);
+	self.config = config;
+	self.profile = Some(profile);
+	self.error = error;
+	self
+    }
+
     /// Gets the title of the app.
     pub fn title(&self) -> String {
-        return "Order Management Application".into();
+	match &self.profile {
+	    Some(profile) => format!("Order Management Application - {}", profile.name),
+	    None => "Order Management Application".into(),
+	}
     }
 
     /// Gets the theme of the app.
@@ -119,12 +540,75 @@ impl App {
 	Theme::Dark
     }
 
+    /// Listens for Ctrl+Z (Cmd+Z on macOS) to undo, and for when it is time
+    /// for the next automatic backup or to send the reminder emails.
+    pub fn subscription(&self) -> Subscription<Message> {
+	Subscription::batch([
+	    keyboard::on_key_press(|key, modifiers| match key.as_ref() {
+		keyboard::Key::Character("z") if modifiers.command() => Some(Message::Undo),
+		_ => None,
+	    }),
+	    time::every(Duration::from_secs(self.backup_interval_minutes as u64 * 60))
+		.map(|_| Message::BackupTimer),
+	    time::every(EMAIL_CHECK_INTERVAL).map(|_| Message::EmailTimer),
+	])
+    }
+
     /// Responds to events from the UI.
     pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
 	    Message::TabSelected(tab) => {
+		if tab == TabId::Settings {
+		    let form = SettingsForm::load(&self.db_connection);
+		    if let Some(form) = self.show_error(form) {
+			self.settings_form = form;
+		    }
+		    self.settings_message = None;
+		} else if tab == TabId::Packing {
+		    self.refresh_packing_plan();
+		    self.packing_message = None;
+		} else if tab == TabId::Overdue {
+		    // Orders become overdue as the days go by
+		    let overdue = OverdueOrder::get_all(&self.db_connection, Local::now().date_naive());
+		    self.overdue = self.show_error(overdue).unwrap_or_default();
+		    self.reminder_message = None;
+		} else if tab == TabId::Email {
+		    let form = SmtpForm::load(&self.db_connection);
+		    if let Some(form) = self.show_error(form) {
+			self.smtp_form = form;
+		    }
+		    self.refresh_emails();
+		    self.email_message = None;
+		} else if tab == TabId::Backups {
+		    self.refresh_backups();
+		    self.backup_message = None;
+		} else if tab == TabId::Dashboard {
+		    // The day may have changed since it was worked out
+		    let dashboard = Dashboard::load(&self.db_connection, Local::now().date_naive());
+		    self.dashboard = self.show_error(dashboard);
+		}
+
 		self.active_tab = tab;
-		self.order_form = OrderForm::default();
+		self.set_order_form(OrderForm::default());
+		self.order_form_error = None;
+		self.customer_form = CustomerForm::default();
+		self.customer_form_error = None;
+		self.item_form = ItemForm::default();
+		self.item_form_error = None;
+	    },
+	    Message::ShowDashboardOrders(figure) => {
+		// Keep the sorting, as it isn't a filter
+		self.order_query = OrderQuery {
+		    sort: self.order_query.sort,
+		    ..figure.query(Local::now().date_naive())
+		};
+		self.filter_from = String::new();
+		self.filter_to = String::new();
+		self.dashboard_filter = Some(figure);
+		self.show_deleted_orders = false;
+		self.viewed_payments = None;
+		self.active_tab = TabId::Orders;
+		self.refresh_orders();
 	    },
 	    Message::CustomerNameChanged(customer_name) => {
 		self.order_form.customer_name = customer_name;
@@ -133,14 +617,43 @@ impl App {
 	    Message::ReceiptNumberChanged(receipt_number) => {
 		self.order_form.receipt_number = receipt_number;
 		self.order_form.receipt_number_show_error = true;
+		self.order_form.receipt_reuse_confirmed = false;
+		self.order_form_confirm = None;
+	    },
+	    Message::NextReceiptNumber => {
+		let next = Order::next_receipt_number(&self.db_connection);
+
+		if let Some(next) = self.show_error(next) {
+		    return self.update(Message::ReceiptNumberChanged(next.to_string()));
+		}
+	    },
+	    Message::ConfirmReceiptReuse => {
+		self.order_form.receipt_reuse_confirmed = true;
+		self.order_form_confirm = None;
+
+		return match self.order_form.editing_id {
+		    Some(_) => self.update(Message::SaveOrder),
+		    None => self.update(Message::AddOrder),
+		};
+	    },
+	    Message::ItemHiredChanged(index, item_hired) => {
+		if let Some(line) = self.order_form.lines.get_mut(index) {
+		    line.item_hired = item_hired;
+		    line.item_hired_show_error = true;
+		    self.order_form.lines_show_error = true;
+		}
 	    },
-	    Message::ItemHiredChanged(item_hired) => {
-		self.order_form.item_hired = item_hired;
-		self.order_form.item_hired_show_error = true;
+	    Message::HowManyChanged(index, how_many) => {
+		if let Some(line) = self.order_form.lines.get_mut(index) {
+		    line.how_many = how_many;
+		    line.how_many_show_error = true;
+		}
+	    },
+	    Message::AddOrderLine => {
+		self.order_form.add_line();
 	    },
-	    Message::HowManyChanged(how_many) => {
-		self.order_form.how_many = how_many;
-		self.order_form.how_many_show_error = true;
+	    Message::RemoveOrderLine(index) => {
+		self.order_form.remove_line(index);
 	    },
 	    Message::HiredOnChanged(hired_on) => {
 		self.order_form.hired_on = hired_on;
@@ -150,28 +663,672 @@ impl App {
 		self.order_form.return_on = return_on;
 		self.order_form.return_on_show_error = true;
 	    },
+	    Message::OpenDatePicker(field) => {
+		self.open_date_picker = Some(field);
+	    },
+	    Message::CloseDatePicker => {
+		self.open_date_picker = None;
+	    },
+	    Message::DatePicked(field, date) => {
+		self.open_date_picker = None;
+
+		// Picked dates are typed in for the user, so they are checked the same way
+		let date = NaiveDate::from(date).format("%Y-%m-%d").to_string();
+		return match field {
+		    "hired_on" => self.update(Message::HiredOnChanged(date)),
+		    _ => self.update(Message::ReturnOnChanged(date)),
+		};
+	    },
 	    Message::AddOrder => {
 		match self.order_form.create_order(&self.db_connection) {
-		    Ok(order) => {
-			self.orders.push(order);
+		    Ok(_) => {
+			self.refresh_orders();
+			self.active_tab = TabId::Orders;
+		    },
+		    Err(Error::NeedsConfirmation(message)) => {
+			self.order_form_error = None;
+			self.order_form_confirm = Some(message);
+		    },
+		    Err(e) => {
+			Self::show_form_error(&mut self.order_form, &mut self.order_form_error, e);
+		    },
+		}
+	    },
+	    Message::EditOrder(id) => {
+		let order = Order::get_by_id(&self.db_connection, id);
+
+		if let Some(order) = self.show_error(order) {
+		    self.set_order_form(OrderForm::from_order(&order));
+		    self.active_tab = TabId::AddOrder;
+		}
+	    },
+	    Message::SaveOrder => {
+		let Some(id) = self.order_form.editing_id else {
+		    return Task::none();
+		};
+		let result = Order::get_by_id(&self.db_connection, id).and_then(|order| {
+		    self.order_form.update_order(&self.db_connection, &order).map(|_| order)
+		});
+
+		match result {
+		    Ok(before) => {
+			self.push_undo(UndoAction::Edited(before));
+			self.refresh_orders();
+			self.set_order_form(OrderForm::default());
 			self.active_tab = TabId::Orders;
 		    },
-		    Err(_) => {
-			self.order_form.customer_name_show_error = true;
-			self.order_form.receipt_number_show_error = true;
-			self.order_form.item_hired_show_error = true;
-			self.order_form.how_many_show_error = true;
-			self.order_form.hired_on_show_error = true;
-			self.order_form.return_on_show_error = true;
+		    Err(Error::NeedsConfirmation(message)) => {
+			self.order_form_error = None;
+			self.order_form_confirm = Some(message);
 		    },
+		    Err(e) => {
+			Self::show_form_error(&mut self.order_form, &mut self.order_form_error, e);
+		    },
+		}
+	    },
+	    Message::MarkOrderOut(id) => {
+		let result = Order::get_by_id(&self.db_connection, id)
+		    .and_then(|order| order.mark_out(&self.db_connection));
+		self.show_error(result);
+		self.refresh_orders();
+	    },
+	    Message::MarkOrderReturned(id) => {
+		let returned_at = Local::now().naive_local();
+		let result = Order::get_by_id(&self.db_connection, id)
+		    .and_then(|order| order.mark_returned(&self.db_connection, returned_at));
+		let returned = self.show_error(result);
+		self.refresh_orders();
+
+		// Shows the late fee it was charged, so it can be paid or waived
+		if returned.is_some_and(|order| order.late_fee > 0) {
+		    return self.update(Message::ViewPayments(id));
+		}
+	    },
+	    Message::CancelOrder(id) => {
+		let result = Order::get_by_id(&self.db_connection, id)
+		    .and_then(|order| order.cancel(&self.db_connection));
+		self.show_error(result);
+		self.refresh_orders();
+	    },
+	    Message::ToggleOrderLines(id) => {
+		if !self.expanded_orders.remove(&id) {
+		    self.expanded_orders.insert(id);
 		}
+		self.order_rows = OrderRow::build(&self.orders, &self.expanded_orders);
 	    },
 	    Message::DeleteOrder(id) => {
-		let order = Order::get_by_id(&self.db_connection, id).unwrap();
-		order.delete(&self.db_connection).unwrap();
+		let result = Order::get_by_id(&self.db_connection, id)
+		    .and_then(|order| order.delete(&self.db_connection, Local::now().naive_local()));
+
+		if self.show_error(result).is_some() {
+		    self.push_undo(UndoAction::Deleted(id));
+		}
 
 		// Refresh the order list to not show the deleted order
-		self.orders = Order::get_all(&self.db_connection)
+		self.refresh_orders();
+	    },
+	    Message::ToggleDeletedOrders => {
+		self.show_deleted_orders = !self.show_deleted_orders;
+		self.viewed_payments = None;
+	    },
+	    Message::RestoreOrder(id) => {
+		let result = Order::restore(&self.db_connection, id);
+
+		// It can't be undone again once it is back
+		if self.show_error(result).is_some() {
+		    self.undo_stack.retain(|action| *action != UndoAction::Deleted(id));
+		}
+		self.refresh_orders();
+	    },
+	    Message::Undo => {
+		if let Some(action) = self.undo_stack.pop() {
+		    let result = action.undo(&self.db_connection, Local::now().naive_local());
+
+		    if self.show_error(result).is_some() {
+			self.undo_message = Some(format!("Undid {}", action.description()));
+		    }
+		    self.refresh_orders();
+		}
+	    },
+	    Message::ViewPayments(id) => {
+		let result = Order::get_by_id(&self.db_connection, id).and_then(|order| {
+		    let payments = Payment::get_for_order(&self.db_connection, id)?;
+		    let late_fee = LateFee::get_for_order(&self.db_connection, id)?;
+		    Ok((order, payments, late_fee))
+		});
+
+		if let Some((order, payments, late_fee)) = self.show_error(result) {
+		    self.payment_form = PaymentForm::new(Local::now().date_naive());
+		    self.charges_form = ChargesForm::from_order(&order);
+		    self.waive_reason = String::new();
+		    self.payment_form_error = None;
+		    self.viewed_payments = Some((order, payments, late_fee));
+		}
+	    },
+	    Message::ClosePayments => {
+		self.viewed_payments = None;
+	    },
+	    Message::PaymentAmountChanged(amount) => {
+		self.payment_form.amount = amount;
+		self.payment_form.amount_show_error = true;
+	    },
+	    Message::PaymentRefundToggled(refund) => {
+		self.payment_form.refund = refund;
+	    },
+	    Message::PaymentMethodSelected(method) => {
+		self.payment_form.method = method;
+	    },
+	    Message::PaymentDateChanged(paid_on) => {
+		self.payment_form.paid_on = paid_on;
+		self.payment_form.paid_on_show_error = true;
+	    },
+	    Message::PaymentNoteChanged(note) => {
+		self.payment_form.note = note;
+		self.payment_form.note_show_error = true;
+	    },
+	    Message::RecordPayment => {
+		let Some(order_id) = self.viewed_payments.as_ref().map(|(order, _, _)| order.id) else {
+		    return Task::none();
+		};
+
+		match self.payment_form.save(&self.db_connection, order_id) {
+		    Ok(_) => {
+			self.payment_form = PaymentForm::new(Local::now().date_naive());
+			self.payment_form_error = None;
+			self.refresh_orders();
+		    },
+		    Err(e) => {
+			Self::show_form_error(&mut self.payment_form, &mut self.payment_form_error, e);
+		    },
+		}
+	    },
+	    Message::DeletePayment(id) => {
+		let result = Payment::delete(&self.db_connection, id);
+		self.show_error(result);
+		self.refresh_orders();
+	    },
+	    Message::DepositChanged(deposit) => {
+		self.charges_form.deposit = deposit;
+		self.charges_form.deposit_show_error = true;
+	    },
+	    Message::DamageChargeChanged(damage_charge) => {
+		self.charges_form.damage_charge = damage_charge;
+		self.charges_form.damage_charge_show_error = true;
+	    },
+	    Message::SaveCharges => {
+		let Some((order, _, _)) = &self.viewed_payments else {
+		    return Task::none();
+		};
+
+		match self.charges_form.save(&self.db_connection, order) {
+		    Ok(_) => {
+			self.payment_form_error = None;
+			self.refresh_orders();
+		    },
+		    Err(e) => {
+			Self::show_form_error(&mut self.charges_form, &mut self.payment_form_error, e);
+		    },
+		}
+	    },
+	    Message::WaiveReasonChanged(reason) => {
+		self.waive_reason = reason;
+	    },
+	    Message::WaiveLateFee => {
+		let Some((_, _, Some(late_fee))) = &self.viewed_payments else {
+		    return Task::none();
+		};
+
+		match late_fee.waive(&self.db_connection, &self.waive_reason, Local::now().naive_local()) {
+		    Ok(_) => {
+			self.waive_reason = String::new();
+			self.payment_form_error = None;
+			self.refresh_orders();
+		    },
+		    Err(e) => self.payment_form_error = Some(e.to_string()),
+		}
+	    },
+	    Message::CustomerFormNameChanged(name) => {
+		self.customer_form.name = name;
+		self.customer_form.name_show_error = true;
+	    },
+	    Message::CustomerPhoneChanged(phone) => {
+		self.customer_form.phone = phone;
+		self.customer_form.phone_show_error = true;
+	    },
+	    Message::CustomerEmailChanged(email) => {
+		self.customer_form.email = email;
+		self.customer_form.email_show_error = true;
+	    },
+	    Message::CustomerAddressChanged(address) => {
+		self.customer_form.address = address;
+	    },
+	    Message::CustomerNotesChanged(notes) => {
+		self.customer_form.notes = notes;
+	    },
+	    Message::SaveCustomer => {
+		match self.customer_form.save(&self.db_connection) {
+		    Ok(_) => {
+			self.customer_form = CustomerForm::default();
+			self.customer_form_error = None;
+			self.refresh_orders();
+		    },
+		    Err(e) => {
+			Self::show_form_error(&mut self.customer_form, &mut self.customer_form_error, e);
+		    },
+		}
+	    },
+	    Message::EditCustomer(id) => {
+		let customer = Customer::get_by_id(&self.db_connection, id);
+
+		if let Some(customer) = self.show_error(customer) {
+		    self.customer_form = CustomerForm::from_customer(&customer);
+		    self.customer_form_error = None;
+		}
+	    },
+	    Message::CancelEditCustomer => {
+		self.customer_form = CustomerForm::default();
+		self.customer_form_error = None;
+	    },
+	    Message::DeleteCustomer(id) => {
+		let result = Customer::get_by_id(&self.db_connection, id)
+		    .and_then(|customer| customer.delete(&self.db_connection));
+
+		if self.show_error(result).is_some()
+		    && self.viewed_customer.as_ref().is_some_and(|(customer, _)| customer.id == id) {
+		    self.viewed_customer = None;
+		}
+		self.refresh_orders();
+	    },
+	    Message::ViewCustomer(id) => {
+		let result = Customer::get_by_id(&self.db_connection, id).and_then(|customer| {
+		    let orders = customer.get_orders(&self.db_connection)?;
+		    Ok((customer, orders))
+		});
+		self.viewed_customer = self.show_error(result);
+	    },
+	    Message::CloseCustomer => {
+		self.viewed_customer = None;
+	    },
+	    Message::NewOrderForCustomer(id) => {
+		let customer = Customer::get_by_id(&self.db_connection, id);
+
+		if let Some(customer) = self.show_error(customer) {
+		    let form = OrderForm {
+			customer_name: customer.name,
+			..Default::default()
+		    };
+		    self.set_order_form(form);
+		    self.order_form_error = None;
+		    self.active_tab = TabId::AddOrder;
+		}
+	    },
+	    Message::ItemNameChanged(name) => {
+		self.item_form.name = name;
+		self.item_form.name_show_error = true;
+	    },
+	    Message::ItemStockChanged(total_stock) => {
+		self.item_form.total_stock = total_stock;
+		self.item_form.total_stock_show_error = true;
+	    },
+	    Message::ItemBoxCapacityChanged(box_capacity) => {
+		self.item_form.box_capacity = box_capacity;
+		self.item_form.box_capacity_show_error = true;
+	    },
+	    Message::ItemHirePriceChanged(hire_price) => {
+		self.item_form.hire_price = hire_price;
+		self.item_form.hire_price_show_error = true;
+	    },
+	    Message::SaveItem => {
+		match self.item_form.save(&self.db_connection) {
+		    Ok(_) => {
+			self.item_form = ItemForm::default();
+			self.item_form_error = None;
+			self.refresh_orders();
+		    },
+		    Err(e) => {
+			Self::show_form_error(&mut self.item_form, &mut self.item_form_error, e);
+		    },
+		}
+	    },
+	    Message::EditItem(id) => {
+		let item = Item::get_by_id(&self.db_connection, id);
+
+		if let Some(item) = self.show_error(item) {
+		    self.item_form = ItemForm::from_item(&item);
+		    self.item_form_error = None;
+		}
+	    },
+	    Message::CancelEditItem => {
+		self.item_form = ItemForm::default();
+		self.item_form_error = None;
+	    },
+	    Message::DeleteItem(id) => {
+		let result = Item::get_by_id(&self.db_connection, id)
+		    .and_then(|item| item.delete(&self.db_connection));
+		self.show_error(result);
+		self.refresh_orders();
+	    },
+	    Message::SearchChanged(search) => {
+		self.order_query.search = search;
+		self.refresh_orders();
+	    },
+	    Message::FilterFromChanged(from) => {
+		self.order_query.from = parse_filter_date(&from);
+		self.filter_from = from;
+		self.refresh_orders();
+	    },
+	    Message::FilterToChanged(to) => {
+		self.order_query.to = parse_filter_date(&to);
+		self.filter_to = to;
+		self.refresh_orders();
+	    },
+	    Message::StatusFilterSelected(status) => {
+		self.order_query.status = Some(status);
+		self.refresh_orders();
+	    },
+	    Message::ItemFilterSelected(item) => {
+		self.order_query.item = Some(item);
+		self.refresh_orders();
+	    },
+	    Message::ClearOrderFilters => {
+		// Keep the sorting, as it isn't a filter
+		self.order_query = OrderQuery {
+		    sort: self.order_query.sort,
+		    ..Default::default()
+		};
+		self.filter_from = String::new();
+		self.filter_to = String::new();
+		self.dashboard_filter = None;
+		self.refresh_orders();
+	    },
+	    Message::SortOrders(field) => {
+		// Clicking the sorted column again flips the direction
+		let direction = match self.order_query.sort {
+		    Some((sorted_field, direction)) if sorted_field == field => direction.reversed(),
+		    _ => SortDirection::Ascending,
+		};
+		self.order_query.sort = Some((field, direction));
+
+		for column in self.order_table_columns.iter_mut() {
+		    column.sort = match column.kind.sort_field() {
+			Some(f) if f == field => Some(direction),
+			_ => None,
+		    };
+		}
+
+		self.refresh_orders();
+	    },
+	    Message::PrizeNameChanged(prize_name) => {
+		self.raffle_form.prize_name = prize_name;
+		self.raffle_form.prize_name_show_error = true;
+	    },
+	    Message::RaffleWinnersChanged(winners) => {
+		self.raffle_form.winners = winners;
+		self.raffle_form.winners_show_error = true;
+	    },
+	    Message::DrawRaffle => {
+		match self.raffle_form.draw(&self.db_connection, Local::now().naive_local()) {
+		    Ok(draws) => {
+			let winners: Vec<String> = draws.iter().map(|d| format!(
+			    "{} ({})",
+			    d.winner_name.clone().unwrap_or_default(),
+			    d.winner_raffle_number.unwrap_or_default(),
+			)).collect();
+
+			self.raffle_message = Some(format!(
+			    "{} won by {}",
+			    draws[0].prize_name,
+			    winners.join(", "),
+			));
+			self.raffle_form = RaffleDrawForm::default();
+			self.refresh_orders();
+		    },
+		    Err(e) => {
+			Self::show_form_error(&mut self.raffle_form, &mut self.raffle_message, e);
+		    },
+		}
+	    },
+	    Message::VerifyRaffleDraw(id) => {
+		let result = RaffleDraw::get_by_id(&self.db_connection, id).and_then(|draw| {
+		    let recorded = draw.get_draw_winners(&self.db_connection)?;
+		    let replayed = draw.replay(&self.db_connection)?;
+		    Ok((draw, recorded, replayed))
+		});
+		let Some((draw, recorded, replayed)) = self.show_error(result) else {
+		    return Task::none();
+		};
+
+		self.raffle_message = Some(if recorded == replayed {
+		    format!("Re-running the {} draw with seed {} gives the same winners", draw.prize_name, draw.seed)
+		} else {
+		    format!(
+			"Re-running the {} draw with seed {} gives different winners, as the entrants have changed since",
+			draw.prize_name,
+			draw.seed,
+		    )
+		});
+	    },
+	    Message::PackingFromChanged(from) => {
+		self.packing_from = from;
+		self.refresh_packing_plan();
+	    },
+	    Message::PackingPathChanged(path) => {
+		self.packing_path = path;
+		self.packing_message = None;
+	    },
+	    Message::SavePackingPlan => {
+		if let Some(plan) = &self.packing_plan {
+		    self.packing_message = match fs::write(self.packing_path.trim(), plan.to_text()) {
+			Ok(_) => Some("Packing plan saved".to_string()),
+			Err(e) => Some(format!("Couldn't save: {}", e)),
+		    };
+		}
+	    },
+	    Message::ReminderMethodSelected(method) => {
+		self.reminder_method = method;
+	    },
+	    Message::WriteReminders => {
+		let now = Local::now().naive_local();
+		let result = reminder::write_reminders(&self.db_connection, &self.overdue, self.reminder_method, None, now);
+		self.reminder_message = match result {
+		    Ok(written) if written.is_empty() => {
+			Some("Everyone has been reminded lately, so no reminders were written".to_string())
+		    },
+		    Ok(written) => Some(format!(
+			"Wrote {} reminder(s) to {}",
+			written.len(),
+			reminder::default_reminder_dir(&self.db_connection).display(),
+		    )),
+		    Err(e) => Some(format!("The reminders couldn't be written: {}", e)),
+		};
+		self.refresh_orders();
+	    },
+	    Message::SmtpFieldChanged(field, value) => {
+		self.smtp_form.set_value(field, value);
+		self.email_message = None;
+	    },
+	    Message::SmtpSecuritySelected(security) => {
+		self.smtp_form.security = security;
+		self.email_message = None;
+	    },
+	    Message::SaveSmtpSettings => {
+		self.smtp_form.show_errors = true;
+		self.email_message = match self.smtp_form.save(&self.db_connection) {
+		    Ok(settings) if settings.is_configured() => Some("Email settings saved".to_string()),
+		    Ok(_) => Some("Email settings saved. Reminders won't be emailed until a server is given".to_string()),
+		    Err(e) => Some(e.to_string()),
+		};
+	    },
+	    Message::EmailTimer => {
+		if !self.sending_emails {
+		    return self.send_emails(false);
+		}
+	    },
+	    Message::SendEmailsNow => {
+		if self.sending_emails {
+		    self.email_message = Some("Reminders are already being sent".to_string());
+		} else {
+		    return self.send_emails(true);
+		}
+	    },
+	    Message::EmailsSent(by_hand, sent) => {
+		self.sending_emails = false;
+		let result = sent.record(&self.db_connection);
+		self.finish_sending_emails(by_hand, result.map(Some));
+	    },
+	    Message::RetryEmail(id) => {
+		let result = OutgoingEmail::retry(&self.db_connection, id, Local::now().naive_local());
+		self.show_error(result);
+		self.refresh_emails();
+	    },
+	    Message::CsvPathChanged(path) => {
+		self.csv_path = path;
+		self.import_preview = None;
+		self.csv_message = None;
+	    },
+	    Message::ExportOrders => {
+		// Exports the orders as they are filtered on the orders tab
+		self.csv_message = match export_csv_file(&self.orders, Path::new(self.csv_path.trim())) {
+		    Ok(_) => Some(format!("Exported {} order(s)", self.orders.len())),
+		    Err(e) => Some(format!("Couldn't export: {}", e)),
+		};
+	    },
+	    Message::PreviewImport => {
+		match ImportPreview::from_file(&self.db_connection, Path::new(self.csv_path.trim())) {
+		    Ok(preview) => {
+			self.csv_message = Some(format!(
+			    "{} row(s) read, {} with errors",
+			    preview.rows.len(),
+			    preview.invalid_rows().count(),
+			));
+			self.import_preview = Some(preview);
+		    },
+		    Err(e) => {
+			self.csv_message = Some(e.to_string());
+			self.import_preview = None;
+		    },
+		}
+	    },
+	    Message::ImportOrders => {
+		if let Some(preview) = &self.import_preview {
+		    self.csv_message = match preview.commit(&self.db_connection) {
+			Ok(orders) => {
+			    self.push_undo(UndoAction::Imported(orders.iter().map(|order| order.id).collect()));
+			    Some(format!("Imported {} order(s)", orders.len()))
+			},
+			Err(e) => Some(format!("Nothing was imported: {}", e)),
+		    };
+		    self.import_preview = None;
+		    self.refresh_orders();
+		}
+	    },
+	    Message::CancelImport => {
+		self.import_preview = None;
+		self.csv_message = None;
+	    },
+	    Message::SettingChanged(key, value) => {
+		self.settings_form.set_value(key, value);
+		self.settings_message = None;
+	    },
+	    Message::SaveSettings => {
+		self.settings_form.show_errors = true;
+		self.settings_message = match self.settings_form.save(&self.db_connection) {
+		    Ok(_) => Some("Settings saved".to_string()),
+		    Err(e) => Some(e.to_string()),
+		};
+		self.refresh_backup_interval();
+	    },
+	    Message::BackupTimer => {
+		let result = backup::run_scheduled_backup(&self.db_connection, Local::now().naive_local());
+		self.show_error(result);
+		self.refresh_backups();
+	    },
+	    Message::BackUpNow => {
+		self.backup_message = match backup::run_scheduled_backup(&self.db_connection, Local::now().naive_local()) {
+		    Ok(Some(backup)) => Some(format!("Backed up {} order(s) to {}", backup.order_count.unwrap_or(0), backup.name())),
+		    Ok(None) => Some("The database isn't kept in a file, so it can't be backed up".to_string()),
+		    Err(e) => Some(format!("The backup failed: {}", e)),
+		};
+		self.refresh_backups();
+	    },
+	    // Sent reminders are recorded in the database that is open when they
+	    // finish, so it can't be swapped out from under them
+	    Message::RestoreBackup(_) if self.sending_emails => {
+		self.backup_message = Some("Wait for the reminders to finish sending before restoring".to_string());
+	    },
+	    Message::ProfileSelected(_) | Message::AddProfile if self.sending_emails => {
+		self.profile_message = Some("Wait for the reminders to finish sending before changing profile".to_string());
+	    },
+	    Message::RestoreBackup(path) => {
+		self.backup_message = match backup::restore_backup(&self.db_connection, &path, Local::now().naive_local()) {
+		    Ok(before_restore) => {
+			self.reload_database();
+
+			Some(format!(
+			    "Restored the backup. The data from before is in {}",
+			    before_restore.name(),
+			))
+		    },
+		    Err(e) => Some(format!("Nothing was restored: {}", e)),
+		};
+		self.refresh_backups();
+	    },
+	    Message::ProfileSelected(name) => {
+		let profile = self.config.get_profile(&name);
+		self.profile_message = match profile.and_then(|profile| self.open_profile(profile)) {
+		    Ok(_) => Some(format!("Opened {}", name)),
+		    Err(e) => Some(e.to_string()),
+		};
+	    },
+	    Message::NewProfileNameChanged(name) => {
+		self.new_profile_name = name;
+		self.profile_message = None;
+	    },
+	    Message::NewProfileDbChanged(db) => {
+		self.new_profile_db = db;
+		self.profile_message = None;
+	    },
+	    Message::AddProfile => {
+		// The profile is only kept if its database can be opened
+		let before = self.config.clone();
+		let result = self.config.add_profile(&self.new_profile_name, Some(&self.new_profile_db))
+		    .and_then(|profile| self.open_profile(profile));
+
+		self.profile_message = match result {
+		    Ok(_) => {
+			self.new_profile_name.clear();
+			self.new_profile_db.clear();
+			Some("Added the profile and opened it".to_string())
+		    },
+		    Err(e) => {
+			self.config = before;
+			Some(e.to_string())
+		    },
+		};
+	    },
+	    Message::SaveDocket(id) => {
+		let result = Order::get_by_id(&self.db_connection, id).and_then(|order| {
+		    docket::save_docket(&self.db_connection, &order, None, Local::now().date_naive())
+		});
+		self.docket = Some(result.map_err(|e| format!("The docket couldn't be made: {}", e)));
+	    },
+	    Message::OpenDocket(path) => {
+		let result = docket::open_docket(&path);
+		self.show_error(result);
+	    },
+	    Message::AskToConfirm(question, action) => {
+		self.confirm = Some((question, *action));
+	    },
+	    Message::Confirm => {
+		if let Some((_, action)) = self.confirm.take() {
+		    return self.update(action);
+		}
+	    },
+	    Message::CancelConfirm => {
+		self.confirm = None;
+	    },
+	    Message::DismissError => {
+		self.error = None;
 	    },
 	    Message::SyncOrderTableHeader(offset) => {
 		// Return background task to synch the order table header
@@ -193,6 +1350,46 @@ impl App {
                     }
 		})
 	    },
+	    Message::SyncCustomerTableHeader(offset) => {
+		// Returns a background task to sync the customer table header position
+		return Task::batch(vec![
+		    scrollable::scroll_to(self.customer_table_header.clone(), offset),
+		])
+	    }
+	    Message::CustomerTableResizing(index, offset) => {
+		// Updates the resize offset for a specific customer table column
+		if let Some(column) = self.customer_table_columns.get_mut(index) {
+		    column.resize_offset = Some(offset);
+		}
+	    }
+	    Message::CustomerTableResized => {
+		// Applies the stored resize offsets to update column widths in the customer table
+		self.customer_table_columns.iter_mut().for_each(|column| {
+		    if let Some(offset) = column.resize_offset.take() {
+			column.width += offset;
+		    }
+		})
+	    },
+	    Message::SyncItemTableHeader(offset) => {
+		// Returns a background task to sync the item table header position
+		return Task::batch(vec![
+		    scrollable::scroll_to(self.item_table_header.clone(), offset),
+		])
+	    }
+	    Message::ItemTableResizing(index, offset) => {
+		// Updates the resize offset for a specific item table column
+		if let Some(column) = self.item_table_columns.get_mut(index) {
+		    column.resize_offset = Some(offset);
+		}
+	    }
+	    Message::ItemTableResized => {
+		// Applies the stored resize offsets to update column widths in the item table
+		self.item_table_columns.iter_mut().for_each(|column| {
+		    if let Some(offset) = column.resize_offset.take() {
+			column.width += offset;
+		    }
+		})
+	    },
 	    Message::SyncRaffleTableHeader(offset) => {
 		// Returns a background task to sync the raffle table header position
                 return Task::batch(vec![
@@ -213,114 +1410,1181 @@ impl App {
                     }
 		})
 	    },
+	    Message::SyncRaffleDrawTableHeader(offset) => {
+		// Returns a background task to sync the raffle draw table header position
+		return Task::batch(vec![
+		    scrollable::scroll_to(self.raffle_draw_table_header.clone(), offset),
+		])
+	    }
+	    Message::RaffleDrawTableResizing(index, offset) => {
+		// Updates the resize offset for a specific raffle draw table column
+		if let Some(column) = self.raffle_draw_table_columns.get_mut(index) {
+		    column.resize_offset = Some(offset);
+		}
+	    }
+	    Message::RaffleDrawTableResized => {
+		// Applies the stored resize offsets to update column widths in the raffle draw table
+		self.raffle_draw_table_columns.iter_mut().for_each(|column| {
+		    if let Some(offset) = column.resize_offset.take() {
+			column.width += offset;
+		    }
+		})
+	    },
         }
 
 	Task::none()
     }
 
-    /// Produces the UI tree of the application.
-    pub fn view(&self) -> Element<Message> {
-	Tabs::new(Message::TabSelected)
-	    .push(
-		TabId::Orders,
-		TabLabel::Text("Orders".to_string()),
-		column![
-		    container(
-			text("Orders").size(30)
-		    ).padding(10),
-		    responsive(|size| {
-			table(
-			    self.order_table_header.clone(),
-			    self.order_table_body.clone(),
-			    &self.order_table_columns,
-			    &self.orders,
-			    Message::SyncOrderTableHeader,
-			).on_column_resize(
-			    Message::OrderTableResizing,
-			    Message::OrderTableResized
-			).min_width(
-			    size.width
-			).into()
-		    })
-		],
-	    )
-	    .push(
-		TabId::AddOrder,
-		TabLabel::Text("Add Order".to_string()),
-		center(scrollable(column![
-		    text("Add Order").size(30),
+    /// Reloads the orders from the database using the current search, filters and sorting.
+    /// If anything can't be loaded, the error is shown and the lists are left empty.
+    fn refresh_orders(&mut self) {
+	let result = (|| -> Result<(), Error> {
+	    self.orders = Order::query(&self.db_connection, &self.order_query)?;
+	    self.dashboard = Some(Dashboard::load(&self.db_connection, Local::now().date_naive())?);
+	    self.order_rows = OrderRow::build(&self.orders, &self.expanded_orders);
+	    self.deleted_orders = Order::get_deleted(&self.db_connection)?;
+	    self.hired_item_names = Order::get_item_names(&self.db_connection)?;
+	    self.customers = Customer::get_all(&self.db_connection)?;
+	    if let Some(id) = self.viewed_customer.as_ref().map(|(customer, _)| customer.id) {
+		let customer = Customer::get_by_id(&self.db_connection, id)?;
+		let orders = customer.get_orders(&self.db_connection)?;
+		self.viewed_customer = Some((customer, orders));
+	    }
+	    if let Some(id) = self.viewed_payments.as_ref().map(|(order, _, _)| order.id) {
+		self.viewed_payments = match Order::get_by_id(&self.db_connection, id) {
+		    // Deleted since it was opened, e.g. by an undo
+		    Err(Error::Database(rusqlite::Error::QueryReturnedNoRows)) => None,
+		    order => Some((
+			order?,
+			Payment::get_for_order(&self.db_connection, id)?,
+			LateFee::get_for_order(&self.db_connection, id)?,
+		    )),
+		};
+	    }
+	    self.items = Item::get_all(&self.db_connection)?;
+	    self.item_names = self.items.iter().map(|item| item.name.clone()).collect();
+	    self.raffle_rows = OrderRow::build(&Order::get_all(&self.db_connection)?, &HashSet::new());
+	    self.raffle_draws = RaffleDraw::get_all(&self.db_connection)?;
+	    self.overdue = OverdueOrder::get_all(&self.db_connection, Local::now().date_naive())?;
+	    Ok(())
+	})();
+
+	if self.show_error(result).is_none() {
+	    self.orders = Vec::new();
+	    self.dashboard = None;
+	    self.order_rows = Vec::new();
+	    self.deleted_orders = Vec::new();
+	    self.hired_item_names = Vec::new();
+	    self.customers = Vec::new();
+	    self.viewed_customer = None;
+	    self.viewed_payments = None;
+	    self.items = Vec::new();
+	    self.item_names = Vec::new();
+	    self.raffle_rows = Vec::new();
+	    self.raffle_draws = Vec::new();
+	    self.overdue = Vec::new();
+	}
+
+	self.refresh_customer_names();
+    }
+
+    /// Switches to the database of another profile, and remembers it as the
+    /// one to open next time. The open database is kept if it can't be opened.
+    fn open_profile(&mut self, profile: Profile) -> Result<(), Error> {
+	self.db_connection = init_db(&profile.db_path)?;
+	self.config.profile = Some(profile.name.clone());
+	self.profile = Some(profile);
+	self.reload_database();
+
+	// Deleted orders are only kept for so long
+	let purged = Order::purge_deleted(&self.db_connection, Local::now().naive_local());
+	self.show_error(purged);
+
+	if let Some(config_path) = &self.config_path {
+	    let saved = self.config.save(config_path);
+	    self.show_error(saved);
+	}
+
+	Ok(())
+    }
+
+    /// Reloads everything shown after the data in the database has been
+    /// replaced, e.g. by opening another profile.
+    fn reload_database(&mut self) {
+	// What could be undone was done to the data that was replaced
+	self.undo_stack.clear();
+	self.undo_message = None;
+	self.confirm = None;
+	self.viewed_customer = None;
+	self.viewed_payments = None;
+	self.refresh_orders();
+	self.set_order_form(OrderForm::default());
+	self.refresh_backup_interval();
+
+	let form = SettingsForm::load(&self.db_connection);
+	if let Some(form) = self.show_error(form) {
+	    self.settings_form = form;
+	}
+    }
+
+    /// Reloads the reminder emails on the email tab, and how many tries each gets.
+    fn refresh_emails(&mut self) {
+	let result = (|| -> Result<(), Error> {
+	    self.emails = OutgoingEmail::get_all(&self.db_connection)?;
+	    self.email_max_attempts = EMAIL_MAX_ATTEMPTS.get(&self.db_connection)?;
+	    Ok(())
+	})();
+
+	if self.show_error(result).is_none() {
+	    self.emails = Vec::new();
+	}
+    }
+
+    /// Reloads the list of backups on the backups tab.
+    fn refresh_backups(&mut self) {
+	let backups = match backup::backup_dir(&self.db_connection) {
+	    Some(dir) => backup::list_backups(&dir),
+	    None => Ok(Vec::new()),
+	};
+
+	self.backups = self.show_error(backups).unwrap_or_default();
+    }
+
+    /// Reads how often to back up from the settings.
+    fn refresh_backup_interval(&mut self) {
+	let minutes = BACKUP_INTERVAL_MINUTES.get(&self.db_connection);
+
+	if let Some(minutes) = self.show_error(minutes) {
+	    self.backup_interval_minutes = minutes.max(BACKUP_INTERVAL_MINUTES.min);
+	}
+    }
+
+    /// Remembers an action so it can be undone, forgetting the oldest ones
+    /// past the limit.
+    fn push_undo(&mut self, action: UndoAction) {
+	self.undo_stack.push(action);
+	if self.undo_stack.len() > UNDO_LIMIT {
+	    self.undo_stack.remove(0);
+	}
+	self.undo_message = None;
+    }
+
+    /// Puts a new form on the add order tab, checking its dates against the
+    /// limits in the settings.
+    fn set_order_form(&mut self, mut form: OrderForm) {
+	let limits = DateLimits::load(&self.db_connection, Local::now().date_naive());
+	if let Some(limits) = self.show_error(limits) {
+	    form.date_limits = limits;
+	}
+
+	self.order_form = form;
+	self.order_form_confirm = None;
+	self.open_date_picker = None;
+	self.refresh_customer_names();
+    }
+
+    /// Builds a date input for the order form, with a button that opens a
+    /// calendar. Relative dates like "+3d" have the day they mean shown below.
+    fn order_date_input<'a>(
+	&self,
+	field: &'static str,
+	value: &'a str,
+	on_input: fn(String) -> Message,
+    ) -> Element<'a, Message> {
+	let today = self.order_form.date_limits.today;
+	let parsed = parse_date(value, today);
+
+	let picker = date_picker(
+	    self.open_date_picker == Some(field),
+	    parsed.unwrap_or(today),
+	    button("Pick").on_press(Message::OpenDatePicker(field)),
+	    Message::CloseDatePicker,
+	    move |date| Message::DatePicked(field, date),
+	);
+
+	column![
+	    row![
+		text_input("YYYY-mm-dd, today, +3d, next fri", value).on_input(on_input),
+		picker,
+	    ].spacing(5),
+	].push_maybe(
+	    parsed
+		.filter(|date| date.format("%Y-%m-%d").to_string() != value.trim())
+		.map(|date| text(date.format("%A %-d %B %Y").to_string()).size(14))
+	).into()
+    }
+
+    /// Rebuilds the names the customer name box suggests, starting it off with
+    /// the name already in the order form.
+    fn refresh_customer_names(&mut self) {
+	self.customer_names = combo_box::State::with_selection(
+	    self.customers.iter().map(|customer| customer.name.clone()).collect(),
+	    Some(&self.order_form.customer_name),
+	);
+    }
+
+    /// Plans the boxes for the pickups over the week starting on the chosen day,
+    /// or clears the plan if the day isn't a full date yet.
+    fn refresh_packing_plan(&mut self) {
+	self.packing_plan = match parse_filter_date(&self.packing_from) {
+	    Some(from) => {
+		let plan = PackingPlan::build(&self.db_connection, from, from + Days::new(PACKING_PLAN_DAYS - 1));
+		self.show_error(plan)
+	    },
+	    None => None,
+	};
+    }
+
+    /// Queues the reminders that are due and sends the emails that are ready on
+    /// another thread, so a slow mail server doesn't stop the window
+    /// responding. `by_hand` is true when staff asked for them to be sent.
+    fn send_emails(&mut self, by_hand: bool) -> Task<Message> {
+	match email::take_reminders(&self.db_connection, Local::now().naive_local()) {
+	    Ok(Some(batch)) => {
+		self.sending_emails = true;
+		if by_hand {
+		    self.email_message = Some("Sending reminders...".to_string());
+		}
+		self.refresh_emails();
+
+		Task::perform(
+		    async move {
+			let (sender, receiver) = oneshot::channel();
+			thread::spawn(move || sender.send(batch.send()));
+			receiver.await
+		    },
+		    move |sent| Message::EmailsSent(
+			by_hand,
+			sent.unwrap_or_else(|_| SentBatch::failed("Sending the emails stopped unexpectedly".to_string())),
+		    ),
+		)
+	    },
+	    result => {
+		self.finish_sending_emails(by_hand, result.map(|_| None));
+		Task::none()
+	    },
+	}
+    }
+
+    /// Shows what sending the emails did. A failure of an automatic send goes
+    /// in the error banner, but only if it isn't the same as the last one, so
+    /// a server that is down isn't reported every time the emails are tried.
+    fn finish_sending_emails(&mut self, by_hand: bool, result: Result<Option<SendSummary>, Error>) {
+	let error = result.as_ref().err().map(|e| e.to_string());
+
+	if by_hand {
+	    self.email_message = Some(match result {
+		Ok(Some(summary)) => format!(
+		    "Queued {} email(s), sent {} and {} failed",
+		    summary.queued,
+		    summary.sent,
+		    summary.failed,
+		),
+		Ok(None) => "Save the email settings before sending reminders".to_string(),
+		Err(e) => format!("The emails couldn't be sent: {}", e),
+	    });
+	} else if error.is_some() && error != self.email_error {
+	    self.error = error.clone();
+	}
+
+	self.email_error = error;
+	self.refresh_emails();
+    }
+
+    /// Shows why a form couldn't be saved. Errors in its fields are already
+    /// shown under their inputs, so `message` is only used for other errors.
+    fn show_form_error(form: &mut impl Form, message: &mut Option<String>, error: Error) {
+	form.show_all_errors();
+
+	if !form.has_visible_errors() {
+	    *message = Some(error.to_string());
+	}
+    }
+
+    /// Shows the error in the error banner if there is one, otherwise gives back the value.
+    fn show_error<T>(&mut self, result: Result<T, Error>) -> Option<T> {
+	match result {
+	    Ok(value) => Some(value),
+	    Err(e) => {
+		self.error = Some(e.to_string());
+		None
+	    },
+	}
+    }
+
+    /// Produces the UI tree of the application.
+    pub fn view(&self) -> Element<'_, Message> {
+	// The add order tab doubles as the edit screen when an order is being edited
+	let (order_form_title, order_form_button) = match self.order_form.editing_id {
+	    Some(_) => ("Edit Order", button("Save").on_press(Message::SaveOrder)),
+	    None => ("Add Order", button("Add").on_press(Message::AddOrder)),
+	};
+
+	let (customer_form_title, customer_form_button) = match self.customer_form.editing_id {
+	    Some(_) => ("Edit Customer", "Save"),
+	    None => ("Add Customer", "Add"),
+	};
+
+	// Shows the hires of the customer being looked at in place of the table
+	let customer_pane: Element<_> = match &self.viewed_customer {
+	    Some((customer, orders)) => {
+		let hire_text = |order: &Order| text(format!(
+		    "{} to {}   Receipt {}   {}   {}",
+		    order.hired_on.format("%Y-%m-%d"),
+		    order.return_on.format("%Y-%m-%d"),
+		    order.receipt_number,
+		    order.lines.iter()
+			.map(|line| format!("{} x{}", line.item_hired, line.how_many))
+			.collect::<Vec<String>>()
+			.join(", "),
+		    order.status,
+		)).into();
+		let contact = [
+		    ("Phone", &customer.phone),
+		    ("Email", &customer.email),
+		    ("Address", &customer.address),
+		    ("Notes", &customer.notes),
+		];
+
+		scrollable(column![
+		    text(customer.name.clone()).size(30),
+		    column(contact.into_iter()
+			.filter(|(_, value)| !value.is_empty())
+			.map(|(label, value)| text(format!("{}: {}", label, value)).into())
+		    ).spacing(5),
+		    text("Current Hires").size(20),
+		    column(orders.iter().filter(|order| order.status.is_open()).map(hire_text)).spacing(5),
+		    text("Past Hires").size(20),
+		    column(orders.iter().filter(|order| !order.status.is_open()).map(hire_text)).spacing(5),
+		    row![
+			button("New order").on_press(Message::NewOrderForCustomer(customer.id)),
+			button("Back").on_press(Message::CloseCustomer),
+		    ].spacing(10),
+		].padding(10).spacing(10)).width(Length::Fill).into()
+	    },
+	    None => responsive(|size| {
+		table(
+		    self.customer_table_header.clone(),
+		    self.customer_table_body.clone(),
+		    &self.customer_table_columns,
+		    &self.customers,
+		    Message::SyncCustomerTableHeader,
+		).on_column_resize(
+		    Message::CustomerTableResizing,
+		    Message::CustomerTableResized
+		).min_width(
+		    size.width
+		).into()
+	    }).into(),
+	};
+
+	let (item_form_title, item_form_button) = match self.item_form.editing_id {
+	    Some(_) => ("Edit Item", "Save"),
+	    None => ("Add Item", "Add"),
+	};
+
+	let tabs = Tabs::new(Message::TabSelected)
+	    .push(
+		TabId::Dashboard,
+		TabLabel::Text("Dashboard".to_string()),
+		self.dashboard(),
+	    )
+	    .push(
+		TabId::Orders,
+		TabLabel::Text("Orders".to_string()),
+		column![
+		    row![
+			text("Orders").size(30).width(Length::Fill),
+			text(self.undo_message.clone().unwrap_or_default()),
+			self.docket_message(),
+			button(text(match self.undo_stack.last() {
+			    Some(action) => format!("Undo {}", action.description()),
+			    None => "Undo".to_string(),
+			})).on_press_maybe((!self.undo_stack.is_empty()).then_some(Message::Undo)),
+			button(text(match self.show_deleted_orders {
+			    true => "Back to orders".to_string(),
+			    false => format!("Recently deleted ({})", self.deleted_orders.len()),
+			})).on_press(Message::ToggleDeletedOrders),
+		    ].spacing(10).padding(10).align_y(iced::Alignment::Center),
+		    if self.show_deleted_orders {
+			self.deleted_orders_list()
+		    } else if let Some((order, payments, late_fee)) = &self.viewed_payments {
+			self.payments_pane(order, payments, late_fee.as_ref())
+		    } else {
+			self.order_table()
+		    },
+		],
+	    )
+	    .push(
+		TabId::AddOrder,
+		TabLabel::Text("Add Order".to_string()),
+		center(scrollable(column![
+		    text(order_form_title).size(30),
 		    column![
 			required_input_label("Customer Name"),
-			text_input("", &self.order_form.customer_name)
-			    .on_input(Message::CustomerNameChanged),
+			combo_box(
+			    &self.customer_names,
+			    "Start typing to find a customer",
+			    Some(&self.order_form.customer_name),
+			    Message::CustomerNameChanged,
+			).on_input(Message::CustomerNameChanged),
 			field_error(self.order_form.get_visible_field_error("customer_name")),
 		    ],
 		    column![
 			required_input_label("Receipt Number"),
-			text_input("", &self.order_form.receipt_number)
-			    .on_input(Message::ReceiptNumberChanged),
+			row![
+			    text_input("", &self.order_form.receipt_number)
+				.on_input(Message::ReceiptNumberChanged),
+			    button("Next number").on_press(Message::NextReceiptNumber),
+			].spacing(10),
 			field_error(self.order_form.get_visible_field_error("receipt_number")),
 		    ],
 		    column![
-			required_input_label("Item Hired"),
-			text_input("", &self.order_form.item_hired)
-			    .on_input(Message::ItemHiredChanged),
-			field_error(self.order_form.get_visible_field_error("item_hired")),
-		    ],
-		    column![
-			required_input_label("How Many"),
-			text_input("", &self.order_form.how_many)
-			    .on_input(Message::HowManyChanged),
-			field_error(self.order_form.get_visible_field_error("how_many")),
-		    ],
+			required_input_label("Items Hired"),
+			column(self.order_form.lines.iter().enumerate().map(|(index, line)| {
+			    column![
+				row![
+				    pick_list(
+					self.item_names.as_slice(),
+					Some(line.item_hired.clone()).filter(|item| !item.is_empty()),
+					move |item| Message::ItemHiredChanged(index, item),
+				    ).placeholder("Pick an item from the catalogue").width(Length::Fill),
+				    text_input("How many", &line.how_many)
+					.on_input(move |how_many| Message::HowManyChanged(index, how_many))
+					.width(Length::Fixed(100.0)),
+				    button("Remove").on_press_maybe(
+					(self.order_form.lines.len() > 1).then_some(Message::RemoveOrderLine(index))
+				    ),
+				].spacing(10),
+				field_error(
+				    self.order_form.get_visible_line_error(index, "item_hired")
+					.or_else(|| self.order_form.get_visible_line_error(index, "how_many"))
+				),
+			    ].into()
+			})).spacing(5),
+			field_error(self.order_form.get_visible_field_error("lines")),
+			button("Add another item").on_press(Message::AddOrderLine),
+		    ].spacing(5),
 		    row![
 			column![
 			    required_input_label("Hired On"),
-			    text_input("YYYY-mm-dd", &self.order_form.hired_on)
-				.on_input(Message::HiredOnChanged),
+			    self.order_date_input("hired_on", &self.order_form.hired_on, Message::HiredOnChanged),
 			    field_error(self.order_form.get_visible_field_error("hired_on")),
 			],
 			column![
 			    required_input_label("Return On"),
-			    text_input("YYYY-mm-dd", &self.order_form.return_on)
-				.on_input(Message::ReturnOnChanged),
+			    self.order_date_input("return_on", &self.order_form.return_on, Message::ReturnOnChanged),
 			    field_error(self.order_form.get_visible_field_error("return_on")),
 			],
 		    ].spacing(20),
-		    button("Add").on_press(Message::AddOrder),
+		    field_error(self.order_form_error.clone()),
+		    column![field_error(self.order_form_confirm.clone())].push_maybe(
+			self.order_form_confirm.as_ref()
+			    .map(|_| button("Use it anyway").on_press(Message::ConfirmReceiptReuse))
+		    ),
+		    order_form_button,
 		].padding([10, 0]).width(Length::Fixed(500.0)).spacing(10)))
 	    )
 	    .push(
-		TabId::Raffle,
-		TabLabel::Text("Raffle".to_string()),
-		column![
-		    container(
-			text("Raffle").size(30)
-		    ).padding(10),
+		TabId::Customers,
+		TabLabel::Text("Customers".to_string()),
+		row![
+		    scrollable(column![
+			text(customer_form_title).size(30),
+			column![
+			    required_input_label("Name"),
+			    text_input("", &self.customer_form.name)
+				.on_input(Message::CustomerFormNameChanged),
+			    field_error(self.customer_form.get_visible_field_error("name")),
+			],
+			column![
+			    text("Phone"),
+			    text_input("", &self.customer_form.phone)
+				.on_input(Message::CustomerPhoneChanged),
+			    field_error(self.customer_form.get_visible_field_error("phone")),
+			],
+			column![
+			    text("Email"),
+			    text_input("", &self.customer_form.email)
+				.on_input(Message::CustomerEmailChanged),
+			    field_error(self.customer_form.get_visible_field_error("email")),
+			],
+			column![
+			    text("Address"),
+			    text_input("", &self.customer_form.address)
+				.on_input(Message::CustomerAddressChanged),
+			],
+			column![
+			    text("Notes"),
+			    text_input("", &self.customer_form.notes)
+				.on_input(Message::CustomerNotesChanged),
+			],
+			field_error(self.customer_form_error.clone()),
+			row![
+			    button(customer_form_button).on_press(Message::SaveCustomer),
+			    button("Cancel").on_press_maybe(
+				self.customer_form.editing_id.map(|_| Message::CancelEditCustomer)
+			    ),
+			].spacing(10),
+		    ].padding(10).spacing(10)).width(Length::Fixed(320.0)),
+		    customer_pane,
+		],
+	    )
+	    .push(
+		TabId::Items,
+		TabLabel::Text("Items".to_string()),
+		row![
+		    scrollable(column![
+			text(item_form_title).size(30),
+			column![
+			    required_input_label("Name"),
+			    text_input("", &self.item_form.name)
+				.on_input(Message::ItemNameChanged),
+			    field_error(self.item_form.get_visible_field_error("name")),
+			],
+			column![
+			    required_input_label("Stock"),
+			    text_input("", &self.item_form.total_stock)
+				.on_input(Message::ItemStockChanged),
+			    field_error(self.item_form.get_visible_field_error("total_stock")),
+			],
+			column![
+			    required_input_label("Items Per Box"),
+			    text_input("", &self.item_form.box_capacity)
+				.on_input(Message::ItemBoxCapacityChanged),
+			    field_error(self.item_form.get_visible_field_error("box_capacity")),
+			],
+			column![
+			    required_input_label("Hire Price per Day ($)"),
+			    text_input("e.g. 12.50", &self.item_form.hire_price)
+				.on_input(Message::ItemHirePriceChanged),
+			    field_error(self.item_form.get_visible_field_error("hire_price")),
+			],
+			field_error(self.item_form_error.clone()),
+			row![
+			    button(item_form_button).on_press(Message::SaveItem),
+			    button("Cancel").on_press_maybe(
+				self.item_form.editing_id.map(|_| Message::CancelEditItem)
+			    ),
+			].spacing(10),
+		    ].padding(10).spacing(10)).width(Length::Fixed(320.0)),
 		    responsive(|size| {
 			table(
-			    self.raffle_table_header.clone(),
-			    self.raffle_table_body.clone(),
-			    &self.raffle_table_columns,
-			    &self.orders,
-			    Message::SyncRaffleTableHeader,
+			    self.item_table_header.clone(),
+			    self.item_table_body.clone(),
+			    &self.item_table_columns,
+			    &self.items,
+			    Message::SyncItemTableHeader,
 			).on_column_resize(
-			    Message::RaffleTableResizing,
-			    Message::RaffleTableResized
+			    Message::ItemTableResizing,
+			    Message::ItemTableResized
 			).min_width(
 			    size.width
 			).into()
-		    })
+		    }),
 		],
 	    )
-	    .set_active_tab(&self.active_tab)
-	    .into()
+	    .push(
+		TabId::Packing,
+		TabLabel::Text("Packing".to_string()),
+		center(scrollable(column![
+		    text("Packing Plan").size(30),
+		    column![
+			text("First Day (YYYY-MM-DD)"),
+			text_input("", &self.packing_from).on_input(Message::PackingFromChanged),
+		    ],
+		    match &self.packing_plan {
+			Some(plan) => text(plan.to_text()).font(Font::MONOSPACE),
+			None => text("Enter the first day to plan for, e.g. 2025-03-18"),
+		    },
+		    column![
+			text("Save As"),
+			text_input("e.g. /home/shop/packing.txt", &self.packing_path)
+			    .on_input(Message::PackingPathChanged),
+		    ],
+		    button("Save printout").on_press_maybe(
+			self.packing_plan.as_ref().map(|_| Message::SavePackingPlan)
+		    ),
+		    text(self.packing_message.clone().unwrap_or_default()),
+		].padding([10, 0]).width(Length::Fixed(500.0)).spacing(10)))
+	    )
+	    .push(
+		TabId::Overdue,
+		TabLabel::Text("Overdue".to_string()),
+		center(scrollable(column![
+		    text("Overdue Returns").size(30),
+		    self.overdue_list(),
+		    row![
+			pick_list(ReminderMethod::FILES, Some(self.reminder_method), Message::ReminderMethodSelected),
+			button("Write reminders").on_press_maybe(
+			    (!self.overdue.is_empty()).then_some(Message::WriteReminders)
+			),
+		    ].spacing(10),
+		    text("Customers reminded in the last few days, as set on the settings tab, are skipped. \
+			  Customers with no email address get a text file."),
+		    text(self.reminder_message.clone().unwrap_or_default()),
+		].padding([10, 0]).width(Length::Fixed(700.0)).spacing(10)))
+	    )
+	    .push(
+		TabId::Email,
+		TabLabel::Text("Email".to_string()),
+		center(scrollable(column![
+		    text("Email").size(30),
+		    text("While the app is open, reminders are emailed to customers before their orders are \
+			  picked up or due back, and while they are overdue, as set on the settings tab."),
+		    column([
+			("host", "Server", "e.g. smtp.example.com, or empty to not send email"),
+			("port", "Port", "e.g. 587"),
+			("username", "Username", "Empty if the server doesn't need one"),
+			("from", "From", "e.g. Party Hire <hire@example.com>"),
+		    ].into_iter().map(|(field, label, placeholder)| {
+			column![
+			    text(label),
+			    text_input(placeholder, self.smtp_form.get_value(field))
+				.on_input(move |value| Message::SmtpFieldChanged(field, value)),
+			    field_error(self.smtp_form.get_visible_field_error(field)),
+			].into()
+		    })).spacing(10),
+		    column![
+			text("Password"),
+			text_input("", &self.smtp_form.password)
+			    .secure(true)
+			    .on_input(|value| Message::SmtpFieldChanged("password", value)),
+		    ],
+		    column![
+			text("Security"),
+			pick_list(SmtpSecurity::ALL, Some(self.smtp_form.security), Message::SmtpSecuritySelected),
+		    ],
+		    row![
+			button("Save").on_press(Message::SaveSmtpSettings),
+			button("Send reminders now").on_press_maybe((!self.sending_emails).then_some(Message::SendEmailsNow)),
+		    ].spacing(10),
+		    text(self.email_message.clone().unwrap_or_default()),
+		    text("Reminder Emails").size(20),
+		    self.email_list(),
+		].padding([10, 0]).width(Length::Fixed(700.0)).spacing(10)))
+	    )
+	    .push(
+		TabId::Raffle,
+		TabLabel::Text("Raffle".to_string()),
+		column![
+		    container(
+			text("Raffle").size(30)
+		    ).padding(10),
+		    row![
+			column![
+			    required_input_label("Prize"),
+			    text_input("", &self.raffle_form.prize_name)
+				.on_input(Message::PrizeNameChanged),
+			    field_error(self.raffle_form.get_visible_field_error("prize_name")),
+			].width(Length::Fixed(300.0)),
+			column![
+			    required_input_label("Winners"),
+			    text_input("", &self.raffle_form.winners)
+				.on_input(Message::RaffleWinnersChanged),
+			    field_error(self.raffle_form.get_visible_field_error("winners")),
+			].width(Length::Fixed(100.0)),
+			column![
+			    text(""),
+			    button("Draw").on_press(Message::DrawRaffle),
+			],
+		    ].spacing(10).padding([0, 10]),
+		    container(
+			text(self.raffle_message.clone().unwrap_or_default())
+		    ).padding([0, 10]),
+		    row![
+			container(responsive(|size| {
+			    table(
+				self.raffle_table_header.clone(),
+				self.raffle_table_body.clone(),
+				&self.raffle_table_columns,
+				&self.raffle_rows,
+				Message::SyncRaffleTableHeader,
+			    ).on_column_resize(
+				Message::RaffleTableResizing,
+				Message::RaffleTableResized
+			    ).min_width(
+				size.width
+			    ).into()
+			})).width(Length::FillPortion(1)),
+			column![
+			    text("Past Draws").size(20),
+			    responsive(|size| {
+				table(
+				    self.raffle_draw_table_header.clone(),
+				    self.raffle_draw_table_body.clone(),
+				    &self.raffle_draw_table_columns,
+				    &self.raffle_draws,
+				    Message::SyncRaffleDrawTableHeader,
+				).on_column_resize(
+				    Message::RaffleDrawTableResizing,
+				    Message::RaffleDrawTableResized
+				).min_width(
+				    size.width
+				).into()
+			    }),
+			].width(Length::FillPortion(2)).spacing(5),
+		    ].spacing(10),
+		],
+	    )
+	    .push(
+		TabId::ImportExport,
+		TabLabel::Text("Import / Export".to_string()),
+		center(scrollable(column![
+		    text("Import / Export").size(30),
+		    column![
+			required_input_label("CSV File"),
+			text_input("e.g. /home/shop/orders.csv", &self.csv_path)
+			    .on_input(Message::CsvPathChanged),
+		    ],
+		    row![
+			button("Export filtered orders").on_press(Message::ExportOrders),
+			button("Preview import").on_press(Message::PreviewImport),
+		    ].spacing(10),
+		    text(self.csv_message.clone().unwrap_or_default()),
+		    match &self.import_preview {
+			Some(preview) => column![
+			    column(preview.invalid_rows().map(|row| {
+				field_error(Some(format!("Line {}: {}", row.line, row.errors.join(", "))))
+			    })).spacing(5),
+			    row![
+				button("Import").on_press_maybe(
+				    preview.is_valid().then_some(Message::ImportOrders)
+				),
+				button("Cancel").on_press(Message::CancelImport),
+			    ].spacing(10),
+			].spacing(10),
+			None => column![],
+		    },
+		].padding([10, 0]).width(Length::Fixed(500.0)).spacing(10)))
+	    )
+	    .push(
+		TabId::Backups,
+		TabLabel::Text("Backups".to_string()),
+		center(scrollable(column![
+		    text("Backups").size(30),
+		    text(format!(
+			"The database is backed up when the app starts and every {} minute(s)",
+			self.backup_interval_minutes,
+		    )),
+		    button("Back up now").on_press(Message::BackUpNow),
+		    text(self.backup_message.clone().unwrap_or_default()),
+		    column(self.backups.iter().map(|backup| {
+			let orders = match backup.order_count {
+			    Some(count) => format!("{} order(s)", count),
+			    None => "Unreadable".to_string(),
+			};
+			let taken_at = backup.taken_at.format("%Y-%m-%d %H:%M:%S").to_string();
+
+			row![
+			    text(format!("{}   {}   {} KB", taken_at, orders, backup.size.div_ceil(1024)))
+				.width(Length::Fill),
+			    button("Restore").on_press(Message::AskToConfirm(
+				format!(
+				    "Replace all the data with the backup from {}? The data as it is now is backed up first.",
+				    taken_at,
+				),
+				Box::new(Message::RestoreBackup(backup.path.clone())),
+			    )),
+			].spacing(10).align_y(iced::Alignment::Center).into()
+		    })).spacing(5),
+		].padding([10, 0]).width(Length::Fixed(600.0)).spacing(10)))
+	    )
+	    .push(
+		TabId::Settings,
+		TabLabel::Text("Settings".to_string()),
+		center(scrollable(column![
+		    text("Settings").size(30),
+		    self.profile_switcher(),
+		    column(SETTINGS.iter().map(|setting| {
+			column![
+			    required_input_label(setting.label),
+			    text_input("", self.settings_form.get_value(setting.key))
+				.on_input(|value| Message::SettingChanged(setting.key, value)),
+			    field_error(self.settings_form.get_visible_field_error(setting.key)),
+			].into()
+		    })).spacing(10),
+		    button("Save").on_press(Message::SaveSettings),
+		    text(self.settings_message.clone().unwrap_or_default()),
+		].padding([10, 0]).width(Length::Fixed(500.0)).spacing(10)))
+	    )
+	    .set_active_tab(&self.active_tab);
+
+	let content = column![
+	    error_banner(self.error.clone()),
+	    tabs,
+	];
+
+	match &self.confirm {
+	    Some((question, _)) => confirm_dialog(content, question),
+	    None => content.into(),
+	}
+    }
+
+    /// Builds the choice of which profile's database to use, and the form to
+    /// add a profile. Empty if the database wasn't opened from a profile.
+    fn profile_switcher(&self) -> Element<'_, Message> {
+	if self.config_path.is_none() {
+	    return column![].into();
+	}
+
+	let selected = self.profile.as_ref().map(|profile| profile.name.clone());
+	let db_path = self.profile.as_ref()
+	    .map(|profile| profile.db_path.display().to_string())
+	    .unwrap_or_default();
+
+	column![
+	    text("Profile"),
+	    pick_list(self.config.profile_names(), selected, Message::ProfileSelected)
+		.width(Length::Fill),
+	    text(format!("The database is at {}", db_path)).size(12),
+	    row![
+		text_input("New profile name", &self.new_profile_name)
+		    .on_input(Message::NewProfileNameChanged),
+		text_input("Database path (optional)", &self.new_profile_db)
+		    .on_input(Message::NewProfileDbChanged),
+		button("Add profile").on_press(Message::AddProfile),
+	    ].spacing(10),
+	    text(self.profile_message.clone().unwrap_or_default()),
+	].spacing(10).into()
+    }
+
+    /// Builds the overview of today's work, where each figure can be clicked
+    /// to see the orders behind it.
+    fn dashboard(&self) -> Element<'_, Message> {
+	let Some(dashboard) = &self.dashboard else {
+	    return container(text("The dashboard couldn't be loaded")).padding(10).into();
+	};
+
+	let figure = |value: String, label: String, figure: DashboardFigure| -> Element<'_, Message> {
+	    button(column![text(value).size(30), text(label)].spacing(5))
+		.on_press(Message::ShowDashboardOrders(figure))
+		.width(Length::Fill)
+		.padding(15)
+		.into()
+	};
+
+	scrollable(column![
+	    text(format!("Dashboard for {}", dashboard.today.format("%A %-d %B %Y"))).size(30),
+	    row![
+		figure(
+		    dashboard.pickups_today.orders.to_string(),
+		    format!("Pickups today ({} items)", dashboard.pickups_today.items),
+		    DashboardFigure::PickupsToday,
+		),
+		figure(
+		    dashboard.returns_today.orders.to_string(),
+		    format!("Returns today ({} items)", dashboard.returns_today.items),
+		    DashboardFigure::ReturnsToday,
+		),
+		figure(
+		    dashboard.overdue.orders.to_string(),
+		    "Overdue orders".to_string(),
+		    DashboardFigure::Overdue,
+		),
+	    ].spacing(10),
+	    row![
+		figure(
+		    dashboard.boxes_to_prepare.boxes.to_string(),
+		    format!(
+			"Boxes to prepare in the next {} days ({} orders)",
+			PACKING_PLAN_DAYS,
+			dashboard.boxes_to_prepare.orders,
+		    ),
+		    DashboardFigure::BoxesToPrepare,
+		),
+		figure(
+		    dashboard.hires_this_month.orders.to_string(),
+		    format!("Hires in {}", dashboard.today.format("%B")),
+		    DashboardFigure::HiresThisMonth,
+		),
+	    ].spacing(10),
+	    text("Out on hire now").size(20),
+	    column(dashboard.hired_out.iter().map(|(item, hired_out)| {
+		button(row![
+		    text(item.name.clone()).width(Length::Fill),
+		    text(format!("{} of {} out", hired_out, item.total_stock)),
+		])
+		    .on_press(Message::ShowDashboardOrders(DashboardFigure::HiredOut(item.name.clone())))
+		    .width(Length::Fill)
+		    .into()
+	    })).spacing(5),
+	].spacing(10).padding(10)).into()
+    }
+
+    /// Builds the search and filters for the orders, and the table of them.
+    fn order_table(&self) -> Element<'_, Message> {
+	column![
+	    row![
+		text_input("Search customer, receipt or item", &self.order_query.search)
+		    .on_input(Message::SearchChanged)
+		    .width(Length::Fill),
+		text_input("From YYYY-mm-dd", &self.filter_from)
+		    .on_input(Message::FilterFromChanged)
+		    .width(Length::Fixed(140.0)),
+		text_input("To YYYY-mm-dd", &self.filter_to)
+		    .on_input(Message::FilterToChanged)
+		    .width(Length::Fixed(140.0)),
+		pick_list(
+		    StatusFilter::ALL,
+		    self.order_query.status,
+		    Message::StatusFilterSelected,
+		).placeholder("Any status"),
+		pick_list(
+		    self.hired_item_names.as_slice(),
+		    self.order_query.item.clone(),
+		    Message::ItemFilterSelected,
+		).placeholder("Any item"),
+		button("Clear").on_press(Message::ClearOrderFilters),
+	    ].spacing(10).padding([0, 10]),
+	    text(match &self.dashboard_filter {
+		Some(figure) => format!("Showing the orders from the dashboard: {}", figure.description()),
+		None => String::new(),
+	    }).size(14),
+	    responsive(|size| {
+		table(
+		    self.order_table_header.clone(),
+		    self.order_table_body.clone(),
+		    &self.order_table_columns,
+		    &self.order_rows,
+		    Message::SyncOrderTableHeader,
+		).on_column_resize(
+		    Message::OrderTableResizing,
+		    Message::OrderTableResized
+		).min_width(
+		    size.width
+		).into()
+	    }),
+	].into()
+    }
+
+    /// Builds where the last docket was saved, with a button to open it to
+    /// print, or why it couldn't be made.
+    fn docket_message(&self) -> Element<'_, Message> {
+	match &self.docket {
+	    Some(Ok(path)) => row![
+		text(format!("Saved the docket to {}", path.display())),
+		button("Open to print").on_press(Message::OpenDocket(path.clone())),
+	    ].spacing(10).align_y(iced::Alignment::Center).into(),
+	    Some(Err(message)) => text(message.clone()).into(),
+	    None => row![].into(),
+	}
+    }
+
+    /// Builds the list of orders that are late back, with who has them and
+    /// how to get in touch.
+    fn overdue_list(&self) -> Element<'_, Message> {
+	if self.overdue.is_empty() {
+	    return text("Every order due back has come back").into();
+	}
+
+	column(self.overdue.iter().map(|overdue_order| {
+	    let customer = &overdue_order.customer;
+	    let contact: Vec<&str> = [customer.phone.as_str(), customer.email.as_str()]
+		.into_iter()
+		.filter(|detail| !detail.is_empty())
+		.collect();
+	    let reminded = match overdue_order.last_reminded {
+		Some(reminded) => format!("Reminded {}", reminded.format("%Y-%m-%d")),
+		None => "Not reminded yet".to_string(),
+	    };
+
+	    column![
+		text(format!(
+		    "{} (receipt {}): {} day(s) late, due back {}",
+		    customer.name,
+		    overdue_order.order.receipt_number,
+		    overdue_order.days_overdue,
+		    overdue_order.order.return_on.format("%Y-%m-%d"),
+		)).size(18),
+		text(overdue_order.order.items_hired()),
+		text(match contact.is_empty() {
+		    true => "No phone or email".to_string(),
+		    false => contact.join(", "),
+		}),
+		text(reminded),
+	    ].spacing(2).into()
+	})).spacing(10).into()
+    }
+
+    /// Builds the list of reminder emails, newest first, with a button to try
+    /// again on each one that was given up on.
+    fn email_list(&self) -> Element<'_, Message> {
+	if self.emails.is_empty() {
+	    return text("No reminders have been emailed yet").into();
+	}
+
+	column(self.emails.iter().map(|email| {
+	    row![
+		column![
+		    text(format!(
+			"{} reminder for order {} to {}",
+			email.kind,
+			email.order_id,
+			email.to_address,
+		    )),
+		    text(format!(
+			"Queued {}. {}",
+			email.queued_at.format("%Y-%m-%d %H:%M"),
+			email.describe_status(self.email_max_attempts),
+		    )),
+		].width(Length::Fill),
+		button("Retry").on_press_maybe(
+		    (email.status(self.email_max_attempts) == EmailStatus::Failed)
+			.then_some(Message::RetryEmail(email.id))
+		),
+	    ].spacing(10).align_y(iced::Alignment::Center).into()
+	})).spacing(10).into()
     }
+
+    /// Builds what the order costs and the payments made towards it, with the
+    /// forms to record another payment, change its charges and waive its late
+    /// fee.
+    fn payments_pane<'a>(
+	&'a self,
+	order: &'a Order,
+	payments: &'a [Payment],
+	late_fee: Option<&'a LateFee>,
+    ) -> Element<'a, Message> {
+	let charges = column(order.lines.iter().map(|line| {
+	    text(format!(
+		"{} x{} at {} a day for {} day(s): {}",
+		line.item_hired,
+		line.how_many,
+		format_price(line.daily_rate),
+		order.hire_days(),
+		format_price(line.charge(order.hire_days())),
+	    )).into()
+	})).spacing(5);
+
+	let payment_rows = column(payments.iter().map(|payment| {
+	    row![
+		text(format!(
+		    "{}   {}   {}   {}",
+		    payment.paid_on.format("%Y-%m-%d"),
+		    format_price(payment.amount),
+		    payment.method,
+		    payment.note,
+		)).width(Length::Fill),
+		button("Delete").on_press(Message::AskToConfirm(
+		    format!("Delete the payment of {}?", format_price(payment.amount)),
+		    Box::new(Message::DeletePayment(payment.id)),
+		)),
+	    ].spacing(10).align_y(iced::Alignment::Center).into()
+	})).spacing(5);
+
+	let late_fee_row: Element<_> = match late_fee {
+	    Some(late_fee) if !late_fee.is_waived() => row![
+		text(late_fee.description()).width(Length::Fill),
+		text_input("Reason to waive it", &self.waive_reason)
+		    .on_input(Message::WaiveReasonChanged)
+		    .width(Length::Fixed(250.0)),
+		button("Waive").on_press(Message::WaiveLateFee),
+	    ].spacing(10).align_y(iced::Alignment::Center).into(),
+	    Some(late_fee) => text(late_fee.description()).into(),
+	    None => text("").into(),
+	};
+
+	scrollable(column![
+	    text(format!("Order {} for {}", order.id, order.customer_name)).size(30),
+	    charges,
+	    text(format!("Hire charge: {}", format_price(order.hire_charge()))),
+	    late_fee_row,
+	    row![
+		column![
+		    text("Deposit ($)"),
+		    text_input("e.g. 50.00", &self.charges_form.deposit).on_input(Message::DepositChanged),
+		    field_error(self.charges_form.get_visible_field_error("deposit")),
+		],
+		column![
+		    text("Damage charge ($)"),
+		    text_input("e.g. 12.50", &self.charges_form.damage_charge).on_input(Message::DamageChargeChanged),
+		    field_error(self.charges_form.get_visible_field_error("damage_charge")),
+		],
+		button("Save charges").on_press(Message::SaveCharges),
+	    ].spacing(10).align_y(iced::Alignment::End),
+	    text(format!(
+		"Due: {}   Paid: {}   {}",
+		format_price(order.amount_due()),
+		format_price(order.paid),
+		describe_balance(order.balance()),
+	    )).size(20),
+	    text("Payments").size(20),
+	    if payments.is_empty() {
+		column![text("Nothing has been paid yet")]
+	    } else {
+		payment_rows
+	    },
+	    row![
+		column![
+		    required_input_label("Amount ($)"),
+		    text_input("e.g. 12.50", &self.payment_form.amount).on_input(Message::PaymentAmountChanged),
+		    field_error(self.payment_form.get_visible_field_error("amount")),
+		],
+		column![
+		    required_input_label("Paid On"),
+		    text_input("YYYY-MM-DD", &self.payment_form.paid_on).on_input(Message::PaymentDateChanged),
+		    field_error(self.payment_form.get_visible_field_error("paid_on")),
+		],
+		column![
+		    text("Method"),
+		    pick_list(PaymentMethod::ALL, Some(self.payment_form.method), Message::PaymentMethodSelected),
+		],
+		column![
+		    text("Note"),
+		    text_input("", &self.payment_form.note).on_input(Message::PaymentNoteChanged),
+		    field_error(self.payment_form.get_visible_field_error("note")),
+		],
+	    ].spacing(10),
+	    checkbox("Refund to the customer", self.payment_form.refund).on_toggle(Message::PaymentRefundToggled),
+	    field_error(self.payment_form_error.clone()),
+	    row![
+		button("Record payment").on_press(Message::RecordPayment),
+		button("Back").on_press(Message::ClosePayments),
+	    ].spacing(10),
+	].padding(10).spacing(10)).width(Length::Fill).into()
+    }
+
+    /// Builds the list of recently deleted orders, each with a button to restore it.
+    fn deleted_orders_list(&self) -> Element<'_, Message> {
+	if self.deleted_orders.is_empty() {
+	    return container(text("No orders have been deleted recently")).padding(10).into();
+	}
+
+	scrollable(column(self.deleted_orders.iter().map(|order| {
+	    row![
+		text(format!(
+		    "Order {}   {}   Receipt {}   {}   {} to {}   Deleted {}",
+		    order.id,
+		    order.customer_name,
+		    order.receipt_number,
+		    order.items_hired(),
+		    order.hired_on.format("%Y-%m-%d"),
+		    order.return_on.format("%Y-%m-%d"),
+		    order.deleted_at.map(|d| d.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default(),
+		)).width(Length::Fill),
+		button("Restore").on_press(Message::RestoreOrder(order.id)),
+	    ].spacing(10).align_y(iced::Alignment::Center).into()
+	})).spacing(5).padding(10)).into()
+    }
+}
+
+/// Parses a date typed into a filter box, or None if it isn't a full date yet.
+fn parse_filter_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()
 }
 
 #[cfg(test)]
 mod test {
     use crate::database::init_db_tables;
+    use crate::settings::MAX_DAYS_AGO;
+    use crate::order::OrderStatus;
+    use crate::settings::RAFFLE_NUMBER_MAX;
+    use crate::settings::LATE_FEE_PER_DAY;
+    use crate::reminder::Reminder;
+    use crate::email::SendBatch;
+    use crate::email::smtp::SmtpSettings;
+    use crate::email::test_server::TestSmtpServer;
 
     use super::*;
 
@@ -328,7 +2592,7 @@ mod test {
     fn test_app_tab_selected_message() {
 	let connection = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&connection);
+	init_db_tables(&connection).unwrap();
 	
 	let mut app = App::new(connection).0;
 
@@ -337,10 +2601,11 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_app_customer_name_changed() {
 	let connection = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&connection);
+	init_db_tables(&connection).unwrap();
 	
 	let mut app = App::new(connection).0;
 
@@ -350,10 +2615,11 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_app_receipt_number_changed() {
 	let connection = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&connection);
+	init_db_tables(&connection).unwrap();
 	
 	let mut app = App::new(connection).0;
 
@@ -363,36 +2629,39 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_app_item_hired_changed() {
 	let connection = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&connection);
+	init_db_tables(&connection).unwrap();
 	
 	let mut app = App::new(connection).0;
 
-	let _ = app.update(Message::ItemHiredChanged("Test".to_string()));
-	assert_eq!(app.order_form.item_hired, "Test".to_string());
-	assert_eq!(app.order_form.item_hired_show_error, true);
+	let _ = app.update(Message::ItemHiredChanged(0, "Test".to_string()));
+	assert_eq!(app.order_form.lines[0].item_hired, "Test".to_string());
+	assert_eq!(app.order_form.lines[0].item_hired_show_error, true);
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_app_how_many_changed() {
 	let connection = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&connection);
+	init_db_tables(&connection).unwrap();
 	
 	let mut app = App::new(connection).0;
 
-	let _ = app.update(Message::HowManyChanged("Test".to_string()));
-	assert_eq!(app.order_form.how_many, "Test".to_string());
-	assert_eq!(app.order_form.how_many_show_error, true);
+	let _ = app.update(Message::HowManyChanged(0, "Test".to_string()));
+	assert_eq!(app.order_form.lines[0].how_many, "Test".to_string());
+	assert_eq!(app.order_form.lines[0].how_many_show_error, true);
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_app_hired_on_changed() {
 	let connection = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&connection);
+	init_db_tables(&connection).unwrap();
 	
 	let mut app = App::new(connection).0;
 
@@ -402,10 +2671,11 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_app_return_on_changed() {
 	let connection = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&connection);
+	init_db_tables(&connection).unwrap();
 	
 	let mut app = App::new(connection).0;
 
@@ -415,19 +2685,974 @@ mod test {
     }
 
     #[test]
+    fn test_app_pick_date() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::OpenDatePicker("hired_on"));
+	assert_eq!(app.open_date_picker, Some("hired_on"));
+
+	let _ = app.update(Message::DatePicked("hired_on", Date::from_ymd(2025, 3, 23)));
+	assert_eq!(app.open_date_picker, None);
+	assert_eq!(app.order_form.hired_on, "2025-03-23".to_string());
+	assert!(app.order_form.hired_on_show_error);
+
+	let _ = app.update(Message::OpenDatePicker("return_on"));
+	let _ = app.update(Message::CloseDatePicker);
+	assert_eq!(app.open_date_picker, None);
+	assert_eq!(app.order_form.return_on, String::new());
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_app_add_order() {
 	let connection = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&connection);
+	init_db_tables(&connection).unwrap();
 	
 	let mut app = App::new(connection).0;
 
 	let _ = app.update(Message::AddOrder);
 	assert_eq!(app.order_form.customer_name_show_error, true);
 	assert_eq!(app.order_form.receipt_number_show_error, true);
-	assert_eq!(app.order_form.item_hired_show_error, true);
-	assert_eq!(app.order_form.how_many_show_error, true);
+	assert_eq!(app.order_form.lines[0].item_hired_show_error, true);
+	assert_eq!(app.order_form.lines[0].how_many_show_error, true);
 	assert_eq!(app.order_form.hired_on_show_error, true);
 	assert_eq!(app.order_form.return_on_show_error, true);
     }
+
+    #[test]
+    fn test_app_edit_order() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	Item::new(&connection, "Test Item".to_string(), 100, 25, 100).unwrap();
+
+	let order = Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    chrono::NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    chrono::NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::EditOrder(order.id));
+	assert_eq!(app.active_tab, TabId::AddOrder);
+	assert_eq!(app.order_form.editing_id, Some(order.id));
+
+	let _ = app.update(Message::HowManyChanged(0, "60".to_string()));
+	let _ = app.update(Message::SaveOrder);
+	assert_eq!(app.active_tab, TabId::Orders);
+	assert_eq!(app.orders.len(), 1);
+	assert_eq!(app.orders[0].how_many(), 60);
+	assert_eq!(app.orders[0].raffle_number, order.raffle_number);
+    }
+
+    #[test]
+    fn test_app_order_status_changes() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+
+	let order = Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    chrono::NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    chrono::NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::MarkOrderOut(order.id));
+	assert_eq!(app.orders[0].status, OrderStatus::Out);
+
+	let _ = app.update(Message::MarkOrderReturned(order.id));
+	assert_eq!(app.orders[0].status, OrderStatus::Returned);
+	assert!(app.orders[0].returned_at.is_some());
+    }
+
+    #[test]
+    fn test_app_search_and_sort_orders() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+
+	for (name, how_many) in [("Alice Smith", 40), ("Bob Jones", 5), ("Bobby Tables", 20)] {
+	    Order::new(
+		&connection,
+		name.to_string(),
+		15,
+		vec![("Test Item".to_string(), how_many)],
+		chrono::NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+		chrono::NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	    ).unwrap();
+	}
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::SearchChanged("bob".to_string()));
+	assert_eq!(app.orders.len(), 2);
+	assert_eq!(app.raffle_rows.len(), 3);
+
+	let _ = app.update(Message::SortOrders(OrderSortField::HowMany));
+	assert_eq!(app.orders[0].customer_name, "Bob Jones".to_string());
+	assert_eq!(app.order_table_columns[4].sort, Some(SortDirection::Ascending));
+
+	let _ = app.update(Message::SortOrders(OrderSortField::HowMany));
+	assert_eq!(app.orders[0].customer_name, "Bobby Tables".to_string());
+	assert_eq!(app.order_table_columns[4].sort, Some(SortDirection::Descending));
+
+	let _ = app.update(Message::ClearOrderFilters);
+	assert_eq!(app.orders.len(), 3);
+	assert_eq!(app.orders[0].customer_name, "Alice Smith".to_string());
+    }
+
+    #[test]
+    fn test_app_filter_dates() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::FilterFromChanged("2025-03".to_string()));
+	assert_eq!(app.order_query.from, None);
+
+	let _ = app.update(Message::FilterFromChanged("2025-03-01".to_string()));
+	assert_eq!(app.order_query.from, chrono::NaiveDate::from_ymd_opt(2025, 3, 1));
+	assert_eq!(app.filter_from, "2025-03-01".to_string());
+    }
+
+    #[test]
+    fn test_app_draw_raffle() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+
+	let order = Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    chrono::NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    chrono::NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::DrawRaffle);
+	assert!(app.raffle_form.prize_name_show_error);
+	assert!(app.raffle_draws.is_empty());
+
+	let _ = app.update(Message::PrizeNameChanged("Hamper".to_string()));
+	let _ = app.update(Message::DrawRaffle);
+	assert_eq!(app.raffle_draws.len(), 1);
+	assert_eq!(app.raffle_draws[0].winner_order_id, order.id);
+
+	// The only entrant has already won
+	let _ = app.update(Message::PrizeNameChanged("Voucher".to_string()));
+	let _ = app.update(Message::DrawRaffle);
+	assert_eq!(app.raffle_draws.len(), 1);
+	assert!(app.raffle_message.is_some());
+    }
+
+    #[test]
+    fn test_app_save_settings() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::TabSelected(TabId::Settings));
+	assert_eq!(app.settings_form.get_value(RAFFLE_NUMBER_MAX.key), "1000");
+
+	let _ = app.update(Message::SettingChanged(RAFFLE_NUMBER_MAX.key, "asdf".to_string()));
+	let _ = app.update(Message::SaveSettings);
+	assert_eq!(RAFFLE_NUMBER_MAX.get(&app.db_connection).unwrap(), 1000);
+
+	let _ = app.update(Message::SettingChanged(RAFFLE_NUMBER_MAX.key, "5000".to_string()));
+	let _ = app.update(Message::SaveSettings);
+	assert_eq!(RAFFLE_NUMBER_MAX.get(&app.db_connection).unwrap(), 5000);
+    }
+
+    #[test]
+    fn test_app_add_order_raffle_numbers_used_up() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	MAX_DAYS_AGO.set(&connection, MAX_DAYS_AGO.max).unwrap();
+	Item::new(&connection, "Test Item".to_string(), 100, 25, 100).unwrap();
+	RAFFLE_NUMBER_MAX.set(&connection, 0).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::CustomerNameChanged("Test Person".to_string()));
+	let _ = app.update(Message::ReceiptNumberChanged("15".to_string()));
+	let _ = app.update(Message::ItemHiredChanged(0, "Test Item".to_string()));
+	let _ = app.update(Message::HowManyChanged(0, "5".to_string()));
+	let _ = app.update(Message::HiredOnChanged("2025-03-23".to_string()));
+	let _ = app.update(Message::ReturnOnChanged("2025-03-25".to_string()));
+
+	let _ = app.update(Message::AddOrder);
+	assert_eq!(app.orders.len(), 1);
+
+	let _ = app.update(Message::ReceiptNumberChanged("16".to_string()));
+	let _ = app.update(Message::AddOrder);
+	assert_eq!(app.orders.len(), 1);
+	assert!(app.order_form_error.is_some());
+    }
+
+    #[test]
+    fn test_app_receipt_reuse() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	MAX_DAYS_AGO.set(&connection, MAX_DAYS_AGO.max).unwrap();
+	Item::new(&connection, "Test Item".to_string(), 100, 25, 100).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::CustomerNameChanged("Test Person".to_string()));
+	let _ = app.update(Message::NextReceiptNumber);
+	assert_eq!(app.order_form.receipt_number, "1".to_string());
+	let _ = app.update(Message::ItemHiredChanged(0, "Test Item".to_string()));
+	let _ = app.update(Message::HowManyChanged(0, "5".to_string()));
+	let _ = app.update(Message::HiredOnChanged("2025-03-23".to_string()));
+	let _ = app.update(Message::ReturnOnChanged("2025-03-25".to_string()));
+	let _ = app.update(Message::AddOrder);
+	assert_eq!(app.orders.len(), 1);
+
+	// The form keeps its values, so adding again reuses the receipt
+	let _ = app.update(Message::AddOrder);
+	assert_eq!(app.orders.len(), 1);
+	assert!(app.order_form_confirm.as_ref().unwrap().contains("already used"));
+
+	let _ = app.update(Message::ConfirmReceiptReuse);
+	assert_eq!(app.orders.len(), 2);
+	assert!(app.order_form_confirm.is_none());
+
+	let _ = app.update(Message::NextReceiptNumber);
+	assert_eq!(app.order_form.receipt_number, "2".to_string());
+	assert!(!app.order_form.receipt_reuse_confirmed);
+    }
+
+    #[test]
+    fn test_import_export_orders() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	MAX_DAYS_AGO.set(&connection, MAX_DAYS_AGO.max).unwrap();
+	Item::new(&connection, "Chairs".to_string(), 100, 25, 250).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let path = std::env::temp_dir().join(format!("as91896-test-{}.csv", std::process::id()));
+	std::fs::write(
+	    &path,
+	    "Customer,Receipt No.,Item,Qty,Hire Date,Return Date\n\
+	     Alice Smith,1001,Chairs,40,2025-03-01,2025-03-03\n",
+	).unwrap();
+
+	let _ = app.update(Message::CsvPathChanged(path.display().to_string()));
+	let _ = app.update(Message::PreviewImport);
+	assert!(app.import_preview.as_ref().unwrap().is_valid());
+	assert!(app.orders.is_empty());
+
+	let _ = app.update(Message::ImportOrders);
+	assert!(app.import_preview.is_none());
+	assert_eq!(app.orders.len(), 1);
+
+	let _ = app.update(Message::ExportOrders);
+	let exported = std::fs::read_to_string(&path).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	assert_eq!(exported.lines().count(), 2);
+	assert!(exported.contains("Alice Smith"));
+
+	// The whole import can be undone
+	let _ = app.update(Message::Undo);
+	assert!(app.orders.is_empty());
+	assert_eq!(app.deleted_orders.len(), 1);
+    }
+
+    #[test]
+    fn test_app_corrupt_order_shows_error() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	).unwrap();
+	connection.execute("UPDATE CustomerOrder SET hired_on = 'soon'", []).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	assert!(app.error.is_some());
+	assert!(app.orders.is_empty());
+
+	let _ = app.update(Message::DismissError);
+	assert!(app.error.is_none());
+    }
+
+    #[test]
+    fn test_app_delete_missing_order_shows_error() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+
+	let mut app = App::new(connection).0;
+	let _ = app.update(Message::DeleteOrder(42));
+
+	assert!(app.error.is_some());
+    }
+
+    #[test]
+    fn test_app_items() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::ItemNameChanged("Chairs".to_string()));
+	let _ = app.update(Message::ItemStockChanged("100".to_string()));
+	let _ = app.update(Message::ItemBoxCapacityChanged("25".to_string()));
+	let _ = app.update(Message::ItemHirePriceChanged("2.50".to_string()));
+	let _ = app.update(Message::SaveItem);
+	assert_eq!(app.item_names, vec!["Chairs".to_string()]);
+	assert_eq!(app.item_form.name, "".to_string());
+
+	let _ = app.update(Message::EditItem(app.items[0].id));
+	assert_eq!(app.item_form.editing_id, Some(app.items[0].id));
+	let _ = app.update(Message::ItemStockChanged("80".to_string()));
+	let _ = app.update(Message::SaveItem);
+	assert_eq!(app.items[0].total_stock, 80);
+
+	let _ = app.update(Message::DeleteItem(app.items[0].id));
+	assert!(app.items.is_empty());
+    }
+
+    #[test]
+    fn test_app_packing_plan() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+
+	Item::new(&connection, "Chairs".to_string(), 100, 20, 250).unwrap();
+	Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Chairs".to_string(), 30)],
+	    NaiveDate::from_ymd_opt(2025, 3, 4).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 6).unwrap(),
+	).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::TabSelected(TabId::Packing));
+	let _ = app.update(Message::PackingFromChanged("2025-03-0".to_string()));
+	assert!(app.packing_plan.is_none());
+
+	let _ = app.update(Message::PackingFromChanged("2025-03-01".to_string()));
+	let plan = app.packing_plan.clone().unwrap();
+	assert_eq!(plan.days.len(), 1);
+	assert_eq!(plan.days[0].total_boxes(), 2);
+
+	let path = std::env::temp_dir().join(format!("as91896-packing-{}.txt", std::process::id()));
+	let _ = app.update(Message::PackingPathChanged(path.display().to_string()));
+	let _ = app.update(Message::SavePackingPlan);
+	assert_eq!(fs::read_to_string(&path).unwrap(), plan.to_text());
+
+	fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_app_order_lines() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	MAX_DAYS_AGO.set(&connection, MAX_DAYS_AGO.max).unwrap();
+	Item::new(&connection, "Chairs".to_string(), 100, 20, 250).unwrap();
+	Item::new(&connection, "Tables".to_string(), 100, 4, 1000).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::AddOrderLine);
+	let _ = app.update(Message::AddOrderLine);
+	assert_eq!(app.order_form.lines.len(), 3);
+
+	let _ = app.update(Message::RemoveOrderLine(2));
+	assert_eq!(app.order_form.lines.len(), 2);
+
+	let _ = app.update(Message::CustomerNameChanged("Test Person".to_string()));
+	let _ = app.update(Message::ReceiptNumberChanged("15".to_string()));
+	let _ = app.update(Message::ItemHiredChanged(0, "Chairs".to_string()));
+	let _ = app.update(Message::HowManyChanged(0, "30".to_string()));
+	let _ = app.update(Message::ItemHiredChanged(1, "Tables".to_string()));
+	let _ = app.update(Message::HowManyChanged(1, "5".to_string()));
+	let _ = app.update(Message::HiredOnChanged("2025-03-04".to_string()));
+	let _ = app.update(Message::ReturnOnChanged("2025-03-06".to_string()));
+	let _ = app.update(Message::AddOrder);
+	assert_eq!(app.orders.len(), 1);
+	assert_eq!(app.orders[0].lines.len(), 2);
+	assert_eq!(app.order_rows.len(), 1);
+
+	let id = app.orders[0].id;
+	let _ = app.update(Message::ToggleOrderLines(id));
+	assert_eq!(app.order_rows.len(), 3);
+
+	let _ = app.update(Message::ToggleOrderLines(id));
+	assert_eq!(app.order_rows.len(), 1);
+    }
+
+    #[test]
+    fn test_app_customers() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	MAX_DAYS_AGO.set(&connection, MAX_DAYS_AGO.max).unwrap();
+	Item::new(&connection, "Chairs".to_string(), 100, 20, 250).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::TabSelected(TabId::Customers));
+	let _ = app.update(Message::CustomerFormNameChanged("Alice Smith".to_string()));
+	let _ = app.update(Message::CustomerEmailChanged("alice".to_string()));
+	let _ = app.update(Message::SaveCustomer);
+	assert!(app.customer_form.get_visible_field_error("email").is_some());
+	assert!(app.customers.is_empty());
+
+	let _ = app.update(Message::CustomerEmailChanged("alice@example.com".to_string()));
+	let _ = app.update(Message::SaveCustomer);
+	assert_eq!(app.customers.len(), 1);
+	assert_eq!(app.customer_names.options(), &["Alice Smith".to_string()]);
+
+	let id = app.customers[0].id;
+	let _ = app.update(Message::NewOrderForCustomer(id));
+	assert_eq!(app.active_tab, TabId::AddOrder);
+	assert_eq!(app.order_form.customer_name, "Alice Smith".to_string());
+
+	let _ = app.update(Message::ReceiptNumberChanged("15".to_string()));
+	let _ = app.update(Message::ItemHiredChanged(0, "Chairs".to_string()));
+	let _ = app.update(Message::HowManyChanged(0, "30".to_string()));
+	let _ = app.update(Message::HiredOnChanged("2025-03-04".to_string()));
+	let _ = app.update(Message::ReturnOnChanged("2025-03-06".to_string()));
+	let _ = app.update(Message::AddOrder);
+	assert_eq!(app.orders[0].customer_id, id);
+
+	let _ = app.update(Message::ViewCustomer(id));
+	let (customer, orders) = app.viewed_customer.clone().unwrap();
+	assert_eq!(customer.email, "alice@example.com".to_string());
+	assert_eq!(orders.len(), 1);
+
+	// A customer with orders can't be deleted
+	let _ = app.update(Message::DeleteCustomer(id));
+	assert!(app.error.is_some());
+	assert_eq!(app.customers.len(), 1);
+	assert!(app.viewed_customer.is_some());
+
+	let _ = app.update(Message::CloseCustomer);
+	assert!(app.viewed_customer.is_none());
+    }
+
+    #[test]
+    fn test_app_delete_undo_and_restore() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	let order = Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap();
+
+	let mut app = App::new(connection).0;
+	let delete = Message::AskToConfirm("Delete?".to_string(), Box::new(Message::DeleteOrder(order.id)));
+
+	// Nothing is deleted until the user confirms
+	let _ = app.update(delete.clone());
+	assert!(app.confirm.is_some());
+	let _ = app.update(Message::CancelConfirm);
+	assert!(app.confirm.is_none());
+	assert_eq!(app.orders.len(), 1);
+
+	let _ = app.update(delete.clone());
+	let _ = app.update(Message::Confirm);
+	assert!(app.confirm.is_none());
+	assert!(app.orders.is_empty());
+	assert_eq!(app.deleted_orders.len(), 1);
+
+	let _ = app.update(Message::Undo);
+	assert_eq!(app.orders, vec![order.clone()]);
+	assert_eq!(app.undo_message, Some(format!("Undid delete order {}", order.id)));
+
+	// Restoring it by hand leaves nothing to undo
+	let _ = app.update(delete);
+	let _ = app.update(Message::Confirm);
+	let _ = app.update(Message::ToggleDeletedOrders);
+	assert!(app.show_deleted_orders);
+	let _ = app.update(Message::RestoreOrder(order.id));
+	assert_eq!(app.orders.len(), 1);
+	assert!(app.undo_stack.is_empty());
+    }
+
+    #[test]
+    fn test_app_undo_edit() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	MAX_DAYS_AGO.set(&connection, MAX_DAYS_AGO.max).unwrap();
+	Item::new(&connection, "Test Item".to_string(), 100, 25, 100).unwrap();
+	let order = Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::EditOrder(order.id));
+	let _ = app.update(Message::HowManyChanged(0, "30".to_string()));
+	let _ = app.update(Message::SaveOrder);
+	assert_eq!(app.orders[0].how_many(), 30);
+
+	let _ = app.update(Message::Undo);
+	assert_eq!(app.orders[0].how_many(), 26);
+	assert!(app.undo_stack.is_empty());
+
+	// Undoing with nothing left does nothing
+	let _ = app.update(Message::Undo);
+	assert_eq!(app.orders[0].how_many(), 26);
+    }
+
+    #[test]
+    fn test_app_backup_and_restore() {
+	let dir = std::env::temp_dir().join(format!("as91896-app-backup-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let connection = Connection::open(dir.join("orders.db")).unwrap();
+
+	init_db_tables(&connection).unwrap();
+	MAX_DAYS_AGO.set(&connection, MAX_DAYS_AGO.max).unwrap();
+	Item::new(&connection, "Test Item".to_string(), 100, 25, 100).unwrap();
+	let new_order = |connection: &Connection, receipt_number| Order::new(
+	    connection,
+	    "Test Person".to_string(),
+	    receipt_number,
+	    vec![("Test Item".to_string(), 1)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap();
+	new_order(&connection, 15);
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::TabSelected(TabId::Backups));
+	let _ = app.update(Message::BackUpNow);
+	assert_eq!(app.backups.len(), 1);
+	assert_eq!(app.backups[0].order_count, Some(1));
+	let backup = app.backups[0].path.clone();
+
+	new_order(&app.db_connection, 16);
+	let _ = app.update(Message::RestoreBackup(backup));
+	assert_eq!(app.orders.len(), 1);
+	assert_eq!(app.backups.len(), 2);
+	assert!(app.backup_message.unwrap().contains("The data from before is in"));
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_app_switch_profile() {
+	let dir = std::env::temp_dir().join(format!("as91896-app-profile-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let config_path = dir.join("config.json");
+
+	let mut config = Config::default();
+	let first = config.add_profile("first", Some(dir.join("first.db").to_str().unwrap())).unwrap();
+	let connection = init_db(&first.db_path).unwrap();
+	MAX_DAYS_AGO.set(&connection, MAX_DAYS_AGO.max).unwrap();
+	Item::new(&connection, "Test Item".to_string(), 100, 25, 100).unwrap();
+	Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 1)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap();
+
+	let mut app = App::new(connection).0.with_profile(config_path.clone(), config, first, None);
+	assert_eq!(app.title(), "Order Management Application - first");
+	assert_eq!(app.orders.len(), 1);
+
+	// A profile whose folder is missing isn't added
+	let _ = app.update(Message::NewProfileNameChanged("second".to_string()));
+	let _ = app.update(Message::NewProfileDbChanged(dir.join("missing").join("second.db").display().to_string()));
+	let _ = app.update(Message::AddProfile);
+	assert!(app.profile_message.clone().unwrap().contains("doesn't exist"));
+	assert_eq!(app.config.profile_names(), vec!["default", "first"]);
+	assert_eq!(app.orders.len(), 1);
+
+	let _ = app.update(Message::NewProfileDbChanged(dir.join("second.db").display().to_string()));
+	let _ = app.update(Message::AddProfile);
+	assert_eq!(app.profile.clone().unwrap().name, "second");
+	assert!(app.orders.is_empty());
+	assert_eq!(Config::load(&config_path).unwrap().profile, Some("second".to_string()));
+
+	let _ = app.update(Message::ProfileSelected("first".to_string()));
+	assert_eq!(app.orders.len(), 1);
+	assert_eq!(Config::load(&config_path).unwrap(), app.config);
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_app_profile_kept_while_sending_emails() {
+	let dir = std::env::temp_dir().join(format!("as91896-app-profile-sending-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+
+	let mut config = Config::default();
+	let first = config.add_profile("first", Some(dir.join("first.db").to_str().unwrap())).unwrap();
+	config.add_profile("second", Some(dir.join("second.db").to_str().unwrap())).unwrap();
+	let connection = init_db(&first.db_path).unwrap();
+	let mut app = App::new(connection).0.with_profile(dir.join("config.json"), config, first, None);
+
+	let _ = app.update(Message::SmtpFieldChanged("host", "127.0.0.1".to_string()));
+	let _ = app.update(Message::SmtpFieldChanged("port", "2525".to_string()));
+	let _ = app.update(Message::SmtpSecuritySelected(SmtpSecurity::None));
+	let _ = app.update(Message::SmtpFieldChanged("from", "hire@example.com".to_string()));
+	let _ = app.update(Message::SaveSmtpSettings);
+	let _ = app.update(Message::BackUpNow);
+	let backup = app.backups[0].path.clone();
+
+	let _ = app.update(Message::SendEmailsNow);
+	assert!(app.sending_emails);
+	let _ = app.update(Message::ProfileSelected("second".to_string()));
+	assert_eq!(app.profile.clone().unwrap().name, "first");
+	assert!(app.profile_message.clone().unwrap().starts_with("Wait for the reminders"));
+	let _ = app.update(Message::NewProfileNameChanged("third".to_string()));
+	let _ = app.update(Message::AddProfile);
+	assert_eq!(app.config.profile_names(), vec!["default", "first", "second"]);
+	let _ = app.update(Message::RestoreBackup(backup));
+	assert_eq!(app.backups.len(), 1);
+	assert!(app.backup_message.clone().unwrap().starts_with("Wait for the reminders"));
+
+	// The sends are recorded in the first profile's database
+	finish_sending_emails(&mut app, true);
+	assert_eq!(app.db_connection.path(), Some(dir.join("first.db").to_str().unwrap()));
+	let _ = app.update(Message::ProfileSelected("second".to_string()));
+	assert_eq!(app.profile.clone().unwrap().name, "second");
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_app_dashboard() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	Item::new(&connection, "Test Item".to_string(), 100, 25, 100).unwrap();
+	let today = Local::now().date_naive();
+	let new_order = |receipt_number, hired_on| Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    receipt_number,
+	    vec![("Test Item".to_string(), 26)],
+	    hired_on,
+	    hired_on + Days::new(2),
+	).unwrap();
+	let pickup = new_order(15, today);
+	new_order(16, today + Days::new(1)).mark_out(&connection).unwrap();
+
+	let mut app = App::new(connection).0;
+	assert_eq!(app.active_tab, TabId::Dashboard);
+	let dashboard = app.dashboard.clone().unwrap();
+	assert_eq!(dashboard.pickups_today.orders, 1);
+	assert_eq!(dashboard.hired_out[0].1, 26);
+
+	let _ = app.update(Message::ShowDashboardOrders(DashboardFigure::PickupsToday));
+	assert_eq!(app.active_tab, TabId::Orders);
+	assert_eq!(app.orders, vec![pickup]);
+
+	let _ = app.update(Message::ClearOrderFilters);
+	assert_eq!(app.orders.len(), 2);
+	assert_eq!(app.dashboard_filter, None);
+
+	let _ = app.update(Message::MarkOrderOut(app.orders[0].id));
+	assert_eq!(app.dashboard.clone().unwrap().pickups_today.orders, 0);
+    }
+
+    #[test]
+    fn test_app_save_docket() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	let order = Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::SaveDocket(order.id));
+	let path = app.docket.clone().unwrap().unwrap();
+	assert!(fs::read(&path).unwrap().starts_with(b"%PDF"));
+	fs::remove_file(&path).unwrap();
+
+	let _ = app.update(Message::SaveDocket(order.id + 1));
+	assert!(app.docket.clone().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_app_write_reminders() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	let order = Order::new(
+	    &connection,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap().mark_out(&connection).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::TabSelected(TabId::Overdue));
+	assert_eq!(app.overdue.len(), 1);
+	assert_eq!(app.overdue[0].order, order);
+	assert_eq!(app.overdue[0].last_reminded, None);
+
+	let _ = app.update(Message::ReminderMethodSelected(ReminderMethod::TextFile));
+	let _ = app.update(Message::WriteReminders);
+	assert!(app.reminder_message.clone().unwrap().starts_with("Wrote 1 reminder(s)"));
+	assert!(app.overdue[0].last_reminded.is_some());
+
+	let reminders = Reminder::get_for_order(&app.db_connection, order.id).unwrap();
+	assert_eq!(reminders[0].method, ReminderMethod::TextFile);
+	let path = reminder::default_reminder_dir(&app.db_connection).join(format!(
+	    "reminder-15-{}-{}.txt",
+	    order.id,
+	    reminders[0].sent_at.format("%Y-%m-%d"),
+	));
+	assert!(fs::read_to_string(&path).unwrap().starts_with("To: Test Person\n"));
+	fs::remove_file(&path).unwrap();
+
+	// The customer was just reminded, so isn't again
+	let _ = app.update(Message::WriteReminders);
+	assert!(app.reminder_message.clone().unwrap().starts_with("Everyone has been reminded lately"));
+
+	let _ = app.update(Message::MarkOrderReturned(order.id));
+	assert!(app.overdue.is_empty());
+    }
+
+    #[test]
+    fn test_app_email_reminders() {
+	let connection = Connection::open_in_memory().unwrap();
+	let server = TestSmtpServer::start();
+
+	init_db_tables(&connection).unwrap();
+	let today = Local::now().date_naive();
+	for (name, email) in [("Test Person", "test@example.com"), ("Gone Person", "gone@example.com")] {
+	    Customer::new(&connection, name.to_string(), String::new(), email.to_string(), String::new(), String::new())
+		.unwrap();
+	    Order::new(
+		&connection,
+		name.to_string(),
+		15,
+		vec![("Test Item".to_string(), 26)],
+		today - Days::new(2),
+		today,
+	    ).unwrap().mark_out(&connection).unwrap();
+	}
+	server.reject("gone@example.com");
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::TabSelected(TabId::Email));
+	let _ = app.update(Message::SendEmailsNow);
+	assert_eq!(app.email_message.clone().unwrap(), "Save the email settings before sending reminders");
+
+	let _ = app.update(Message::SmtpFieldChanged("host", "127.0.0.1".to_string()));
+	let _ = app.update(Message::SmtpFieldChanged("port", server.port.to_string()));
+	let _ = app.update(Message::SmtpSecuritySelected(SmtpSecurity::None));
+	let _ = app.update(Message::SaveSmtpSettings);
+	assert!(app.smtp_form.get_visible_field_error("from").is_some());
+	let _ = app.update(Message::SmtpFieldChanged("from", "hire@example.com".to_string()));
+	let _ = app.update(Message::SaveSmtpSettings);
+	assert_eq!(app.email_message.clone().unwrap(), "Email settings saved");
+
+	// The emails are queued straight away, but sent in the background
+	let _ = app.update(Message::SendEmailsNow);
+	assert_eq!(app.email_message.clone().unwrap(), "Sending reminders...");
+	assert_eq!(app.emails.len(), 2);
+	assert!(server.received().is_empty());
+	let _ = app.update(Message::SendEmailsNow);
+	assert_eq!(app.email_message.clone().unwrap(), "Reminders are already being sent");
+
+	finish_sending_emails(&mut app, true);
+	assert_eq!(app.email_message.clone().unwrap(), "Queued 0 email(s), sent 1 and 1 failed");
+	assert_eq!(server.received()[0].to, vec!["test@example.com".to_string()]);
+
+	let failed = app.emails.iter().find(|email| email.to_address.contains("gone")).unwrap().clone();
+	assert_eq!(failed.status(app.email_max_attempts), EmailStatus::Failed);
+	let _ = app.update(Message::RetryEmail(failed.id));
+	let failed = app.emails.iter().find(|email| email.id == failed.id).unwrap();
+	assert_eq!(failed.status(app.email_max_attempts), EmailStatus::Waiting);
+
+	// The timer sends what is due, without touching the message
+	let _ = app.update(Message::EmailTimer);
+	assert!(app.sending_emails);
+	finish_sending_emails(&mut app, false);
+	assert_eq!(app.email_message.clone().unwrap(), "Queued 0 email(s), sent 1 and 1 failed");
+	assert!(app.error.is_none());
+    }
+
+    /// Sends the emails that are due and gives the app what happened, as the
+    /// task from SendEmailsNow or EmailTimer does when it is run.
+    fn finish_sending_emails(app: &mut App, by_hand: bool) {
+	let batch = email::take_reminders(&app.db_connection, Local::now().naive_local()).unwrap().unwrap();
+	let _ = app.update(Message::EmailsSent(by_hand, batch.send()));
+    }
+
+    #[test]
+    fn test_app_email_failure_shown_once() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::EmailsSent(false, SentBatch::failed("Server down".to_string())));
+	assert_eq!(app.error.take(), Some("Server down".to_string()));
+
+	// Trying again and failing the same way doesn't show it again
+	let _ = app.update(Message::EmailsSent(false, SentBatch::failed("Server down".to_string())));
+	assert_eq!(app.error, None);
+
+	let _ = app.update(Message::EmailsSent(false, SentBatch::failed("Login failed".to_string())));
+	assert_eq!(app.error.take(), Some("Login failed".to_string()));
+
+	// Once sending works again, the next failure is shown
+	let sent = SendBatch::take(&app.db_connection, &SmtpSettings::default(), Local::now().naive_local())
+	    .unwrap()
+	    .send();
+	let _ = app.update(Message::EmailsSent(false, sent));
+	assert_eq!(app.error, None);
+	let _ = app.update(Message::EmailsSent(false, SentBatch::failed("Login failed".to_string())));
+	assert!(app.error.is_some());
+    }
+
+    #[test]
+    fn test_app_payments() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	Item::new(&connection, "Chairs".to_string(), 100, 20, 250).unwrap();
+	let today = Local::now().date_naive();
+	let order = Order::new(
+	    &connection,
+	    "Alice Smith".to_string(),
+	    1,
+	    vec![("Chairs".to_string(), 10)],
+	    today,
+	    today.checked_add_days(Days::new(2)).unwrap(),
+	).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	let _ = app.update(Message::ViewPayments(order.id));
+	let (viewed, payments, _) = app.viewed_payments.clone().unwrap();
+	assert_eq!(viewed.balance(), 5000);
+	assert!(payments.is_empty());
+
+	let _ = app.update(Message::DepositChanged("lots".to_string()));
+	let _ = app.update(Message::SaveCharges);
+	assert!(app.charges_form.get_visible_field_error("deposit").is_some());
+
+	let _ = app.update(Message::DepositChanged("20".to_string()));
+	let _ = app.update(Message::SaveCharges);
+	assert_eq!(app.viewed_payments.as_ref().unwrap().0.balance(), 7000);
+
+	let _ = app.update(Message::RecordPayment);
+	assert!(app.payment_form.get_visible_field_error("amount").is_some());
+
+	let _ = app.update(Message::PaymentAmountChanged("70".to_string()));
+	let _ = app.update(Message::PaymentMethodSelected(PaymentMethod::Card));
+	let _ = app.update(Message::RecordPayment);
+	let (viewed, payments, _) = app.viewed_payments.clone().unwrap();
+	assert_eq!(viewed.balance(), 0);
+	assert_eq!(payments[0].method, PaymentMethod::Card);
+	assert_eq!(app.payment_form.amount, String::new());
+	assert_eq!(app.orders[0].paid, 7000);
+
+	let _ = app.update(Message::DeletePayment(payments[0].id));
+	assert!(app.viewed_payments.as_ref().unwrap().1.is_empty());
+
+	let _ = app.update(Message::ClosePayments);
+	assert!(app.viewed_payments.is_none());
+    }
+
+    #[test]
+    fn test_app_late_fee() {
+	let connection = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&connection).unwrap();
+	LATE_FEE_PER_DAY.set(&connection, 500).unwrap();
+	let today = Local::now().date_naive();
+	let order = Order::new(
+	    &connection,
+	    "Alice Smith".to_string(),
+	    1,
+	    vec![("Chairs".to_string(), 10)],
+	    today.checked_sub_days(Days::new(5)).unwrap(),
+	    today.checked_sub_days(Days::new(3)).unwrap(),
+	).unwrap();
+	order.mark_out(&connection).unwrap();
+
+	let mut app = App::new(connection).0;
+
+	// Coming back late charges the fee and shows it
+	let _ = app.update(Message::MarkOrderReturned(order.id));
+	let (viewed, _, late_fee) = app.viewed_payments.clone().unwrap();
+	assert_eq!(late_fee.unwrap().amount, 1500);
+	assert_eq!(viewed.balance(), 1500);
+
+	let _ = app.update(Message::WaiveLateFee);
+	assert!(app.payment_form_error.is_some());
+
+	let _ = app.update(Message::WaiveReasonChanged("Regular customer".to_string()));
+	let _ = app.update(Message::WaiveLateFee);
+	let (viewed, _, late_fee) = app.viewed_payments.clone().unwrap();
+	assert!(late_fee.unwrap().is_waived());
+	assert_eq!(viewed.balance(), 0);
+	assert_eq!(app.waive_reason, String::new());
+    }
 }
@@ -0,0 +1,413 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::{Datelike, NaiveDateTime, TimeDelta};
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use crate::database::{db_dir, init_db_tables, migrations};
+use crate::error::Error;
+use crate::settings::{BACKUP_KEEP_DAILY, BACKUP_KEEP_WEEKLY};
+
+/// Backups are named with this, then when they were taken.
+const BACKUP_PREFIX: &str = "orders-";
+
+/// The format of when a backup was taken in its file name.
+const BACKUP_TIME_FORMAT: &str = "%Y-%m-%d-%H%M%S";
+
+/// Represents a backup of the database, and what is in it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupFile {
+    pub path: PathBuf,
+    pub taken_at: NaiveDateTime,
+    pub size: u64,
+    // How many orders are in it, or None if it can't be read
+    pub order_count: Option<i64>,
+}
+
+impl BackupFile {
+    /// Reads the details of a backup from its file, or None if the file isn't
+    /// named like a backup.
+    fn load(path: PathBuf) -> Result<Option<Self>, Error> {
+	let taken_at = path.file_name()
+	    .and_then(|name| name.to_str())
+	    .and_then(|name| name.strip_prefix(BACKUP_PREFIX))
+	    .and_then(|name| name.strip_suffix(".db"))
+	    .and_then(|time| NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).ok());
+
+	let Some(taken_at) = taken_at else {
+	    return Ok(None);
+	};
+
+	Ok(Some(Self {
+	    size: fs::metadata(&path)?.len(),
+	    order_count: count_orders(&path).ok(),
+	    path,
+	    taken_at,
+	}))
+    }
+
+    /// Gets the file name of the backup, e.g. "orders-2025-03-20-093000.db".
+    pub fn name(&self) -> String {
+	self.path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+    }
+}
+
+/// Gets the folder backups of the database are kept in, which is next to the
+/// database file. Gives None for a database that is only in memory.
+pub fn backup_dir(connection: &Connection) -> Option<PathBuf> {
+    Some(db_dir(connection)?.join("backups"))
+}
+
+/// Copies the database into a new backup in `dir`, using SQLite's online
+/// backup so it is consistent even while the app is using it.
+pub fn create_backup(connection: &Connection, dir: &Path, now: NaiveDateTime) -> Result<BackupFile, Error> {
+    fs::create_dir_all(dir)?;
+
+    // A backup taken in the same second as another is named a second later,
+    // so it can never overwrite one, such as the one being restored
+    let mut taken_at = now;
+    let mut path = backup_path(dir, taken_at);
+    while path.exists() {
+	taken_at += TimeDelta::seconds(1);
+	path = backup_path(dir, taken_at);
+    }
+
+    connection.backup(DatabaseName::Main, &path, None)?;
+
+    BackupFile::load(path)?.ok_or_else(|| Error::Parse("The new backup couldn't be read".to_string()))
+}
+
+/// Gets the path of the backup in `dir` taken at the given time.
+fn backup_path(dir: &Path, taken_at: NaiveDateTime) -> PathBuf {
+    dir.join(format!("{}{}.db", BACKUP_PREFIX, taken_at.format(BACKUP_TIME_FORMAT)))
+}
+
+/// Gets the backups in `dir`, newest first. A folder that doesn't exist yet
+/// has no backups.
+pub fn list_backups(dir: &Path) -> Result<Vec<BackupFile>, Error> {
+    let entries = match fs::read_dir(dir) {
+	Ok(entries) => entries,
+	Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+	Err(e) => return Err(e.into()),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+	if let Some(backup) = BackupFile::load(entry?.path())? {
+	    backups.push(backup);
+	}
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.taken_at));
+
+    Ok(backups)
+}
+
+/// Picks which of the backups, sorted newest first, to keep: the newest of
+/// each of the latest `daily` days with a backup, and the newest of each of the
+/// latest `weekly` weeks with a backup. The newest backup is always kept.
+pub fn backups_to_keep(backups: &[BackupFile], daily: usize, weekly: usize) -> HashSet<PathBuf> {
+    let mut keep = HashSet::new();
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+
+    if let Some(newest) = backups.first() {
+	keep.insert(newest.path.clone());
+    }
+
+    for backup in backups {
+	let day = backup.taken_at.date();
+	if days.len() < daily && days.insert(day) {
+	    keep.insert(backup.path.clone());
+	}
+
+	let week = (day.iso_week().year(), day.iso_week().week());
+	if weeks.len() < weekly && weeks.insert(week) {
+	    keep.insert(backup.path.clone());
+	}
+    }
+
+    keep
+}
+
+/// Deletes the backups in `dir` that the retention settings don't keep.
+/// Gives how many were deleted.
+pub fn rotate_backups(connection: &Connection, dir: &Path) -> Result<usize, Error> {
+    let backups = list_backups(dir)?;
+    let keep = backups_to_keep(
+	&backups,
+	BACKUP_KEEP_DAILY.get(connection)? as usize,
+	BACKUP_KEEP_WEEKLY.get(connection)? as usize,
+    );
+
+    let mut deleted = 0;
+    for backup in backups.iter().filter(|backup| !keep.contains(&backup.path)) {
+	fs::remove_file(&backup.path)?;
+	deleted += 1;
+    }
+
+    Ok(deleted)
+}
+
+/// Takes a backup and clears out old ones, if the database is kept in a file.
+pub fn run_scheduled_backup(connection: &Connection, now: NaiveDateTime) -> Result<Option<BackupFile>, Error> {
+    let Some(dir) = backup_dir(connection) else {
+	return Ok(None);
+    };
+
+    let backup = create_backup(connection, &dir, now)?;
+    rotate_backups(connection, &dir)?;
+
+    Ok(Some(backup))
+}
+
+/// Checks a backup isn't damaged and can be opened by this version of the program.
+pub fn verify_backup(path: &Path) -> Result<(), Error> {
+    let connection = open_read_only(path)?;
+
+    let result: String = connection.query_row("PRAGMA integrity_check", [], |row| row.get(0))
+	.map_err(|e| damaged(path, &e.to_string()))?;
+    if result != "ok" {
+	return Err(damaged(path, &result));
+    }
+
+    let version = migrations::get_version(&connection)?;
+    let supported = migrations::MIGRATIONS.last().map(|m| m.version).unwrap_or(0);
+    if version > supported {
+	return Err(Error::Validation(format!(
+	    "{} was made by a newer version of the program",
+	    path.display(),
+	)));
+    }
+
+    count_orders(path).map_err(|e| damaged(path, &e.to_string()))?;
+
+    Ok(())
+}
+
+/// Replaces the database with a backup, after checking the backup and backing
+/// up the database as it is now, which is given back so it can be undone.
+pub fn restore_backup(connection: &Connection, backup: &Path, now: NaiveDateTime) -> Result<BackupFile, Error> {
+    verify_backup(backup)?;
+
+    let (Some(db_path), Some(dir)) = (connection.path(), backup_dir(connection)) else {
+	return Err(Error::Validation("Only a database kept in a file can be restored".to_string()));
+    };
+    let before_restore = create_backup(connection, &dir, now)?;
+
+    // The pages are copied in through a second connection, which the open one
+    // picks up on its next query
+    let mut target = Connection::open(db_path)?;
+    target.restore(DatabaseName::Main, backup, None::<fn(rusqlite::backup::Progress)>)?;
+
+    // Older backups are brought up to date
+    init_db_tables(connection)?;
+
+    Ok(before_restore)
+}
+
+/// Counts the orders in a database file that haven't been deleted, without changing it.
+fn count_orders(path: &Path) -> Result<i64, Error> {
+    let connection = open_read_only(path)?;
+
+    // Backups from before orders could be deleted have no deleted_at column
+    let count = connection.query_row(
+	"SELECT COUNT(*) FROM CustomerOrder WHERE deleted_at IS NULL",
+	[],
+	|row| row.get(0),
+    ).or_else(|_| connection.query_row("SELECT COUNT(*) FROM CustomerOrder", [], |row| row.get(0)))?;
+
+    Ok(count)
+}
+
+/// Opens a database file only for reading.
+fn open_read_only(path: &Path) -> Result<Connection, Error> {
+    Ok(Connection::open_with_flags(
+	path,
+	OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?)
+}
+
+/// Makes the error for a backup that failed its checks.
+fn damaged(path: &Path, reason: &str) -> Error {
+    Error::Validation(format!("{} is damaged and can't be restored: {}", path.display(), reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use crate::order::Order;
+
+    use super::*;
+
+    /// Makes an empty folder for a test to keep a database and its backups in.
+    fn test_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("as91896-backup-{}-{}", std::process::id(), name));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+    }
+
+    fn test_database(dir: &Path) -> Connection {
+	let con = Connection::open(dir.join("orders.db")).unwrap();
+	init_db_tables(&con).unwrap();
+	con
+    }
+
+    fn test_order(con: &Connection) -> Order {
+	Order::new(
+	    con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap()
+    }
+
+    fn at(month: u32, day: u32, hour: u32) -> NaiveDateTime {
+	NaiveDate::from_ymd_opt(2025, month, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    fn backup_at(taken_at: NaiveDateTime) -> BackupFile {
+	BackupFile {
+	    path: PathBuf::from(format!("{}{}.db", BACKUP_PREFIX, taken_at.format(BACKUP_TIME_FORMAT))),
+	    taken_at,
+	    size: 0,
+	    order_count: None,
+	}
+    }
+
+    #[test]
+    fn test_backup_dir() {
+	let dir = test_dir("dir");
+	let con = test_database(&dir);
+
+	assert_eq!(backup_dir(&con), Some(dir.join("backups")));
+	assert_eq!(backup_dir(&Connection::open_in_memory().unwrap()), None);
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_create_and_list_backups() {
+	let dir = test_dir("create");
+	let con = test_database(&dir);
+	let backups = dir.join("backups");
+
+	test_order(&con);
+	create_backup(&con, &backups, at(3, 20, 9)).unwrap();
+	test_order(&con);
+	let newest = create_backup(&con, &backups, at(3, 21, 9)).unwrap();
+	fs::write(backups.join("notes.txt"), "not a backup").unwrap();
+
+	let listed = list_backups(&backups).unwrap();
+	assert_eq!(listed.len(), 2);
+	assert_eq!(listed[0], newest);
+	assert_eq!(listed[0].name(), "orders-2025-03-21-090000.db".to_string());
+	assert_eq!(listed[0].order_count, Some(2));
+	assert_eq!(listed[1].order_count, Some(1));
+	assert!(list_backups(&dir.join("missing")).unwrap().is_empty());
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_backups_to_keep() {
+	// Two a day for three weeks, newest first
+	let mut backups = Vec::new();
+	for day in (1..=21).rev() {
+	    backups.push(backup_at(at(3, day, 17)));
+	    backups.push(backup_at(at(3, day, 9)));
+	}
+
+	let keep = backups_to_keep(&backups, 3, 2);
+
+	let mut kept: Vec<NaiveDateTime> = backups.iter()
+	    .filter(|backup| keep.contains(&backup.path))
+	    .map(|backup| backup.taken_at)
+	    .collect();
+	kept.sort();
+	// The 2025-03-21 is a Friday, so the week before ends on the 16th
+	assert_eq!(kept, vec![at(3, 16, 17), at(3, 19, 17), at(3, 20, 17), at(3, 21, 17)]);
+    }
+
+    #[test]
+    fn test_backups_to_keep_always_keeps_newest() {
+	let backups = vec![backup_at(at(3, 21, 17)), backup_at(at(3, 21, 9))];
+
+	assert_eq!(backups_to_keep(&backups, 0, 0), HashSet::from([backups[0].path.clone()]));
+	assert!(backups_to_keep(&[], 7, 4).is_empty());
+    }
+
+    #[test]
+    fn test_rotate_backups() {
+	let dir = test_dir("rotate");
+	let con = test_database(&dir);
+	let backups = dir.join("backups");
+	BACKUP_KEEP_DAILY.set(&con, 2).unwrap();
+	BACKUP_KEEP_WEEKLY.set(&con, 0).unwrap();
+
+	for day in 1..=4 {
+	    create_backup(&con, &backups, at(3, day, 9)).unwrap();
+	}
+
+	assert_eq!(rotate_backups(&con, &backups).unwrap(), 2);
+
+	let left: Vec<NaiveDateTime> = list_backups(&backups).unwrap().iter().map(|b| b.taken_at).collect();
+	assert_eq!(left, vec![at(3, 4, 9), at(3, 3, 9)]);
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_backup_damaged() {
+	let dir = test_dir("verify");
+	let con = test_database(&dir);
+	let backup = create_backup(&con, &dir.join("backups"), at(3, 20, 9)).unwrap();
+
+	assert!(verify_backup(&backup.path).is_ok());
+
+	fs::write(&backup.path, "this isn't a database").unwrap();
+	assert!(matches!(verify_backup(&backup.path), Err(Error::Validation(_))));
+	assert!(verify_backup(&dir.join("missing.db")).is_err());
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_backup() {
+	let dir = test_dir("restore");
+	let con = test_database(&dir);
+	let backups = dir.join("backups");
+
+	let order = test_order(&con);
+	let backup = create_backup(&con, &backups, at(3, 20, 9)).unwrap();
+	test_order(&con);
+
+	// Restoring in the same second as the backup was taken
+	let before_restore = restore_backup(&con, &backup.path, at(3, 20, 9)).unwrap();
+
+	assert_eq!(Order::get_all(&con).unwrap(), vec![order]);
+	assert_eq!(before_restore.order_count, Some(2));
+	assert_eq!(before_restore.taken_at, at(3, 20, 9) + TimeDelta::seconds(1));
+	assert_eq!(list_backups(&backups).unwrap().len(), 2);
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_damaged_backup_changes_nothing() {
+	let dir = test_dir("restore-damaged");
+	let con = test_database(&dir);
+	let backup = dir.join("broken.db");
+	fs::write(&backup, "this isn't a database").unwrap();
+
+	test_order(&con);
+
+	assert!(restore_backup(&con, &backup, at(3, 20, 10)).is_err());
+	assert_eq!(Order::get_all(&con).unwrap().len(), 1);
+	assert!(list_backups(&dir.join("backups")).unwrap().is_empty());
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+}
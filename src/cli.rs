@@ -0,0 +1,1589 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use chrono::{Days, Local, NaiveDate};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+
+use crate::order::{LateFee, Order, OrderForm, OrderQuery};
+use crate::order::form::{DateLimits, OrderLineForm};
+use crate::order::export::export_csv;
+use crate::order::query::StatusFilter;
+use crate::customer::{Customer, CustomerForm};
+use crate::item::{format_price, Item, ItemForm};
+use crate::packing::{PackingPlan, PACKING_PLAN_DAYS};
+use crate::payment::{describe_balance, ChargesForm, Payment, PaymentForm, PaymentMethod};
+use crate::raffle::{RaffleDraw, RaffleDrawForm};
+use crate::backup::{self, BackupFile};
+use crate::docket;
+use crate::email::{self, OutgoingEmail};
+use crate::email::smtp::{SmtpForm, SmtpSecurity};
+use crate::reminder::{self, OverdueOrder, Reminder, ReminderMethod};
+use crate::settings::EMAIL_MAX_ATTEMPTS;
+use crate::error::Error;
+
+/// Printed when the command line can't be understood.
+const USAGE: &str = "Usage:
+  as91896                                   Start the app
+  as91896 orders list [filters] [--json]    List orders
+  as91896 orders add --customer NAME --receipt NUMBER|next --item ITEM --quantity N
+                     [--item ITEM --quantity N ...] --from YYYY-MM-DD --to YYYY-MM-DD
+                     [--reuse-receipt] [--json]
+  as91896 orders delete ID                  Delete an order, which can be restored for a while
+  as91896 orders deleted [--json]           List the recently deleted orders
+  as91896 orders restore ID                 Bring back a recently deleted order
+  as91896 orders docket ID [--file PATH]    Save the PDF docket for an order
+  as91896 orders payments ID [--json]       Show what an order costs and what has been paid
+  as91896 orders pay ID --amount DOLLARS [--method cash|card|bank transfer|other]
+                     [--on YYYY-MM-DD] [--note NOTE] [--refund]
+                                            Record a payment towards an order, or a refund
+  as91896 orders charges ID [--deposit DOLLARS] [--damage DOLLARS]
+                                            Change the deposit and damage charge on an order
+  as91896 orders waive-fee ID --reason REASON
+                                            Waive the late fee charged when an order came back
+  as91896 orders export [filters] [--file PATH]
+                                            Write orders as CSV, to stdout by default
+  as91896 customers list [--json]           List customers and their contact details
+  as91896 customers add --name NAME [--phone PHONE] [--email EMAIL] [--address ADDRESS]
+                        [--notes NOTES] [--json]
+  as91896 items list [--json]               List the item catalogue
+  as91896 items add --name NAME --stock N --per-box N --price DOLLARS_PER_DAY [--json]
+  as91896 packing [--from YYYY-MM-DD] [--days N]
+                                            Print the boxes to pack for each day's pickups
+  as91896 raffle draw --prize PRIZE [--winners N] [--json]
+  as91896 overdue [--json]                  List the orders that are late back, and who has them
+  as91896 overdue remind [--text] [--dir PATH]
+                                            Write reminders for late orders not reminded lately
+  as91896 email setup [--host HOST] [--port N] [--security none|starttls|tls] [--user USER]
+                      [--password-stdin] [--from ADDRESS]
+                                            Change how reminder emails are sent. The password
+                                            is read from the first line of stdin, so it isn't
+                                            left in the shell history
+  as91896 email queue [--json]              List the reminder emails, newest first
+  as91896 email send                        Queue the reminder emails that are due and send them
+  as91896 email retry ID                    Send an email that was given up on again
+  as91896 backups list [--json]             List the backups, newest first
+  as91896 backups create                    Back up the database now
+  as91896 backups restore FILE              Check a backup and replace the database with it
+  as91896 --profile NAME|--db PATH ...      Use another database for the app or a command
+
+Filters: --search TEXT --status STATUS --item ITEM --from YYYY-MM-DD --to YYYY-MM-DD";
+
+/// The options the list and export commands use to filter orders.
+const FILTER_OPTIONS: [&str; 5] = ["search", "status", "item", "from", "to"];
+
+/// The options that are given on their own, without a value.
+const FLAGS: [&str; 5] = ["json", "reuse-receipt", "text", "refund", "password-stdin"];
+
+/// Represents the arguments given on the command line, after the program name.
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, Vec<String>>,
+    flags: HashSet<String>,
+}
+
+impl Args {
+    /// Splits the arguments into positional ones, `--name value` options and
+    /// flags such as `--json`. Options can be given more than once.
+    fn parse(args: &[String]) -> Result<Self, Error> {
+	let mut parsed = Self {
+	    positional: Vec::new(),
+	    options: HashMap::new(),
+	    flags: HashSet::new(),
+	};
+
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+	    match arg.strip_prefix("--") {
+		Some(name) if FLAGS.contains(&name) => {
+		    parsed.flags.insert(name.to_string());
+		},
+		Some(name) => {
+		    let (name, value) = match name.split_once('=') {
+			Some((name, value)) => (name, value.to_string()),
+			None => match args.next() {
+			    Some(value) => (name, value.clone()),
+			    None => return Err(Error::Validation(format!("--{} needs a value", name))),
+			},
+		    };
+		    parsed.options.entry(name.to_string()).or_default().push(value);
+		},
+		None => parsed.positional.push(arg.clone()),
+	    }
+	}
+
+	Ok(parsed)
+    }
+
+    /// Checks that only the given options were used. `--json` is allowed everywhere.
+    fn allow_options(&self, allowed: &[&str]) -> Result<(), Error> {
+	let mut names = self.options.keys().chain(self.flags.iter().filter(|flag| *flag != "json"));
+
+	match names.find(|name| !allowed.contains(&name.as_str())) {
+	    Some(name) => Err(Error::Validation(format!("Unknown option --{}\n\n{}", name, USAGE))),
+	    None => Ok(()),
+	}
+    }
+
+    /// Checks if a flag was given.
+    fn flag(&self, name: &str) -> bool {
+	self.flags.contains(name)
+    }
+
+    /// Gets the value of an option, the last one if it was given more than once.
+    fn option(&self, name: &str) -> Option<&String> {
+	self.options.get(name).and_then(|values| values.last())
+    }
+
+    /// Gets the value of an option, or an empty string if it wasn't given.
+    fn get(&self, name: &str) -> String {
+	self.option(name).cloned().unwrap_or_default()
+    }
+
+    /// Gets every value given for an option, in order.
+    fn get_all(&self, name: &str) -> Vec<String> {
+	self.options.get(name).cloned().unwrap_or_default()
+    }
+}
+
+/// Takes the `--db PATH` and `--profile NAME` options out of the arguments, as
+/// they choose the database rather than being part of a command. Gives back
+/// the path and the profile name.
+pub fn take_database_options(args: &mut Vec<String>) -> Result<(Option<String>, Option<String>), Error> {
+    let mut db = None;
+    let mut profile = None;
+
+    let mut i = 0;
+    while i < args.len() {
+	let (option, value) = match args[i].split_once('=') {
+	    Some((name, value)) => (name.to_string(), Some(value.to_string())),
+	    None => (args[i].clone(), None),
+	};
+	let target = match option.as_str() {
+	    "--db" => &mut db,
+	    "--profile" => &mut profile,
+	    _ => {
+		i += 1;
+		continue;
+	    },
+	};
+
+	args.remove(i);
+	let value = match value {
+	    Some(value) => value,
+	    None if i < args.len() => args.remove(i),
+	    None => return Err(Error::Validation(format!("{} needs a value", option))),
+	};
+	if target.replace(value).is_some() {
+	    return Err(Error::Validation(format!("{} can only be given once", option)));
+	}
+    }
+
+    Ok((db, profile))
+}
+
+/// Runs a command given on the command line, writing its output to `out`.
+pub fn run<W: Write>(connection: &Connection, args: &[String], out: &mut W) -> Result<(), Error> {
+    let args = Args::parse(args)?;
+    let command: Vec<&str> = args.positional.iter().map(|a| a.as_str()).collect();
+
+    match command.as_slice() {
+	["orders", "list"] => {
+	    args.allow_options(&FILTER_OPTIONS)?;
+	    list_orders(connection, &args, out)
+	},
+	["orders", "add"] => {
+	    args.allow_options(&["customer", "receipt", "item", "quantity", "from", "to", "reuse-receipt"])?;
+	    add_order(connection, &args, out)
+	},
+	["orders", "delete", id] => {
+	    args.allow_options(&[])?;
+	    delete_order(connection, id, out)
+	},
+	["orders", "deleted"] => {
+	    args.allow_options(&[])?;
+	    list_deleted_orders(connection, &args, out)
+	},
+	["orders", "restore", id] => {
+	    args.allow_options(&[])?;
+	    restore_order(connection, id, out)
+	},
+	["orders", "docket", id] => {
+	    args.allow_options(&["file"])?;
+	    save_docket(connection, id, &args, out)
+	},
+	["orders", "payments", id] => {
+	    args.allow_options(&[])?;
+	    show_payments(connection, id, &args, out)
+	},
+	["orders", "pay", id] => {
+	    args.allow_options(&["amount", "method", "on", "note", "refund"])?;
+	    record_payment(connection, id, &args, out)
+	},
+	["orders", "charges", id] => {
+	    args.allow_options(&["deposit", "damage"])?;
+	    set_charges(connection, id, &args, out)
+	},
+	["orders", "waive-fee", id] => {
+	    args.allow_options(&["reason"])?;
+	    waive_late_fee(connection, id, &args, out)
+	},
+	["orders", "export"] => {
+	    args.allow_options(&[FILTER_OPTIONS.as_slice(), &["file"]].concat())?;
+	    export_orders(connection, &args, out)
+	},
+	["customers", "list"] => {
+	    args.allow_options(&[])?;
+	    list_customers(connection, &args, out)
+	},
+	["customers", "add"] => {
+	    args.allow_options(&["name", "phone", "email", "address", "notes"])?;
+	    add_customer(connection, &args, out)
+	},
+	["items", "list"] => {
+	    args.allow_options(&[])?;
+	    list_items(connection, &args, out)
+	},
+	["items", "add"] => {
+	    args.allow_options(&["name", "stock", "per-box", "price"])?;
+	    add_item(connection, &args, out)
+	},
+	["packing"] => {
+	    args.allow_options(&["from", "days"])?;
+	    print_packing_plan(connection, &args, out)
+	},
+	["raffle", "draw"] => {
+	    args.allow_options(&["prize", "winners"])?;
+	    draw_raffle(connection, &args, out)
+	},
+	["overdue"] => {
+	    args.allow_options(&[])?;
+	    list_overdue(connection, &args, out)
+	},
+	["overdue", "remind"] => {
+	    args.allow_options(&["text", "dir"])?;
+	    write_reminders(connection, &args, out)
+	},
+	["email", "setup"] => {
+	    args.allow_options(&["host", "port", "security", "user", "password-stdin", "from"])?;
+	    setup_email(connection, &args, out)
+	},
+	["email", "queue"] => {
+	    args.allow_options(&[])?;
+	    list_emails(connection, &args, out)
+	},
+	["email", "send"] => {
+	    args.allow_options(&[])?;
+	    send_emails(connection, out)
+	},
+	["email", "retry", id] => {
+	    args.allow_options(&[])?;
+	    retry_email(connection, id, out)
+	},
+	["backups", "list"] => {
+	    args.allow_options(&[])?;
+	    list_backups(connection, &args, out)
+	},
+	["backups", "create"] => {
+	    args.allow_options(&[])?;
+	    create_backup(connection, out)
+	},
+	["backups", "restore", file] => {
+	    args.allow_options(&[])?;
+	    restore_backup(connection, file, out)
+	},
+	_ => Err(Error::Validation(USAGE.to_string())),
+    }
+}
+
+/// Prints the orders matching the filters.
+fn list_orders<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let orders = Order::query(connection, &parse_query(args)?)?;
+    let today = Local::now().date_naive();
+
+    if args.flag("json") {
+	let orders: Vec<Value> = orders.iter().map(|order| order_json(order, today)).collect();
+	return write_json(out, &Value::Array(orders));
+    }
+
+    let rows: Vec<Vec<String>> = orders.iter().map(|order| order_row(order, today)).collect();
+    write_table(
+	out,
+	&["ID", "Customer", "Receipt", "Item", "How Many", "Hired On", "Return On", "Boxes", "Raffle", "Status", "Paid"],
+	&rows,
+    )
+}
+
+/// Adds an order, checking it the same way the Add Order form does. Each
+/// `--item` is paired with the `--quantity` in the same position. A receipt of
+/// "next" takes the next number in the sequence.
+fn add_order<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let items = args.get_all("item");
+    let quantities = args.get_all("quantity");
+    if items.len() != quantities.len() {
+	return Err(Error::Validation("Each --item needs a --quantity".to_string()));
+    }
+
+    let mut form = OrderForm {
+	customer_name: args.get("customer"),
+	receipt_number: match args.get("receipt").as_str() {
+	    "next" => Order::next_receipt_number(connection)?.to_string(),
+	    receipt => receipt.to_string(),
+	},
+	receipt_reuse_confirmed: args.flag("reuse-receipt"),
+	..Default::default()
+    };
+    if !items.is_empty() {
+	form.lines = items.into_iter().zip(quantities).map(|(item_hired, how_many)| OrderLineForm {
+	    item_hired,
+	    how_many,
+	    ..Default::default()
+	}).collect();
+    }
+    form.hired_on = args.get("from");
+    form.return_on = args.get("to");
+    form.date_limits = DateLimits::load(connection, Local::now().date_naive())?;
+
+    let errors = form.get_errors();
+    if !errors.is_empty() {
+	return Err(Error::Validation(errors.join("\n")));
+    }
+
+    let order = form.create_order(connection).map_err(|e| match e {
+	Error::NeedsConfirmation(message) => {
+	    Error::Validation(format!("{}\nAdd --reuse-receipt to use it anyway", message))
+	},
+	e => e,
+    })?;
+    let today = Local::now().date_naive();
+
+    if args.flag("json") {
+	return write_json(out, &order_json(&order, today));
+    }
+
+    writeln!(
+	out,
+	"Added order {} for {}, raffle number {}",
+	order.id,
+	order.customer_name,
+	order.raffle_number,
+    )?;
+
+    Ok(())
+}
+
+/// Deletes the order with the given id.
+fn delete_order<W: Write>(connection: &Connection, id: &str, out: &mut W) -> Result<(), Error> {
+    let id = parse_order_id(id)?;
+    let order = match Order::get_by_id(connection, id) {
+	Err(Error::Database(rusqlite::Error::QueryReturnedNoRows)) => {
+	    return Err(Error::Validation(format!("There is no order {}", id)));
+	},
+	order => order?,
+    };
+
+    order.delete(connection, Local::now().naive_local())?;
+    writeln!(out, "Deleted order {}. Restore it with: as91896 orders restore {}", id, id)?;
+
+    Ok(())
+}
+
+/// Prints the orders that were deleted but can still be restored.
+fn list_deleted_orders<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let orders = Order::get_deleted(connection)?;
+    let today = Local::now().date_naive();
+    let deleted_at = |order: &Order| {
+	order.deleted_at.map(|d| d.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default()
+    };
+
+    if args.flag("json") {
+	let orders: Vec<Value> = orders.iter().map(|order| {
+	    let mut json = order_json(order, today);
+	    json["deleted_at"] = json!(deleted_at(order));
+	    json
+	}).collect();
+	return write_json(out, &Value::Array(orders));
+    }
+
+    let rows: Vec<Vec<String>> = orders.iter().map(|order| {
+	let mut row = order_row(order, today);
+	row.push(deleted_at(order));
+	row
+    }).collect();
+    write_table(
+	out,
+	&["ID", "Customer", "Receipt", "Item", "How Many", "Hired On", "Return On", "Boxes", "Raffle", "Status", "Paid", "Deleted At"],
+	&rows,
+    )
+}
+
+/// Restores the recently deleted order with the given id.
+fn restore_order<W: Write>(connection: &Connection, id: &str, out: &mut W) -> Result<(), Error> {
+    let order = Order::restore(connection, parse_order_id(id)?)?;
+    writeln!(out, "Restored order {}", order.id)?;
+
+    Ok(())
+}
+
+/// Parses an order id given on the command line.
+fn parse_order_id(id: &str) -> Result<i32, Error> {
+    id.parse().map_err(|_| Error::Validation(format!("Order id must be an integer, not {}", id)))
+}
+
+/// Saves the docket for an order as a PDF, next to the database unless a file is given.
+fn save_docket<W: Write>(connection: &Connection, id: &str, args: &Args, out: &mut W) -> Result<(), Error> {
+    let order = Order::get_by_id(connection, parse_order_id(id)?)?;
+    let file = args.option("file").map(PathBuf::from);
+
+    let path = docket::save_docket(connection, &order, file.as_deref(), Local::now().date_naive())?;
+    writeln!(out, "Saved the docket for order {} to {}", order.id, path.display())?;
+
+    Ok(())
+}
+
+/// Prints what an order costs, line by line, and the payments made towards it.
+fn show_payments<W: Write>(connection: &Connection, id: &str, args: &Args, out: &mut W) -> Result<(), Error> {
+    let order = Order::get_by_id(connection, parse_order_id(id)?)?;
+    let payments = Payment::get_for_order(connection, order.id)?;
+    let late_fee = LateFee::get_for_order(connection, order.id)?;
+
+    if args.flag("json") {
+	return write_json(out, &json!({
+	    "order_id": order.id,
+	    "hire_days": order.hire_days(),
+	    "hire_charge_cents": order.hire_charge(),
+	    "deposit_cents": order.deposit,
+	    "damage_charge_cents": order.damage_charge,
+	    "late_fee": late_fee.as_ref().map(late_fee_json),
+	    "amount_due_cents": order.amount_due(),
+	    "paid_cents": order.paid,
+	    "balance_cents": order.balance(),
+	    "payments": payments.iter().map(payment_json).collect::<Vec<Value>>(),
+	}));
+    }
+
+    writeln!(out, "Order {} for {}", order.id, order.customer_name)?;
+    for line in &order.lines {
+	writeln!(
+	    out,
+	    "{} x{} at {} a day for {} day(s): {}",
+	    line.item_hired,
+	    line.how_many,
+	    format_price(line.daily_rate),
+	    order.hire_days(),
+	    format_price(line.charge(order.hire_days())),
+	)?;
+    }
+    writeln!(out, "Hire charge: {}", format_price(order.hire_charge()))?;
+    writeln!(out, "Deposit: {}", format_price(order.deposit))?;
+    writeln!(out, "Damage charge: {}", format_price(order.damage_charge))?;
+    if let Some(late_fee) = &late_fee {
+	writeln!(out, "{}", late_fee.description())?;
+    }
+    writeln!(
+	out,
+	"Due: {}   Paid: {}   {}",
+	format_price(order.amount_due()),
+	format_price(order.paid),
+	describe_balance(order.balance()),
+    )?;
+
+    if payments.is_empty() {
+	writeln!(out, "Nothing has been paid yet")?;
+	return Ok(());
+    }
+
+    writeln!(out)?;
+    let rows: Vec<Vec<String>> = payments.iter().map(|payment| vec![
+	payment.id.to_string(),
+	payment.paid_on.format("%Y-%m-%d").to_string(),
+	format_price(payment.amount),
+	payment.method.to_string(),
+	payment.note.clone(),
+    ]).collect();
+    write_table(out, &["ID", "Paid On", "Amount", "Method", "Note"], &rows)
+}
+
+/// Records a payment towards an order, or a refund with `--refund`, checking
+/// it the same way the payments screen does. It is paid today in cash unless
+/// told otherwise.
+fn record_payment<W: Write>(connection: &Connection, id: &str, args: &Args, out: &mut W) -> Result<(), Error> {
+    let order_id = parse_order_id(id)?;
+    let mut form = PaymentForm::new(Local::now().date_naive());
+    form.amount = args.get("amount");
+    form.refund = args.flag("refund");
+    form.note = args.get("note");
+    if let Some(paid_on) = args.option("on") {
+	form.paid_on = paid_on.clone();
+    }
+    if let Some(method) = args.option("method") {
+	form.method = PaymentMethod::parse(method).ok_or_else(|| {
+	    let methods: Vec<&str> = PaymentMethod::ALL.iter().map(|m| m.as_str()).collect();
+	    Error::Validation(format!("--method must be one of: {}", methods.join(", ")))
+	})?;
+    }
+
+    let payment = form.save(connection, order_id)?;
+    let order = Order::get_by_id(connection, order_id)?;
+    writeln!(
+	out,
+	"Recorded {} by {} for order {}. {}",
+	format_price(payment.amount),
+	payment.method,
+	order.id,
+	describe_balance(order.balance()),
+    )?;
+
+    Ok(())
+}
+
+/// Changes the deposit and damage charge on an order, keeping the ones that
+/// aren't given.
+fn set_charges<W: Write>(connection: &Connection, id: &str, args: &Args, out: &mut W) -> Result<(), Error> {
+    let order = Order::get_by_id(connection, parse_order_id(id)?)?;
+    let mut form = ChargesForm::from_order(&order);
+    if let Some(deposit) = args.option("deposit") {
+	form.deposit = deposit.clone();
+    }
+    if let Some(damage_charge) = args.option("damage") {
+	form.damage_charge = damage_charge.clone();
+    }
+
+    let order = form.save(connection, &order)?;
+    writeln!(
+	out,
+	"Order {} has a deposit of {} and a damage charge of {}. {}",
+	order.id,
+	format_price(order.deposit),
+	format_price(order.damage_charge),
+	describe_balance(order.balance()),
+    )?;
+
+    Ok(())
+}
+
+/// Waives the late fee on an order, recording why.
+fn waive_late_fee<W: Write>(connection: &Connection, id: &str, args: &Args, out: &mut W) -> Result<(), Error> {
+    let order = Order::get_by_id(connection, parse_order_id(id)?)?;
+    let late_fee = LateFee::get_for_order(connection, order.id)?
+	.ok_or_else(|| Error::Validation(format!("Order {} wasn't charged a late fee", order.id)))?;
+
+    late_fee.waive(connection, &args.get("reason"), Local::now().naive_local())?;
+    let order = Order::get_by_id(connection, order.id)?;
+    writeln!(
+	out,
+	"Waived the late fee of {} on order {}. {}",
+	format_price(late_fee.amount),
+	order.id,
+	describe_balance(order.balance()),
+    )?;
+
+    Ok(())
+}
+
+/// Writes the orders matching the filters as CSV, to a file or `out`.
+fn export_orders<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let orders = Order::query(connection, &parse_query(args)?)?;
+
+    match args.option("file") {
+	Some(path) => export_csv(&orders, File::create(path)?),
+	None => export_csv(&orders, out),
+    }
+}
+
+/// Prints every customer and how to contact them.
+fn list_customers<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let customers = Customer::get_all(connection)?;
+
+    if args.flag("json") {
+	let customers: Vec<Value> = customers.iter().map(customer_json).collect();
+	return write_json(out, &Value::Array(customers));
+    }
+
+    let rows: Vec<Vec<String>> = customers.iter().map(|customer| vec![
+	customer.id.to_string(),
+	customer.name.clone(),
+	customer.phone.clone(),
+	customer.email.clone(),
+	customer.address.clone(),
+    ]).collect();
+
+    write_table(out, &["ID", "Name", "Phone", "Email", "Address"], &rows)
+}
+
+/// Adds a customer, checking it the same way the Customers tab does.
+fn add_customer<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let form = CustomerForm {
+	name: args.get("name"),
+	phone: args.get("phone"),
+	email: args.get("email"),
+	address: args.get("address"),
+	notes: args.get("notes"),
+	..Default::default()
+    };
+
+    let customer = form.save(connection)?;
+
+    if args.flag("json") {
+	return write_json(out, &customer_json(&customer));
+    }
+
+    writeln!(out, "Added customer {} ({})", customer.id, customer.name)?;
+
+    Ok(())
+}
+
+/// Prints the item catalogue.
+fn list_items<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let items = Item::get_all(connection)?;
+
+    if args.flag("json") {
+	let items: Vec<Value> = items.iter().map(item_json).collect();
+	return write_json(out, &Value::Array(items));
+    }
+
+    let rows: Vec<Vec<String>> = items.iter().map(|item| vec![
+	item.id.to_string(),
+	item.name.clone(),
+	item.total_stock.to_string(),
+	item.box_capacity.to_string(),
+	format_price(item.hire_price),
+    ]).collect();
+
+    write_table(out, &["ID", "Name", "Stock", "Per Box", "Price / Day"], &rows)
+}
+
+/// Adds an item to the catalogue, checking it the same way the Items tab does.
+fn add_item<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let form = ItemForm {
+	name: args.get("name"),
+	total_stock: args.get("stock"),
+	box_capacity: args.get("per-box"),
+	hire_price: args.get("price"),
+	..Default::default()
+    };
+
+    let item = form.save(connection)?;
+
+    if args.flag("json") {
+	return write_json(out, &item_json(&item));
+    }
+
+    writeln!(out, "Added item {} ({})", item.id, item.name)?;
+
+    Ok(())
+}
+
+/// Prints the packing plan for the pickups over the next few days, a week from
+/// today by default.
+fn print_packing_plan<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let from = parse_date_option(args, "from")?.unwrap_or_else(|| Local::now().date_naive());
+    let days = match args.option("days") {
+	Some(days) => match days.parse::<u64>() {
+	    Ok(days) if (1..=366).contains(&days) => days,
+	    _ => return Err(Error::Validation("--days must be a number from 1 to 366".to_string())),
+	},
+	None => PACKING_PLAN_DAYS,
+    };
+
+    let plan = PackingPlan::build(connection, from, from + Days::new(days - 1))?;
+
+    write!(out, "{}", plan.to_text())?;
+
+    Ok(())
+}
+
+/// Draws the raffle, checking it the same way the Raffle tab does.
+fn draw_raffle<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let mut form = RaffleDrawForm {
+	prize_name: args.get("prize"),
+	..Default::default()
+    };
+    if let Some(winners) = args.option("winners") {
+	form.winners = winners.clone();
+    }
+
+    let draws = form.draw(connection, Local::now().naive_local())?;
+
+    if args.flag("json") {
+	let draws: Vec<Value> = draws.iter().map(draw_json).collect();
+	return write_json(out, &Value::Array(draws));
+    }
+
+    let rows: Vec<Vec<String>> = draws.iter().map(|draw| vec![
+	draw.position.to_string(),
+	draw.winner_name.clone().unwrap_or_default(),
+	draw.winner_raffle_number.map(|n| n.to_string()).unwrap_or_default(),
+	draw.winner_order_id.to_string(),
+    ]).collect();
+
+    write_table(out, &["Place", "Winner", "Raffle", "Order"], &rows)
+}
+
+/// Prints the backups of the database, newest first.
+fn list_backups<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let backups = backup::list_backups(&get_backup_dir(connection)?)?;
+
+    if args.flag("json") {
+	let backups: Vec<Value> = backups.iter().map(backup_json).collect();
+	return write_json(out, &Value::Array(backups));
+    }
+
+    let rows: Vec<Vec<String>> = backups.iter().map(|backup| vec![
+	backup.name(),
+	backup.taken_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+	backup.order_count.map(|count| count.to_string()).unwrap_or("Unreadable".to_string()),
+	format!("{} KB", backup.size.div_ceil(1024)),
+    ]).collect();
+
+    write_table(out, &["File", "Taken At", "Orders", "Size"], &rows)
+}
+
+/// Backs up the database, clearing out old backups the same way the app does.
+fn create_backup<W: Write>(connection: &Connection, out: &mut W) -> Result<(), Error> {
+    let dir = get_backup_dir(connection)?;
+    let backup = backup::create_backup(connection, &dir, Local::now().naive_local())?;
+    backup::rotate_backups(connection, &dir)?;
+    writeln!(out, "Backed up {} order(s) to {}", backup.order_count.unwrap_or(0), backup.path.display())?;
+
+    Ok(())
+}
+
+/// Restores a backup, given by its file name in the backups folder or its path.
+fn restore_backup<W: Write>(connection: &Connection, file: &str, out: &mut W) -> Result<(), Error> {
+    let path = match Path::new(file).exists() {
+	true => PathBuf::from(file),
+	false => get_backup_dir(connection)?.join(file),
+    };
+
+    let before_restore = backup::restore_backup(connection, &path, Local::now().naive_local())?;
+    writeln!(out, "Restored {}", path.display())?;
+    writeln!(out, "The database as it was before is in {}", before_restore.path.display())?;
+
+    Ok(())
+}
+
+/// Prints the orders that are late back, most overdue first.
+fn list_overdue<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let today = Local::now().date_naive();
+    let overdue = OverdueOrder::get_all(connection, today)?;
+
+    if args.flag("json") {
+	let overdue = overdue.iter().map(|overdue_order| {
+	    let reminders = Reminder::get_for_order(connection, overdue_order.order.id)?;
+	    Ok(overdue_json(overdue_order, &reminders, today))
+	}).collect::<Result<Vec<Value>, Error>>()?;
+	return write_json(out, &Value::Array(overdue));
+    }
+
+    let rows: Vec<Vec<String>> = overdue.iter().map(|overdue_order| vec![
+	overdue_order.order.id.to_string(),
+	overdue_order.order.receipt_number.to_string(),
+	overdue_order.customer.name.clone(),
+	overdue_order.customer.phone.clone(),
+	overdue_order.customer.email.clone(),
+	overdue_order.order.items_hired(),
+	overdue_order.order.return_on.format("%Y-%m-%d").to_string(),
+	overdue_order.days_overdue.to_string(),
+	overdue_order.last_reminded
+	    .map(|reminded| reminded.format("%Y-%m-%d").to_string())
+	    .unwrap_or("Never".to_string()),
+    ]).collect();
+
+    write_table(
+	out,
+	&["ID", "Receipt", "Customer", "Phone", "Email", "Items", "Due Back", "Days Late", "Reminded"],
+	&rows,
+    )
+}
+
+/// Writes reminders for the late orders whose customers haven't been reminded
+/// lately, as emails unless --text is given.
+fn write_reminders<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let now = Local::now().naive_local();
+    let overdue = OverdueOrder::get_all(connection, now.date())?;
+    let method = match args.flag("text") {
+	true => ReminderMethod::TextFile,
+	false => ReminderMethod::EmailFile,
+    };
+    let dir = args.option("dir").map(PathBuf::from);
+
+    let written = reminder::write_reminders(connection, &overdue, method, dir.as_deref(), now)?;
+    for reminder in &written {
+	writeln!(out, "Reminded {} about order {}: {}", reminder.customer_name, reminder.order_id, reminder.path.display())?;
+    }
+    writeln!(out, "Wrote {} reminder(s) for {} late order(s)", written.len(), overdue.len())?;
+
+    Ok(())
+}
+
+/// Changes the given email settings, checking them the same way the Email tab
+/// does.
+fn setup_email<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let mut form = SmtpForm::load(connection)?;
+    for (option, field) in [("host", "host"), ("port", "port"), ("user", "username"), ("from", "from")] {
+	if let Some(value) = args.option(option) {
+	    form.set_value(field, value.clone());
+	}
+    }
+    if args.flag("password-stdin") {
+	form.set_value("password", read_password(io::stdin().lock())?);
+    }
+    if let Some(security) = args.option("security") {
+	form.security = SmtpSecurity::parse(security)
+	    .ok_or_else(|| Error::Validation("--security must be none, starttls or tls".to_string()))?;
+    }
+
+    let settings = form.save(connection)?;
+    match settings.is_configured() {
+	true => writeln!(out, "Reminder emails are sent through {}:{}", settings.host, settings.port)?,
+	false => writeln!(out, "Reminder emails are off until a --host is given")?,
+    }
+
+    Ok(())
+}
+
+/// Reads a password from the first line of `input`, rather than the command
+/// line, where it would be kept in the shell history and seen by other users.
+fn read_password<R: BufRead>(mut input: R) -> Result<String, Error> {
+    let mut password = String::new();
+    input.read_line(&mut password)?;
+
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Prints the reminder emails, newest first.
+fn list_emails<W: Write>(connection: &Connection, args: &Args, out: &mut W) -> Result<(), Error> {
+    let emails = OutgoingEmail::get_all(connection)?;
+    let max_attempts = EMAIL_MAX_ATTEMPTS.get(connection)?;
+
+    if args.flag("json") {
+	let emails: Vec<Value> = emails.iter().map(|email| email_json(email, max_attempts)).collect();
+	return write_json(out, &Value::Array(emails));
+    }
+
+    let rows: Vec<Vec<String>> = emails.iter().map(|email| vec![
+	email.id.to_string(),
+	email.order_id.to_string(),
+	email.kind.to_string(),
+	email.to_address.clone(),
+	email.queued_at.format("%Y-%m-%d %H:%M").to_string(),
+	email.describe_status(max_attempts),
+    ]).collect();
+
+    write_table(out, &["ID", "Order", "Reminder", "To", "Queued", "Status"], &rows)
+}
+
+/// Queues the reminder emails that are due and sends them.
+fn send_emails<W: Write>(connection: &Connection, out: &mut W) -> Result<(), Error> {
+    match email::send_reminders(connection, Local::now().naive_local())? {
+	Some(summary) => writeln!(
+	    out,
+	    "Queued {} email(s), sent {} and {} failed",
+	    summary.queued,
+	    summary.sent,
+	    summary.failed,
+	)?,
+	None => return Err(Error::Validation("Email isn't set up yet, see email setup".to_string())),
+    }
+
+    Ok(())
+}
+
+/// Sends an email that was given up on again.
+fn retry_email<W: Write>(connection: &Connection, id: &str, out: &mut W) -> Result<(), Error> {
+    let id = id.parse().map_err(|_| Error::Validation(format!("{} isn't an email ID", id)))?;
+    OutgoingEmail::retry(connection, id, Local::now().naive_local())?;
+    writeln!(out, "Email {} will be sent again the next time emails are sent", id)?;
+
+    Ok(())
+}
+
+/// Gets the folder backups are kept in, or Err for a database only in memory.
+fn get_backup_dir(connection: &Connection) -> Result<PathBuf, Error> {
+    backup::backup_dir(connection)
+	.ok_or_else(|| Error::Validation("The database isn't kept in a file, so it has no backups".to_string()))
+}
+
+/// Builds the query for the filters given on the command line.
+fn parse_query(args: &Args) -> Result<OrderQuery, Error> {
+    let mut query = OrderQuery {
+	search: args.get("search"),
+	item: args.option("item").cloned(),
+	from: parse_date_option(args, "from")?,
+	to: parse_date_option(args, "to")?,
+	..Default::default()
+    };
+
+    if let Some(status) = args.option("status") {
+	let filter = StatusFilter::ALL.into_iter()
+	    .find(|filter| filter.to_string().eq_ignore_ascii_case(status))
+	    .ok_or_else(|| {
+		let statuses: Vec<String> = StatusFilter::ALL.iter().map(|f| f.to_string()).collect();
+		Error::Validation(format!("--status must be one of: {}", statuses.join(", ")))
+	    })?;
+	query.status = Some(filter);
+    }
+
+    Ok(query)
+}
+
+/// Parses a date option, or None if it wasn't given.
+fn parse_date_option(args: &Args, name: &str) -> Result<Option<NaiveDate>, Error> {
+    match args.option(name) {
+	Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+	    .map(Some)
+	    .map_err(|_| Error::Validation(format!("--{} must be formatted as YYYY-MM-DD e.g. 2025-03-18", name))),
+	None => Ok(None),
+    }
+}
+
+/// Gets the status to show for an order, which is Overdue if it should be back by now.
+fn status_text(order: &Order, today: NaiveDate) -> String {
+    if order.is_overdue(today) {
+	"Overdue".to_string()
+    } else {
+	order.status.to_string()
+    }
+}
+
+/// Gets an order as a row of the orders table.
+fn order_row(order: &Order, today: NaiveDate) -> Vec<String> {
+    vec![
+	order.id.to_string(),
+	order.customer_name.clone(),
+	order.receipt_number.to_string(),
+	order.items_hired(),
+	order.how_many().to_string(),
+	order.hired_on.format("%Y-%m-%d").to_string(),
+	order.return_on.format("%Y-%m-%d").to_string(),
+	order.boxes_needed.to_string(),
+	order.raffle_number.to_string(),
+	status_text(order, today),
+	describe_balance(order.balance()),
+    ]
+}
+
+/// Gets an order as a JSON object.
+fn order_json(order: &Order, today: NaiveDate) -> Value {
+    json!({
+	"id": order.id,
+	"customer_id": order.customer_id,
+	"customer_name": order.customer_name,
+	"receipt_number": order.receipt_number,
+	"lines": order.lines.iter().map(|line| json!({
+	    "item_hired": line.item_hired,
+	    "how_many": line.how_many,
+	    "boxes_needed": line.boxes_needed,
+	    "daily_rate_cents": line.daily_rate,
+	})).collect::<Vec<Value>>(),
+	"how_many": order.how_many(),
+	"hired_on": order.hired_on.format("%Y-%m-%d").to_string(),
+	"return_on": order.return_on.format("%Y-%m-%d").to_string(),
+	"boxes_needed": order.boxes_needed,
+	"raffle_number": order.raffle_number,
+	"status": order.status.as_str(),
+	"overdue": order.is_overdue(today),
+	"returned_at": order.returned_at.map(|r| r.format("%Y-%m-%d %H:%M:%S").to_string()),
+	"hire_charge_cents": order.hire_charge(),
+	"deposit_cents": order.deposit,
+	"damage_charge_cents": order.damage_charge,
+	"late_fee_cents": order.late_fee,
+	"paid_cents": order.paid,
+	"balance_cents": order.balance(),
+    })
+}
+
+/// Gets a late fee as a JSON object.
+fn late_fee_json(late_fee: &LateFee) -> Value {
+    json!({
+	"id": late_fee.id,
+	"days_late": late_fee.days_late,
+	"amount_cents": late_fee.amount,
+	"charged_at": late_fee.charged_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+	"waived_at": late_fee.waived_at.map(|w| w.format("%Y-%m-%d %H:%M:%S").to_string()),
+	"waive_reason": late_fee.waive_reason,
+    })
+}
+
+/// Gets a payment as a JSON object.
+fn payment_json(payment: &Payment) -> Value {
+    json!({
+	"id": payment.id,
+	"order_id": payment.order_id,
+	"amount_cents": payment.amount,
+	"method": payment.method.as_str(),
+	"paid_on": payment.paid_on.format("%Y-%m-%d").to_string(),
+	"note": payment.note,
+    })
+}
+
+/// Gets a customer as a JSON object.
+fn customer_json(customer: &Customer) -> Value {
+    json!({
+	"id": customer.id,
+	"name": customer.name,
+	"phone": customer.phone,
+	"email": customer.email,
+	"address": customer.address,
+	"notes": customer.notes,
+    })
+}
+
+/// Gets an item as a JSON object.
+fn item_json(item: &Item) -> Value {
+    json!({
+	"id": item.id,
+	"name": item.name,
+	"total_stock": item.total_stock,
+	"box_capacity": item.box_capacity,
+	"hire_price_cents": item.hire_price,
+    })
+}
+
+/// Gets a raffle winner as a JSON object.
+fn draw_json(draw: &RaffleDraw) -> Value {
+    json!({
+	"id": draw.id,
+	"drawn_at": draw.drawn_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+	"prize_name": draw.prize_name,
+	"position": draw.position,
+	"winner_order_id": draw.winner_order_id,
+	"winner_name": draw.winner_name,
+	"winner_raffle_number": draw.winner_raffle_number,
+	"seed": draw.seed,
+    })
+}
+
+/// Gets a backup as a JSON object.
+fn backup_json(backup: &BackupFile) -> Value {
+    json!({
+	"file": backup.name(),
+	"path": backup.path.display().to_string(),
+	"taken_at": backup.taken_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+	"order_count": backup.order_count,
+	"size": backup.size,
+    })
+}
+
+/// Gets a late order as a JSON object.
+fn overdue_json(overdue_order: &OverdueOrder, reminders: &[Reminder], today: NaiveDate) -> Value {
+    json!({
+	"order": order_json(&overdue_order.order, today),
+	"customer": customer_json(&overdue_order.customer),
+	"days_overdue": overdue_order.days_overdue,
+	"reminders": reminders.iter().map(|reminder| json!({
+	    "sent_at": reminder.sent_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+	    "method": reminder.method.as_str(),
+	})).collect::<Vec<Value>>(),
+    })
+}
+
+/// Gets a reminder email as a JSON object.
+fn email_json(email: &OutgoingEmail, max_attempts: i64) -> Value {
+    json!({
+	"id": email.id,
+	"order_id": email.order_id,
+	"kind": email.kind.as_str(),
+	"to": email.to_address,
+	"subject": email.subject,
+	"queued_at": email.queued_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+	"status": email.status(max_attempts).as_str(),
+	"attempts": email.attempts,
+	"next_attempt_at": email.next_attempt_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+	"last_error": email.last_error,
+	"sent_at": email.sent_at.map(|s| s.format("%Y-%m-%d %H:%M:%S").to_string()),
+    })
+}
+
+/// Writes a JSON value, pretty printed.
+fn write_json<W: Write>(out: &mut W, value: &Value) -> Result<(), Error> {
+    writeln!(out, "{:#}", value)?;
+
+    Ok(())
+}
+
+/// Writes rows as a table, with each column as wide as its widest value.
+fn write_table<W: Write>(out: &mut W, headers: &[&str], rows: &[Vec<String>]) -> Result<(), Error> {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+	for (width, cell) in widths.iter_mut().zip(row) {
+	    *width = (*width).max(cell.chars().count());
+	}
+    }
+
+    let format_row = |cells: Vec<&str>| -> String {
+	let cells: Vec<String> = cells.iter().zip(&widths)
+	    .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+	    .collect();
+	cells.join("  ").trim_end().to_string()
+    };
+
+    let mut table = vec![format_row(headers.to_vec())];
+    for row in rows {
+	table.push(format_row(row.iter().map(|c| c.as_str()).collect()));
+    }
+
+    writeln!(out, "{}", table.join("\n"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+    use crate::email::test_server::TestSmtpServer;
+    use crate::settings::{LATE_FEE_PER_DAY, MAX_DAYS_AGO};
+
+    use super::*;
+
+    /// Runs a command and returns what it printed.
+    fn run_command(connection: &Connection, args: &[&str]) -> Result<String, Error> {
+	let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+	let mut out = Vec::new();
+
+	run(connection, &args, &mut out)?;
+
+	Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn test_connection() -> Connection {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	// The tests hire in March 2025, which is long ago now
+	MAX_DAYS_AGO.set(&con, MAX_DAYS_AGO.max).unwrap();
+
+	run_command(&con, &[
+	    "items", "add", "--name", "Chairs", "--stock", "100", "--per-box", "25", "--price", "2.50",
+	]).unwrap();
+	run_command(&con, &[
+	    "orders", "add", "--customer", "Alice Smith", "--receipt", "1001", "--item", "Chairs",
+	    "--quantity", "40", "--from", "2025-03-01", "--to", "2025-03-03",
+	]).unwrap();
+
+	con
+    }
+
+    #[test]
+    fn test_args_parse() {
+	let args: Vec<String> = ["orders", "list", "--search", "Ali", "--json", "--item=Chairs"]
+	    .iter().map(|a| a.to_string()).collect();
+	let args = Args::parse(&args).unwrap();
+
+	assert_eq!(args.positional, vec!["orders".to_string(), "list".to_string()]);
+	assert_eq!(args.get("search"), "Ali".to_string());
+	assert_eq!(args.get("item"), "Chairs".to_string());
+	assert!(args.flag("json"));
+    }
+
+    #[test]
+    fn test_args_parse_missing_value() {
+	assert!(Args::parse(&["--search".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_unknown_command() {
+	let con = test_connection();
+
+	assert!(run_command(&con, &["orders", "fly"]).is_err());
+	assert!(run_command(&con, &["orders", "list", "--colour", "red"]).is_err());
+    }
+
+    #[test]
+    fn test_orders_list() {
+	let con = test_connection();
+
+	let output = run_command(&con, &["orders", "list"]).unwrap();
+	let lines: Vec<&str> = output.lines().collect();
+
+	assert_eq!(lines.len(), 2);
+	assert!(lines[0].starts_with("ID  Customer"));
+	assert!(lines[1].contains("Alice Smith"));
+    }
+
+    #[test]
+    fn test_orders_list_json() {
+	let con = test_connection();
+
+	let output = run_command(&con, &["orders", "list", "--json"]).unwrap();
+	let orders: Value = serde_json::from_str(&output).unwrap();
+
+	assert_eq!(orders[0]["customer_name"], "Alice Smith");
+	assert_eq!(orders[0]["how_many"], 40);
+	assert_eq!(orders[0]["lines"][0]["item_hired"], "Chairs");
+	assert_eq!(orders[0]["status"], "Booked");
+    }
+
+    #[test]
+    fn test_orders_list_filtered() {
+	let con = test_connection();
+
+	let output = run_command(&con, &["orders", "list", "--status", "cancelled", "--json"]).unwrap();
+	assert_eq!(serde_json::from_str::<Value>(&output).unwrap(), json!([]));
+
+	assert!(run_command(&con, &["orders", "list", "--status", "lost"]).is_err());
+	assert!(run_command(&con, &["orders", "list", "--from", "March"]).is_err());
+    }
+
+    #[test]
+    fn test_orders_add_invalid() {
+	let con = test_connection();
+
+	let error = run_command(&con, &["orders", "add", "--customer", "Bob Jones"]).err().unwrap().to_string();
+
+	assert!(error.contains("Receipt number is required"));
+	assert_eq!(Order::get_all(&con).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_orders_add_several_items() {
+	let con = test_connection();
+	run_command(&con, &[
+	    "items", "add", "--name", "Tables", "--stock", "10", "--per-box", "1", "--price", "15",
+	]).unwrap();
+
+	let output = run_command(&con, &[
+	    "orders", "add", "--customer", "Bob Jones", "--receipt", "1002",
+	    "--item", "Chairs", "--quantity", "10", "--item", "Tables", "--quantity", "2",
+	    "--from", "2025-03-02", "--to", "2025-03-04", "--json",
+	]).unwrap();
+	let order: Value = serde_json::from_str(&output).unwrap();
+
+	assert_eq!(order["lines"][1]["item_hired"], "Tables");
+	assert_eq!(order["how_many"], 12);
+	assert!(run_command(&con, &["orders", "add", "--item", "Chairs"]).is_err());
+    }
+
+    #[test]
+    fn test_orders_delete() {
+	let con = test_connection();
+
+	assert!(run_command(&con, &["orders", "delete", "1"]).is_ok());
+	assert!(Order::get_all(&con).unwrap().is_empty());
+	assert!(run_command(&con, &["orders", "delete", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_orders_delete_and_restore() {
+	let con = test_connection();
+
+	let output = run_command(&con, &["orders", "delete", "1"]).unwrap();
+	assert!(output.contains("orders restore 1"));
+
+	let output = run_command(&con, &["orders", "deleted", "--json"]).unwrap();
+	let deleted: Value = serde_json::from_str(&output).unwrap();
+	assert_eq!(deleted[0]["id"], 1);
+	assert!(deleted[0]["deleted_at"].as_str().is_some_and(|d| !d.is_empty()));
+
+	assert!(run_command(&con, &["orders", "restore", "1"]).is_ok());
+	assert_eq!(Order::get_all(&con).unwrap().len(), 1);
+	assert!(run_command(&con, &["orders", "restore", "1"]).is_err());
+	assert!(run_command(&con, &["orders", "restore", "one"]).is_err());
+    }
+
+    #[test]
+    fn test_orders_deleted() {
+	let con = test_connection();
+	run_command(&con, &["orders", "delete", "1"]).unwrap();
+	let deleted_at = Order::get_deleted(&con).unwrap()[0].deleted_at.unwrap();
+
+	let output = run_command(&con, &["orders", "deleted"]).unwrap();
+	let lines: Vec<&str> = output.lines().collect();
+
+	assert_eq!(lines.len(), 2);
+	let headers: Vec<&str> = lines[0].split_whitespace().collect();
+	assert!(headers.ends_with(&["Status", "Paid", "Deleted", "At"]));
+	assert!(lines[1].contains("Alice Smith"));
+	assert!(lines[1].ends_with(&deleted_at.format("%Y-%m-%d %H:%M:%S").to_string()));
+    }
+
+    #[test]
+    fn test_orders_export() {
+	let con = test_connection();
+
+	let output = run_command(&con, &["orders", "export"]).unwrap();
+
+	assert_eq!(output.lines().count(), 2);
+	assert!(output.lines().nth(1).unwrap().starts_with("Alice Smith,1001,Chairs,40"));
+    }
+
+    #[test]
+    fn test_customers_add_and_list() {
+	let con = test_connection();
+
+	let output = run_command(&con, &[
+	    "customers", "add", "--name", "Carol White", "--phone", "021 555 0100", "--email", "carol@example.com",
+	]).unwrap();
+	assert!(output.starts_with("Added customer"));
+	assert!(run_command(&con, &["customers", "add", "--name", "Dan", "--email", "dan"]).is_err());
+
+	let output = run_command(&con, &["customers", "list", "--json"]).unwrap();
+	let customers: Value = serde_json::from_str(&output).unwrap();
+	let carol = customers.as_array().unwrap().iter().find(|c| c["name"] == "Carol White").unwrap();
+	assert_eq!(carol["phone"], "021 555 0100");
+	assert_eq!(carol["email"], "carol@example.com");
+    }
+
+    #[test]
+    fn test_items_list_json() {
+	let con = test_connection();
+
+	let output = run_command(&con, &["items", "list", "--json"]).unwrap();
+	let items: Value = serde_json::from_str(&output).unwrap();
+
+	assert_eq!(items[0]["name"], "Chairs");
+	assert_eq!(items[0]["hire_price_cents"], 250);
+    }
+
+    #[test]
+    fn test_orders_add_not_enough_stock() {
+	let con = test_connection();
+
+	let error = run_command(&con, &[
+	    "orders", "add", "--customer", "Bob Jones", "--receipt", "1002", "--item", "Chairs",
+	    "--quantity", "61", "--from", "2025-03-02", "--to", "2025-03-04",
+	]).err().unwrap();
+
+	assert!(matches!(error, Error::Validation(_)));
+    }
+
+    #[test]
+    fn test_packing() {
+	let con = test_connection();
+
+	let output = run_command(&con, &["packing", "--from", "2025-03-01", "--days", "2"]).unwrap();
+
+	assert!(output.contains("Saturday 2025-03-01 - 2 box(es)"));
+	assert!(output.contains("Box 2: 15 Chairs"));
+	assert!(run_command(&con, &["packing", "--days", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_raffle_draw() {
+	let con = test_connection();
+
+	let output = run_command(&con, &["raffle", "draw", "--prize", "Hamper", "--json"]).unwrap();
+	let draws: Value = serde_json::from_str(&output).unwrap();
+
+	assert_eq!(draws[0]["winner_name"], "Alice Smith");
+	assert!(run_command(&con, &["raffle", "draw", "--prize", "Hamper"]).is_err());
+    }
+
+    #[test]
+    fn test_orders_add_reused_receipt() {
+	let con = test_connection();
+	let add = |extra: &[&str]| {
+	    let mut args = vec![
+		"orders", "add", "--customer", "Bob Jones", "--receipt", "1001", "--item", "Chairs",
+		"--quantity", "10", "--from", "2025-03-02", "--to", "2025-03-04",
+	    ];
+	    args.extend_from_slice(extra);
+	    run_command(&con, &args)
+	};
+
+	let error = add(&[]).err().unwrap().to_string();
+	assert!(error.contains("already used by Alice Smith's order"));
+	assert!(error.contains("--reuse-receipt"));
+	assert_eq!(Order::get_all(&con).unwrap().len(), 1);
+
+	assert!(add(&["--reuse-receipt"]).is_ok());
+	assert_eq!(Order::get_by_receipt_number(&con, 1001).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_orders_add_next_receipt() {
+	let con = test_connection();
+
+	let output = run_command(&con, &[
+	    "orders", "add", "--customer", "Bob Jones", "--receipt", "next", "--item", "Chairs",
+	    "--quantity", "10", "--from", "2025-03-02", "--to", "2025-03-04", "--json",
+	]).unwrap();
+	let order: Value = serde_json::from_str(&output).unwrap();
+
+	assert_eq!(order["receipt_number"], 1002);
+    }
+
+    #[test]
+    fn test_backups() {
+	let dir = std::env::temp_dir().join(format!("as91896-cli-backups-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	let con = Connection::open(dir.join("orders.db")).unwrap();
+	init_db_tables(&con).unwrap();
+	MAX_DAYS_AGO.set(&con, MAX_DAYS_AGO.max).unwrap();
+
+	let output = run_command(&con, &["backups", "create"]).unwrap();
+	assert!(output.starts_with("Backed up 0 order(s)"));
+
+	let output = run_command(&con, &["backups", "list", "--json"]).unwrap();
+	let backups: Value = serde_json::from_str(&output).unwrap();
+	let file = backups[0]["file"].as_str().unwrap().to_string();
+	assert_eq!(backups[0]["order_count"], 0);
+
+	run_command(&con, &["customers", "add", "--name", "Carol White"]).unwrap();
+	let output = run_command(&con, &["backups", "restore", &file]).unwrap();
+	assert!(output.contains("as it was before"));
+	assert!(Customer::get_all(&con).unwrap().is_empty());
+	assert!(run_command(&con, &["backups", "restore", "missing.db"]).is_err());
+
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert!(run_command(&test_connection(), &["backups", "list"]).is_err());
+    }
+
+    #[test]
+    fn test_take_database_options() {
+	let mut args: Vec<String> = ["--profile", "shop2", "orders", "list", "--db=/tmp/orders.db", "--json"]
+	    .iter().map(|a| a.to_string()).collect();
+
+	let options = take_database_options(&mut args).unwrap();
+	assert_eq!(options, (Some("/tmp/orders.db".to_string()), Some("shop2".to_string())));
+	assert_eq!(args, vec!["orders", "list", "--json"]);
+
+	let mut args = vec!["--profile".to_string()];
+	assert!(take_database_options(&mut args).is_err());
+	let mut args = vec!["--db=a.db".to_string(), "--db=b.db".to_string()];
+	assert!(take_database_options(&mut args).is_err());
+    }
+
+    #[test]
+    fn test_orders_docket() {
+	let con = test_connection();
+	let path = std::env::temp_dir().join(format!("as91896-cli-docket-{}.pdf", std::process::id()));
+
+	let output = run_command(&con, &["orders", "docket", "1", "--file", path.to_str().unwrap()]).unwrap();
+	assert_eq!(output, format!("Saved the docket for order 1 to {}\n", path.display()));
+	assert!(std::fs::read(&path).unwrap().starts_with(b"%PDF"));
+
+	assert!(run_command(&con, &["orders", "docket", "999"]).is_err());
+	std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_overdue() {
+	let con = test_connection();
+
+	let output = run_command(&con, &["overdue"]).unwrap();
+	assert!(output.contains("Alice Smith"));
+	assert!(output.contains("2025-03-03"));
+	assert!(output.contains("Never"));
+
+	let output: Value = serde_json::from_str(&run_command(&con, &["overdue", "--json"]).unwrap()).unwrap();
+	assert_eq!(output[0]["customer"]["name"], "Alice Smith");
+	assert_eq!(output[0]["order"]["how_many"], 40);
+	assert!(output[0]["days_overdue"].as_i64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_overdue_remind() {
+	let con = test_connection();
+	let dir = std::env::temp_dir().join(format!("as91896-cli-reminders-{}", std::process::id()));
+	let dir_arg = dir.to_str().unwrap();
+
+	let output = run_command(&con, &["overdue", "remind", "--text", "--dir", dir_arg]).unwrap();
+	assert!(output.starts_with("Reminded Alice Smith about order 1: "));
+	assert!(output.ends_with("Wrote 1 reminder(s) for 1 late order(s)\n"));
+	assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+	// Alice was just reminded, so isn't again
+	let output = run_command(&con, &["overdue", "remind", "--dir", dir_arg]).unwrap();
+	assert_eq!(output, "Wrote 0 reminder(s) for 1 late order(s)\n");
+	assert!(run_command(&con, &["overdue"]).unwrap().contains(&Local::now().format("%Y-%m-%d").to_string()));
+	let output: Value = serde_json::from_str(&run_command(&con, &["overdue", "--json"]).unwrap()).unwrap();
+	assert_eq!(output[0]["reminders"][0]["method"], "Text file");
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_email() {
+	let con = test_connection();
+	let server = TestSmtpServer::start();
+	let port = server.port.to_string();
+
+	assert!(run_command(&con, &["email", "send"]).is_err());
+	assert!(run_command(&con, &["email", "setup", "--host", "127.0.0.1", "--security", "ssl"]).is_err());
+	let output = run_command(&con, &[
+	    "email", "setup", "--host", "127.0.0.1", "--port", &port, "--security", "none",
+	    "--from", "Party Hire <hire@example.com>",
+	]).unwrap();
+	assert_eq!(output, format!("Reminder emails are sent through 127.0.0.1:{}\n", port));
+
+	// Alice has no email address, so only Bob is reminded
+	run_command(&con, &["customers", "add", "--name", "Bob Jones", "--email", "bob@example.com"]).unwrap();
+	run_command(&con, &[
+	    "orders", "add", "--customer", "Bob Jones", "--receipt", "1002", "--item", "Chairs",
+	    "--quantity", "10", "--from", "2025-03-01", "--to", "2025-03-03",
+	]).unwrap();
+	let output = run_command(&con, &["email", "send"]).unwrap();
+	assert_eq!(output, "Queued 1 email(s), sent 1 and 0 failed\n");
+	assert_eq!(server.received()[0].to, vec!["bob@example.com".to_string()]);
+
+	let output = run_command(&con, &["email", "queue"]).unwrap();
+	assert!(output.contains("Overdue"));
+	assert!(output.contains("Bob Jones <bob@example.com>"));
+	let output: Value = serde_json::from_str(&run_command(&con, &["email", "queue", "--json"]).unwrap()).unwrap();
+	assert_eq!(output[0]["status"], "Sent");
+
+	assert!(run_command(&con, &["email", "retry", "1"]).is_err());
+	assert!(run_command(&con, &["email", "setup", "--password", "secret"]).is_err());
+    }
+
+    #[test]
+    fn test_read_password() {
+	assert_eq!(read_password("pass word\r\nnext line\n".as_bytes()).unwrap(), "pass word".to_string());
+	assert_eq!(read_password("secret".as_bytes()).unwrap(), "secret".to_string());
+	assert_eq!(read_password("".as_bytes()).unwrap(), String::new());
+    }
+
+    #[test]
+    fn test_orders_payments() {
+	let con = test_connection();
+
+	// 40 chairs at $2.50 a day for 2 days
+	let output = run_command(&con, &["orders", "payments", "1"]).unwrap();
+	assert!(output.contains("Chairs x40 at $2.50 a day for 2 day(s): $200.00"));
+	assert!(output.contains("Owes $200.00"));
+
+	let output = run_command(&con, &["orders", "charges", "1", "--deposit", "50"]).unwrap();
+	assert_eq!(output, "Order 1 has a deposit of $50.00 and a damage charge of $0.00. Owes $250.00\n");
+
+	assert!(run_command(&con, &["orders", "pay", "1", "--amount", "20", "--method", "cheque"]).is_err());
+	assert!(run_command(&con, &["orders", "pay", "1", "--amount", "-20"]).is_err());
+	let output = run_command(&con, &[
+	    "orders", "pay", "1", "--amount", "250", "--method", "bank transfer", "--on", "2025-03-01",
+	]).unwrap();
+	assert_eq!(output, "Recorded $250.00 by Bank transfer for order 1. Paid\n");
+	let output = run_command(&con, &["orders", "pay", "1", "--amount", "5", "--refund", "--note", "Early return"]).unwrap();
+	assert_eq!(output, "Recorded -$5.00 by Cash for order 1. Owes $5.00\n");
+
+	let output: Value = serde_json::from_str(&run_command(&con, &["orders", "payments", "1", "--json"]).unwrap()).unwrap();
+	assert_eq!(output["paid_cents"], 24500);
+	assert_eq!(output["balance_cents"], 500);
+	assert_eq!(output["payments"][1]["note"], "Early return");
+	assert!(run_command(&con, &["orders", "list"]).unwrap().contains("Owes $5.00"));
+    }
+
+    #[test]
+    fn test_orders_waive_fee() {
+	let con = test_connection();
+	LATE_FEE_PER_DAY.set(&con, 500).unwrap();
+
+	// Due back 2025-03-03 and returned a day later
+	let order = Order::get_by_id(&con, 1).unwrap().mark_out(&con).unwrap();
+	order.mark_returned(&con, NaiveDate::from_ymd_opt(2025, 3, 4).unwrap().and_hms_opt(9, 0, 0).unwrap()).unwrap();
+
+	let output = run_command(&con, &["orders", "payments", "1"]).unwrap();
+	assert!(output.contains("Late fee for 1 day(s) late: $5.00"));
+	assert!(output.contains("Owes $205.00"));
+
+	assert!(run_command(&con, &["orders", "waive-fee", "1"]).is_err());
+	let output = run_command(&con, &["orders", "waive-fee", "1", "--reason", "Shop was closed"]).unwrap();
+	assert_eq!(output, "Waived the late fee of $5.00 on order 1. Owes $200.00\n");
+
+	let output: Value = serde_json::from_str(&run_command(&con, &["orders", "payments", "1", "--json"]).unwrap()).unwrap();
+	assert_eq!(output["late_fee"]["waive_reason"], "Shop was closed");
+	assert!(run_command(&con, &["orders", "waive-fee", "1", "--reason", "Again"]).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde_json::{json, Map, Value};
+use crate::database::{data_dir, DB_FILE_NAME};
+use crate::error::Error;
+
+/// The profile used when no other is chosen, which keeps the database where
+/// it has always been.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Represents the config file, which says where each profile's database is
+/// and which profile was used last. Looks like:
+///
+/// ```json
+/// {
+///     "profile": "shop2",
+///     "profiles": {
+///         "shop2": "/mnt/shared/shop2/orders.db"
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    // The profile to open when none is given
+    pub profile: Option<String>,
+    // The database of each profile. The default profile only needs to be here
+    // if its database has been moved.
+    pub profiles: BTreeMap<String, PathBuf>,
+}
+
+/// Represents the database the program is using, and the profile it belongs to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    // The name of the profile, or the path if the database was given with --db
+    pub name: String,
+    pub db_path: PathBuf,
+}
+
+/// Gets the path to the config file.
+pub fn config_path() -> Result<PathBuf, Error> {
+    Ok(data_dir()?.join("config.json"))
+}
+
+impl Config {
+    /// Reads the config file. A missing file is the same as an empty one.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+	let contents = match fs::read_to_string(path) {
+	    Ok(contents) => contents,
+	    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+	    Err(e) => return Err(e.into()),
+	};
+
+	Self::parse(&contents).map_err(|message| {
+	    Error::Parse(format!("{}: {}", path.display(), message))
+	})
+    }
+
+    /// Reads the config from the contents of the config file.
+    fn parse(contents: &str) -> Result<Self, String> {
+	let value: Value = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+	let Some(object) = value.as_object() else {
+	    return Err("expected an object".to_string());
+	};
+
+	let mut config = Self::default();
+
+	match object.get("profile") {
+	    None | Some(Value::Null) => (),
+	    Some(Value::String(profile)) => config.profile = Some(profile.clone()),
+	    Some(_) => return Err("\"profile\" must be the name of a profile".to_string()),
+	}
+
+	match object.get("profiles") {
+	    None | Some(Value::Null) => (),
+	    Some(Value::Object(profiles)) => {
+		for (name, db_path) in profiles {
+		    let Some(db_path) = db_path.as_str() else {
+			return Err(format!("the database of profile \"{}\" must be a path", name));
+		    };
+		    config.profiles.insert(name.clone(), PathBuf::from(db_path));
+		}
+	    },
+	    Some(_) => return Err("\"profiles\" must map names to database paths".to_string()),
+	}
+
+	Ok(config)
+    }
+
+    /// Writes the config file, making its folder if needed.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+	if let Some(dir) = path.parent() {
+	    fs::create_dir_all(dir)?;
+	}
+
+	let profiles: Map<String, Value> = self.profiles.iter()
+	    .map(|(name, db_path)| (name.clone(), json!(db_path.to_string_lossy())))
+	    .collect();
+	let value = json!({
+	    "profile": self.profile,
+	    "profiles": profiles,
+	});
+
+	fs::write(path, serde_json::to_string_pretty(&value).unwrap_or_default())?;
+
+	Ok(())
+    }
+
+    /// Gets the names of all the profiles, with the default one first.
+    pub fn profile_names(&self) -> Vec<String> {
+	let mut names = vec![DEFAULT_PROFILE.to_string()];
+	names.extend(self.profiles.keys().filter(|name| *name != DEFAULT_PROFILE).cloned());
+	names
+    }
+
+    /// Gets a profile by its name.
+    pub fn get_profile(&self, name: &str) -> Result<Profile, Error> {
+	let db_path = match self.profiles.get(name) {
+	    Some(db_path) => db_path.clone(),
+	    None if name == DEFAULT_PROFILE => data_dir()?.join(DB_FILE_NAME),
+	    None => return Err(Error::Validation(format!(
+		"There is no profile called \"{}\". The profiles are: {}",
+		name,
+		self.profile_names().join(", "),
+	    ))),
+	};
+
+	Ok(Profile { name: name.to_string(), db_path })
+    }
+
+    /// Works out which database to use from the --db and --profile options,
+    /// falling back to the profile used last.
+    pub fn choose_profile(&self, db: Option<&str>, profile: Option<&str>) -> Result<Profile, Error> {
+	match (db, profile) {
+	    (Some(_), Some(_)) => Err(Error::Validation("Give either --db or --profile, not both".to_string())),
+	    (Some(db), None) => Ok(Profile { name: db.to_string(), db_path: PathBuf::from(db) }),
+	    (None, Some(profile)) => self.get_profile(profile),
+	    (None, None) => match &self.profile {
+		Some(profile) if self.profiles.contains_key(profile) => self.get_profile(profile),
+		// The profile used last has been taken out of the config file
+		_ => self.get_profile(DEFAULT_PROFILE),
+	    },
+	}
+    }
+
+    /// Adds a profile. Without a database path, its database is kept in a
+    /// folder named after it next to the default one.
+    pub fn add_profile(&mut self, name: &str, db_path: Option<&str>) -> Result<Profile, Error> {
+	let name = name.trim();
+	if name.is_empty() {
+	    return Err(Error::Validation("The profile needs a name".to_string()));
+	}
+	if self.profile_names().iter().any(|existing| existing == name) {
+	    return Err(Error::Validation(format!("There is already a profile called \"{}\"", name)));
+	}
+	if name.contains(['/', '\\']) || name.starts_with('.') {
+	    return Err(Error::Validation("The profile name can't contain slashes or start with a dot".to_string()));
+	}
+
+	let db_path = match db_path.map(str::trim).filter(|path| !path.is_empty()) {
+	    Some(db_path) => PathBuf::from(db_path),
+	    None => data_dir()?.join("profiles").join(name).join(DB_FILE_NAME),
+	};
+	self.profiles.insert(name.to_string(), db_path.clone());
+
+	Ok(Profile { name: name.to_string(), db_path })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> Config {
+	Config::parse(r#"{
+	    "profile": "shop2",
+	    "profiles": {
+		"shop2": "/mnt/shared/shop2.db",
+		"default": "/srv/orders.db"
+	    }
+	}"#).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+	let config = test_config();
+
+	assert_eq!(config.profile, Some("shop2".to_string()));
+	assert_eq!(config.profiles["shop2"], PathBuf::from("/mnt/shared/shop2.db"));
+	assert_eq!(Config::parse("{}").unwrap(), Config::default());
+	assert!(Config::parse("[]").is_err());
+	assert!(Config::parse(r#"{"profiles": {"shop2": 5}}"#).is_err());
+	assert!(Config::parse("{").is_err());
+    }
+
+    #[test]
+    fn test_load_and_save() {
+	let dir = std::env::temp_dir().join(format!("as91896-config-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	let path = dir.join("config.json");
+
+	assert_eq!(Config::load(&path).unwrap(), Config::default());
+
+	let config = test_config();
+	config.save(&path).unwrap();
+	assert_eq!(Config::load(&path).unwrap(), config);
+
+	fs::write(&path, "not json").unwrap();
+	assert!(matches!(Config::load(&path), Err(Error::Parse(_))));
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_choose_profile() {
+	let config = test_config();
+
+	assert_eq!(config.choose_profile(None, None).unwrap().db_path, PathBuf::from("/mnt/shared/shop2.db"));
+	assert_eq!(config.choose_profile(None, Some("default")).unwrap().db_path, PathBuf::from("/srv/orders.db"));
+	assert_eq!(
+	    config.choose_profile(Some("/tmp/other.db"), None).unwrap(),
+	    Profile { name: "/tmp/other.db".to_string(), db_path: PathBuf::from("/tmp/other.db") },
+	);
+	assert!(config.choose_profile(None, Some("shop3")).is_err());
+	assert!(config.choose_profile(Some("/tmp/other.db"), Some("shop2")).is_err());
+
+	// The profile used last is gone, so the default one is used
+	let mut config = config;
+	config.profiles.remove("shop2");
+	assert_eq!(config.choose_profile(None, None).unwrap().name, DEFAULT_PROFILE);
+    }
+
+    #[test]
+    fn test_add_profile() {
+	let mut config = test_config();
+
+	let profile = config.add_profile(" shop3 ", Some("/mnt/shop3.db")).unwrap();
+	assert_eq!(profile.name, "shop3");
+	assert_eq!(config.get_profile("shop3").unwrap(), profile);
+	assert_eq!(config.profile_names(), vec!["default", "shop2", "shop3"]);
+
+	let profile = config.add_profile("shop4", None).unwrap();
+	assert!(profile.db_path.ends_with("profiles/shop4/orders.db"));
+
+	assert!(config.add_profile("shop2", None).is_err());
+	assert!(config.add_profile("default", None).is_err());
+	assert!(config.add_profile("  ", None).is_err());
+	assert!(config.add_profile("../shop", None).is_err());
+    }
+}
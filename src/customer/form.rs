@@ -0,0 +1,209 @@
+use crate::customer::Customer;
+use crate::error::Error;
+use crate::helpers::Form;
+use rusqlite::Connection;
+
+/// Represents a form to add or edit a customer
+#[derive(Default)]
+pub struct CustomerForm {
+    pub editing_id: Option<i32>,
+    pub name: String,
+    pub name_show_error: bool,
+    pub phone: String,
+    pub phone_show_error: bool,
+    pub email: String,
+    pub email_show_error: bool,
+    pub address: String,
+    pub notes: String,
+}
+
+impl CustomerForm {
+    /// Creates a form pre-filled with an existing Customer, so it can be edited.
+    pub fn from_customer(customer: &Customer) -> Self {
+	Self {
+	    editing_id: Some(customer.id),
+	    name: customer.name.clone(),
+	    phone: customer.phone.clone(),
+	    email: customer.email.clone(),
+	    address: customer.address.clone(),
+	    notes: customer.notes.clone(),
+	    ..Default::default()
+	}
+    }
+
+    /// Gets name, or Err if it is invalid.
+    fn get_valid_name(&self) -> Result<String, String> {
+	let name = self.name.trim();
+
+	if name.is_empty() {
+	    return Err("Name is required".to_string());
+	} else if name.len() > 30 {
+	    return Err("Name must be less than 30 characters".to_string());
+	} else if name.len() < 3 {
+	    return Err("Name must be at least 3 characters".to_string());
+	}
+
+	Ok(name.to_string())
+    }
+
+    /// Gets phone, which may be left empty, or Err if it is invalid.
+    fn get_valid_phone(&self) -> Result<String, String> {
+	let phone = self.phone.trim();
+
+	if phone.is_empty() {
+	    return Ok(String::new());
+	} else if !phone.chars().all(|c| c.is_ascii_digit() || " +-()".contains(c)) {
+	    return Err("Phone can only have digits, spaces and + - ( )".to_string());
+	} else if phone.chars().filter(|c| c.is_ascii_digit()).count() < 7 {
+	    return Err("Phone must have at least 7 digits".to_string());
+	}
+
+	Ok(phone.to_string())
+    }
+
+    /// Gets email, which may be left empty, or Err if it is invalid.
+    fn get_valid_email(&self) -> Result<String, String> {
+	let email = self.email.trim();
+
+	if email.is_empty() {
+	    return Ok(String::new());
+	}
+
+	match email.split_once('@') {
+	    Some((user, domain)) if !user.is_empty() && !domain.contains('@')
+		&& domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.')
+		&& !email.contains(char::is_whitespace) => Ok(email.to_string()),
+	    _ => Err("Email must look like name@example.com".to_string()),
+	}
+    }
+
+    /// Gets the error for a given field if its corresponding *_show_error is set to true.
+    pub fn get_visible_field_error(&self, field: &str) -> Option<String> {
+	match field {
+	    "name" if self.name_show_error => {
+		if let Err(e) = self.get_valid_name() {
+		    return Some(e);
+		}
+	    },
+	    "phone" if self.phone_show_error => {
+		if let Err(e) = self.get_valid_phone() {
+		    return Some(e);
+		}
+	    },
+	    "email" if self.email_show_error => {
+		if let Err(e) = self.get_valid_email() {
+		    return Some(e);
+		}
+	    },
+	    _ => (),
+	};
+
+	None
+    }
+
+    /// Adds the customer, or saves the one being edited, if all fields of the form are valid.
+    pub fn save(&self, connection: &Connection) -> Result<Customer, Error> {
+	let name = self.get_valid_name().map_err(Error::Validation)?;
+	let phone = self.get_valid_phone().map_err(Error::Validation)?;
+	let email = self.get_valid_email().map_err(Error::Validation)?;
+	let address = self.address.trim().to_string();
+	let notes = self.notes.trim().to_string();
+
+	match self.editing_id {
+	    Some(id) => Customer::get_by_id(connection, id)?
+		.update(connection, name, phone, email, address, notes),
+	    None => Customer::new(connection, name, phone, email, address, notes),
+	}
+    }
+}
+
+impl Form for CustomerForm {
+    /// Sets every *_show_error to true, so all invalid fields show their errors.
+    fn show_all_errors(&mut self) {
+	self.name_show_error = true;
+	self.phone_show_error = true;
+	self.email_show_error = true;
+    }
+
+    /// Checks if any field of the form is showing an error.
+    fn has_visible_errors(&self) -> bool {
+	["name", "phone", "email"]
+	    .iter()
+	    .any(|field| self.get_visible_field_error(field).is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn test_form() -> CustomerForm {
+	CustomerForm {
+	    name: "Alice Smith".to_string(),
+	    phone: "+64 21 123 4567".to_string(),
+	    email: "alice@example.com".to_string(),
+	    ..Default::default()
+	}
+    }
+
+    #[test]
+    fn test_get_valid_phone() {
+	let mut form = test_form();
+	assert!(form.get_valid_phone().is_ok());
+
+	form.phone = "".to_string();
+	assert_eq!(form.get_valid_phone(), Ok(String::new()));
+
+	form.phone = "123".to_string();
+	assert!(form.get_valid_phone().is_err());
+
+	form.phone = "021 CALL ME".to_string();
+	assert!(form.get_valid_phone().is_err());
+    }
+
+    #[test]
+    fn test_get_valid_email() {
+	let mut form = test_form();
+	assert!(form.get_valid_email().is_ok());
+
+	form.email = "".to_string();
+	assert_eq!(form.get_valid_email(), Ok(String::new()));
+
+	for email in ["alice", "alice@", "@example.com", "alice@example", "alice@@example.com", "a b@example.com"] {
+	    form.email = email.to_string();
+	    assert!(form.get_valid_email().is_err(), "{} should be invalid", email);
+	}
+    }
+
+    #[test]
+    fn test_form_save_new_and_edit() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let customer = test_form().save(&con).unwrap();
+	assert_eq!(customer.phone, "+64 21 123 4567".to_string());
+
+	let mut form = CustomerForm::from_customer(&customer);
+	form.address = " 1 Queen Street ".to_string();
+
+	let customer = form.save(&con).unwrap();
+	assert_eq!(customer.address, "1 Queen Street".to_string());
+	assert_eq!(Customer::get_all(&con).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_form_save_invalid() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let mut form = test_form();
+	form.email = "alice".to_string();
+
+	assert!(form.save(&con).is_err());
+	assert!(Customer::get_all(&con).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+pub mod form;
+pub mod table;
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use crate::error::Error;
+use crate::order::Order;
+
+pub use self::form::CustomerForm;
+
+/// The columns of Customer, in the order Customer::from_row reads them.
+const CUSTOMER_COLUMNS: &str = "id, name, phone, email, address, notes";
+
+/// Represents a customer, and how to get in touch with them. Contact details
+/// that aren't known are left empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Customer {
+    pub id: i32,
+    pub name: String,
+    pub phone: String,
+    pub email: String,
+    pub address: String,
+    pub notes: String,
+}
+
+impl Customer {
+    /// Adds a new customer.
+    pub fn new(
+	connection: &Connection,
+	name: String,
+	phone: String,
+	email: String,
+	address: String,
+	notes: String,
+    ) -> Result<Self, Error> {
+	if Self::get_by_name(connection, &name)?.is_some() {
+	    return Err(Error::Validation(format!("{} is already a customer", name)));
+	}
+
+	connection.execute(
+	    "INSERT INTO Customer (name, phone, email, address, notes) VALUES (?1, ?2, ?3, ?4, ?5)",
+	    params![name, phone, email, address, notes],
+	)?;
+
+	Self::get_by_id(connection, connection.last_insert_rowid() as i32)
+    }
+
+    /// Finds the customer with the given name, ignoring case, or adds them
+    /// without any contact details if they are new.
+    pub fn find_or_create(connection: &Connection, name: &str) -> Result<Self, Error> {
+	match Self::get_by_name(connection, name)? {
+	    Some(customer) => Ok(customer),
+	    None => Self::new(
+		connection,
+		name.to_string(),
+		String::new(),
+		String::new(),
+		String::new(),
+		String::new(),
+	    ),
+	}
+    }
+
+    /// Retrieves a customer from the database by their id.
+    pub fn get_by_id(connection: &Connection, id: i32) -> Result<Self, Error> {
+	Ok(connection.query_row(
+	    format!("SELECT {} FROM Customer WHERE id = ?1", CUSTOMER_COLUMNS).as_str(),
+	    [id],
+	    Self::from_row,
+	)?)
+    }
+
+    /// Finds a customer by their name, ignoring case.
+    pub fn get_by_name(connection: &Connection, name: &str) -> Result<Option<Self>, Error> {
+	Ok(connection.query_row(
+	    format!("SELECT {} FROM Customer WHERE name = ?1", CUSTOMER_COLUMNS).as_str(),
+	    [name],
+	    Self::from_row,
+	).optional()?)
+    }
+
+    /// Gets every customer, in alphabetical order.
+    pub fn get_all(connection: &Connection) -> Result<Vec<Self>, Error> {
+	let mut stmt = connection.prepare(
+	    format!("SELECT {} FROM Customer ORDER BY name", CUSTOMER_COLUMNS).as_str()
+	)?;
+
+	let customers = stmt.query_map([], Self::from_row)?.collect::<Result<_, _>>()?;
+
+	Ok(customers)
+    }
+
+    /// Builds a Customer from a row selected with CUSTOMER_COLUMNS.
+    fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
+	Ok(Self {
+	    id: row.get(0)?,
+	    name: row.get(1)?,
+	    phone: row.get(2)?,
+	    email: row.get(3)?,
+	    address: row.get(4)?,
+	    notes: row.get(5)?,
+	})
+    }
+
+    /// Updates the customer. Their orders follow them if they are renamed.
+    pub fn update(
+	&self,
+	connection: &Connection,
+	name: String,
+	phone: String,
+	email: String,
+	address: String,
+	notes: String,
+    ) -> Result<Self, Error> {
+	if let Some(other) = Self::get_by_name(connection, &name)? {
+	    if other.id != self.id {
+		return Err(Error::Validation(format!("{} is already a customer", name)));
+	    }
+	}
+
+	connection.execute(
+	    "UPDATE Customer SET name = ?1, phone = ?2, email = ?3, address = ?4, notes = ?5
+	     WHERE id = ?6",
+	    params![name, phone, email, address, notes, self.id],
+	)?;
+
+	Self::get_by_id(connection, self.id)
+    }
+
+    /// Removes the customer, as long as they have no orders.
+    pub fn delete(self, connection: &Connection) -> Result<usize, Error> {
+	let orders: i32 = connection.query_row(
+	    "SELECT COUNT(*) FROM CustomerOrder WHERE customer_id = ?1",
+	    [self.id],
+	    |row| row.get(0),
+	)?;
+
+	if orders > 0 {
+	    return Err(Error::Validation(format!(
+		"{} can't be deleted as they have {} order(s)",
+		self.name,
+		orders,
+	    )));
+	}
+
+	Ok(connection.execute("DELETE FROM Customer WHERE id = ?1", [self.id])?)
+    }
+
+    /// Gets every order the customer has made, newest hire first.
+    pub fn get_orders(&self, connection: &Connection) -> Result<Vec<Order>, Error> {
+	let mut orders: Vec<Order> = Order::get_all(connection)?
+	    .into_iter()
+	    .filter(|order| order.customer_id == self.id)
+	    .collect();
+	orders.sort_by(|a, b| b.hired_on.cmp(&a.hired_on).then(b.id.cmp(&a.id)));
+
+	Ok(orders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn test_customer(con: &Connection) -> Customer {
+	Customer::new(
+	    con,
+	    "Alice Smith".to_string(),
+	    "021 123 4567".to_string(),
+	    "alice@example.com".to_string(),
+	    String::new(),
+	    String::new(),
+	).unwrap()
+    }
+
+    fn test_hire(con: &Connection, customer_name: &str, day: u32) -> Order {
+	Order::new(
+	    con,
+	    customer_name.to_string(),
+	    15,
+	    vec![("Chairs".to_string(), 10)],
+	    NaiveDate::from_ymd_opt(2025, 3, day).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, day + 2).unwrap(),
+	).unwrap()
+    }
+
+    #[test]
+    fn test_new_customer() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let customer = test_customer(&con);
+
+	assert_eq!(Customer::get_by_name(&con, "alice smith").unwrap(), Some(customer));
+	assert!(matches!(
+	    Customer::new(&con, "ALICE SMITH".to_string(), String::new(), String::new(), String::new(), String::new()),
+	    Err(Error::Validation(_)),
+	));
+    }
+
+    #[test]
+    fn test_orders_link_to_customers() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let customer = test_customer(&con);
+	let order = test_hire(&con, "alice smith", 1);
+	test_hire(&con, "Bob Jones", 1);
+
+	assert_eq!(order.customer_id, customer.id);
+	assert_eq!(order.customer_name, "Alice Smith".to_string());
+	assert_eq!(Customer::get_all(&con).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_customer_rename_updates_orders() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let customer = test_customer(&con);
+	let order = test_hire(&con, "Alice Smith", 1);
+
+	customer.update(
+	    &con,
+	    "Alice Jones".to_string(),
+	    customer.phone.clone(),
+	    customer.email.clone(),
+	    customer.address.clone(),
+	    customer.notes.clone(),
+	).unwrap();
+
+	assert_eq!(Order::get_by_id(&con, order.id).unwrap().customer_name, "Alice Jones".to_string());
+    }
+
+    #[test]
+    fn test_customer_get_orders() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let customer = test_customer(&con);
+	let first = test_hire(&con, "Alice Smith", 1);
+	let second = test_hire(&con, "Alice Smith", 10);
+	test_hire(&con, "Bob Jones", 5);
+
+	let ids: Vec<i32> = customer.get_orders(&con).unwrap().into_iter().map(|o| o.id).collect();
+	assert_eq!(ids, vec![second.id, first.id]);
+    }
+
+    #[test]
+    fn test_customer_delete_with_orders() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let customer = test_customer(&con);
+	test_hire(&con, "Alice Smith", 1);
+
+	assert!(matches!(customer.delete(&con), Err(Error::Validation(_))));
+	assert!(Customer::find_or_create(&con, "Bob Jones").unwrap().delete(&con).is_ok());
+    }
+}
@@ -0,0 +1,88 @@
+use iced::{Element, Length, Theme, Renderer};
+use iced::widget::{container, text, button};
+use iced_table::table;
+use crate::app::Message;
+use crate::customer::Customer;
+
+/// Represents a column for the customers table.
+pub struct CustomerColumn {
+    pub kind: CustomerColumnKind,
+    pub width: f32,
+    pub resize_offset: Option<f32>,
+}
+
+impl CustomerColumn {
+    pub fn new(kind: CustomerColumnKind) -> Self {
+	let width = match kind {
+	    CustomerColumnKind::Name => 200.0,
+	    CustomerColumnKind::Phone => 140.0,
+	    CustomerColumnKind::Email => 220.0,
+	    CustomerColumnKind::View => 100.0,
+	    CustomerColumnKind::Edit => 100.0,
+	    CustomerColumnKind::Delete => 100.0,
+	};
+
+	Self {
+	    kind,
+	    width,
+	    resize_offset: None,
+	}
+    }
+}
+
+/// Represents what the field the column corrosponds to.
+pub enum CustomerColumnKind {
+    Name,
+    Phone,
+    Email,
+    View,
+    Edit,
+    Delete,
+}
+
+impl<'a> table::Column<'a, Message, Theme, Renderer> for CustomerColumn {
+    type Row = Customer;
+
+    fn header(&'a self, _col_size: usize) -> Element<'a, Message> {
+	let content = match self.kind {
+	    CustomerColumnKind::Name => "Name",
+	    CustomerColumnKind::Phone => "Phone",
+	    CustomerColumnKind::Email => "Email",
+	    CustomerColumnKind::View => "",
+	    CustomerColumnKind::Edit => "",
+	    CustomerColumnKind::Delete => "",
+	};
+
+	container(text(content)).center_y(24).into()
+    }
+
+    fn cell(&'a self, _col_index: usize, _row_index: usize, row: &'a Customer) -> Element<'a, Message> {
+	let content: Element<_> = match self.kind {
+	    CustomerColumnKind::Name => text(row.name.clone()).into(),
+	    CustomerColumnKind::Phone => text(row.phone.clone()).into(),
+	    CustomerColumnKind::Email => text(row.email.clone()).into(),
+	    CustomerColumnKind::View => button(text("Hires"))
+		.on_press(Message::ViewCustomer(row.id))
+		.into(),
+	    CustomerColumnKind::Edit => button(text("Edit"))
+		.on_press(Message::EditCustomer(row.id))
+		.into(),
+	    CustomerColumnKind::Delete => button(text("Delete"))
+		.on_press(Message::AskToConfirm(
+		    format!("Delete {}? This can't be undone.", row.name),
+		    Box::new(Message::DeleteCustomer(row.id)),
+		))
+		.into(),
+	};
+
+	container(content).width(Length::Fill).center_y(32).into()
+    }
+
+    fn width(&self) -> f32 {
+	self.width
+    }
+
+    fn resize_offset(&self) -> Option<f32> {
+	self.resize_offset
+    }
+}
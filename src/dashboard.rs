@@ -0,0 +1,200 @@
+use chrono::{Datelike, Days, Months, NaiveDate};
+use rusqlite::Connection;
+use crate::error::Error;
+use crate::item::Item;
+use crate::order::{Order, OrderQuery, OrderStatus, OrderTotals};
+use crate::order::query::StatusFilter;
+use crate::packing::{PackingPlan, PACKING_PLAN_DAYS};
+
+/// Represents a figure on the dashboard. Each one counts the orders matching a
+/// query, so clicking it can show exactly those orders.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DashboardFigure {
+    PickupsToday,
+    ReturnsToday,
+    Overdue,
+    /// The orders out on hire with the item.
+    HiredOut(String),
+    BoxesToPrepare,
+    HiresThisMonth,
+}
+
+impl DashboardFigure {
+    /// Gets the query for the orders the figure is about.
+    pub fn query(&self, today: NaiveDate) -> OrderQuery {
+	let mut query = OrderQuery::default();
+
+	match self {
+	    DashboardFigure::PickupsToday => {
+		query.status = Some(StatusFilter::Is(OrderStatus::Booked));
+		query.hired_between = Some((today, today));
+	    },
+	    DashboardFigure::ReturnsToday => {
+		query.status = Some(StatusFilter::Is(OrderStatus::Out));
+		query.returning_between = Some((today, today));
+	    },
+	    DashboardFigure::Overdue => {
+		query.status = Some(StatusFilter::Overdue);
+	    },
+	    DashboardFigure::HiredOut(item) => {
+		query.status = Some(StatusFilter::Is(OrderStatus::Out));
+		query.item = Some(item.clone());
+	    },
+	    DashboardFigure::BoxesToPrepare => {
+		// The same days as the packing plan
+		query.status = Some(StatusFilter::Is(OrderStatus::Booked));
+		query.hired_between = Some((today, today + Days::new(PACKING_PLAN_DAYS - 1)));
+	    },
+	    DashboardFigure::HiresThisMonth => {
+		let first = today.with_day(1).unwrap_or(today);
+		let last = first + Months::new(1) - Days::new(1);
+		query.status = Some(StatusFilter::NotCancelled);
+		query.hired_between = Some((first, last));
+	    },
+	}
+
+	query
+    }
+
+    /// Describes the orders the figure is about, e.g. "Picked up today".
+    pub fn description(&self) -> String {
+	match self {
+	    DashboardFigure::PickupsToday => "Picked up today".to_string(),
+	    DashboardFigure::ReturnsToday => "Due back today".to_string(),
+	    DashboardFigure::Overdue => "Overdue".to_string(),
+	    DashboardFigure::HiredOut(item) => format!("Out on hire with {}", item),
+	    DashboardFigure::BoxesToPrepare => format!("Picked up in the next {} days", PACKING_PLAN_DAYS),
+	    DashboardFigure::HiresThisMonth => "Hired this month".to_string(),
+	}
+    }
+}
+
+/// Represents the figures on the dashboard, as of `today`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dashboard {
+    pub today: NaiveDate,
+    pub pickups_today: OrderTotals,
+    pub returns_today: OrderTotals,
+    pub overdue: OrderTotals,
+    // Every item in the catalogue, and how many of it are out on hire
+    pub hired_out: Vec<(Item, i64)>,
+    pub boxes_to_prepare: OrderTotals,
+    pub hires_this_month: OrderTotals,
+}
+
+impl Dashboard {
+    /// Works out every figure on the dashboard in the database.
+    pub fn load(connection: &Connection, today: NaiveDate) -> Result<Self, Error> {
+	let totals = |figure: DashboardFigure| Order::totals(connection, &figure.query(today), today);
+
+	// The boxes are counted from the packing plan, where orders picked up
+	// together share boxes
+	let plan = PackingPlan::build(connection, today, today + Days::new(PACKING_PLAN_DAYS - 1))?;
+	let boxes_to_prepare = OrderTotals {
+	    boxes: plan.total_boxes() as i64,
+	    ..totals(DashboardFigure::BoxesToPrepare)?
+	};
+
+	Ok(Self {
+	    today,
+	    pickups_today: totals(DashboardFigure::PickupsToday)?,
+	    returns_today: totals(DashboardFigure::ReturnsToday)?,
+	    overdue: totals(DashboardFigure::Overdue)?,
+	    hired_out: Item::get_all_with_hired_out(connection)?,
+	    boxes_to_prepare,
+	    hires_this_month: totals(DashboardFigure::HiresThisMonth)?,
+	})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+	NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    fn test_order(con: &Connection, how_many: i32, hired_on: NaiveDate, return_on: NaiveDate) -> Order {
+	Order::new(
+	    con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Chairs".to_string(), how_many)],
+	    hired_on,
+	    return_on,
+	).unwrap()
+    }
+
+    #[test]
+    fn test_dashboard() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let chairs = Item::new(&con, "Chairs".to_string(), 100, 25, 250).unwrap();
+	let tablecloths = Item::new(&con, "Tablecloths".to_string(), 100, 25, 100).unwrap();
+	let today = date(3, 20);
+	test_order(&con, 30, today, date(3, 22));
+	test_order(&con, 10, date(3, 18), today).mark_out(&con).unwrap();
+	test_order(&con, 5, date(3, 10), date(3, 12)).mark_out(&con).unwrap();
+	test_order(&con, 60, date(3, 26), date(3, 27));
+	test_order(&con, 20, date(3, 28), date(3, 29)).cancel(&con).unwrap();
+	test_order(&con, 20, date(4, 1), date(4, 2));
+
+	// Two orders picked up together, whose leftovers share one box
+	for lines in [vec![("Chairs", 10), ("Tablecloths", 5)], vec![("Tablecloths", 5)]] {
+	    Order::new(
+		&con,
+		"Test Person".to_string(),
+		16,
+		lines.into_iter().map(|(item, how_many)| (item.to_string(), how_many)).collect(),
+		date(3, 24),
+		date(3, 25),
+	    ).unwrap();
+	}
+
+	let dashboard = Dashboard::load(&con, today).unwrap();
+
+	assert_eq!(dashboard.pickups_today, OrderTotals { orders: 1, items: 30, boxes: 2 });
+	assert_eq!(dashboard.returns_today.orders, 1);
+	assert_eq!(dashboard.overdue.orders, 1);
+	assert_eq!(dashboard.hired_out, vec![(chairs, 15), (tablecloths, 0)]);
+	assert_eq!(dashboard.boxes_to_prepare, OrderTotals { orders: 4, items: 110, boxes: 6 });
+	assert_eq!(dashboard.hires_this_month.orders, 6);
+    }
+
+    #[test]
+    fn test_figures_match_their_orders() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let today = date(3, 20);
+	test_order(&con, 30, today, date(3, 22));
+	test_order(&con, 10, date(3, 18), today).mark_out(&con).unwrap();
+	test_order(&con, 5, date(3, 1), date(3, 12)).mark_out(&con).unwrap();
+
+	for figure in [
+	    DashboardFigure::PickupsToday,
+	    DashboardFigure::ReturnsToday,
+	    DashboardFigure::HiredOut("chairs".to_string()),
+	    DashboardFigure::BoxesToPrepare,
+	    DashboardFigure::HiresThisMonth,
+	] {
+	    let query = figure.query(today);
+	    let totals = Order::totals(&con, &query, today).unwrap();
+
+	    assert_eq!(totals.orders, Order::query(&con, &query).unwrap().len() as i64, "{:?}", figure);
+	}
+    }
+
+    #[test]
+    fn test_hires_this_month_query() {
+	let query = DashboardFigure::HiresThisMonth.query(date(2, 14));
+
+	assert_eq!(query.hired_between, Some((date(2, 1), date(2, 28))));
+    }
+}
@@ -1,27 +1,691 @@
 use rusqlite::Connection;
 use std::fs;
-use std::path::PathBuf;
-use crate::order::Order;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::email::smtp::move_password_out_of_database;
+use crate::error::Error;
+use crate::order::count_uncounted_boxes;
 
-/// Gets the path to the database. It creates the path to it if it doesn't exist.
-fn get_db_path() -> PathBuf {
-    let mut path = dirs::data_local_dir().expect("Failed to get local data directory");
+/// The name of the database file in the folders the program makes.
+pub const DB_FILE_NAME: &str = "orders.db";
+
+/// How long to wait for another computer to finish writing to a shared database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Gets the folder the program keeps its files in. It creates the folder if it
+/// doesn't exist.
+pub fn data_dir() -> Result<PathBuf, Error> {
+    let mut path = dirs::data_local_dir().ok_or_else(|| {
+	io::Error::new(io::ErrorKind::NotFound, "Failed to get local data directory")
+    })?;
     path.push("OrderManagementToolAS91896");
-    fs::create_dir_all(&path).expect("Failed to create database directory");
-    path.push("orders.db");
-    path
+    fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+/// Gets the folder the database file is in, which other files for it are kept
+/// next to. Gives None for a database that is only in memory.
+pub fn db_dir(connection: &Connection) -> Option<PathBuf> {
+    let path = Path::new(connection.path().filter(|path| !path.is_empty())?);
+
+    Some(path.parent()?.to_path_buf())
 }
 
-pub fn init_db_tables(connection: &Connection) {
-    Order::init_table(&connection).unwrap();
+/// Brings the database schema up to date by running any pending migrations,
+/// and moves out anything that is no longer kept in the database.
+pub fn init_db_tables(connection: &Connection) -> Result<(), Error> {
+    migrations::migrate(connection)?;
+    move_password_out_of_database(connection)?;
+    count_uncounted_boxes(connection)?;
+
+    Ok(())
 }
 
-/// Initializes the database and adds all the required tables.
-pub fn init_db() -> Connection {
-    let db_path = get_db_path();
-    let connection = Connection::open(db_path).expect("Failed to open SQLite database");
+/// Runs `f` inside a savepoint, so everything it changes is undone if it fails.
+/// Unlike a transaction, this also works inside another transaction.
+pub fn with_savepoint<T>(
+    connection: &Connection,
+    f: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    connection.execute_batch("SAVEPOINT with_savepoint")?;
+
+    match f() {
+	Ok(value) => {
+	    connection.execute_batch("RELEASE with_savepoint")?;
+	    Ok(value)
+	},
+	Err(e) => {
+	    connection.execute_batch("ROLLBACK TO with_savepoint; RELEASE with_savepoint")?;
+	    Err(e)
+	},
+    }
+}
+
+/// Opens the database at `db_path` and adds all the required tables. Folders
+/// the program keeps its own files in are made if needed, but any other folder
+/// must already exist, so a shared drive that isn't connected isn't mistaken
+/// for an empty database.
+pub fn init_db(db_path: &Path) -> Result<Connection, Error> {
+    let open = || -> Result<Connection, Error> {
+	let own_folder = data_dir().is_ok_and(|dir| db_path.starts_with(dir));
+	match db_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+	    Some(dir) if own_folder => fs::create_dir_all(dir)?,
+	    Some(dir) if !dir.is_dir() => {
+		return Err(Error::Validation(format!("the folder {} doesn't exist", dir.display())));
+	    },
+	    _ => (),
+	}
+
+	let connection = Connection::open(db_path)?;
+	connection.busy_timeout(BUSY_TIMEOUT)?;
+
+	init_db_tables(&connection)?;
+
+	Ok(connection)
+    };
+
+    open().map_err(|e| Error::Validation(format!(
+	"Couldn't open the database at {}: {}",
+	db_path.display(),
+	e,
+    )))
+}
+
+/// Versioned upgrades of the database schema.
+///
+/// The schema version is stored in SQLite's `PRAGMA user_version`. Each
+/// migration runs in its own transaction together with the version bump, so
+/// a failed step leaves the database at the last good version.
+pub mod migrations {
+    use rusqlite::Connection;
+    use std::fmt;
+
+    /// A single step that upgrades the schema to `version`.
+    #[derive(Clone, Copy)]
+    pub struct Migration {
+	pub version: i32,
+	pub description: &'static str,
+	pub sql: &'static str,
+    }
+
+    /// Every migration, in the order they are applied. Never edit a migration
+    /// that has been released; add a new one instead.
+    pub const MIGRATIONS: &[Migration] = &[
+	Migration {
+	    version: 1,
+	    description: "Create the CustomerOrder table",
+	    // Uses IF NOT EXISTS so databases made before versioning (user_version 0) still upgrade.
+	    sql: "CREATE TABLE IF NOT EXISTS CustomerOrder (
+		id             INTEGER PRIMARY KEY,
+		customer_name  TEXT NOT NULL,
+		receipt_number INTEGER NOT NULL,
+		item_hired     TEXT NOT NULL,
+		how_many       INTEGER NOT NULL,
+		hired_on       TEXT NOT NULL,
+		return_on      TEXT NOT NULL,
+		boxes_needed   INTEGER NOT NULL,
+		raffle_number  INTEGER NOT NULL
+	    );",
+	},
+	Migration {
+	    version: 2,
+	    description: "Track the status of orders and when they were returned",
+	    sql: "ALTER TABLE CustomerOrder ADD COLUMN status TEXT NOT NULL DEFAULT 'Booked';
+		  ALTER TABLE CustomerOrder ADD COLUMN returned_at TEXT;",
+	},
+	Migration {
+	    version: 3,
+	    description: "Record raffle draws",
+	    sql: "CREATE TABLE RaffleDraw (
+		id              INTEGER PRIMARY KEY,
+		drawn_at        TEXT NOT NULL,
+		prize_name      TEXT NOT NULL,
+		winner_order_id INTEGER NOT NULL,
+		seed            INTEGER NOT NULL,
+		position        INTEGER NOT NULL
+	    );",
+	},
+	Migration {
+	    version: 4,
+	    description: "Store settings",
+	    sql: "CREATE TABLE Setting (
+		key   TEXT PRIMARY KEY,
+		value NOT NULL
+	    );",
+	},
+	Migration {
+	    version: 5,
+	    description: "Create the Item catalogue",
+	    // Prices are stored in cents so they add up exactly
+	    sql: "CREATE TABLE Item (
+		id           INTEGER PRIMARY KEY,
+		name         TEXT NOT NULL UNIQUE COLLATE NOCASE,
+		total_stock  INTEGER NOT NULL,
+		box_capacity INTEGER NOT NULL,
+		hire_price   INTEGER NOT NULL
+	    );",
+	},
+	Migration {
+	    version: 6,
+	    description: "Move the items of orders into order lines",
+	    // Every existing order becomes an order with a single line
+	    sql: "CREATE TABLE OrderLine (
+		id           INTEGER PRIMARY KEY,
+		order_id     INTEGER NOT NULL REFERENCES CustomerOrder (id),
+		item_hired   TEXT NOT NULL,
+		how_many     INTEGER NOT NULL,
+		boxes_needed INTEGER NOT NULL
+	    );
+	    INSERT INTO OrderLine (order_id, item_hired, how_many, boxes_needed)
+		SELECT id, item_hired, how_many, boxes_needed FROM CustomerOrder ORDER BY id;
+	    ALTER TABLE CustomerOrder DROP COLUMN item_hired;
+	    ALTER TABLE CustomerOrder DROP COLUMN how_many;
+	    ALTER TABLE CustomerOrder DROP COLUMN boxes_needed;",
+	},
+	Migration {
+	    version: 7,
+	    description: "Move customers into their own table",
+	    // Orders whose names only differ by case become the same customer,
+	    // named as on their first order
+	    sql: "CREATE TABLE Customer (
+		id      INTEGER PRIMARY KEY,
+		name    TEXT NOT NULL UNIQUE COLLATE NOCASE,
+		phone   TEXT NOT NULL DEFAULT '',
+		email   TEXT NOT NULL DEFAULT '',
+		address TEXT NOT NULL DEFAULT '',
+		notes   TEXT NOT NULL DEFAULT ''
+	    );
+	    INSERT INTO Customer (name)
+		SELECT customer_name FROM (
+		    SELECT customer_name, MIN(id) AS first_id FROM CustomerOrder
+		    GROUP BY customer_name COLLATE NOCASE
+		) ORDER BY first_id;
+	    ALTER TABLE CustomerOrder ADD COLUMN customer_id INTEGER REFERENCES Customer (id);
+	    UPDATE CustomerOrder SET customer_id = (
+		SELECT id FROM Customer WHERE name = CustomerOrder.customer_name
+	    );
+	    ALTER TABLE CustomerOrder DROP COLUMN customer_name;",
+	},
+	Migration {
+	    version: 8,
+	    description: "Keep deleted orders until they are purged",
+	    sql: "ALTER TABLE CustomerOrder ADD COLUMN deleted_at TEXT;",
+	},
+	Migration {
+	    version: 9,
+	    description: "Record the reminders sent about overdue orders",
+	    sql: "CREATE TABLE Reminder (
+		id       INTEGER PRIMARY KEY,
+		order_id INTEGER NOT NULL REFERENCES CustomerOrder (id),
+		sent_at  TEXT NOT NULL,
+		method   TEXT NOT NULL
+	    );",
+	},
+	Migration {
+	    version: 10,
+	    description: "Queue reminder emails and say what each reminder was about",
+	    // The reminders from before were all about overdue orders
+	    sql: "ALTER TABLE Reminder ADD COLUMN kind TEXT NOT NULL DEFAULT 'Overdue';
+	    CREATE TABLE OutgoingEmail (
+		id              INTEGER PRIMARY KEY,
+		order_id        INTEGER NOT NULL REFERENCES CustomerOrder (id),
+		kind            TEXT NOT NULL,
+		to_address      TEXT NOT NULL,
+		subject         TEXT NOT NULL,
+		body            TEXT NOT NULL,
+		queued_at       TEXT NOT NULL,
+		attempts        INTEGER NOT NULL DEFAULT 0,
+		next_attempt_at TEXT NOT NULL,
+		last_error      TEXT,
+		sent_at         TEXT
+	    );",
+	},
+	Migration {
+	    version: 11,
+	    description: "Record what orders cost and what has been paid",
+	    // Lines already booked are charged at the catalogue's price for their item
+	    sql: "ALTER TABLE OrderLine ADD COLUMN daily_rate INTEGER NOT NULL DEFAULT 0;
+	    UPDATE OrderLine SET daily_rate = COALESCE(
+		(SELECT hire_price FROM Item WHERE Item.name = OrderLine.item_hired),
+		0
+	    );
+	    ALTER TABLE CustomerOrder ADD COLUMN deposit INTEGER NOT NULL DEFAULT 0;
+	    ALTER TABLE CustomerOrder ADD COLUMN damage_charge INTEGER NOT NULL DEFAULT 0;
+	    CREATE TABLE Payment (
+		id       INTEGER PRIMARY KEY,
+		order_id INTEGER NOT NULL REFERENCES CustomerOrder (id),
+		amount   INTEGER NOT NULL,
+		method   TEXT NOT NULL,
+		paid_on  TEXT NOT NULL,
+		note     TEXT NOT NULL DEFAULT ''
+	    );",
+	},
+	Migration {
+	    version: 12,
+	    description: "Record the late fees charged on returned orders",
+	    sql: "CREATE TABLE LateFee (
+		id           INTEGER PRIMARY KEY,
+		order_id     INTEGER NOT NULL UNIQUE REFERENCES CustomerOrder (id),
+		days_late    INTEGER NOT NULL,
+		amount       INTEGER NOT NULL,
+		charged_at   TEXT NOT NULL,
+		waived_at    TEXT,
+		waive_reason TEXT
+	    );",
+	},
+	Migration {
+	    version: 13,
+	    description: "Make sure no two orders share a raffle number",
+	    // Orders from before raffle numbers were checked can share one. The
+	    // earliest order, or the one that won a draw, keeps it and the rest
+	    // are given numbers past the highest in use.
+	    sql: "CREATE TEMP TABLE RaffleRenumber AS
+		SELECT id, (SELECT MAX(raffle_number) FROM CustomerOrder)
+		    + ROW_NUMBER() OVER (ORDER BY id) AS raffle_number
+		FROM (
+		    SELECT id, ROW_NUMBER() OVER (
+			PARTITION BY raffle_number
+			ORDER BY id IN (SELECT winner_order_id FROM RaffleDraw) DESC, id
+		    ) AS position
+		    FROM CustomerOrder
+		)
+		WHERE position > 1;
+	    UPDATE CustomerOrder
+		SET raffle_number = (SELECT raffle_number FROM RaffleRenumber WHERE id = CustomerOrder.id)
+		WHERE id IN (SELECT id FROM RaffleRenumber);
+	    DROP TABLE RaffleRenumber;
+	    CREATE UNIQUE INDEX CustomerOrderRaffleNumber ON CustomerOrder (raffle_number);",
+	},
+	Migration {
+	    version: 14,
+	    description: "Count the boxes of each order as its items are packed together",
+	    // Packing is worked out by the program, so the counts are filled in by
+	    // init_db_tables once the schema is up to date
+	    sql: "ALTER TABLE CustomerOrder ADD COLUMN boxes_needed INTEGER;",
+	},
+    ];
+
+    /// Represents a failure to bring the database up to date.
+    #[derive(Debug)]
+    pub enum MigrationError {
+	Database(rusqlite::Error),
+	/// A migration failed, and was rolled back.
+	Step { version: i32, description: &'static str, error: rusqlite::Error },
+	/// The database was written by a newer version of the program.
+	TooNew { found: i32, supported: i32 },
+    }
+
+    impl fmt::Display for MigrationError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	    match self {
+		MigrationError::Database(e) => write!(f, "{}", e),
+		MigrationError::Step { version, description, error } => write!(
+		    f,
+		    "Migration {} ({}) failed: {}",
+		    version,
+		    description,
+		    error,
+		),
+		MigrationError::TooNew { found, supported } => write!(
+		    f,
+		    "Database schema version {} is newer than this program supports ({})",
+		    found,
+		    supported,
+		),
+	    }
+	}
+    }
+
+    impl From<rusqlite::Error> for MigrationError {
+	fn from(e: rusqlite::Error) -> Self {
+	    MigrationError::Database(e)
+	}
+    }
+
+    /// Gets the schema version of the database.
+    pub fn get_version(connection: &Connection) -> Result<i32, rusqlite::Error> {
+	connection.pragma_query_value(None, "user_version", |row| row.get(0))
+    }
+
+    /// Applies all pending migrations to the database.
+    pub fn migrate(connection: &Connection) -> Result<i32, MigrationError> {
+	migrate_with(connection, MIGRATIONS)
+    }
+
+    /// Applies the pending migrations out of the given list, returning the new schema version.
+    pub fn migrate_with(connection: &Connection, migrations: &[Migration]) -> Result<i32, MigrationError> {
+	let current = get_version(connection)?;
+	let supported = migrations.last().map(|m| m.version).unwrap_or(0);
+
+	if current > supported {
+	    return Err(MigrationError::TooNew { found: current, supported });
+	}
+
+	let mut version = current;
+	for migration in migrations.iter().filter(|m| m.version > current) {
+	    apply(connection, migration).map_err(|error| MigrationError::Step {
+		version: migration.version,
+		description: migration.description,
+		error,
+	    })?;
+
+	    version = migration.version;
+	}
+
+	Ok(version)
+    }
+
+    /// Runs a migration and bumps the schema version inside one transaction.
+    fn apply(connection: &Connection, migration: &Migration) -> Result<(), rusqlite::Error> {
+	let tx = connection.unchecked_transaction()?;
+	tx.execute_batch(migration.sql)?;
+	tx.pragma_update(None, "user_version", migration.version)?;
+	tx.commit()
+    }
+
+    #[cfg(test)]
+    mod tests {
+	use super::*;
+
+	fn latest_version() -> i32 {
+	    MIGRATIONS.last().unwrap().version
+	}
+
+	// Pretend future migrations, used to check upgrading from v1.
+	const TEST_MIGRATIONS: &[Migration] = &[
+	    MIGRATIONS[0],
+	    Migration {
+		version: 2,
+		description: "Add a notes column",
+		sql: "ALTER TABLE CustomerOrder ADD COLUMN notes TEXT NOT NULL DEFAULT '';",
+	    },
+	    Migration {
+		version: 3,
+		description: "Index orders by hire date",
+		sql: "CREATE INDEX CustomerOrderHiredOn ON CustomerOrder (hired_on);",
+	    },
+	];
+
+	/// Makes a database at schema version 1 with a single order in it.
+	fn v1_database() -> Connection {
+	    let con = Connection::open_in_memory().unwrap();
+
+	    migrate_with(&con, &MIGRATIONS[..1]).unwrap();
+	    con.execute(
+		"INSERT INTO CustomerOrder (
+		    customer_name, receipt_number, item_hired, how_many,
+		    hired_on, return_on, boxes_needed, raffle_number
+		) VALUES ('Test Person', 15, 'Test Item', 26, '2025-03-23', '2025-03-24', 2, 42)",
+		(),
+	    ).unwrap();
+
+	    con
+	}
+
+	#[test]
+	fn test_migrations_are_ordered() {
+	    for pair in MIGRATIONS.windows(2) {
+		assert_eq!(pair[0].version + 1, pair[1].version);
+	    }
+	    assert_eq!(MIGRATIONS[0].version, 1);
+	}
+
+	#[test]
+	fn test_migrate_fresh_database() {
+	    let con = Connection::open_in_memory().unwrap();
+
+	    assert_eq!(migrate(&con).unwrap(), latest_version());
+	    assert_eq!(get_version(&con).unwrap(), latest_version());
+	}
+
+	#[test]
+	fn test_migrate_twice() {
+	    let con = Connection::open_in_memory().unwrap();
+
+	    migrate(&con).unwrap();
+	    assert_eq!(migrate(&con).unwrap(), latest_version());
+	}
+
+	#[test]
+	fn test_migrate_unversioned_database() {
+	    let con = Connection::open_in_memory().unwrap();
+
+	    // Databases made before versioning already have the table but a user_version of 0
+	    con.execute_batch(MIGRATIONS[0].sql).unwrap();
+	    assert_eq!(get_version(&con).unwrap(), 0);
+
+	    assert_eq!(migrate(&con).unwrap(), latest_version());
+	}
+
+	#[test]
+	fn test_migrate_v1_database() {
+	    let con = v1_database();
+
+	    assert_eq!(migrate_with(&con, TEST_MIGRATIONS).unwrap(), 3);
+	    assert_eq!(get_version(&con).unwrap(), 3);
+
+	    let (name, notes): (String, String) = con.query_row(
+		"SELECT customer_name, notes FROM CustomerOrder",
+		[],
+		|row| Ok((row.get(0)?, row.get(1)?)),
+	    ).unwrap();
+	    assert_eq!(name, "Test Person".to_string());
+	    assert_eq!(notes, "".to_string());
+
+	    let indexes: i32 = con.query_row(
+		"SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = 'CustomerOrderHiredOn'",
+		[],
+		|row| row.get(0),
+	    ).unwrap();
+	    assert_eq!(indexes, 1);
+	}
+
+	#[test]
+	fn test_migrate_v1_database_adds_status() {
+	    let con = v1_database();
+
+	    migrate(&con).unwrap();
+
+	    let (status, returned_at): (String, Option<String>) = con.query_row(
+		"SELECT status, returned_at FROM CustomerOrder",
+		[],
+		|row| Ok((row.get(0)?, row.get(1)?)),
+	    ).unwrap();
+	    assert_eq!(status, "Booked".to_string());
+	    assert_eq!(returned_at, None);
+	}
+
+	#[test]
+	fn test_migrate_v1_database_moves_items_to_lines() {
+	    let con = v1_database();
+
+	    migrate(&con).unwrap();
+
+	    let (order_id, item_hired, how_many, boxes_needed): (i32, String, i32, i32) = con.query_row(
+		"SELECT order_id, item_hired, how_many, boxes_needed FROM OrderLine",
+		[],
+		|row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+	    ).unwrap();
+	    assert_eq!(order_id, 1);
+	    assert_eq!(item_hired, "Test Item".to_string());
+	    assert_eq!(how_many, 26);
+	    assert_eq!(boxes_needed, 2);
+	    assert!(con.prepare("SELECT item_hired FROM CustomerOrder").is_err());
+	}
+
+	#[test]
+	fn test_migrate_v1_database_moves_customers() {
+	    let con = v1_database();
+	    con.execute(
+		"INSERT INTO CustomerOrder (
+		    customer_name, receipt_number, item_hired, how_many,
+		    hired_on, return_on, boxes_needed, raffle_number
+		) VALUES ('TEST PERSON', 16, 'Test Item', 1, '2025-03-23', '2025-03-24', 1, 43)",
+		(),
+	    ).unwrap();
+
+	    migrate(&con).unwrap();
+
+	    let names: Vec<(i32, String)> = con.prepare(
+		"SELECT CustomerOrder.id, Customer.name
+		 FROM CustomerOrder JOIN Customer ON Customer.id = CustomerOrder.customer_id
+		 ORDER BY CustomerOrder.id"
+	    ).unwrap()
+		.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+		.unwrap()
+		.collect::<Result<_, _>>()
+		.unwrap();
+	    assert_eq!(names, vec![(1, "Test Person".to_string()), (2, "Test Person".to_string())]);
+	    assert!(con.prepare("SELECT customer_name FROM CustomerOrder").is_err());
+	}
+
+	#[test]
+	fn test_migrate_v12_database_renumbers_shared_raffle_numbers() {
+	    let con = v1_database();
+	    for receipt_number in 16..=18 {
+		con.execute(
+		    "INSERT INTO CustomerOrder (
+			customer_name, receipt_number, item_hired, how_many,
+			hired_on, return_on, boxes_needed, raffle_number
+		    ) VALUES ('Test Person', ?1, 'Test Item', 1, '2025-03-23', '2025-03-24', 1, 42)",
+		    [receipt_number],
+		).unwrap();
+	    }
+	    migrate_with(&con, &MIGRATIONS[..12]).unwrap();
+	    con.execute(
+		"INSERT INTO RaffleDraw (drawn_at, prize_name, winner_order_id, seed, position)
+		 VALUES ('2025-03-25 10:00:00', 'Prize', 3, 1, 1)",
+		(),
+	    ).unwrap();
+
+	    migrate(&con).unwrap();
+
+	    let numbers: Vec<(i32, i32)> = con.prepare("SELECT id, raffle_number FROM CustomerOrder ORDER BY id")
+		.unwrap()
+		.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+		.unwrap()
+		.collect::<Result<_, _>>()
+		.unwrap();
+	    // The winner of the draw keeps its number
+	    assert_eq!(numbers, vec![(1, 43), (2, 44), (3, 42), (4, 45)]);
+
+	    assert!(con.execute("UPDATE CustomerOrder SET raffle_number = 42 WHERE id = 1", ()).is_err());
+	}
+
+	#[test]
+	fn test_init_v13_database_counts_packed_boxes() {
+	    let con = v1_database();
+	    con.execute(
+		"INSERT INTO CustomerOrder (
+		    customer_name, receipt_number, item_hired, how_many,
+		    hired_on, return_on, boxes_needed, raffle_number
+		) VALUES ('Test Person', 16, 'Test Item', 26, '2025-03-23', '2025-03-24', 2, 42)",
+		(),
+	    ).unwrap();
+	    migrate_with(&con, &MIGRATIONS[..13]).unwrap();
+	    con.execute(
+		"INSERT INTO OrderLine (order_id, item_hired, how_many, boxes_needed, daily_rate)
+		 VALUES (1, 'Other Item', 3, 1, 0)",
+		(),
+	    ).unwrap();
+
+	    crate::database::init_db_tables(&con).unwrap();
+
+	    // The leftover Test Item shares a box with the Other Items
+	    let boxes: i32 = con.query_row("SELECT boxes_needed FROM CustomerOrder", [], |row| row.get(0)).unwrap();
+	    assert_eq!(boxes, 2);
+	}
+
+	#[test]
+	fn test_migrate_v10_database_prices_lines() {
+	    let con = v1_database();
+	    migrate_with(&con, &MIGRATIONS[..10]).unwrap();
+	    con.execute(
+		"INSERT INTO Item (name, total_stock, box_capacity, hire_price) VALUES ('test item', 50, 25, 250)",
+		(),
+	    ).unwrap();
+
+	    migrate(&con).unwrap();
+
+	    let daily_rate: i64 = con.query_row("SELECT daily_rate FROM OrderLine", [], |row| row.get(0)).unwrap();
+	    assert_eq!(daily_rate, 250);
+
+	    let (deposit, damage_charge): (i64, i64) = con.query_row(
+		"SELECT deposit, damage_charge FROM CustomerOrder",
+		[],
+		|row| Ok((row.get(0)?, row.get(1)?)),
+	    ).unwrap();
+	    assert_eq!((deposit, damage_charge), (0, 0));
+	}
+
+	#[test]
+	fn test_migrate_failure_rolls_back() {
+	    let con = v1_database();
+	    let broken = [
+		MIGRATIONS[0],
+		Migration {
+		    version: 2,
+		    description: "Half applied",
+		    sql: "ALTER TABLE CustomerOrder ADD COLUMN notes TEXT;
+			  ALTER TABLE NoSuchTable ADD COLUMN oops TEXT;",
+		},
+	    ];
+
+	    assert!(migrate_with(&con, &broken).is_err());
+	    assert_eq!(get_version(&con).unwrap(), 1);
+	    assert!(con.prepare("SELECT notes FROM CustomerOrder").is_err());
+	}
+
+	#[test]
+	fn test_migrate_database_too_new() {
+	    let con = Connection::open_in_memory().unwrap();
+
+	    con.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+
+	    assert!(matches!(migrate(&con), Err(MigrationError::TooNew { .. })));
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_savepoint_rolls_back() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let result: Result<(), Error> = with_savepoint(&con, || {
+	    con.execute("INSERT INTO Setting (key, value) VALUES ('test', 1)", [])?;
+	    Err(Error::Validation("Failed".to_string()))
+	});
+	assert!(result.is_err());
+
+	with_savepoint(&con, || Ok(con.execute("INSERT INTO Setting (key, value) VALUES ('test', 2)", [])?))
+	    .unwrap();
+
+	let value: i64 = con.query_row("SELECT value FROM Setting WHERE key = 'test'", [], |row| row.get(0))
+	    .unwrap();
+	assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn test_init_db() {
+	let dir = std::env::temp_dir().join(format!("as91896-init-db-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+
+	init_db(&dir.join(DB_FILE_NAME)).unwrap();
+	assert!(dir.join(DB_FILE_NAME).exists());
+
+	// A folder outside the program's own isn't made, e.g. an unconnected drive
+	let error = init_db(&dir.join("missing").join(DB_FILE_NAME)).unwrap_err();
+	assert!(error.to_string().contains("doesn't exist"));
+	assert!(!dir.join("missing").exists());
 
-    init_db_tables(&connection);
-    
-    connection
+	fs::remove_dir_all(&dir).unwrap();
+    }
 }
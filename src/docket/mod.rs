@@ -0,0 +1,393 @@
+pub mod pdf;
+pub mod template;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use crate::customer::Customer;
+use crate::database::db_dir;
+use crate::error::Error;
+use crate::order::Order;
+use crate::placeholder::fill;
+
+use self::pdf::{Font, Image, Pdf, PAGE_HEIGHT, PAGE_WIDTH};
+use self::template::{Block, DocketTemplate, DEFAULT_TEMPLATE};
+
+/// The template for dockets is kept next to the database with this name.
+pub const TEMPLATE_FILE_NAME: &str = "docket-template.txt";
+
+/// The space around the edge of the page, in points.
+const MARGIN: f32 = 50.0;
+
+/// The size of each kind of text, in points.
+const TITLE_SIZE: f32 = 20.0;
+const HEADING_SIZE: f32 = 13.0;
+const TEXT_SIZE: f32 = 11.0;
+
+/// How dates are written on a docket, e.g. "Thursday 20 March 2025".
+const DATE_FORMAT: &str = "%A %-d %B %Y";
+
+/// Gets the template next to the database, writing the default one there
+/// first if there isn't one. A database only in memory uses the default.
+pub fn load_template(connection: &Connection) -> Result<DocketTemplate, Error> {
+    let Some(dir) = db_dir(connection) else {
+	return DocketTemplate::parse(DEFAULT_TEMPLATE, Path::new("")).map_err(Error::Parse);
+    };
+    let path = dir.join(TEMPLATE_FILE_NAME);
+
+    let template = match fs::read_to_string(&path) {
+	Ok(template) => template,
+	Err(e) if e.kind() == io::ErrorKind::NotFound => {
+	    fs::write(&path, DEFAULT_TEMPLATE)?;
+	    DEFAULT_TEMPLATE.to_string()
+	},
+	Err(e) => return Err(e.into()),
+    };
+
+    DocketTemplate::parse(&template, &dir)
+	.map_err(|message| Error::Parse(format!("{}, {}", path.display(), message)))
+}
+
+/// Gets where the docket for an order is saved if no file is given, which is
+/// the dockets folder next to the database. It makes the folder if needed.
+pub fn default_docket_path(connection: &Connection, order: &Order) -> Result<PathBuf, Error> {
+    let dir = match db_dir(connection) {
+	Some(dir) => dir.join("dockets"),
+	None => std::env::temp_dir(),
+    };
+    fs::create_dir_all(&dir)?;
+
+    Ok(dir.join(format!("docket-{}-{}.pdf", order.receipt_number, order.id)))
+}
+
+/// Makes the docket for the order and saves it to `path`, or the default path
+/// for it if None. Gives back where it was saved.
+pub fn save_docket(
+    connection: &Connection,
+    order: &Order,
+    path: Option<&Path>,
+    printed_on: NaiveDate,
+) -> Result<PathBuf, Error> {
+    let template = load_template(connection)?;
+    let docket = Docket::new(connection, order.clone(), printed_on)?;
+    let pdf = docket.render(&template)?;
+
+    let path = match path {
+	Some(path) => path.to_path_buf(),
+	None => default_docket_path(connection, order)?,
+    };
+    fs::write(&path, pdf)?;
+
+    Ok(path)
+}
+
+/// Opens a saved docket in the program the computer uses for PDFs, so it can
+/// be printed from there.
+pub fn open_docket(path: &Path) -> Result<(), Error> {
+    #[cfg(target_os = "windows")]
+    let mut command = {
+	let mut command = Command::new("cmd");
+	command.args(["/C", "start", ""]);
+	command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = Command::new("xdg-open");
+
+    command.arg(path).spawn()?;
+
+    Ok(())
+}
+
+/// Represents the docket handed over with an order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Docket {
+    pub order: Order,
+    pub customer: Customer,
+    pub printed_on: NaiveDate,
+}
+
+impl Docket {
+    /// Gets what is needed for the docket of an order.
+    pub fn new(connection: &Connection, order: Order, printed_on: NaiveDate) -> Result<Self, Error> {
+	let customer = Customer::get_by_id(connection, order.customer_id)?;
+
+	Ok(Self { order, customer, printed_on })
+    }
+
+    /// Gets the value of each placeholder in the template.
+    pub fn values(&self) -> HashMap<&'static str, String> {
+	HashMap::from([
+	    ("customer_name", self.customer.name.clone()),
+	    ("customer_phone", self.customer.phone.clone()),
+	    ("customer_email", self.customer.email.clone()),
+	    ("customer_address", self.customer.address.clone()),
+	    ("order_id", self.order.id.to_string()),
+	    ("receipt_number", self.order.receipt_number.to_string()),
+	    ("hired_on", self.order.hired_on.format(DATE_FORMAT).to_string()),
+	    ("return_on", self.order.return_on.format(DATE_FORMAT).to_string()),
+	    ("raffle_number", self.order.raffle_number.to_string()),
+	    ("status", self.order.status.to_string()),
+	    ("total_items", self.order.how_many().to_string()),
+	    ("total_boxes", self.order.boxes_needed.to_string()),
+	    ("printed_on", self.printed_on.format(DATE_FORMAT).to_string()),
+	])
+    }
+
+    /// Lays the docket out as the template says, and makes it into a PDF.
+    pub fn render(&self, template: &DocketTemplate) -> Result<Vec<u8>, Error> {
+	let values = self.values();
+	let mut page = Page::new();
+
+	for block in &template.blocks {
+	    match block {
+		Block::Logo { path, width } => {
+		    let image = Image::load(path)?;
+		    let height = width * image.height as f32 / image.width.max(1) as f32;
+		    page.make_room(height);
+		    page.y -= height;
+		    page.pdf.image(&image, MARGIN, page.y, *width, height);
+		    page.y -= TEXT_SIZE;
+		},
+		Block::Title(title) => {
+		    if let Some(title) = fill(title, &values) {
+			page.paragraph(&title, Font::Bold, TITLE_SIZE);
+		    }
+		},
+		Block::Heading(heading) => {
+		    if let Some(heading) = fill(heading, &values) {
+			page.paragraph(&heading, Font::Bold, HEADING_SIZE);
+		    }
+		},
+		Block::Items => page.items_table(&self.order),
+		Block::Rule => page.rule(),
+		Block::Text(line) => {
+		    if let Some(line) = fill(line, &values) {
+			page.paragraph(&line, Font::Regular, TEXT_SIZE);
+		    }
+		},
+	    }
+	}
+
+	Ok(page.pdf.to_bytes())
+    }
+}
+
+/// Keeps track of where the next thing goes as a docket is laid out, going
+/// down the page and onto new ones.
+struct Page {
+    pdf: Pdf,
+    // The top of the next line, in points from the bottom of the page
+    y: f32,
+}
+
+impl Page {
+    fn new() -> Self {
+	Self { pdf: Pdf::new(), y: PAGE_HEIGHT - MARGIN }
+    }
+
+    /// Starts a new page if there isn't `height` points left on this one.
+    fn make_room(&mut self, height: f32) {
+	if self.y - height < MARGIN {
+	    self.pdf.new_page();
+	    self.y = PAGE_HEIGHT - MARGIN;
+	}
+    }
+
+    /// Writes one line of text at `x`, and moves down to the next line.
+    fn line(&mut self, x: f32, text: &str, font: Font, size: f32) {
+	let height = size * 1.3;
+	self.make_room(height);
+	if !text.is_empty() {
+	    self.pdf.text(x, self.y - size, size, font, text);
+	}
+	self.y -= height;
+    }
+
+    /// Writes text across the page, wrapping it onto more lines if needed.
+    fn paragraph(&mut self, text: &str, font: Font, size: f32) {
+	for line in wrap(text, font, size, PAGE_WIDTH - MARGIN * 2.0) {
+	    self.line(MARGIN, &line, font, size);
+	}
+    }
+
+    /// Draws a line across the page.
+    fn rule(&mut self) {
+	self.make_room(TEXT_SIZE);
+	self.y -= TEXT_SIZE / 2.0;
+	self.pdf.line(MARGIN, self.y, PAGE_WIDTH - MARGIN, self.y);
+	self.y -= TEXT_SIZE / 2.0;
+    }
+
+    /// Writes a table of the order's lines, with how many of each item and
+    /// the boxes they need, and the totals.
+    fn items_table(&mut self, order: &Order) {
+	let boxes_right = PAGE_WIDTH - MARGIN;
+	let how_many_right = boxes_right - 100.0;
+	let item_width = how_many_right - 100.0 - MARGIN;
+
+	let row = |page: &mut Self, item: &str, how_many: &str, boxes: &str, font: Font| {
+	    let lines = wrap(item, font, TEXT_SIZE, item_width);
+	    page.make_room(TEXT_SIZE * 1.3 * lines.len() as f32);
+
+	    let top = page.y;
+	    for (i, line) in lines.iter().enumerate() {
+		page.line(MARGIN, line, font, TEXT_SIZE);
+		if i == 0 {
+		    let baseline = top - TEXT_SIZE;
+		    page.pdf.text(how_many_right - font.width(how_many, TEXT_SIZE), baseline, TEXT_SIZE, font, how_many);
+		    page.pdf.text(boxes_right - font.width(boxes, TEXT_SIZE), baseline, TEXT_SIZE, font, boxes);
+		}
+	    }
+	};
+
+	row(self, "Item", "How many", "Boxes", Font::Bold);
+	self.rule();
+	for line in &order.lines {
+	    row(self, &line.item_hired, &line.how_many.to_string(), &line.boxes_needed.to_string(), Font::Regular);
+	}
+	self.rule();
+	row(self, "Total", &order.how_many().to_string(), &order.boxes_needed.to_string(), Font::Bold);
+    }
+}
+
+/// Splits text into lines that fit in `width` points. A word too long for a
+/// line is left on its own line.
+fn wrap(text: &str, font: Font, size: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split(' ') {
+	let candidate = match line.is_empty() {
+	    true => word.to_string(),
+	    false => format!("{} {}", line, word),
+	};
+
+	if font.width(&candidate, size) > width && !line.is_empty() {
+	    lines.push(line);
+	    line = word.to_string();
+	} else {
+	    line = candidate;
+	}
+    }
+    lines.push(line);
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::customer::Customer;
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn test_order(con: &Connection) -> Order {
+	Customer::new(
+	    con,
+	    "Test Person".to_string(),
+	    "021 123 4567".to_string(),
+	    String::new(),
+	    String::new(),
+	    String::new(),
+	).unwrap();
+
+	Order::new(
+	    con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26), ("Other Item".to_string(), 3)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap()
+    }
+
+    /// Gets the text on the pages of a PDF, decoded from the hex strings.
+    fn pdf_text(pdf: &[u8]) -> String {
+	let pdf = String::from_utf8_lossy(pdf);
+
+	pdf.split('<')
+	    .filter_map(|part| part.split_once("> Tj").map(|(hex, _)| hex))
+	    .map(|hex| {
+		(0..hex.len()).step_by(2)
+		    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap() as char)
+		    .collect::<String>()
+	    })
+	    .collect::<Vec<_>>()
+	    .join("\n")
+    }
+
+    #[test]
+    fn test_render_default_template() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let docket = Docket::new(&con, test_order(&con), NaiveDate::from_ymd_opt(2025, 3, 18).unwrap()).unwrap();
+	let template = load_template(&con).unwrap();
+	let text = pdf_text(&docket.render(&template).unwrap());
+
+	assert!(text.contains("Receipt 15"));
+	assert!(text.contains("021 123 4567"));
+	assert!(text.contains("Picked up on Thursday 20 March 2025"));
+	assert!(text.contains("Due back by Sunday 23 March 2025"));
+	assert!(text.contains(&format!("Raffle number {}", docket.order.raffle_number)));
+	assert!(text.contains("Test Item\n26\n2"));
+	// The leftover Test Item shares a box with the Other Items
+	assert!(text.contains("Total\n29\n2"));
+    }
+
+    #[test]
+    fn test_render_goes_onto_more_pages() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let docket = Docket::new(&con, test_order(&con), NaiveDate::from_ymd_opt(2025, 3, 18).unwrap()).unwrap();
+	let template = DocketTemplate::parse(&"Lots of terms and conditions\n".repeat(100), Path::new("")).unwrap();
+	let pdf = String::from_utf8_lossy(&docket.render(&template).unwrap()).to_string();
+
+	// About 50 lines fit on a page
+	assert!(pdf.contains("/Count 2"));
+    }
+
+    #[test]
+    fn test_save_docket_uses_template_next_to_database() {
+	let dir = std::env::temp_dir().join(format!("as91896-docket-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let con = Connection::open(dir.join("orders.db")).unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con);
+	let today = NaiveDate::from_ymd_opt(2025, 3, 18).unwrap();
+	let path = save_docket(&con, &order, None, today).unwrap();
+	assert_eq!(path, dir.join("dockets").join(format!("docket-15-{}.pdf", order.id)));
+	assert_eq!(fs::read_to_string(dir.join(TEMPLATE_FILE_NAME)).unwrap(), DEFAULT_TEMPLATE);
+
+	fs::write(dir.join(TEMPLATE_FILE_NAME), "@title Shop Two\n{customer_name}").unwrap();
+	save_docket(&con, &order, None, today).unwrap();
+	assert_eq!(pdf_text(&fs::read(&path).unwrap()), "Shop Two\nTest Person");
+
+	fs::write(dir.join(TEMPLATE_FILE_NAME), "@logo missing.png").unwrap();
+	assert!(save_docket(&con, &order, None, today).is_err());
+
+	fs::write(dir.join(TEMPLATE_FILE_NAME), "{customer}").unwrap();
+	assert!(matches!(save_docket(&con, &order, None, today), Err(Error::Parse(_))));
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wrap() {
+	assert_eq!(wrap("one two three", Font::Regular, 10.0, 50.0), vec!["one two", "three"]);
+	assert_eq!(wrap("", Font::Regular, 10.0, 50.0), vec![""]);
+	assert_eq!(wrap("unbreakablelongword", Font::Regular, 10.0, 20.0), vec!["unbreakablelongword"]);
+    }
+}
@@ -0,0 +1,392 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use crate::error::Error;
+
+/// The size of an A4 page, in points.
+pub const PAGE_WIDTH: f32 = 595.28;
+pub const PAGE_HEIGHT: f32 = 841.89;
+
+/// How wide each printable ASCII character is in Helvetica, in thousandths of
+/// the font size, starting from the space.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// The same as HELVETICA_WIDTHS, for Helvetica-Bold.
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+/// The fonts text can be written in. Both are built into every PDF reader, so
+/// they don't need to be embedded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    /// Gets the name the font is given in each page's resources.
+    fn resource_name(&self) -> &'static str {
+	match self {
+	    Font::Regular => "F1",
+	    Font::Bold => "F2",
+	}
+    }
+
+    /// Gets how wide the text is at the given size, in points.
+    pub fn width(&self, text: &str, size: f32) -> f32 {
+	let widths = match self {
+	    Font::Regular => &HELVETICA_WIDTHS,
+	    Font::Bold => &HELVETICA_BOLD_WIDTHS,
+	};
+
+	let thousandths: u32 = text.chars()
+	    .map(|c| match c {
+		' '..='~' => widths[c as usize - ' ' as usize] as u32,
+		_ => 556,
+	    })
+	    .sum();
+
+	thousandths as f32 * size / 1000.0
+    }
+}
+
+/// Represents an image to put on a page, already in the form PDF stores it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    color_space: &'static str,
+    filter: &'static str,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Loads a PNG or JPEG image.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+	let bytes = fs::read(path)?;
+
+	let image = if bytes.starts_with(&[0x89, b'P', b'N', b'G']) {
+	    Self::from_png(&bytes)
+	} else if bytes.starts_with(&[0xFF, 0xD8]) {
+	    Self::from_jpeg(bytes)
+	} else {
+	    Err("only PNG and JPEG images can be used".to_string())
+	};
+
+	image.map_err(|message| Error::Parse(format!("{}: {}", path.display(), message)))
+    }
+
+    /// Decodes a PNG, putting any transparent parts on white, as PDF can't
+    /// read PNGs itself.
+    fn from_png(bytes: &[u8]) -> Result<Self, String> {
+	let mut decoder = png::Decoder::new(bytes);
+	decoder.set_transformations(png::Transformations::normalize_to_color8());
+	let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+	let mut pixels = vec![0; reader.output_buffer_size()];
+	let info = reader.next_frame(&mut pixels).map_err(|e| e.to_string())?;
+	pixels.truncate(info.buffer_size());
+
+	let on_white = |value: u8, alpha: u8| {
+	    ((value as u32 * alpha as u32 + 255 * (255 - alpha as u32)) / 255) as u8
+	};
+	let rgb: Vec<u8> = match info.color_type {
+	    png::ColorType::Rgb => pixels,
+	    png::ColorType::Rgba => pixels.chunks(4)
+		.flat_map(|p| [on_white(p[0], p[3]), on_white(p[1], p[3]), on_white(p[2], p[3])])
+		.collect(),
+	    png::ColorType::Grayscale => pixels.iter().flat_map(|&v| [v, v, v]).collect(),
+	    png::ColorType::GrayscaleAlpha => pixels.chunks(2)
+		.flat_map(|p| [on_white(p[0], p[1]); 3])
+		.collect(),
+	    png::ColorType::Indexed => return Err("the colours of the image couldn't be read".to_string()),
+	};
+
+	let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+	encoder.write_all(&rgb).map_err(|e| e.to_string())?;
+
+	Ok(Self {
+	    width: info.width,
+	    height: info.height,
+	    color_space: "DeviceRGB",
+	    filter: "FlateDecode",
+	    data: encoder.finish().map_err(|e| e.to_string())?,
+	})
+    }
+
+    /// Reads the size of a JPEG, which PDF can read as it is.
+    fn from_jpeg(bytes: Vec<u8>) -> Result<Self, String> {
+	let mut i = 2;
+
+	// Each segment is a marker, then its length, until the frame header
+	while i + 9 < bytes.len() {
+	    if bytes[i] != 0xFF {
+		break;
+	    }
+	    let marker = bytes[i + 1];
+	    let length = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]) as usize;
+
+	    let is_frame_header = (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker);
+	    if is_frame_header {
+		let color_space = match bytes[i + 9] {
+		    1 => "DeviceGray",
+		    3 => "DeviceRGB",
+		    4 => "DeviceCMYK",
+		    _ => return Err("the colours of the image couldn't be read".to_string()),
+		};
+
+		return Ok(Self {
+		    height: u16::from_be_bytes([bytes[i + 5], bytes[i + 6]]) as u32,
+		    width: u16::from_be_bytes([bytes[i + 7], bytes[i + 8]]) as u32,
+		    color_space,
+		    filter: "DCTDecode",
+		    data: bytes,
+		});
+	    }
+
+	    i += 2 + length;
+	}
+
+	Err("the size of the image couldn't be read".to_string())
+    }
+}
+
+/// Builds a PDF of A4 pages with text, lines and images on them. Positions are
+/// in points from the bottom left of the page.
+#[derive(Debug, Default)]
+pub struct Pdf {
+    // The content stream of each page
+    pages: Vec<String>,
+    images: Vec<Image>,
+}
+
+impl Pdf {
+    /// Starts a PDF with one empty page.
+    pub fn new() -> Self {
+	Self {
+	    pages: vec![String::new()],
+	    images: Vec::new(),
+	}
+    }
+
+    /// Starts a new page, which everything after goes on.
+    pub fn new_page(&mut self) {
+	self.pages.push(String::new());
+    }
+
+    /// Writes text with its baseline starting at (x, y).
+    pub fn text(&mut self, x: f32, y: f32, size: f32, font: Font, text: &str) {
+	self.draw(format!(
+	    "BT /{} {:.2} Tf {:.2} {:.2} Td <{}> Tj ET\n",
+	    font.resource_name(),
+	    size,
+	    x,
+	    y,
+	    encode_text(text),
+	));
+    }
+
+    /// Draws a thin line.
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+	self.draw(format!("0.5 w {:.2} {:.2} m {:.2} {:.2} l S\n", x1, y1, x2, y2));
+    }
+
+    /// Draws an image with its bottom left corner at (x, y), stretched to the given size.
+    pub fn image(&mut self, image: &Image, x: f32, y: f32, width: f32, height: f32) {
+	let index = match self.images.iter().position(|existing| existing == image) {
+	    Some(index) => index,
+	    None => {
+		self.images.push(image.clone());
+		self.images.len() - 1
+	    },
+	};
+
+	self.draw(format!(
+	    "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q\n",
+	    width,
+	    height,
+	    x,
+	    y,
+	    index,
+	));
+    }
+
+    /// Adds drawing operators to the current page.
+    fn draw(&mut self, operators: String) {
+	if let Some(page) = self.pages.last_mut() {
+	    page.push_str(&operators);
+	}
+    }
+
+    /// Writes out the PDF file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+	// Objects are numbered from 1: the catalog, the page tree, the two
+	// fonts, the images, then each page followed by its contents
+	let first_image = 5;
+	let first_page = first_image + self.images.len();
+	let page_ids: Vec<usize> = (0..self.pages.len()).map(|i| first_page + i * 2).collect();
+
+	let mut objects: Vec<Vec<u8>> = vec![
+	    b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+	    format!(
+		"<< /Type /Pages /Kids [{}] /Count {} >>",
+		page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+		self.pages.len(),
+	    ).into_bytes(),
+	    b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+	    b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec(),
+	];
+
+	for image in &self.images {
+	    let mut object = format!(
+		"<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /{} \
+		 /BitsPerComponent 8 /Filter /{} /Length {} >>\nstream\n",
+		image.width,
+		image.height,
+		image.color_space,
+		image.filter,
+		image.data.len(),
+	    ).into_bytes();
+	    object.extend_from_slice(&image.data);
+	    object.extend_from_slice(b"\nendstream");
+	    objects.push(object);
+	}
+
+	let images: String = (0..self.images.len())
+	    .map(|i| format!("/Im{} {} 0 R ", i, first_image + i))
+	    .collect();
+	for (page, id) in self.pages.iter().zip(&page_ids) {
+	    objects.push(format!(
+		"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+		 /Resources << /Font << /F1 3 0 R /F2 4 0 R >> /XObject << {}>> >> /Contents {} 0 R >>",
+		PAGE_WIDTH,
+		PAGE_HEIGHT,
+		images,
+		id + 1,
+	    ).into_bytes());
+	    objects.push(format!("<< /Length {} >>\nstream\n{}endstream", page.len(), page).into_bytes());
+	}
+
+	let mut pdf = b"%PDF-1.4\n".to_vec();
+	let mut offsets = Vec::new();
+	for (i, object) in objects.iter().enumerate() {
+	    offsets.push(pdf.len());
+	    pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+	    pdf.extend_from_slice(object);
+	    pdf.extend_from_slice(b"\nendobj\n");
+	}
+
+	let xref = pdf.len();
+	pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+	for offset in offsets {
+	    pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+	}
+	pdf.extend_from_slice(format!(
+	    "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+	    objects.len() + 1,
+	    xref,
+	).as_bytes());
+
+	pdf
+    }
+}
+
+/// Encodes text as a hex string in the fonts' encoding. Characters the fonts
+/// don't have are written as a question mark.
+fn encode_text(text: &str) -> String {
+    text.chars()
+	.map(|c| match c {
+	    ' '..='~' | '\u{A0}'..='\u{FF}' => c as u8,
+	    '€' => 0x80,
+	    '‘' => 0x91,
+	    '’' => 0x92,
+	    '“' => 0x93,
+	    '”' => 0x94,
+	    '•' => 0x95,
+	    '–' => 0x96,
+	    '—' => 0x97,
+	    _ => b'?',
+	})
+	.map(|byte| format!("{:02X}", byte))
+	.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_font_width() {
+	assert_eq!(Font::Regular.width("Hi", 10.0), 9.44);
+	assert!(Font::Bold.width("Hi", 10.0) > Font::Regular.width("Hi", 10.0));
+    }
+
+    #[test]
+    fn test_encode_text() {
+	assert_eq!(encode_text("A(é)"), "4128E929");
+	assert_eq!(encode_text("’☃"), "923F");
+    }
+
+    #[test]
+    fn test_to_bytes() {
+	let mut pdf = Pdf::new();
+	pdf.text(50.0, 800.0, 12.0, Font::Bold, "Hello");
+	pdf.new_page();
+	pdf.line(50.0, 700.0, 100.0, 700.0);
+
+	let bytes = pdf.to_bytes();
+	let text = String::from_utf8_lossy(&bytes);
+
+	assert!(text.starts_with("%PDF-1.4"));
+	assert!(text.contains("/Count 2"));
+	assert!(text.contains("/F2 12.00 Tf 50.00 800.00 Td <48656C6C6F> Tj"));
+	assert!(text.ends_with("%%EOF\n"));
+
+	// Every object is where the cross-reference table says
+	let xref = text.find("xref").unwrap();
+	for (i, line) in text[xref..].lines().skip(3).take(8).enumerate() {
+	    let offset: usize = line[..10].parse().unwrap();
+	    assert!(text[offset..].starts_with(&format!("{} 0 obj", i + 1)));
+	}
+    }
+
+    #[test]
+    fn test_load_png() {
+	let dir = std::env::temp_dir().join(format!("as91896-pdf-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let path = dir.join("logo.png");
+
+	// A 2x1 image, with a red pixel and a see-through one
+	let mut png = Vec::new();
+	let mut encoder = png::Encoder::new(&mut png, 2, 1);
+	encoder.set_color(png::ColorType::Rgba);
+	encoder.set_depth(png::BitDepth::Eight);
+	encoder.write_header().unwrap().write_image_data(&[255, 0, 0, 255, 0, 0, 0, 0]).unwrap();
+	fs::write(&path, &png).unwrap();
+
+	let image = Image::load(&path).unwrap();
+	assert_eq!((image.width, image.height), (2, 1));
+	let mut pixels = Vec::new();
+	std::io::Read::read_to_end(&mut flate2::read::ZlibDecoder::new(image.data.as_slice()), &mut pixels).unwrap();
+	assert_eq!(pixels, vec![255, 0, 0, 255, 255, 255]);
+
+	fs::write(&path, "not an image").unwrap();
+	assert!(matches!(Image::load(&path), Err(Error::Parse(_))));
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+}
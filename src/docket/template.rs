@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+use crate::placeholder::check_placeholders;
+
+/// The template used when there isn't one next to the database, which is
+/// written there so it can be changed.
+pub const DEFAULT_TEMPLATE: &str = "\
+# The layout of the dockets printed for orders. Change it to add a logo or
+# change the wording, then print the docket again to see the change.
+#
+# Lines starting with @ lay out the page:
+#   @logo FILE [WIDTH]   a PNG or JPEG image, next to this file, WIDTH points wide
+#   @title TEXT          large bold text
+#   @heading TEXT        bold text
+#   @items               the table of items, how many and the boxes they need
+#   @rule                a line across the page
+# Any other line is printed as it is, and wrapped if it is too long. A line
+# whose {placeholders} are all empty, like a customer with no phone number, is
+# left out. The placeholders are:
+#   {customer_name} {customer_phone} {customer_email} {customer_address}
+#   {order_id} {receipt_number} {hired_on} {return_on} {raffle_number}
+#   {status} {total_items} {total_boxes} {printed_on}
+# Lines starting with # are ignored.
+
+@title Hire Docket
+Receipt {receipt_number}
+Printed {printed_on}
+@rule
+@heading Customer
+{customer_name}
+{customer_phone}
+{customer_email}
+{customer_address}
+
+@heading Hire
+Picked up on {hired_on}
+Due back by {return_on}
+Raffle number {raffle_number}
+
+@items
+
+@heading Terms
+Items must be returned by the return date in the boxes they were hired in.
+Lost or damaged items are charged at their replacement cost.
+
+Signed: ______________________________
+";
+
+/// The placeholders that can be used in a template.
+pub const PLACEHOLDERS: [&str; 13] = [
+    "customer_name",
+    "customer_phone",
+    "customer_email",
+    "customer_address",
+    "order_id",
+    "receipt_number",
+    "hired_on",
+    "return_on",
+    "raffle_number",
+    "status",
+    "total_items",
+    "total_boxes",
+    "printed_on",
+];
+
+/// How wide a logo is if the template doesn't say, in points.
+const DEFAULT_LOGO_WIDTH: f32 = 150.0;
+
+/// Represents one line of a template.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Logo { path: PathBuf, width: f32 },
+    Title(String),
+    Heading(String),
+    Items,
+    Rule,
+    Text(String),
+}
+
+/// Represents the layout of a docket, read from a template file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocketTemplate {
+    pub blocks: Vec<Block>,
+}
+
+impl DocketTemplate {
+    /// Reads a template. Files it names are found in `dir`.
+    pub fn parse(template: &str, dir: &Path) -> Result<Self, String> {
+	let mut blocks = Vec::new();
+
+	for (number, line) in template.lines().enumerate() {
+	    let error = |message: String| format!("line {}: {}", number + 1, message);
+	    let line = line.trim_end();
+
+	    // Blank lines before anything is on the page would only push it down
+	    if line.starts_with('#') || (line.is_empty() && blocks.is_empty()) {
+		continue;
+	    }
+	    check_placeholders(line, &PLACEHOLDERS).map_err(error)?;
+
+	    let Some(directive) = line.strip_prefix('@') else {
+		blocks.push(Block::Text(line.to_string()));
+		continue;
+	    };
+	    let (name, rest) = directive.split_once(' ').unwrap_or((directive, ""));
+	    let rest = rest.trim();
+
+	    let block = match name {
+		"logo" => {
+		    let (file, width) = match rest.rsplit_once(' ') {
+			Some((file, width)) if width.parse::<f32>().is_ok() => (file.trim(), width.parse().ok()),
+			_ => (rest, None),
+		    };
+		    if file.is_empty() {
+			return Err(error("@logo needs the image file".to_string()));
+		    }
+
+		    Block::Logo {
+			path: dir.join(file),
+			width: width.filter(|width| *width > 0.0).unwrap_or(DEFAULT_LOGO_WIDTH),
+		    }
+		},
+		"title" => Block::Title(rest.to_string()),
+		"heading" => Block::Heading(rest.to_string()),
+		"items" => Block::Items,
+		"rule" => Block::Rule,
+		_ => return Err(error(format!("there is no @{}", name))),
+	    };
+	    blocks.push(block);
+	}
+
+	Ok(Self { blocks })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_default_template() {
+	let template = DocketTemplate::parse(DEFAULT_TEMPLATE, Path::new("/docs")).unwrap();
+
+	assert_eq!(template.blocks[0], Block::Title("Hire Docket".to_string()));
+	assert!(template.blocks.contains(&Block::Items));
+    }
+
+    #[test]
+    fn test_parse() {
+	let template = DocketTemplate::parse(
+	    "# A comment\n@logo logo.png\n@logo my logo.jpg 80\n@rule\nHi {customer_name}",
+	    Path::new("/docs"),
+	).unwrap();
+
+	assert_eq!(template.blocks, vec![
+	    Block::Logo { path: PathBuf::from("/docs/logo.png"), width: DEFAULT_LOGO_WIDTH },
+	    Block::Logo { path: PathBuf::from("/docs/my logo.jpg"), width: 80.0 },
+	    Block::Rule,
+	    Block::Text("Hi {customer_name}".to_string()),
+	]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+	let dir = Path::new("/docs");
+
+	assert_eq!(DocketTemplate::parse("\n@footer Bye", dir), Err("line 2: there is no @footer".to_string()));
+	assert_eq!(
+	    DocketTemplate::parse("Hi {name}", dir),
+	    Err("line 1: {name} isn't a placeholder".to_string()),
+	);
+	assert!(DocketTemplate::parse("Hi {customer_name", dir).is_err());
+	assert!(DocketTemplate::parse("@logo", dir).is_err());
+    }
+}
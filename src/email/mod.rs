@@ -0,0 +1,578 @@
+pub mod smtp;
+#[cfg(test)]
+pub mod test_server;
+
+use chrono::{Days, NaiveDateTime, TimeDelta};
+use lettre::message::Mailbox;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use crate::customer::Customer;
+use crate::database::with_savepoint;
+use crate::error::Error;
+use crate::order::{Order, OrderQuery, OrderStatus};
+use crate::order::query::StatusFilter;
+use crate::reminder::{self, reminder_values, OverdueOrder, Reminder, ReminderKind, ReminderMethod};
+use crate::settings::{EMAIL_MAX_ATTEMPTS, PICKUP_REMINDER_DAYS, REMINDER_INTERVAL_DAYS, RETURN_REMINDER_DAYS};
+
+use self::smtp::{SendError, SmtpMailer, SmtpSettings};
+
+/// How long to wait before trying a failed email again. Each try after that
+/// waits twice as long as the one before.
+const RETRY_DELAY_MINUTES: i64 = 5;
+
+/// The columns of OutgoingEmail, in the order OutgoingEmail::from_row reads them.
+const EMAIL_COLUMNS: &str = "id, order_id, kind, to_address, subject, body, queued_at, attempts,
+    next_attempt_at, last_error, sent_at";
+
+/// Where an email in the queue is up to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmailStatus {
+    /// It will be sent, or tried again, when the queue is next sent.
+    Waiting,
+    Sent,
+    /// It was tried too many times, or can never be sent, so was given up on.
+    Failed,
+}
+
+impl EmailStatus {
+    /// Gets the name of the status.
+    pub fn as_str(&self) -> &'static str {
+	match self {
+	    EmailStatus::Waiting => "Waiting",
+	    EmailStatus::Sent => "Sent",
+	    EmailStatus::Failed => "Failed",
+	}
+    }
+}
+
+/// Represents a reminder email about an order, waiting to be sent or already
+/// sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutgoingEmail {
+    pub id: i32,
+    pub order_id: i32,
+    pub kind: ReminderKind,
+    // The address, with the customer's name, e.g. "Test Person <test@example.com>"
+    pub to_address: String,
+    pub subject: String,
+    pub body: String,
+    pub queued_at: NaiveDateTime,
+    pub attempts: i64,
+    pub next_attempt_at: NaiveDateTime,
+    // Why the last try failed
+    pub last_error: Option<String>,
+    pub sent_at: Option<NaiveDateTime>,
+}
+
+/// Represents what sending the queue did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SendSummary {
+    pub queued: usize,
+    pub sent: usize,
+    pub failed: usize,
+}
+
+impl OutgoingEmail {
+    /// Gets the email with the given id.
+    pub fn get_by_id(connection: &Connection, id: i32) -> Result<Self, Error> {
+	Ok(connection.query_row(
+	    format!("SELECT {} FROM OutgoingEmail WHERE id = ?1", EMAIL_COLUMNS).as_str(),
+	    [id],
+	    Self::from_row,
+	)?)
+    }
+
+    /// Gets every email in the queue, newest first.
+    pub fn get_all(connection: &Connection) -> Result<Vec<Self>, Error> {
+	let mut stmt = connection.prepare(
+	    format!("SELECT {} FROM OutgoingEmail ORDER BY queued_at DESC, id DESC", EMAIL_COLUMNS).as_str()
+	)?;
+
+	let emails = stmt.query_map([], Self::from_row)?.collect::<Result<_, _>>()?;
+
+	Ok(emails)
+    }
+
+    /// Gets where the email is up to, given how many tries it gets.
+    pub fn status(&self, max_attempts: i64) -> EmailStatus {
+	if self.sent_at.is_some() {
+	    EmailStatus::Sent
+	} else if self.attempts >= max_attempts {
+	    EmailStatus::Failed
+	} else {
+	    EmailStatus::Waiting
+	}
+    }
+
+    /// Describes where the email is up to, e.g. "Sent 2025-03-20 12:00".
+    pub fn describe_status(&self, max_attempts: i64) -> String {
+	let error = self.last_error.clone().unwrap_or_default();
+
+	match (self.status(max_attempts), self.sent_at) {
+	    (EmailStatus::Sent, Some(sent_at)) => format!("Sent {}", sent_at.format("%Y-%m-%d %H:%M")),
+	    (EmailStatus::Failed, _) => format!("Failed: {}", error),
+	    _ if self.attempts > 0 => format!("Trying again {}: {}", self.next_attempt_at.format("%Y-%m-%d %H:%M"), error),
+	    _ => "Waiting".to_string(),
+	}
+    }
+
+    /// Queues a reminder about the order to the customer, filled in from the
+    /// template for the kind of reminder.
+    fn queue(
+	connection: &Connection,
+	order: &Order,
+	customer: &Customer,
+	kind: ReminderKind,
+	now: NaiveDateTime,
+    ) -> Result<Self, Error> {
+	let template = reminder::load_template(connection, kind)?;
+	let (subject, body) = template.render(&reminder_values(order, customer, now.date()));
+	// An address that can't be sent to is kept as it is, and fails when sent
+	let to_address = match customer.email.parse() {
+	    Ok(address) => Mailbox::new(Some(customer.name.clone()), address).to_string(),
+	    Err(_) => customer.email.clone(),
+	};
+
+	connection.execute(
+	    "INSERT INTO OutgoingEmail (order_id, kind, to_address, subject, body, queued_at, next_attempt_at)
+	     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+	    params![order.id, kind, to_address, subject, body, now],
+	)?;
+
+	Self::get_by_id(connection, connection.last_insert_rowid() as i32)
+    }
+
+    /// Sends an email that was given up on again, the next time the queue is sent.
+    pub fn retry(connection: &Connection, id: i32, now: NaiveDateTime) -> Result<Self, Error> {
+	let email = Self::get_by_id(connection, id)?;
+	if email.sent_at.is_some() {
+	    return Err(Error::Validation(format!("Email {} has already been sent", id)));
+	}
+
+	connection.execute(
+	    "UPDATE OutgoingEmail SET attempts = 0, next_attempt_at = ?2 WHERE id = ?1",
+	    params![id, now],
+	)?;
+
+	Self::get_by_id(connection, id)
+    }
+
+    /// Builds an OutgoingEmail from a row selected with EMAIL_COLUMNS.
+    fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
+	Ok(Self {
+	    id: row.get(0)?,
+	    order_id: row.get(1)?,
+	    kind: row.get(2)?,
+	    to_address: row.get(3)?,
+	    subject: row.get(4)?,
+	    body: row.get(5)?,
+	    queued_at: row.get(6)?,
+	    attempts: row.get(7)?,
+	    next_attempt_at: row.get(8)?,
+	    last_error: row.get(9)?,
+	    sent_at: row.get(10)?,
+	})
+    }
+}
+
+/// Queues an email for each reminder that is due: orders picked up or due back
+/// in the next few days, as the settings say, and overdue orders whose customer
+/// hasn't been reminded lately. Customers are only emailed once before a pickup
+/// or return, and not while an email about the same thing is still in the
+/// queue. Gives how many were queued.
+pub fn queue_reminders(connection: &Connection, now: NaiveDateTime) -> Result<usize, Error> {
+    let today = now.date();
+    let pickup_days = PICKUP_REMINDER_DAYS.get(connection)? as u64;
+    let return_days = RETURN_REMINDER_DAYS.get(connection)? as u64;
+    let interval_days = REMINDER_INTERVAL_DAYS.get(connection)?;
+
+    let pickups = OrderQuery {
+	status: Some(StatusFilter::Is(OrderStatus::Booked)),
+	hired_between: Some((today, today + Days::new(pickup_days))),
+	..Default::default()
+    };
+    let returns = OrderQuery {
+	status: Some(StatusFilter::Is(OrderStatus::Out)),
+	returning_between: Some((today, today + Days::new(return_days))),
+	..Default::default()
+    };
+
+    let mut due = Vec::new();
+    for order in Order::query_as_of(connection, &pickups, today)? {
+	due.push((order, ReminderKind::Pickup));
+    }
+    for order in Order::query_as_of(connection, &returns, today)? {
+	due.push((order, ReminderKind::Return));
+    }
+    for overdue_order in OverdueOrder::get_all(connection, today)? {
+	if overdue_order.needs_reminder(now, interval_days) {
+	    due.push((overdue_order.order, ReminderKind::Overdue));
+	}
+    }
+
+    with_savepoint(connection, || {
+	let mut queued = 0;
+
+	for (order, kind) in &due {
+	    let customer = Customer::get_by_id(connection, order.customer_id)?;
+	    if customer.email.is_empty() || is_queued(connection, order.id, *kind)? {
+		continue;
+	    }
+	    // Overdue orders are reminded again after a while, which was checked above
+	    let reminded = Reminder::get_for_order(connection, order.id)?
+		.iter()
+		.any(|reminder| reminder.kind == *kind);
+	    if *kind != ReminderKind::Overdue && reminded {
+		continue;
+	    }
+
+	    OutgoingEmail::queue(connection, order, &customer, *kind, now)?;
+	    queued += 1;
+	}
+
+	Ok(queued)
+    })
+}
+
+/// Checks if an email about the order that hasn't been sent is in the queue,
+/// even one that was given up on.
+fn is_queued(connection: &Connection, order_id: i32, kind: ReminderKind) -> Result<bool, Error> {
+    let id: Option<i32> = connection.query_row(
+	"SELECT id FROM OutgoingEmail WHERE order_id = ?1 AND kind = ?2 AND sent_at IS NULL",
+	params![order_id, kind],
+	|row| row.get(0),
+    ).optional()?;
+
+    Ok(id.is_some())
+}
+
+/// An email that was tried, and whether it was sent.
+type SendResult = (OutgoingEmail, Result<(), SendError>);
+
+/// Represents the emails taken from the queue to be sent. Sending doesn't use
+/// the database, so it can be done on another thread while the program keeps
+/// responding.
+#[derive(Clone, Debug)]
+pub struct SendBatch {
+    settings: SmtpSettings,
+    emails: Vec<OutgoingEmail>,
+    now: NaiveDateTime,
+    // How many reminders were queued before the batch was taken
+    queued: usize,
+}
+
+/// Represents what happened when a batch was sent, to be recorded in the
+/// database.
+#[derive(Clone, Debug)]
+pub struct SentBatch {
+    // Each email with whether it was sent, or Err if the mail server couldn't
+    // be used at all
+    results: Result<Vec<SendResult>, String>,
+    now: NaiveDateTime,
+    queued: usize,
+}
+
+impl SendBatch {
+    /// Takes the emails in the queue that are due to be tried, to be sent
+    /// through the mail server in the settings.
+    pub fn take(connection: &Connection, settings: &SmtpSettings, now: NaiveDateTime) -> Result<Self, Error> {
+	let max_attempts = EMAIL_MAX_ATTEMPTS.get(connection)?;
+	let mut stmt = connection.prepare(
+	    format!(
+		"SELECT {} FROM OutgoingEmail
+		 WHERE sent_at IS NULL AND attempts < ?1 AND next_attempt_at <= ?2
+		    AND order_id NOT IN (SELECT id FROM CustomerOrder WHERE deleted_at IS NOT NULL)
+		 ORDER BY id",
+		EMAIL_COLUMNS,
+	    ).as_str()
+	)?;
+	let emails = stmt.query_map(params![max_attempts, now], OutgoingEmail::from_row)?
+	    .collect::<Result<_, _>>()?;
+
+	Ok(Self { settings: settings.clone(), emails, now, queued: 0 })
+    }
+
+    /// Sends the emails, which waits on the mail server, so can take a while.
+    pub fn send(self) -> SentBatch {
+	let results = match self.emails.is_empty() {
+	    true => Ok(Vec::new()),
+	    false => SmtpMailer::new(&self.settings).map_err(|e| e.to_string()).map(|mailer| {
+		self.emails.into_iter().map(|email| {
+		    let result = mailer.send(&email.to_address, &email.subject, &email.body);
+		    (email, result)
+		}).collect()
+	    }),
+	};
+
+	SentBatch { results, now: self.now, queued: self.queued }
+    }
+}
+
+impl SentBatch {
+    /// Makes a batch where nothing could be sent, e.g. because the thread
+    /// sending it stopped, so nothing is recorded.
+    pub fn failed(message: String) -> Self {
+	Self { results: Err(message), now: NaiveDateTime::default(), queued: 0 }
+    }
+
+    /// Records what happened to each email. One that was sent is recorded as a
+    /// reminder about its order. One that failed is tried again later, waiting
+    /// longer each time, until it has been tried as many times as the settings
+    /// allow.
+    pub fn record(self, connection: &Connection) -> Result<SendSummary, Error> {
+	let max_attempts = EMAIL_MAX_ATTEMPTS.get(connection)?;
+	let now = self.now;
+	let mut summary = SendSummary { queued: self.queued, ..Default::default() };
+
+	for (email, result) in self.results.map_err(Error::Validation)? {
+	    let attempts = email.attempts + 1;
+
+	    match result {
+		Ok(()) => {
+		    with_savepoint(connection, || {
+			connection.execute(
+			    "UPDATE OutgoingEmail SET attempts = ?2, sent_at = ?3, last_error = NULL WHERE id = ?1",
+			    params![email.id, attempts, now],
+			)?;
+			Reminder::record(connection, email.order_id, email.kind, now, ReminderMethod::Email)
+		    })?;
+		    summary.sent += 1;
+		},
+		Err(e) => {
+		    let (attempts, retry_at) = match e {
+			SendError::Permanent(_) => (max_attempts, now),
+			SendError::Transient(_) => {
+			    let delay = RETRY_DELAY_MINUTES << (attempts - 1).min(16);
+			    (attempts, now + TimeDelta::minutes(delay))
+			},
+		    };
+		    connection.execute(
+			"UPDATE OutgoingEmail SET attempts = ?2, next_attempt_at = ?3, last_error = ?4 WHERE id = ?1",
+			params![email.id, attempts, retry_at, e.to_string()],
+		    )?;
+		    summary.failed += 1;
+		},
+	    }
+	}
+
+	Ok(summary)
+    }
+}
+
+/// Queues the reminders that are due and takes the emails that are ready to
+/// send, if email has been set up. Gives None if it hasn't.
+pub fn take_reminders(connection: &Connection, now: NaiveDateTime) -> Result<Option<SendBatch>, Error> {
+    let settings = SmtpSettings::load(connection)?;
+    if !settings.is_configured() {
+	return Ok(None);
+    }
+
+    let queued = queue_reminders(connection, now)?;
+    let batch = SendBatch::take(connection, &settings, now)?;
+
+    Ok(Some(SendBatch { queued, ..batch }))
+}
+
+/// Queues the reminders that are due and sends the queue, if email has been
+/// set up. Gives None if it hasn't.
+pub fn send_reminders(connection: &Connection, now: NaiveDateTime) -> Result<Option<SendSummary>, Error> {
+    match take_reminders(connection, now)? {
+	Some(batch) => Ok(Some(batch.send().record(connection)?)),
+	None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use crate::database::init_db_tables;
+
+    use super::*;
+    use super::test_server::TestSmtpServer;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+	NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    fn noon(month: u32, day: u32) -> NaiveDateTime {
+	date(month, day).and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    /// Sends the emails in the queue that are due to be tried, and records
+    /// what happened.
+    fn send_queued(connection: &Connection, settings: &SmtpSettings, now: NaiveDateTime) -> Result<SendSummary, Error> {
+	SendBatch::take(connection, settings, now)?.send().record(connection)
+    }
+
+    fn test_connection(server: &TestSmtpServer) -> Connection {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	server.settings().save(&con).unwrap();
+
+	con
+    }
+
+    fn test_order(con: &Connection, name: &str, email: &str, hired_on: NaiveDate, return_on: NaiveDate) -> Order {
+	Customer::new(con, name.to_string(), String::new(), email.to_string(), String::new(), String::new())
+	    .unwrap();
+
+	Order::new(
+	    con,
+	    name.to_string(),
+	    15,
+	    vec![("Chairs".to_string(), 10)],
+	    hired_on,
+	    return_on,
+	).unwrap()
+    }
+
+    #[test]
+    fn test_queue_reminders() {
+	let server = TestSmtpServer::start();
+	let con = test_connection(&server);
+
+	let pickup = test_order(&con, "Pickup Person", "pickup@example.com", date(3, 21), date(3, 23));
+	let returning = test_order(&con, "Return Person", "return@example.com", date(3, 18), date(3, 20))
+	    .mark_out(&con).unwrap();
+	let overdue = test_order(&con, "Late Person", "late@example.com", date(3, 10), date(3, 15))
+	    .mark_out(&con).unwrap();
+	// Too far off, or no email address
+	test_order(&con, "Later Person", "later@example.com", date(3, 25), date(3, 26));
+	test_order(&con, "Phone Person", "", date(3, 20), date(3, 21));
+
+	assert_eq!(queue_reminders(&con, noon(3, 20)).unwrap(), 3);
+
+	let emails = OutgoingEmail::get_all(&con).unwrap();
+	let kinds: Vec<(i32, ReminderKind)> = emails.iter().map(|email| (email.order_id, email.kind)).collect();
+	assert!(kinds.contains(&(pickup.id, ReminderKind::Pickup)));
+	assert!(kinds.contains(&(returning.id, ReminderKind::Return)));
+	assert!(kinds.contains(&(overdue.id, ReminderKind::Overdue)));
+
+	let email = emails.iter().find(|email| email.kind == ReminderKind::Pickup).unwrap();
+	assert_eq!(email.to_address, "Pickup Person <pickup@example.com>");
+	assert!(email.subject.starts_with("Your hire is ready to pick up"));
+	assert!(email.body.contains("  10 x Chairs"));
+	assert_eq!(email.status(5), EmailStatus::Waiting);
+
+	// They are in the queue already
+	assert_eq!(queue_reminders(&con, noon(3, 20)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_send_queued() {
+	let server = TestSmtpServer::start();
+	let con = test_connection(&server);
+
+	let order = test_order(&con, "Return Person", "return@example.com", date(3, 18), date(3, 20))
+	    .mark_out(&con).unwrap();
+	let now = noon(3, 20);
+	queue_reminders(&con, now).unwrap();
+
+	let summary = send_queued(&con, &server.settings(), now).unwrap();
+	assert_eq!(summary, SendSummary { queued: 0, sent: 1, failed: 0 });
+
+	let received = server.received();
+	assert_eq!(received.len(), 1);
+	assert_eq!(received[0].from, "hire@example.com");
+	assert_eq!(received[0].to, vec!["return@example.com".to_string()]);
+	assert!(received[0].data.contains("Subject: Your hire is due back on"));
+	assert!(received[0].data.contains("Hi Return Person,"));
+
+	let email = &OutgoingEmail::get_all(&con).unwrap()[0];
+	assert_eq!(email.sent_at, Some(now));
+	assert_eq!(email.status(5), EmailStatus::Sent);
+	let reminders = Reminder::get_for_order(&con, order.id).unwrap();
+	assert_eq!((reminders[0].kind, reminders[0].method), (ReminderKind::Return, ReminderMethod::Email));
+
+	// Customers are only reminded once before a return
+	assert_eq!(queue_reminders(&con, now).unwrap(), 0);
+	assert_eq!(send_queued(&con, &server.settings(), now).unwrap(), SendSummary::default());
+    }
+
+    #[test]
+    fn test_send_queued_retries() {
+	let server = TestSmtpServer::start();
+	let con = test_connection(&server);
+
+	test_order(&con, "Return Person", "return@example.com", date(3, 18), date(3, 20)).mark_out(&con).unwrap();
+	let now = noon(3, 20);
+	queue_reminders(&con, now).unwrap();
+
+	server.fail_next(2);
+	assert_eq!(send_queued(&con, &server.settings(), now).unwrap().failed, 1);
+	let email = &OutgoingEmail::get_all(&con).unwrap()[0];
+	assert_eq!(email.attempts, 1);
+	assert_eq!(email.next_attempt_at, now + TimeDelta::minutes(RETRY_DELAY_MINUTES));
+	assert!(email.last_error.clone().unwrap().contains("Try again later"));
+
+	// It isn't tried again until it is time to, and waits longer each time
+	assert_eq!(send_queued(&con, &server.settings(), now).unwrap(), SendSummary::default());
+	let now = email.next_attempt_at;
+	assert_eq!(send_queued(&con, &server.settings(), now).unwrap().failed, 1);
+	let email = &OutgoingEmail::get_all(&con).unwrap()[0];
+	assert_eq!(email.next_attempt_at, now + TimeDelta::minutes(RETRY_DELAY_MINUTES * 2));
+
+	let summary = send_queued(&con, &server.settings(), email.next_attempt_at).unwrap();
+	assert_eq!(summary.sent, 1);
+	assert_eq!(server.received().len(), 1);
+    }
+
+    #[test]
+    fn test_send_queued_gives_up() {
+	let server = TestSmtpServer::start();
+	let con = test_connection(&server);
+
+	test_order(&con, "Gone Person", "gone@example.com", date(3, 18), date(3, 20)).mark_out(&con).unwrap();
+	test_order(&con, "Busy Person", "busy@example.com", date(3, 18), date(3, 20)).mark_out(&con).unwrap();
+	EMAIL_MAX_ATTEMPTS.set(&con, 2).unwrap();
+	let mut now = noon(3, 20);
+	queue_reminders(&con, now).unwrap();
+
+	// A missing address is given up on at once, a busy server after every try
+	server.reject("gone@example.com");
+	server.fail_next(10);
+	for _ in 0..2 {
+	    assert!(send_queued(&con, &server.settings(), now).unwrap().failed > 0);
+	    now += TimeDelta::days(1);
+	}
+	assert_eq!(send_queued(&con, &server.settings(), now).unwrap(), SendSummary::default());
+
+	let emails = OutgoingEmail::get_all(&con).unwrap();
+	assert!(emails.iter().all(|email| email.status(2) == EmailStatus::Failed));
+	let gone = emails.iter().find(|email| email.to_address.contains("gone")).unwrap();
+	assert_eq!(gone.attempts, 2);
+	assert!(gone.last_error.clone().unwrap().contains("No such user"));
+
+	// Given up on emails stay given up on, so the customer isn't queued again
+	assert_eq!(queue_reminders(&con, noon(3, 20)).unwrap(), 0);
+
+	server.fail_next(0);
+	OutgoingEmail::retry(&con, gone.id, now).unwrap();
+	let busy = emails.iter().find(|email| email.to_address.contains("busy")).unwrap();
+	OutgoingEmail::retry(&con, busy.id, now).unwrap();
+	assert_eq!(send_queued(&con, &server.settings(), now).unwrap(), SendSummary { queued: 0, sent: 1, failed: 1 });
+	assert!(OutgoingEmail::retry(&con, busy.id, now).is_err());
+    }
+
+    #[test]
+    fn test_send_reminders() {
+	let server = TestSmtpServer::start();
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	test_order(&con, "Late Person", "late@example.com", date(3, 10), date(3, 15)).mark_out(&con).unwrap();
+
+	// Nothing is sent until email is set up
+	assert_eq!(send_reminders(&con, noon(3, 20)).unwrap(), None);
+
+	server.settings().save(&con).unwrap();
+	let summary = send_reminders(&con, noon(3, 20)).unwrap();
+	assert_eq!(summary, Some(SendSummary { queued: 1, sent: 1, failed: 0 }));
+	assert!(server.received()[0].data.contains("Subject: Your hire is overdue"));
+
+	// Overdue orders are reminded again once the interval has passed
+	let interval = REMINDER_INTERVAL_DAYS.default as u64;
+	assert_eq!(send_reminders(&con, noon(3, 20) + Days::new(interval - 1)).unwrap().unwrap().sent, 0);
+	assert_eq!(send_reminders(&con, noon(3, 20) + Days::new(interval)).unwrap().unwrap().sent, 1);
+    }
+}
@@ -0,0 +1,555 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::error::Error;
+
+/// How long to wait for the mail server before giving up on an email.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The keys the SMTP settings are stored under in the Setting table.
+const HOST_KEY: &str = "smtp_host";
+const PORT_KEY: &str = "smtp_port";
+const SECURITY_KEY: &str = "smtp_security";
+const USERNAME_KEY: &str = "smtp_username";
+// The password used to be kept in the Setting table, and is moved out of it
+const PASSWORD_KEY: &str = "smtp_password";
+const FROM_KEY: &str = "smtp_from";
+
+/// What is added to the path of the database to get the file the password is
+/// kept in, e.g. orders.db.smtp-password. It is kept out of the database so it
+/// isn't copied into every backup.
+const PASSWORD_FILE_SUFFIX: &str = ".smtp-password";
+
+/// How the connection to the mail server is secured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// Nothing is encrypted, which is only safe for a server on this computer.
+    None,
+    /// The connection is upgraded to TLS after connecting, usually on port 587.
+    #[default]
+    StartTls,
+    /// The connection uses TLS from the start, usually on port 465.
+    Tls,
+}
+
+impl SmtpSecurity {
+    pub const ALL: [SmtpSecurity; 3] = [SmtpSecurity::None, SmtpSecurity::StartTls, SmtpSecurity::Tls];
+
+    /// Gets the value stored in the database for the security.
+    pub fn as_str(&self) -> &'static str {
+	match self {
+	    SmtpSecurity::None => "None",
+	    SmtpSecurity::StartTls => "STARTTLS",
+	    SmtpSecurity::Tls => "TLS",
+	}
+    }
+
+    /// Parses a security, ignoring case, or None if it is unknown.
+    pub fn parse(security: &str) -> Option<Self> {
+	SmtpSecurity::ALL.into_iter().find(|s| s.as_str().eq_ignore_ascii_case(security.trim()))
+    }
+}
+
+impl fmt::Display for SmtpSecurity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "{}", self.as_str())
+    }
+}
+
+/// Represents how to send email. They are kept in the database, so each
+/// profile can send from its own address, apart from the password, which is
+/// kept in a file next to it. Email is off while there is no host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    // Left empty if the server doesn't need logging in to
+    pub username: String,
+    pub password: String,
+    // Who the emails are from, e.g. "Party Hire <hire@example.com>"
+    pub from: String,
+}
+
+impl Default for SmtpSettings {
+    fn default() -> Self {
+	Self {
+	    host: String::new(),
+	    port: 587,
+	    security: SmtpSecurity::default(),
+	    username: String::new(),
+	    password: String::new(),
+	    from: String::new(),
+	}
+    }
+}
+
+impl SmtpSettings {
+    /// Reads the settings from the database, using the defaults for any that
+    /// haven't been set.
+    pub fn load(connection: &Connection) -> Result<Self, Error> {
+	let get = |key: &str| -> Result<Option<String>, Error> {
+	    Ok(connection.query_row(
+		"SELECT CAST(value AS TEXT) FROM Setting WHERE key = ?1",
+		[key],
+		|row| row.get(0),
+	    ).optional()?)
+	};
+	let mut settings = Self::default();
+
+	if let Some(host) = get(HOST_KEY)? {
+	    settings.host = host;
+	}
+	if let Some(port) = get(PORT_KEY)? {
+	    settings.port = port.parse().map_err(|_| Error::Parse(format!("{} isn't a port", port)))?;
+	}
+	if let Some(security) = get(SECURITY_KEY)? {
+	    settings.security = SmtpSecurity::parse(&security)
+		.ok_or_else(|| Error::Parse(format!("{} isn't a kind of SMTP security", security)))?;
+	}
+	if let Some(username) = get(USERNAME_KEY)? {
+	    settings.username = username;
+	}
+	if let Some(password) = read_password(connection)? {
+	    settings.password = password;
+	} else if let Some(password) = get(PASSWORD_KEY)? {
+	    settings.password = password;
+	}
+	if let Some(from) = get(FROM_KEY)? {
+	    settings.from = from;
+	}
+
+	Ok(settings)
+    }
+
+    /// Stores the settings in the database, apart from the password, which is
+    /// kept in a file next to it.
+    pub fn save(&self, connection: &Connection) -> Result<(), Error> {
+	let port = self.port.to_string();
+
+	write_password(connection, &self.password)?;
+	connection.execute("DELETE FROM Setting WHERE key = ?1", [PASSWORD_KEY])?;
+
+	for (key, value) in [
+	    (HOST_KEY, self.host.as_str()),
+	    (PORT_KEY, port.as_str()),
+	    (SECURITY_KEY, self.security.as_str()),
+	    (USERNAME_KEY, self.username.as_str()),
+	    (FROM_KEY, self.from.as_str()),
+	] {
+	    connection.execute(
+		"INSERT INTO Setting (key, value) VALUES (?1, ?2)
+		 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+		params![key, value],
+	    )?;
+	}
+
+	Ok(())
+    }
+
+    /// Checks if email has been set up.
+    pub fn is_configured(&self) -> bool {
+	!self.host.is_empty()
+    }
+}
+
+/// Gets the file the password of the database is kept in, or None for a
+/// database that is only in memory.
+fn password_path(connection: &Connection) -> Option<PathBuf> {
+    let db_path = connection.path().filter(|path| !path.is_empty())?;
+
+    Some(PathBuf::from(format!("{}{}", db_path, PASSWORD_FILE_SUFFIX)))
+}
+
+/// Reads the password from its file, or None if it hasn't been saved there.
+fn read_password(connection: &Connection) -> Result<Option<String>, Error> {
+    let Some(path) = password_path(connection) else {
+	return Ok(None);
+    };
+
+    match fs::read_to_string(path) {
+	Ok(password) => Ok(Some(password)),
+	Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+	Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes the password to its file, which only the user running the program
+/// can read, or removes the file when there is no password.
+fn write_password(connection: &Connection, password: &str) -> Result<(), Error> {
+    let path = match password_path(connection) {
+	Some(path) => path,
+	None if password.is_empty() => return Ok(()),
+	None => return Err(Error::Validation(
+	    "The email password can only be saved for a database kept in a file".to_string()
+	)),
+    };
+
+    if password.is_empty() {
+	return match fs::remove_file(path) {
+	    Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+	    _ => Ok(()),
+	};
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+	use std::os::unix::fs::OpenOptionsExt;
+	options.mode(0o600);
+    }
+    options.open(path)?.write_all(password.as_bytes())?;
+
+    Ok(())
+}
+
+/// Moves a password saved in the database by an older version into its file,
+/// so it stops being copied into backups. A database only in memory keeps it.
+pub fn move_password_out_of_database(connection: &Connection) -> Result<(), Error> {
+    if password_path(connection).is_none() {
+	return Ok(());
+    }
+
+    let password: Option<String> = connection.query_row(
+	"SELECT CAST(value AS TEXT) FROM Setting WHERE key = ?1",
+	[PASSWORD_KEY],
+	|row| row.get(0),
+    ).optional()?;
+
+    if let Some(password) = password {
+	// A password saved since, e.g. before restoring an old backup, is kept
+	if read_password(connection)?.is_none() {
+	    write_password(connection, &password)?;
+	}
+	connection.execute("DELETE FROM Setting WHERE key = ?1", [PASSWORD_KEY])?;
+    }
+
+    Ok(())
+}
+
+/// Represents why an email couldn't be sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SendError {
+    /// Trying again later may work, e.g. the server couldn't be reached.
+    Transient(String),
+    /// Trying again won't help, e.g. the server said the address doesn't exist.
+    Permanent(String),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    SendError::Transient(message) => write!(f, "{}", message),
+	    SendError::Permanent(message) => write!(f, "{}", message),
+	}
+    }
+}
+
+/// Sends emails through the mail server in the settings.
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /// Gets ready to send with the settings, or Err if they can't be used.
+    pub fn new(settings: &SmtpSettings) -> Result<Self, Error> {
+	let from: Mailbox = settings.from.parse()
+	    .map_err(|_| Error::Validation(format!("{} isn't an email address to send from", settings.from)))?;
+	let builder = match settings.security {
+	    SmtpSecurity::None => Ok(SmtpTransport::builder_dangerous(settings.host.as_str())),
+	    SmtpSecurity::StartTls => SmtpTransport::starttls_relay(&settings.host),
+	    SmtpSecurity::Tls => SmtpTransport::relay(&settings.host),
+	};
+	let mut builder = builder
+	    .map_err(|e| Error::Validation(format!("Can't send email through {}: {}", settings.host, e)))?
+	    .port(settings.port)
+	    .timeout(Some(SEND_TIMEOUT));
+	if !settings.username.is_empty() {
+	    builder = builder.credentials(Credentials::new(settings.username.clone(), settings.password.clone()));
+	}
+
+	Ok(Self { transport: builder.build(), from })
+    }
+
+    /// Sends a plain-text email to `to`, which is an address, optionally with
+    /// a name, e.g. "Test Person <test@example.com>".
+    pub fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), SendError> {
+	let to: Mailbox = to.parse()
+	    .map_err(|_| SendError::Permanent(format!("{} isn't an email address", to)))?;
+	let message = Message::builder()
+	    .from(self.from.clone())
+	    .to(to)
+	    .subject(subject)
+	    .header(ContentType::TEXT_PLAIN)
+	    .body(body.to_string())
+	    .map_err(|e| SendError::Permanent(e.to_string()))?;
+
+	match self.transport.send(&message) {
+	    Ok(_) => Ok(()),
+	    Err(e) if e.is_permanent() => Err(SendError::Permanent(e.to_string())),
+	    Err(e) => Err(SendError::Transient(e.to_string())),
+	}
+    }
+}
+
+/// Represents the email settings form.
+#[derive(Default)]
+pub struct SmtpForm {
+    pub host: String,
+    pub port: String,
+    pub security: SmtpSecurity,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+    pub show_errors: bool,
+}
+
+impl SmtpForm {
+    /// Creates a form filled in with the current settings.
+    pub fn load(connection: &Connection) -> Result<Self, Error> {
+	let settings = SmtpSettings::load(connection)?;
+
+	Ok(Self {
+	    host: settings.host,
+	    port: settings.port.to_string(),
+	    security: settings.security,
+	    username: settings.username,
+	    password: settings.password,
+	    from: settings.from,
+	    show_errors: false,
+	})
+    }
+
+    /// Sets the text typed in for a field.
+    pub fn set_value(&mut self, field: &str, value: String) {
+	match field {
+	    "host" => self.host = value,
+	    "port" => self.port = value,
+	    "username" => self.username = value,
+	    "password" => self.password = value,
+	    "from" => self.from = value,
+	    _ => return,
+	}
+	self.show_errors = true;
+    }
+
+    /// Gets the text typed in for a field.
+    pub fn get_value(&self, field: &str) -> &str {
+	match field {
+	    "host" => &self.host,
+	    "port" => &self.port,
+	    "username" => &self.username,
+	    "password" => &self.password,
+	    "from" => &self.from,
+	    _ => "",
+	}
+    }
+
+    /// Gets host, which is left empty to turn email off, or Err if it is invalid.
+    fn get_valid_host(&self) -> Result<String, String> {
+	let host = self.host.trim();
+
+	if host.contains(char::is_whitespace) {
+	    return Err("Server can't have spaces".to_string());
+	}
+
+	Ok(host.to_string())
+    }
+
+    /// Gets port, or Err if it is invalid.
+    fn get_valid_port(&self) -> Result<u16, String> {
+	match self.port.trim().parse::<u16>() {
+	    Ok(port) if port > 0 => Ok(port),
+	    _ => Err("Port must be a number from 1 to 65535".to_string()),
+	}
+    }
+
+    /// Gets from, which is needed if there is a host, or Err if it is invalid.
+    fn get_valid_from(&self) -> Result<String, String> {
+	let from = self.from.trim();
+
+	if from.is_empty() && self.host.trim().is_empty() {
+	    return Ok(String::new());
+	} else if from.parse::<Mailbox>().is_err() {
+	    return Err("From must look like name@example.com or Name <name@example.com>".to_string());
+	}
+
+	Ok(from.to_string())
+    }
+
+    /// Gets the error for a field if errors are being shown.
+    pub fn get_visible_field_error(&self, field: &str) -> Option<String> {
+	if !self.show_errors {
+	    return None;
+	}
+
+	match field {
+	    "host" => self.get_valid_host().err(),
+	    "port" => self.get_valid_port().err(),
+	    "from" => self.get_valid_from().err(),
+	    _ => None,
+	}
+    }
+
+    /// Saves the settings, if all fields of the form are valid.
+    pub fn save(&self, connection: &Connection) -> Result<SmtpSettings, Error> {
+	let settings = SmtpSettings {
+	    host: self.get_valid_host().map_err(Error::Validation)?,
+	    port: self.get_valid_port().map_err(Error::Validation)?,
+	    security: self.security,
+	    username: self.username.trim().to_string(),
+	    password: self.password.clone(),
+	    from: self.get_valid_from().map_err(Error::Validation)?,
+	};
+	settings.save(connection)?;
+
+	Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn test_settings() -> SmtpSettings {
+	SmtpSettings {
+	    host: "mail.example.com".to_string(),
+	    port: 465,
+	    security: SmtpSecurity::Tls,
+	    username: "hire".to_string(),
+	    password: "secret".to_string(),
+	    from: "Party Hire <hire@example.com>".to_string(),
+	}
+    }
+
+    /// Makes a database in a file, as the password is kept next to it.
+    fn file_connection(name: &str) -> (Connection, PathBuf) {
+	let dir = std::env::temp_dir().join(format!("as91896-smtp-{}-{}", name, std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let con = Connection::open(dir.join("orders.db")).unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	(con, dir)
+    }
+
+    fn stored_password(con: &Connection) -> Option<String> {
+	con.query_row("SELECT value FROM Setting WHERE key = ?1", [PASSWORD_KEY], |row| row.get(0))
+	    .optional()
+	    .unwrap()
+    }
+
+    #[test]
+    fn test_settings_save_and_load() {
+	let (con, dir) = file_connection("save");
+
+	assert_eq!(SmtpSettings::load(&con).unwrap(), SmtpSettings::default());
+	assert!(!SmtpSettings::default().is_configured());
+
+	let settings = test_settings();
+	settings.save(&con).unwrap();
+
+	assert_eq!(SmtpSettings::load(&con).unwrap(), settings);
+
+	// The password is kept out of the database, so backups don't have it
+	let password_path = dir.join("orders.db.smtp-password");
+	assert_eq!(stored_password(&con), None);
+	assert_eq!(fs::read_to_string(&password_path).unwrap(), "secret".to_string());
+	#[cfg(unix)]
+	{
+	    use std::os::unix::fs::PermissionsExt;
+	    assert_eq!(fs::metadata(&password_path).unwrap().permissions().mode() & 0o777, 0o600);
+	}
+
+	SmtpSettings { password: String::new(), ..settings }.save(&con).unwrap();
+	assert!(!password_path.exists());
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_password_moved_out_of_database() {
+	let (con, dir) = file_connection("move");
+	con.execute("INSERT INTO Setting (key, value) VALUES (?1, 'old secret')", [PASSWORD_KEY]).unwrap();
+	assert_eq!(SmtpSettings::load(&con).unwrap().password, "old secret".to_string());
+
+	init_db_tables(&con).unwrap();
+
+	assert_eq!(stored_password(&con), None);
+	assert_eq!(SmtpSettings::load(&con).unwrap().password, "old secret".to_string());
+
+	// A password saved since isn't replaced by an old one, e.g. from a backup
+	con.execute("INSERT INTO Setting (key, value) VALUES (?1, 'older secret')", [PASSWORD_KEY]).unwrap();
+	init_db_tables(&con).unwrap();
+	assert_eq!(SmtpSettings::load(&con).unwrap().password, "old secret".to_string());
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_password_needs_database_file() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	assert!(matches!(test_settings().save(&con), Err(Error::Validation(_))));
+	let settings = SmtpSettings { password: String::new(), ..test_settings() };
+	settings.save(&con).unwrap();
+	assert_eq!(SmtpSettings::load(&con).unwrap(), settings);
+    }
+
+    #[test]
+    fn test_security_parse() {
+	assert_eq!(SmtpSecurity::parse("starttls"), Some(SmtpSecurity::StartTls));
+	assert_eq!(SmtpSecurity::parse(" TLS "), Some(SmtpSecurity::Tls));
+	assert_eq!(SmtpSecurity::parse("ssl"), None);
+    }
+
+    #[test]
+    fn test_form() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let mut form = SmtpForm::load(&con).unwrap();
+	assert_eq!(form.get_visible_field_error("from"), None);
+
+	form.set_value("host", "mail.example.com".to_string());
+	form.set_value("port", "0".to_string());
+	assert!(form.get_visible_field_error("from").is_some());
+	assert!(form.get_visible_field_error("port").is_some());
+	assert!(matches!(form.save(&con), Err(Error::Validation(_))));
+
+	form.set_value("port", "2525".to_string());
+	form.set_value("from", "Party Hire <hire@example.com>".to_string());
+	let settings = form.save(&con).unwrap();
+	assert_eq!(settings.port, 2525);
+	assert_eq!(SmtpSettings::load(&con).unwrap(), settings);
+    }
+
+    #[test]
+    fn test_mailer_new() {
+	let mut settings = SmtpSettings {
+	    host: "localhost".to_string(),
+	    security: SmtpSecurity::None,
+	    ..Default::default()
+	};
+
+	assert!(SmtpMailer::new(&settings).is_err());
+
+	settings.from = "hire@example.com".to_string();
+	assert!(SmtpMailer::new(&settings).is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+//! A small SMTP server for the tests to send email to, so no real mail server
+//! is needed. It accepts everything unless told otherwise, and keeps what it
+//! was sent.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use crate::email::smtp::{SmtpSecurity, SmtpSettings};
+
+/// Represents an email the server was sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceivedEmail {
+    pub from: String,
+    pub to: Vec<String>,
+    // The headers and body, with lines ending in \n
+    pub data: String,
+}
+
+#[derive(Default)]
+struct State {
+    received: Vec<ReceivedEmail>,
+    // How many of the next emails to turn away for now
+    failures: usize,
+    // Addresses to say don't exist
+    rejected: Vec<String>,
+}
+
+/// Represents the server, which runs until the tests finish.
+pub struct TestSmtpServer {
+    pub port: u16,
+    state: Arc<Mutex<State>>,
+}
+
+impl TestSmtpServer {
+    /// Starts a server on a free port on this computer.
+    pub fn start() -> Self {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let port = listener.local_addr().unwrap().port();
+	let state = Arc::new(Mutex::new(State::default()));
+
+	let server_state = state.clone();
+	thread::spawn(move || {
+	    for stream in listener.incoming().flatten() {
+		let state = server_state.clone();
+		thread::spawn(move || {
+		    let _ = handle(stream, &state);
+		});
+	    }
+	});
+
+	Self { port, state }
+    }
+
+    /// Gets settings that send to the server.
+    pub fn settings(&self) -> SmtpSettings {
+	SmtpSettings {
+	    host: "127.0.0.1".to_string(),
+	    port: self.port,
+	    security: SmtpSecurity::None,
+	    username: String::new(),
+	    password: String::new(),
+	    from: "Party Hire <hire@example.com>".to_string(),
+	}
+    }
+
+    /// Gets the emails the server has been sent, oldest first.
+    pub fn received(&self) -> Vec<ReceivedEmail> {
+	self.state.lock().unwrap().received.clone()
+    }
+
+    /// Turns away the next `count` emails with a temporary failure.
+    pub fn fail_next(&self, count: usize) {
+	self.state.lock().unwrap().failures = count;
+    }
+
+    /// Says the address doesn't exist from now on, which is a permanent failure.
+    pub fn reject(&self, address: &str) {
+	self.state.lock().unwrap().rejected.push(address.to_string());
+    }
+}
+
+/// Talks SMTP with one client until it quits.
+fn handle(stream: TcpStream, state: &Mutex<State>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut from = String::new();
+    let mut to = Vec::new();
+
+    writer.write_all(b"220 localhost ESMTP test server\r\n")?;
+
+    let mut line = String::new();
+    loop {
+	line.clear();
+	if reader.read_line(&mut line)? == 0 {
+	    return Ok(());
+	}
+	let command = line.trim_end();
+	let verb = command.split(' ').next().unwrap_or("").to_ascii_uppercase();
+
+	let reply = match verb.as_str() {
+	    "EHLO" => "250-localhost\r\n250 AUTH PLAIN LOGIN\r\n".to_string(),
+	    "HELO" => "250 localhost\r\n".to_string(),
+	    "AUTH" => "235 2.7.0 Accepted\r\n".to_string(),
+	    "MAIL" => {
+		from = address(command);
+		to.clear();
+		"250 OK\r\n".to_string()
+	    },
+	    "RCPT" => {
+		let address = address(command);
+		if state.lock().unwrap().rejected.contains(&address) {
+		    "550 5.1.1 No such user\r\n".to_string()
+		} else {
+		    to.push(address);
+		    "250 OK\r\n".to_string()
+		}
+	    },
+	    "DATA" => {
+		writer.write_all(b"354 End with .\r\n")?;
+		let mut data = String::new();
+		loop {
+		    line.clear();
+		    if reader.read_line(&mut line)? == 0 {
+			return Ok(());
+		    }
+		    let data_line = line.trim_end_matches(['\r', '\n']);
+		    if data_line == "." {
+			break;
+		    }
+		    // Lines starting with a dot have another added when sent
+		    data.push_str(data_line.strip_prefix('.').unwrap_or(data_line));
+		    data.push('\n');
+		}
+
+		let mut state = state.lock().unwrap();
+		if state.failures > 0 {
+		    state.failures -= 1;
+		    "451 4.3.0 Try again later\r\n".to_string()
+		} else {
+		    state.received.push(ReceivedEmail { from: from.clone(), to: to.clone(), data });
+		    "250 OK\r\n".to_string()
+		}
+	    },
+	    "RSET" | "NOOP" => "250 OK\r\n".to_string(),
+	    "QUIT" => {
+		writer.write_all(b"221 Bye\r\n")?;
+		return Ok(());
+	    },
+	    _ => "500 Unknown command\r\n".to_string(),
+	};
+	writer.write_all(reply.as_bytes())?;
+    }
+}
+
+/// Gets the address in a MAIL FROM:<...> or RCPT TO:<...> command.
+fn address(command: &str) -> String {
+    let start = command.find('<').map(|i| i + 1).unwrap_or(0);
+    let end = command.rfind('>').unwrap_or(command.len());
+
+    command[start..end.max(start)].to_string()
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::io;
+use crate::database::migrations::MigrationError;
+
+/// Represents anything that can go wrong in the program, so it can be shown to
+/// the user instead of crashing.
+#[derive(Debug)]
+pub enum Error {
+    /// The database couldn't be read from or written to.
+    Database(rusqlite::Error),
+    /// The database couldn't be brought up to date.
+    Migration(MigrationError),
+    /// Something the user asked for isn't allowed, e.g. an invalid field.
+    Validation(String),
+    /// Something the user asked for is allowed, but looks like a mistake, so
+    /// they need to confirm it first.
+    NeedsConfirmation(String),
+    /// Stored data couldn't be understood, e.g. a corrupt date in the database.
+    Parse(String),
+    /// A file couldn't be read or written.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Error::Database(e) => write!(f, "Database error: {}", e),
+	    Error::Migration(e) => write!(f, "{}", e),
+	    Error::Validation(message) => write!(f, "{}", message),
+	    Error::NeedsConfirmation(message) => write!(f, "{}", message),
+	    Error::Parse(message) => write!(f, "Couldn't read the data: {}", message),
+	    Error::Io(e) => write!(f, "{}", e),
+	}
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+	match e {
+	    // A value in the database that can't be turned into its Rust type is
+	    // corrupt data rather than a failure of the database itself
+	    rusqlite::Error::FromSqlConversionFailure(column, _, error) => {
+		Error::Parse(format!("column {}: {}", column, error))
+	    },
+	    rusqlite::Error::InvalidColumnType(column, name, kind) => {
+		Error::Parse(format!("column {} ({}) has an unexpected {} value", column, name, kind))
+	    },
+	    e => Error::Database(e),
+	}
+    }
+}
+
+impl From<MigrationError> for Error {
+    fn from(e: MigrationError) -> Self {
+	Error::Migration(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+	Error::Io(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+	if !e.is_io_error() {
+	    return Error::Parse(e.to_string());
+	}
+
+	match e.into_kind() {
+	    csv::ErrorKind::Io(e) => Error::Io(e),
+	    kind => Error::Parse(format!("{:?}", kind)),
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use rusqlite::Connection;
+
+    use super::*;
+
+    #[test]
+    fn test_conversion_failure_is_parse_error() {
+	let con = Connection::open_in_memory().unwrap();
+
+	let error = con.query_row("SELECT 'not a date'", [], |row| row.get::<_, NaiveDate>(0))
+	    .unwrap_err();
+
+	assert!(matches!(Error::from(error), Error::Parse(_)));
+    }
+
+    #[test]
+    fn test_database_error() {
+	let con = Connection::open_in_memory().unwrap();
+
+	let error = con.execute("SELECT * FROM Missing", []).unwrap_err();
+
+	assert!(matches!(Error::from(error), Error::Database(_)));
+    }
+}
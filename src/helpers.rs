@@ -1,7 +1,16 @@
-use iced::{Element, Color};
-use iced::widget::{row, Text, column};
+use iced::{Element, Color, Length};
+use iced::widget::{button, center, container, opaque, row, stack, Text, column};
 use crate::app::Message;
 
+/// A form that shows the errors of its fields under their inputs.
+pub trait Form {
+    /// Makes every field show its error, if it has one.
+    fn show_all_errors(&mut self);
+
+    /// Checks if any field is showing an error.
+    fn has_visible_errors(&self) -> bool;
+}
+
 /// Returns a widget to represent an error in a form's field
 pub fn field_error<'a>(error: Option<String>) -> Element<'a, Message> {
     column![
@@ -16,3 +25,41 @@ pub fn required_input_label<'a>(text: &str) -> Element<'a, Message> {
 	Text::new(" *").color(Color::from_rgb(255.0, 0.0, 0.0)),
     ].into()
 }
+
+/// Returns a banner showing something that went wrong, with a button to dismiss
+/// it. Shows nothing if there is no error.
+pub fn error_banner<'a>(error: Option<String>) -> Element<'a, Message> {
+    match error {
+	Some(error) => container(
+	    row![
+		Text::new(error)
+		    .color(Color::from_rgb(255.0, 0.0, 0.0))
+		    .width(Length::Fill),
+		button("Dismiss").on_press(Message::DismissError),
+	    ].spacing(10)
+	).padding(10).into(),
+	None => column![].into(),
+    }
+}
+
+/// Shows a question over the top of `base` with buttons to go ahead or cancel,
+/// blocking the rest of the app until one is pressed.
+pub fn confirm_dialog<'a>(base: impl Into<Element<'a, Message>>, question: &str) -> Element<'a, Message> {
+    let dialog = container(
+	column![
+	    Text::new(question.to_string()),
+	    row![
+		button("Cancel").on_press(Message::CancelConfirm),
+		button("Yes").style(button::danger).on_press(Message::Confirm),
+	    ].spacing(10),
+	].spacing(20)
+    ).padding(20).max_width(500).style(container::bordered_box);
+
+    stack![
+	base.into(),
+	opaque(center(dialog).style(|_theme| container::Style {
+	    background: Some(Color { a: 0.7, ..Color::BLACK }.into()),
+	    ..Default::default()
+	})),
+    ].into()
+}
@@ -0,0 +1,218 @@
+use crate::item::{parse_price, Item};
+use crate::error::Error;
+use crate::helpers::Form;
+use rusqlite::Connection;
+
+/// Represents a form to add or edit an item in the catalogue
+#[derive(Default)]
+pub struct ItemForm {
+    pub editing_id: Option<i32>,
+    pub name: String,
+    pub name_show_error: bool,
+    pub total_stock: String,
+    pub total_stock_show_error: bool,
+    pub box_capacity: String,
+    pub box_capacity_show_error: bool,
+    pub hire_price: String,
+    pub hire_price_show_error: bool,
+}
+
+impl ItemForm {
+    /// Creates a form pre-filled with an existing Item, so it can be edited.
+    pub fn from_item(item: &Item) -> Self {
+	Self {
+	    editing_id: Some(item.id),
+	    name: item.name.clone(),
+	    total_stock: item.total_stock.to_string(),
+	    box_capacity: item.box_capacity.to_string(),
+	    hire_price: format!("{}.{:02}", item.hire_price / 100, item.hire_price % 100),
+	    ..Default::default()
+	}
+    }
+
+    /// Gets name, or Err if it is invalid.
+    fn get_valid_name(&self) -> Result<String, String> {
+	let name = self.name.trim();
+
+	if name.is_empty() {
+	    return Err("Name is required".to_string());
+	} else if name.len() > 30 {
+	    return Err("Name must be less than 30 characters".to_string());
+	} else if name.len() < 3 {
+	    return Err("Name must be at least 3 characters".to_string());
+	}
+
+	Ok(name.to_string())
+    }
+
+    /// Gets total_stock as an integer, or Err if it is invalid.
+    fn get_valid_total_stock(&self) -> Result<i32, String> {
+	match self.total_stock.trim().parse::<i32>() {
+	    Ok(num) => {
+		if num < 0 {
+		    return Err("Stock can't be negative".to_string());
+		} else if num > 100_000 {
+		    return Err("Stock must not be more than 100000".to_string());
+		}
+
+		Ok(num)
+	    },
+	    Err(_) => {
+		Err("Stock must be an integer".to_string())
+	    },
+	}
+    }
+
+    /// Gets box_capacity as an integer, or Err if it is invalid.
+    fn get_valid_box_capacity(&self) -> Result<i32, String> {
+	match self.box_capacity.trim().parse::<i32>() {
+	    Ok(num) => {
+		if num < 1 {
+		    return Err("Items per box must be at least 1".to_string());
+		} else if num > 1000 {
+		    return Err("Items per box must not be more than 1000".to_string());
+		}
+
+		Ok(num)
+	    },
+	    Err(_) => {
+		Err("Items per box must be an integer".to_string())
+	    },
+	}
+    }
+
+    /// Gets hire_price in cents, or Err if it is invalid.
+    fn get_valid_hire_price(&self) -> Result<i64, String> {
+	match parse_price(&self.hire_price) {
+	    Some(cents) if cents > 10_000_000 => {
+		Err("Hire price must not be more than $100000".to_string())
+	    },
+	    Some(cents) => Ok(cents),
+	    None => Err("Hire price must be in dollars, e.g. 12.50".to_string()),
+	}
+    }
+
+    /// Gets the error for a given field if its corresponding *_show_error is set to true.
+    pub fn get_visible_field_error(&self, field: &str) -> Option<String> {
+	match field {
+	    "name" if self.name_show_error => {
+		if let Err(e) = self.get_valid_name() {
+		    return Some(e);
+		}
+	    },
+	    "total_stock" if self.total_stock_show_error => {
+		if let Err(e) = self.get_valid_total_stock() {
+		    return Some(e);
+		}
+	    },
+	    "box_capacity" if self.box_capacity_show_error => {
+		if let Err(e) = self.get_valid_box_capacity() {
+		    return Some(e);
+		}
+	    },
+	    "hire_price" if self.hire_price_show_error => {
+		if let Err(e) = self.get_valid_hire_price() {
+		    return Some(e);
+		}
+	    },
+	    _ => (),
+	};
+
+	None
+    }
+
+    /// Adds the item, or saves the one being edited, if all fields of the form are valid.
+    pub fn save(&self, connection: &Connection) -> Result<Item, Error> {
+	let name = self.get_valid_name().map_err(Error::Validation)?;
+	let total_stock = self.get_valid_total_stock().map_err(Error::Validation)?;
+	let box_capacity = self.get_valid_box_capacity().map_err(Error::Validation)?;
+	let hire_price = self.get_valid_hire_price().map_err(Error::Validation)?;
+
+	match self.editing_id {
+	    Some(id) => Item::get_by_id(connection, id)?
+		.update(connection, name, total_stock, box_capacity, hire_price),
+	    None => Item::new(connection, name, total_stock, box_capacity, hire_price),
+	}
+    }
+}
+
+impl Form for ItemForm {
+    /// Sets every *_show_error to true, so all invalid fields show their errors.
+    fn show_all_errors(&mut self) {
+	self.name_show_error = true;
+	self.total_stock_show_error = true;
+	self.box_capacity_show_error = true;
+	self.hire_price_show_error = true;
+    }
+
+    /// Checks if any field of the form is showing an error.
+    fn has_visible_errors(&self) -> bool {
+	["name", "total_stock", "box_capacity", "hire_price"]
+	    .iter()
+	    .any(|field| self.get_visible_field_error(field).is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn test_form() -> ItemForm {
+	ItemForm {
+	    name: "Chairs".to_string(),
+	    total_stock: "100".to_string(),
+	    box_capacity: "25".to_string(),
+	    hire_price: "2.50".to_string(),
+	    ..Default::default()
+	}
+    }
+
+    #[test]
+    fn test_get_valid_box_capacity_zero() {
+	let mut form = test_form();
+	form.box_capacity = "0".to_string();
+
+	assert!(form.get_valid_box_capacity().is_err());
+    }
+
+    #[test]
+    fn test_get_valid_total_stock_negative() {
+	let mut form = test_form();
+	form.total_stock = "-1".to_string();
+
+	assert!(form.get_valid_total_stock().is_err());
+    }
+
+    #[test]
+    fn test_form_save_new_and_edit() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let item = test_form().save(&con).unwrap();
+	assert_eq!(item.hire_price, 250);
+
+	let mut form = ItemForm::from_item(&item);
+	assert_eq!(form.hire_price, "2.50".to_string());
+	form.total_stock = "120".to_string();
+
+	let item = form.save(&con).unwrap();
+	assert_eq!(item.total_stock, 120);
+	assert_eq!(Item::get_all(&con).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_form_save_invalid() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let mut form = test_form();
+	form.name = "ab".to_string();
+
+	assert!(form.save(&con).is_err());
+	assert!(Item::get_all(&con).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,394 @@
+pub mod form;
+pub mod table;
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use chrono::NaiveDate;
+use crate::error::Error;
+use crate::order::{count_boxes, OrderStatus};
+
+pub use self::form::ItemForm;
+
+/// Represents an item in the catalogue that can be hired out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Item {
+    pub id: i32,
+    pub name: String,
+    // How many of the item the shop owns
+    pub total_stock: i32,
+    // How many of the item fit in one box
+    pub box_capacity: i32,
+    // The price to hire one of the item for a day, in cents
+    pub hire_price: i64,
+}
+
+impl Item {
+    /// Adds a new item to the catalogue.
+    pub fn new(
+	connection: &Connection,
+	name: String,
+	total_stock: i32,
+	box_capacity: i32,
+	hire_price: i64,
+    ) -> Result<Self, Error> {
+	if Self::get_by_name(connection, &name)?.is_some() {
+	    return Err(Error::Validation(format!("{} is already in the catalogue", name)));
+	}
+
+	connection.execute(
+	    "INSERT INTO Item (name, total_stock, box_capacity, hire_price) VALUES (?1, ?2, ?3, ?4)",
+	    params![name, total_stock, box_capacity, hire_price],
+	)?;
+
+	Self::get_by_id(connection, connection.last_insert_rowid() as i32)
+    }
+
+    /// Retrieves an item from the database by its id.
+    pub fn get_by_id(connection: &Connection, id: i32) -> Result<Self, Error> {
+	Ok(connection.query_row(
+	    "SELECT id, name, total_stock, box_capacity, hire_price FROM Item WHERE id = ?1",
+	    [id],
+	    Self::from_row,
+	)?)
+    }
+
+    /// Finds an item by its name, ignoring case.
+    pub fn get_by_name(connection: &Connection, name: &str) -> Result<Option<Self>, Error> {
+	Ok(connection.query_row(
+	    "SELECT id, name, total_stock, box_capacity, hire_price FROM Item WHERE name = ?1",
+	    [name],
+	    Self::from_row,
+	).optional()?)
+    }
+
+    /// Gets every item in the catalogue, in alphabetical order.
+    pub fn get_all(connection: &Connection) -> Result<Vec<Self>, Error> {
+	let mut stmt = connection.prepare(
+	    "SELECT id, name, total_stock, box_capacity, hire_price FROM Item ORDER BY name"
+	)?;
+
+	let items = stmt.query_map([], Self::from_row)?.collect::<Result<_, _>>()?;
+
+	Ok(items)
+    }
+
+    /// Gets every item in the catalogue with how many of it are out on hire
+    /// right now, in alphabetical order.
+    pub fn get_all_with_hired_out(connection: &Connection) -> Result<Vec<(Self, i64)>, Error> {
+	let mut stmt = connection.prepare(
+	    "SELECT Item.id, name, total_stock, box_capacity, hire_price, COALESCE(SUM(HiredOut.how_many), 0)
+	     FROM Item LEFT JOIN (
+		 SELECT item_hired, how_many
+		 FROM OrderLine JOIN CustomerOrder ON CustomerOrder.id = OrderLine.order_id
+		 WHERE status = ?1 AND deleted_at IS NULL
+	     ) AS HiredOut ON HiredOut.item_hired = Item.name COLLATE NOCASE
+	     GROUP BY Item.id
+	     ORDER BY name"
+	)?;
+
+	let items = stmt.query_map([OrderStatus::Out], |row| Ok((Self::from_row(row)?, row.get(5)?)))?
+	    .collect::<Result<_, _>>()?;
+
+	Ok(items)
+    }
+
+    /// Builds an Item from a row.
+    fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
+	Ok(Self {
+	    id: row.get(0)?,
+	    name: row.get(1)?,
+	    total_stock: row.get(2)?,
+	    box_capacity: row.get(3)?,
+	    hire_price: row.get(4)?,
+	})
+    }
+
+    /// Updates the item. Orders for the item follow it if it is renamed, and
+    /// have their boxes counted again if the box capacity changes.
+    pub fn update(
+	&self,
+	connection: &Connection,
+	name: String,
+	total_stock: i32,
+	box_capacity: i32,
+	hire_price: i64,
+    ) -> Result<Self, Error> {
+	if let Some(other) = Self::get_by_name(connection, &name)? {
+	    if other.id != self.id {
+		return Err(Error::Validation(format!("{} is already in the catalogue", name)));
+	    }
+	}
+
+	let tx = connection.unchecked_transaction()?;
+
+	tx.execute(
+	    "UPDATE Item SET name = ?1, total_stock = ?2, box_capacity = ?3, hire_price = ?4
+	     WHERE id = ?5",
+	    params![name, total_stock, box_capacity, hire_price, self.id],
+	)?;
+	tx.execute(
+	    "UPDATE OrderLine SET item_hired = ?1 WHERE item_hired = ?2 COLLATE NOCASE",
+	    params![name, self.name],
+	)?;
+	tx.execute(
+	    "UPDATE OrderLine SET boxes_needed = (how_many + ?1 - 1) / ?1
+	     WHERE item_hired = ?2 COLLATE NOCASE",
+	    params![box_capacity, name],
+	)?;
+
+	// The orders' items may now share boxes differently
+	let order_ids: Vec<i32> = tx.prepare("SELECT DISTINCT order_id FROM OrderLine WHERE item_hired = ?1 COLLATE NOCASE")?
+	    .query_map([&name], |row| row.get(0))?
+	    .collect::<Result<_, _>>()?;
+	for order_id in order_ids {
+	    count_boxes(&tx, order_id)?;
+	}
+
+	tx.commit()?;
+
+	Self::get_by_id(connection, self.id)
+    }
+
+    /// Removes the item from the catalogue, as long as no order uses it.
+    pub fn delete(self, connection: &Connection) -> Result<usize, Error> {
+	let orders: i32 = connection.query_row(
+	    "SELECT COUNT(DISTINCT order_id) FROM OrderLine WHERE item_hired = ?1 COLLATE NOCASE",
+	    [&self.name],
+	    |row| row.get(0),
+	)?;
+
+	if orders > 0 {
+	    return Err(Error::Validation(format!(
+		"{} can't be deleted as {} order(s) use it",
+		self.name,
+		orders,
+	    )));
+	}
+
+	Ok(connection.execute("DELETE FROM Item WHERE id = ?1", [self.id])?)
+    }
+
+    /// Gets how many of the item can still be hired over every day from `from`
+    /// to `to`, counting the orders that are booked or out on those days. The
+    /// order being edited, if any, is left out so it doesn't count against itself.
+    pub fn available(
+	&self,
+	connection: &Connection,
+	from: NaiveDate,
+	to: NaiveDate,
+	excluding_order: Option<i32>,
+    ) -> Result<i32, Error> {
+	let mut stmt = connection.prepare(
+	    "SELECT OrderLine.how_many, hired_on, return_on
+	     FROM OrderLine JOIN CustomerOrder ON CustomerOrder.id = OrderLine.order_id
+	     WHERE OrderLine.item_hired = ?1 COLLATE NOCASE
+	     AND status IN (?2, ?3) AND deleted_at IS NULL
+	     AND hired_on <= ?4 AND return_on >= ?5
+	     AND (?6 IS NULL OR CustomerOrder.id != ?6)"
+	)?;
+
+	let hires: Vec<(i32, NaiveDate, NaiveDate)> = stmt.query_map(
+	    params![self.name, OrderStatus::Booked, OrderStatus::Out, to, from, excluding_order],
+	    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+	)?.collect::<Result<_, _>>()?;
+
+	Ok(self.total_stock - peak_hired(&hires, from))
+    }
+}
+
+/// Gets the most items hired at once out of the given hires. The most is
+/// always reached on `from` or on the first day of one of the hires.
+fn peak_hired(hires: &[(i32, NaiveDate, NaiveDate)], from: NaiveDate) -> i32 {
+    let mut days: Vec<NaiveDate> = hires.iter().map(|(_, start, _)| (*start).max(from)).collect();
+    days.push(from);
+
+    days.into_iter()
+	.map(|day| {
+	    hires.iter()
+		.filter(|(_, start, end)| *start <= day && day <= *end)
+		.map(|(how_many, _, _)| how_many)
+		.sum()
+	})
+	.max()
+	.unwrap_or(0)
+}
+
+/// Formats a price in cents as dollars, e.g. $12.50.
+pub fn format_price(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+
+    format!("{}${}.{:02}", sign, cents.unsigned_abs() / 100, cents.unsigned_abs() % 100)
+}
+
+/// Parses an amount of dollars, with up to 2 decimal places, into cents.
+pub fn parse_price(price: &str) -> Option<i64> {
+    let price = price.trim().trim_start_matches('$');
+    let (dollars, cents) = price.split_once('.').unwrap_or((price, ""));
+
+    if dollars.is_empty() || !dollars.chars().all(|c| c.is_ascii_digit())
+	|| cents.len() > 2 || !cents.chars().all(|c| c.is_ascii_digit()) {
+	return None;
+    }
+
+    let cents = format!("{:0<2}", cents).parse::<i64>().ok()?;
+
+    dollars.parse::<i64>().ok()?.checked_mul(100)?.checked_add(cents)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+    use crate::order::Order;
+
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+	NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    fn test_item(con: &Connection) -> Item {
+	Item::new(con, "Chairs".to_string(), 100, 25, 250).unwrap()
+    }
+
+    fn test_hire(con: &Connection, how_many: i32, from: u32, to: u32) -> Order {
+	Order::new(
+	    con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("chairs".to_string(), how_many)],
+	    date(from),
+	    date(to),
+	).unwrap()
+    }
+
+    #[test]
+    fn test_new_item() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let item = test_item(&con);
+
+	assert_eq!(item.name, "Chairs".to_string());
+	assert_eq!(Item::get_by_name(&con, "CHAIRS").unwrap(), Some(item));
+	assert!(Item::new(&con, "chairs".to_string(), 1, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_item_get_all() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	Item::new(&con, "Tables".to_string(), 10, 1, 1500).unwrap();
+	test_item(&con);
+
+	let names: Vec<String> = Item::get_all(&con).unwrap().into_iter().map(|i| i.name).collect();
+	assert_eq!(names, vec!["Chairs".to_string(), "Tables".to_string()]);
+    }
+
+    #[test]
+    fn test_item_rename_updates_orders() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let item = test_item(&con);
+	let order = test_hire(&con, 10, 1, 3);
+
+	item.update(&con, "Folding Chairs".to_string(), 100, 25, 250).unwrap();
+
+	assert_eq!(Order::get_by_id(&con, order.id).unwrap().items_hired(), "Folding Chairs".to_string());
+    }
+
+    #[test]
+    fn test_item_box_capacity_updates_orders() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let item = test_item(&con);
+	let order = test_hire(&con, 30, 1, 3);
+	assert_eq!(order.boxes_needed, 2);
+
+	item.update(&con, "Chairs".to_string(), 100, 10, 250).unwrap();
+
+	assert_eq!(Order::get_by_id(&con, order.id).unwrap().boxes_needed, 3);
+    }
+
+    #[test]
+    fn test_item_delete_in_use() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let item = test_item(&con);
+	test_hire(&con, 10, 1, 3);
+
+	assert!(matches!(item.delete(&con), Err(Error::Validation(_))));
+	assert!(Item::new(&con, "Tables".to_string(), 1, 1, 1).unwrap().delete(&con).is_ok());
+    }
+
+    #[test]
+    fn test_item_available() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let item = test_item(&con);
+	test_hire(&con, 30, 1, 3);
+	test_hire(&con, 20, 5, 7);
+	test_hire(&con, 40, 3, 5);
+
+	// The 1st-3rd and 5th-7th hires never overlap each other
+	assert_eq!(item.available(&con, date(1), date(7), None).unwrap(), 30);
+	assert_eq!(item.available(&con, date(6), date(9), None).unwrap(), 80);
+	assert_eq!(item.available(&con, date(10), date(12), None).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_item_available_ignores_closed_and_edited_orders() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let item = test_item(&con);
+	let edited = test_hire(&con, 30, 1, 3);
+	test_hire(&con, 20, 1, 3).cancel(&con).unwrap();
+
+	assert_eq!(item.available(&con, date(1), date(3), None).unwrap(), 70);
+	assert_eq!(item.available(&con, date(1), date(3), Some(edited.id)).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_get_all_with_hired_out() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let item = test_item(&con);
+	let tables = Item::new(&con, "Tables".to_string(), 10, 2, 1000).unwrap();
+	test_hire(&con, 30, 1, 3).mark_out(&con).unwrap();
+	test_hire(&con, 20, 1, 3).mark_out(&con).unwrap().delete(&con, date(2).and_hms_opt(9, 0, 0).unwrap()).unwrap();
+	test_hire(&con, 40, 1, 3);
+
+	assert_eq!(Item::get_all_with_hired_out(&con).unwrap(), vec![(item, 30), (tables, 0)]);
+    }
+
+    #[test]
+    fn test_format_price() {
+	assert_eq!(format_price(1250), "$12.50".to_string());
+	assert_eq!(format_price(5), "$0.05".to_string());
+	assert_eq!(format_price(-150), "-$1.50".to_string());
+    }
+
+    #[test]
+    fn test_parse_price() {
+	assert_eq!(parse_price("12.50"), Some(1250));
+	assert_eq!(parse_price("$3"), Some(300));
+	assert_eq!(parse_price("0.5"), Some(50));
+	assert_eq!(parse_price("1.234"), None);
+	assert_eq!(parse_price("-1"), None);
+	assert_eq!(parse_price("free"), None);
+	assert_eq!(parse_price(".50"), None);
+    }
+}
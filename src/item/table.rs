@@ -0,0 +1,86 @@
+use iced::{Element, Length, Theme, Renderer};
+use iced::widget::{container, text, button};
+use iced_table::table;
+use crate::app::Message;
+use crate::item::{format_price, Item};
+
+/// Represents a column for the item catalogue table.
+pub struct ItemColumn {
+    pub kind: ItemColumnKind,
+    pub width: f32,
+    pub resize_offset: Option<f32>,
+}
+
+impl ItemColumn {
+    pub fn new(kind: ItemColumnKind) -> Self {
+	let width = match kind {
+	    ItemColumnKind::Name => 200.0,
+	    ItemColumnKind::TotalStock => 100.0,
+	    ItemColumnKind::BoxCapacity => 100.0,
+	    ItemColumnKind::HirePrice => 100.0,
+	    ItemColumnKind::Edit => 100.0,
+	    ItemColumnKind::Delete => 100.0,
+	};
+
+	Self {
+	    kind,
+	    width,
+	    resize_offset: None,
+	}
+    }
+}
+
+/// Represents what the field the column corrosponds to.
+pub enum ItemColumnKind {
+    Name,
+    TotalStock,
+    BoxCapacity,
+    HirePrice,
+    Edit,
+    Delete,
+}
+
+impl<'a> table::Column<'a, Message, Theme, Renderer> for ItemColumn {
+    type Row = Item;
+
+    fn header(&'a self, _col_size: usize) -> Element<'a, Message> {
+	let content = match self.kind {
+	    ItemColumnKind::Name => "Name",
+	    ItemColumnKind::TotalStock => "Stock",
+	    ItemColumnKind::BoxCapacity => "Per Box",
+	    ItemColumnKind::HirePrice => "Price / Day",
+	    ItemColumnKind::Edit => "",
+	    ItemColumnKind::Delete => "",
+	};
+
+	container(text(content)).center_y(24).into()
+    }
+
+    fn cell(&'a self, _col_index: usize, _row_index: usize, row: &'a Item) -> Element<'a, Message> {
+	let content: Element<_> = match self.kind {
+	    ItemColumnKind::Name => text(row.name.clone()).into(),
+	    ItemColumnKind::TotalStock => text(row.total_stock.to_string()).into(),
+	    ItemColumnKind::BoxCapacity => text(row.box_capacity.to_string()).into(),
+	    ItemColumnKind::HirePrice => text(format_price(row.hire_price)).into(),
+	    ItemColumnKind::Edit => button(text("Edit"))
+		.on_press(Message::EditItem(row.id))
+		.into(),
+	    ItemColumnKind::Delete => button(text("Delete"))
+		.on_press(Message::AskToConfirm(
+		    format!("Delete {} from the catalogue? This can't be undone.", row.name),
+		    Box::new(Message::DeleteItem(row.id)),
+		))
+		.into(),
+	};
+
+	container(content).width(Length::Fill).center_y(32).into()
+    }
+
+    fn width(&self) -> f32 {
+	self.width
+    }
+
+    fn resize_offset(&self) -> Option<f32> {
+	self.resize_offset
+    }
+}
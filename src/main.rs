@@ -1,13 +1,95 @@
 mod app;
+mod cli;
+mod customer;
+mod dashboard;
+mod docket;
+mod email;
 mod order;
+mod item;
+mod packing;
+mod payment;
+mod placeholder;
+mod reminder;
+mod backup;
+mod config;
+mod raffle;
 mod helpers;
 mod database;
+mod error;
+mod settings;
 
 use crate::app::App;
+use crate::config::{config_path, Config, Profile, DEFAULT_PROFILE};
 use crate::database::init_db;
+use crate::error::Error;
+use crate::order::Order;
+use chrono::Local;
 use iced::{window::Settings, Result};
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+/// Reads the config file and works out which profile's database to use from
+/// the --db and --profile options, which are taken out of `args`.
+fn choose_profile(args: &mut Vec<String>) -> std::result::Result<(PathBuf, Config, Profile), Error> {
+    let (db, profile) = cli::take_database_options(args)?;
+    let config_path = config_path()?;
+    let config = Config::load(&config_path)?;
+    let profile = config.choose_profile(db.as_deref(), profile.as_deref())?;
+
+    Ok((config_path, config, profile))
+}
+
+/// Opens the database of `profile`. The window can still be opened if it
+/// fails, with the default profile instead, so the error is given back to show
+/// in it.
+fn open_profile(config: &Config, profile: Profile, for_window: bool) -> std::result::Result<(Connection, Profile, Option<String>), Error> {
+    let error = match init_db(&profile.db_path) {
+	Ok(connection) => return Ok((connection, profile, None)),
+	Err(e) if for_window && profile.name != DEFAULT_PROFILE => e,
+	Err(e) => return Err(e),
+    };
+
+    let default = config.get_profile(DEFAULT_PROFILE)?;
+    let connection = init_db(&default.db_path)?;
+    let error = format!("{}. The {} profile was opened instead.", error, DEFAULT_PROFILE);
+
+    Ok((connection, default, Some(error)))
+}
 
 fn main() -> Result {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let opened = choose_profile(&mut args).and_then(|(config_path, config, profile)| {
+	let (connection, profile, error) = open_profile(&config, profile, args.is_empty())?;
+	Ok((connection, config_path, config, profile, error))
+    });
+    let (connection, config_path, config, profile, error) = match opened {
+	Ok(opened) => opened,
+	Err(e) => {
+	    eprintln!("Failed to open the database: {}", e);
+	    std::process::exit(1);
+	},
+    };
+
+    // Deleted orders are only kept for so long
+    if let Err(e) = Order::purge_deleted(&connection, Local::now().naive_local()) {
+	eprintln!("Failed to purge old deleted orders: {}", e);
+    }
+
+    // Any other arguments run a command instead of opening the window
+    if !args.is_empty() {
+	if let Err(e) = cli::run(&connection, &args, &mut std::io::stdout()) {
+	    eprintln!("{}", e);
+	    std::process::exit(1);
+	}
+
+	return Ok(());
+    }
+
+    if let Err(e) = backup::run_scheduled_backup(&connection, Local::now().naive_local()) {
+	eprintln!("Failed to back up the database: {}", e);
+    }
+
     iced::application(App::title, App::update, App::view)
         .window(Settings {
             size: iced::Size::new(1200.0, 600.0),
@@ -15,5 +97,11 @@ fn main() -> Result {
             ..Default::default()
         })
         .theme(App::theme)
-        .run_with(|| App::new(init_db()))
+	.subscription(App::subscription)
+	// The icons the calendar in the order form uses
+	.font(iced_aw::iced_fonts::REQUIRED_FONT_BYTES)
+	.run_with(move || {
+	    let (app, task) = App::new(connection);
+	    (app.with_profile(config_path, config, profile, error), task)
+	})
 }
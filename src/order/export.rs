@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use crate::order::Order;
+use crate::error::Error;
+
+/// The header row of exported CSV files.
+const EXPORT_HEADER: [&str; 10] = [
+    "customer_name",
+    "receipt_number",
+    "item_hired",
+    "how_many",
+    "hired_on",
+    "return_on",
+    "boxes_needed",
+    "raffle_number",
+    "status",
+    "returned_at",
+];
+
+/// Writes the orders as CSV, with a header row. Each line of an order is a
+/// row of its own, repeating the order's customer, receipt and dates.
+pub fn export_csv<W: Write>(orders: &[Order], writer: W) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+
+    writer.write_record(EXPORT_HEADER)?;
+    for order in orders {
+	for line in &order.lines {
+	    writer.write_record([
+		order.customer_name.clone(),
+		order.receipt_number.to_string(),
+		line.item_hired.clone(),
+		line.how_many.to_string(),
+		order.hired_on.format("%Y-%m-%d").to_string(),
+		order.return_on.format("%Y-%m-%d").to_string(),
+		line.boxes_needed.to_string(),
+		order.raffle_number.to_string(),
+		order.status.to_string(),
+		order.returned_at.map(|r| r.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default(),
+	    ])?;
+	}
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the orders to a CSV file, replacing it if it exists.
+pub fn export_csv_file(orders: &[Order], path: &Path) -> Result<(), Error> {
+    export_csv(orders, File::create(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+    use chrono::NaiveDate;
+    use rusqlite::Connection;
+
+    use super::*;
+
+    #[test]
+    fn test_export_csv() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = Order::new(
+	    &con,
+	    "Smith, Alice".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26), ("Other Item".to_string(), 3)],
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	).unwrap();
+
+	let mut output = Vec::new();
+	export_csv(&[order], &mut output).unwrap();
+
+	let output = String::from_utf8(output).unwrap();
+	let lines: Vec<&str> = output.lines().collect();
+	assert_eq!(lines.len(), 3);
+	assert_eq!(lines[0], EXPORT_HEADER.join(","));
+	assert!(lines[1].starts_with("\"Smith, Alice\",15,Test Item,26,2025-03-23,2025-03-25,2,"));
+	assert!(lines[1].ends_with(",Booked,"));
+	assert!(lines[2].starts_with("\"Smith, Alice\",15,Other Item,3,2025-03-23,2025-03-25,1,"));
+    }
+
+    #[test]
+    fn test_export_csv_empty() {
+	let mut output = Vec::new();
+	export_csv(&[], &mut output).unwrap();
+
+	assert_eq!(String::from_utf8(output).unwrap().lines().count(), 1);
+    }
+}
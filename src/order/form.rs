@@ -1,25 +1,221 @@
+use std::collections::HashSet;
 use crate::order::Order;
+use crate::item::Item;
+use crate::error::Error;
+use crate::helpers::Form;
 use rusqlite::Connection;
-use chrono::NaiveDate;
+use chrono::{Datelike, Days, Local, NaiveDate, Weekday};
+use crate::settings::{MAX_DAYS_AGO, MAX_DAYS_AHEAD, MAX_HIRE_DAYS};
 
-/// Represents a form to create an Order
-#[derive(Default)]
+/// Represents a line of an OrderForm, which is an item and how many of it to hire
+#[derive(Clone, Default)]
+pub struct OrderLineForm {
+    pub item_hired: String,
+    pub item_hired_show_error: bool,
+    pub how_many: String,
+    pub how_many_show_error: bool,
+}
+
+impl OrderLineForm {
+    /// Gets item_hired, or Err if it is invalid.
+    fn get_valid_item_hired(&self) -> Result<String, String> {
+	if self.item_hired.is_empty() {
+	    return Err("Item hired is required".to_string());
+	} else {
+	    if self.item_hired.len() > 30 {
+		return Err("Item hired must be less than 30 characters".to_string());
+	    } else if self.item_hired.len() < 3 {
+		return Err("Item hired must be at least 3 characters".to_string());
+	    }
+	}
+
+	Ok(self.item_hired.clone())
+    }
+
+    /// Gets how_many as an integer, or Err if it is invalid.
+    #[allow(clippy::needless_return)]
+    fn get_valid_how_many(&self) -> Result<i32, String> {
+	if self.how_many.is_empty() {
+	    return Err("How many is required".to_string());
+	} else {
+	    match self.how_many.parse::<i32>() {
+		Ok(num) => {
+		    if num < 1 {
+			return Err("How many must be at least 1".to_string());
+		    } else if num > 500 {
+			return Err("How many must not be more than 500".to_string());
+		    }
+
+		    return Ok(num);
+		},
+		Err(_) => {
+		    return Err("How many must be an integer".to_string());
+		},
+	    }
+	}
+    }
+
+    /// Gets the error for a given field if its corresponding *_show_error is set to true.
+    #[allow(clippy::collapsible_match)]
+    pub fn get_visible_field_error(&self, field: &str) -> Option<String> {
+	match field {
+	    "item_hired" => {
+		if self.item_hired_show_error {
+		    if let Err(e) = self.get_valid_item_hired() {
+			return Some(e);
+		    }
+		}
+	    },
+	    "how_many" => {
+		if self.how_many_show_error {
+		    if let Err(e) = self.get_valid_how_many() {
+			return Some(e);
+		    }
+		}
+	    },
+	    _ => (),
+	};
+
+	None
+    }
+}
+
+/// The limits on the dates of an order, relative to today and to each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateLimits {
+    pub today: NaiveDate,
+    // How many days before or after today a hire can start
+    pub max_days_ago: i64,
+    pub max_days_ahead: i64,
+    // How many days an order can be hired out for
+    pub max_hire_days: i64,
+}
+
+impl Default for DateLimits {
+    fn default() -> Self {
+	Self {
+	    today: Local::now().date_naive(),
+	    max_days_ago: MAX_DAYS_AGO.default,
+	    max_days_ahead: MAX_DAYS_AHEAD.default,
+	    max_hire_days: MAX_HIRE_DAYS.default,
+	}
+    }
+}
+
+impl DateLimits {
+    /// Gets the limits from the settings, counting from `today`.
+    pub fn load(connection: &Connection, today: NaiveDate) -> Result<Self, Error> {
+	Ok(Self {
+	    today,
+	    max_days_ago: MAX_DAYS_AGO.get(connection)?,
+	    max_days_ahead: MAX_DAYS_AHEAD.get(connection)?,
+	    max_hire_days: MAX_HIRE_DAYS.get(connection)?,
+	})
+    }
+}
+
+/// Parses a date typed into the order form. As well as YYYY-MM-DD, it takes
+/// "today", "tomorrow" and "yesterday", a number of days or weeks from today
+/// like "+3d" or "-1w", and a weekday like "fri" or "next friday", which is the
+/// first one after today. Gives None if the text isn't any of these.
+pub fn parse_date(input: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let input = input.trim().to_lowercase();
+
+    if let Ok(date) = NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+	return Some(date);
+    }
+
+    match input.as_str() {
+	"today" => return Some(today),
+	"tomorrow" => return today.checked_add_days(Days::new(1)),
+	"yesterday" => return today.checked_sub_days(Days::new(1)),
+	_ => (),
+    }
+
+    if let Some(offset) = input.strip_prefix(['+', '-']) {
+	let days = if let Some(amount) = offset.strip_suffix('d') {
+	    amount.parse::<u64>().ok()?
+	} else if let Some(amount) = offset.strip_suffix('w') {
+	    amount.parse::<u64>().ok()?.checked_mul(7)?
+	} else {
+	    return None;
+	};
+
+	return match input.starts_with('+') {
+	    true => today.checked_add_days(Days::new(days)),
+	    false => today.checked_sub_days(Days::new(days)),
+	};
+    }
+
+    let weekday = input.strip_prefix("next ").unwrap_or(&input).parse::<Weekday>().ok()?;
+    let days_until = (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+
+    // The same weekday as today means a week away
+    today.checked_add_days(Days::new(if days_until == 0 { 7 } else { days_until } as u64))
+}
+
+/// Represents a form to create or edit an Order
 pub struct OrderForm {
+    pub editing_id: Option<i32>,
     pub customer_name: String,
     pub customer_name_show_error: bool,
     pub receipt_number: String,
     pub receipt_number_show_error: bool,
-    pub item_hired: String,
-    pub item_hired_show_error: bool,
-    pub how_many: String,
-    pub how_many_show_error: bool,
+    // Whether the user has said to use the receipt number even though another
+    // order has it
+    pub receipt_reuse_confirmed: bool,
+    pub lines: Vec<OrderLineForm>,
+    pub lines_show_error: bool,
     pub hired_on: String,
     pub hired_on_show_error: bool,
     pub return_on: String,
     pub return_on_show_error: bool,
+    // What the dates are checked against, and the hire date of the order being
+    // edited, which is left alone even if it is now too long ago
+    pub date_limits: DateLimits,
+    pub saved_hired_on: Option<NaiveDate>,
+}
+
+impl Default for OrderForm {
+    fn default() -> Self {
+	Self {
+	    editing_id: None,
+	    customer_name: String::new(),
+	    customer_name_show_error: false,
+	    receipt_number: String::new(),
+	    receipt_number_show_error: false,
+	    receipt_reuse_confirmed: false,
+	    lines: vec![OrderLineForm::default()],
+	    lines_show_error: false,
+	    hired_on: String::new(),
+	    hired_on_show_error: false,
+	    return_on: String::new(),
+	    return_on_show_error: false,
+	    date_limits: DateLimits::default(),
+	    saved_hired_on: None,
+	}
+    }
 }
 
 impl OrderForm {
+    /// Creates a form pre-filled with an existing Order, so it can be edited.
+    pub fn from_order(order: &Order) -> Self {
+	Self {
+	    editing_id: Some(order.id),
+	    customer_name: order.customer_name.clone(),
+	    receipt_number: order.receipt_number.to_string(),
+	    lines: order.lines.iter().map(|line| OrderLineForm {
+		item_hired: line.item_hired.clone(),
+		how_many: line.how_many.to_string(),
+		..Default::default()
+	    }).collect(),
+	    hired_on: order.hired_on.format("%Y-%m-%d").to_string(),
+	    return_on: order.return_on.format("%Y-%m-%d").to_string(),
+	    saved_hired_on: Some(order.hired_on),
+	    ..Default::default()
+	}
+    }
+
     /// Gets customer_name, or Err if it is invalid.
     fn get_valid_customer_name(&self) -> Result<String, String> {
 	if self.customer_name.is_empty() {
@@ -36,6 +232,7 @@ impl OrderForm {
     }
 
     /// Gets reciept_number as an integer, or Err if it is invalid.
+    #[allow(clippy::needless_return)]
     fn get_valid_receipt_number(&self) -> Result<i64, String> {
 	if self.receipt_number.is_empty() {
             return Err("Receipt number is required".to_string());
@@ -45,84 +242,154 @@ impl OrderForm {
 		    return Err("Receipt number must be a 64-bit integer".to_string());
 		}
 		Ok(rn) => {
+		    if rn < 1 {
+			return Err("Receipt number must be positive".to_string());
+		    }
+
 		    return Ok(rn);
 		}
 	    }
 	}
     }
 
-    /// Gets item_hired, or Err if it is invalid.
-    fn get_valid_item_hired(&self) -> Result<String, String> {
-	if self.item_hired.is_empty() {
-            return Err("Item hired is required".to_string());
-	} else {
-            if self.item_hired.len() > 30 {
-		return Err("Item hired must be less than 30 characters".to_string());
-            } else if self.item_hired.len() < 3 {
-		return Err("Item hired must be at least 3 characters".to_string());
-            }
-	}
+    /// Adds an empty line to the form.
+    pub fn add_line(&mut self) {
+	self.lines.push(OrderLineForm::default());
+    }
 
-	Ok(self.item_hired.clone())
+    /// Removes a line from the form.
+    pub fn remove_line(&mut self, index: usize) {
+	if index < self.lines.len() {
+	    self.lines.remove(index);
+	    self.lines_show_error = true;
+	}
     }
 
-    /// Gets how_many as an integer, or Err if it is invalid.
-    fn get_valid_how_many(&self) -> Result<i32, String> {
-	if self.how_many.is_empty() {
-            return Err("How many is required".to_string());
-	} else {
-            match self.how_many.parse::<i32>() {
-		Ok(num) => {
-                    if num < 1 {
-			return Err("How many must be at least 1".to_string());
-                    } else if num > 500 {
-			return Err("How many must not be more than 500".to_string());
-                    }
+    /// Checks there is at least one line, and that no item is on more than one
+    /// line, or Err if there is a problem with the lines as a whole.
+    fn check_line_list(&self) -> Result<(), String> {
+	if self.lines.is_empty() {
+	    return Err("At least one item is required".to_string());
+	}
 
-		    return Ok(num);
-		},
-		Err(_) => {
-                    return Err("How many must be an integer".to_string());
-		},
-            }
+	let mut seen = HashSet::new();
+	for line in &self.lines {
+	    let item = line.item_hired.trim().to_lowercase();
+	    if !item.is_empty() && !seen.insert(item) {
+		return Err(format!("{} is listed more than once", line.item_hired.trim()));
+	    }
 	}
+
+	Ok(())
+    }
+
+    /// Gets every line as (item, how many), or Err if any of them are invalid.
+    fn get_valid_lines(&self) -> Result<Vec<(String, i32)>, String> {
+	let mut lines = Vec::new();
+	for line in &self.lines {
+	    lines.push((line.get_valid_item_hired()?, line.get_valid_how_many()?));
+	}
+
+	self.check_line_list()?;
+
+	Ok(lines)
     }
 
     /// Gets hired_on as a NaiveDate, or Err if it is invalid.
+    #[allow(clippy::needless_return)]
     fn get_valid_hired_on(&self) -> Result<NaiveDate, String> {
 	if self.hired_on.is_empty() {
             return Err("Hired on date is required".to_string());
 	}
 	else {
-	    match NaiveDate::parse_from_str(self.hired_on.as_str(), "%Y-%m-%d") {
-		Ok(ho) => {
+	    match parse_date(self.hired_on.as_str(), self.date_limits.today) {
+		Some(ho) => {
 		    return Ok(ho);
 		},
-		Err(_) => {
-		    return Err("Hired on date must be formatted as YYYY-MM-DD e.g. 2025-03-18".to_string());
+		None => {
+		    return Err("Hired on date must be like 2025-03-18, today, +3d or next fri".to_string());
 		}
 	    }
 	}
     }
 
     /// Gets return_on as a NaiveDate, or Err if it is invalid.
+    #[allow(clippy::needless_return)]
     fn get_valid_return_on(&self) -> Result<NaiveDate, String> {
 	if self.return_on.is_empty() {
             return Err("Return on date is required".to_string());
 	}
 	else {
-	    match NaiveDate::parse_from_str(self.return_on.as_str(), "%Y-%m-%d") {
-		Ok(ro) => {
+	    match parse_date(self.return_on.as_str(), self.date_limits.today) {
+		Some(ro) => {
 		    return Ok(ro);
 		},
-		Err(_) => {
-		    return Err("Return on date must be formatted as YYYY-MM-DD e.g. 2025-03-18".to_string());
+		None => {
+		    return Err("Return on date must be like 2025-03-18, today, +3d or next fri".to_string());
 		}
 	    }
 	}
     }
 
+    /// Checks the hire starts within the allowed days of today, unless it is
+    /// the unchanged hire date of the order being edited.
+    fn check_hired_on_range(&self, hired_on: NaiveDate) -> Result<(), String> {
+	if self.saved_hired_on == Some(hired_on) {
+	    return Ok(());
+	}
+
+	let limits = &self.date_limits;
+	let days_from_today = (hired_on - limits.today).num_days();
+
+	if days_from_today < -limits.max_days_ago {
+	    return Err(format!("Hired on date can't be more than {} days ago", limits.max_days_ago));
+	} else if days_from_today > limits.max_days_ahead {
+	    return Err(format!("Hired on date can't be more than {} days from today", limits.max_days_ahead));
+	}
+
+	Ok(())
+    }
+
+    /// Checks the order comes back on or after the day it is hired, and isn't
+    /// out for longer than allowed.
+    fn check_hire_length(&self, hired_on: NaiveDate, return_on: NaiveDate) -> Result<(), String> {
+	let max_hire_days = self.date_limits.max_hire_days;
+
+	if return_on < hired_on {
+	    return Err("Return on date can't be before the hired on date".to_string());
+	} else if (return_on - hired_on).num_days() > max_hire_days {
+	    return Err(format!("Hires can't be longer than {} days", max_hire_days));
+	}
+
+	Ok(())
+    }
+
+    /// Checks the dates against today and each other, once they are both
+    /// valid on their own. Gives the field the error belongs to along with it.
+    fn check_dates(&self) -> Result<(), (&'static str, String)> {
+	let (Ok(hired_on), Ok(return_on)) = (self.get_valid_hired_on(), self.get_valid_return_on()) else {
+	    return Ok(());
+	};
+
+	self.check_hired_on_range(hired_on).map_err(|e| ("hired_on", e))?;
+	self.check_hire_length(hired_on, return_on).map_err(|e| ("return_on", e))?;
+
+	Ok(())
+    }
+
+    /// Gets hired_on and return_on as NaiveDates, or Err if either of them is
+    /// invalid on its own or the two don't make sense together.
+    fn get_valid_dates(&self) -> Result<(NaiveDate, NaiveDate), String> {
+	let hired_on = self.get_valid_hired_on()?;
+	let return_on = self.get_valid_return_on()?;
+
+	self.check_dates().map_err(|(_, e)| e)?;
+
+	Ok((hired_on, return_on))
+    }
+
     /// Gets the error for a given field if its corresponding *_show_error is set to true.
+    #[allow(clippy::collapsible_match)]
     pub fn get_visible_field_error(&self, field: &str) -> Option<String> {
 	match field {
             "customer_name" => {
@@ -139,16 +406,9 @@ impl OrderForm {
 		    }
 		}
             },
-	    "item_hired" => {
-		if self.item_hired_show_error {
-		    if let Err(e) = self.get_valid_item_hired() {
-			return Some(e);
-		    }
-		}
-            },
-	    "how_many" => {
-		if self.how_many_show_error {
-		    if let Err(e) = self.get_valid_how_many() {
+	    "lines" => {
+		if self.lines_show_error {
+		    if let Err(e) = self.check_line_list() {
 			return Some(e);
 		    }
 		}
@@ -158,6 +418,9 @@ impl OrderForm {
 		    if let Err(e) = self.get_valid_hired_on() {
 			return Some(e);
 		    }
+		    if let Err(("hired_on", e)) = self.check_dates() {
+			return Some(e);
+		    }
 		}
             },
 	    "return_on" => {
@@ -165,6 +428,9 @@ impl OrderForm {
 		    if let Err(e) = self.get_valid_return_on() {
 			return Some(e);
 		    }
+		    if let Err(("return_on", e)) = self.check_dates() {
+			return Some(e);
+		    }
 		}
             },
             _ => (),
@@ -173,29 +439,179 @@ impl OrderForm {
 	None
     }
 
-    /// Creates an Order if all fields of the form are valid.
-    pub fn create_order(&self, connection: &Connection) -> Result<Order, String> {
-	Ok(
-	    Order::new(
-		connection,
-		self.get_valid_customer_name()?,
-		self.get_valid_receipt_number()?,
-		self.get_valid_item_hired()?,
-		self.get_valid_how_many()?,
-		self.get_valid_hired_on()?,
-		self.get_valid_return_on()?,
-	    ).unwrap()
+    /// Gets the error for a field of the line at index if it is being shown.
+    pub fn get_visible_line_error(&self, index: usize, field: &str) -> Option<String> {
+	self.lines.get(index)?.get_visible_field_error(field)
+    }
+
+    /// Gets the error of every invalid field, whether or not it is being shown.
+    pub fn get_errors(&self) -> Vec<String> {
+	let line_errors = self.lines.iter().flat_map(|line| {
+	    [line.get_valid_item_hired().err(), line.get_valid_how_many().err()]
+	});
+
+	[
+	    self.get_valid_customer_name().err(),
+	    self.get_valid_receipt_number().err(),
+	].into_iter()
+	    .chain(line_errors)
+	    .chain([
+		self.check_line_list().err(),
+		self.get_valid_hired_on().err(),
+		self.get_valid_return_on().err(),
+		self.check_dates().err().map(|(_, e)| e),
+	    ])
+	    .flatten()
+	    .collect()
+    }
+
+    /// Checks that every item is in the catalogue and enough of it is free for
+    /// the whole hire, returning the lines with the item names as written in the
+    /// catalogue. The order being edited, if any, doesn't count against the stock.
+    fn get_available_lines(
+	&self,
+	connection: &Connection,
+	editing_id: Option<i32>,
+    ) -> Result<Vec<(String, i32)>, Error> {
+	let lines = self.get_valid_lines().map_err(Error::Validation)?;
+	let (hired_on, return_on) = self.get_valid_dates().map_err(Error::Validation)?;
+
+	let mut available_lines = Vec::new();
+	for (name, how_many) in lines {
+	    let item = Item::get_by_name(connection, &name)?.ok_or_else(|| {
+		Error::Validation(format!("{} isn't in the item catalogue", name))
+	    })?;
+
+	    let available = item.available(connection, hired_on, return_on, editing_id)?;
+	    if how_many > available {
+		return Err(Error::Validation(format!(
+		    "Only {} {} are free from {} to {}",
+		    available.max(0),
+		    item.name,
+		    hired_on.format("%Y-%m-%d"),
+		    return_on.format("%Y-%m-%d"),
+		)));
+	    }
+
+	    available_lines.push((item.name, how_many));
+	}
+
+	Ok(available_lines)
+    }
+
+    /// Checks no other order has the receipt number, unless the user has
+    /// confirmed they want to use it again.
+    fn check_receipt_reuse(
+	&self,
+	connection: &Connection,
+	receipt_number: i64,
+	editing_id: Option<i32>,
+    ) -> Result<(), Error> {
+	if self.receipt_reuse_confirmed {
+	    return Ok(());
+	}
+
+	let other = Order::get_by_receipt_number(connection, receipt_number)?
+	    .into_iter()
+	    .find(|order| Some(order.id) != editing_id);
+
+	match other {
+	    Some(other) => Err(Error::NeedsConfirmation(format!(
+		"Receipt {} is already used by {}'s order hired on {}",
+		receipt_number,
+		other.customer_name,
+		other.hired_on.format("%Y-%m-%d"),
+	    ))),
+	    None => Ok(()),
+	}
+    }
+
+    /// Creates an Order if all fields of the form are valid and the items are available.
+    pub fn create_order(&self, connection: &Connection) -> Result<Order, Error> {
+	let customer_name = self.get_valid_customer_name().map_err(Error::Validation)?;
+	let receipt_number = self.get_valid_receipt_number().map_err(Error::Validation)?;
+	let (hired_on, return_on) = self.get_valid_dates().map_err(Error::Validation)?;
+	let lines = self.get_available_lines(connection, None)?;
+	self.check_receipt_reuse(connection, receipt_number, None)?;
+
+	Order::new(
+	    connection,
+	    customer_name,
+	    receipt_number,
+	    lines,
+	    hired_on,
+	    return_on,
+	)
+    }
+
+    /// Updates the given Order if all fields of the form are valid and the items are available.
+    pub fn update_order(&self, connection: &Connection, order: &Order) -> Result<Order, Error> {
+	let customer_name = self.get_valid_customer_name().map_err(Error::Validation)?;
+	let receipt_number = self.get_valid_receipt_number().map_err(Error::Validation)?;
+	let (hired_on, return_on) = self.get_valid_dates().map_err(Error::Validation)?;
+	let lines = self.get_available_lines(connection, Some(order.id))?;
+	self.check_receipt_reuse(connection, receipt_number, Some(order.id))?;
+
+	order.update(
+	    connection,
+	    customer_name,
+	    receipt_number,
+	    lines,
+	    hired_on,
+	    return_on,
 	)
     }
 }
 
+impl Form for OrderForm {
+    /// Sets every *_show_error to true, so all invalid fields show their errors.
+    fn show_all_errors(&mut self) {
+	self.customer_name_show_error = true;
+	self.receipt_number_show_error = true;
+	self.lines_show_error = true;
+	for line in self.lines.iter_mut() {
+	    line.item_hired_show_error = true;
+	    line.how_many_show_error = true;
+	}
+	self.hired_on_show_error = true;
+	self.return_on_show_error = true;
+    }
+
+    /// Checks if any field of the form is showing an error.
+    fn has_visible_errors(&self) -> bool {
+	let line_errors = (0..self.lines.len()).any(|index| {
+	    ["item_hired", "how_many"]
+		.iter()
+		.any(|field| self.get_visible_line_error(index, field).is_some())
+	});
+
+	line_errors || ["customer_name", "receipt_number", "lines", "hired_on", "return_on"]
+	    .iter()
+	    .any(|field| self.get_visible_field_error(field).is_some())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::database::init_db_tables;
 
     use super::*;
 
+    /// Gets date limits counting from a day shortly before the tests' hires.
+    fn test_limits() -> DateLimits {
+	DateLimits {
+	    today: NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    ..Default::default()
+	}
+    }
+
+    /// Adds the item the tests hire to the catalogue.
+    fn test_catalogue(con: &Connection) {
+	Item::new(con, "Test Item".to_string(), 200, 25, 100).unwrap();
+    }
+
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_get_valid_customer_name() {
 	let mut form = OrderForm::default();
 	form.customer_name = "Testing".to_string();
@@ -211,6 +627,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_get_valid_customer_name_too_short() {
 	let mut form = OrderForm::default();
 	form.customer_name = "aa".to_string();
@@ -219,6 +636,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_get_valid_customer_name_too_long() {
 	let mut form = OrderForm::default();
 	form.customer_name = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_string();
@@ -227,6 +645,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_get_valid_receipt_number() {
 	let mut form = OrderForm::default();
 	form.receipt_number = "123".to_string();
@@ -242,6 +661,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_get_valid_receipt_number_not_a_number() {
 	let mut form = OrderForm::default();
 	form.receipt_number = "hello".to_string();
@@ -249,77 +669,88 @@ mod test {
 	assert!(form.get_valid_receipt_number().is_err());
     }
 
+    #[test]
+    fn test_get_valid_receipt_number_not_positive() {
+	let form = OrderForm {
+	    receipt_number: "0".to_string(),
+	    ..Default::default()
+	};
+
+	assert!(form.get_valid_receipt_number().is_err());
+    }
+
     #[test]
     fn test_get_valid_item_hired() {
 	let mut form = OrderForm::default();
-	form.item_hired = "Test Item".to_string();
+	form.lines[0].item_hired = "Test Item".to_string();
 
-	assert!(form.get_valid_item_hired().is_ok())
+	assert!(form.lines[0].get_valid_item_hired().is_ok())
     }
 
     #[test]
     fn test_get_valid_item_hired_empty() {
 	let form = OrderForm::default();
 
-	assert!(form.get_valid_item_hired().is_err())
+	assert!(form.lines[0].get_valid_item_hired().is_err())
     }
 
     #[test]
     fn test_get_valid_item_hired_too_short() {
 	let mut form = OrderForm::default();
-	form.item_hired = "aa".to_string();
+	form.lines[0].item_hired = "aa".to_string();
 
-	assert!(form.get_valid_item_hired().is_err())
+	assert!(form.lines[0].get_valid_item_hired().is_err())
     }
 
     #[test]
     fn test_get_valid_item_hired_too_long() {
 	let mut form = OrderForm::default();
-	form.item_hired = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_string();
+	form.lines[0].item_hired = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_string();
 
-	assert!(form.get_valid_item_hired().is_err())
+	assert!(form.lines[0].get_valid_item_hired().is_err())
     }
 
     #[test]
     fn test_get_valid_how_many() {
 	let mut form = OrderForm::default();
-	form.how_many = "250".to_string();
+	form.lines[0].how_many = "250".to_string();
 
-	assert!(form.get_valid_how_many().is_ok());
+	assert!(form.lines[0].get_valid_how_many().is_ok());
     }
 
     #[test]
     fn test_get_valid_how_many_empty() {
 	let form = OrderForm::default();
 
-	assert!(form.get_valid_how_many().is_err());
+	assert!(form.lines[0].get_valid_how_many().is_err());
     }
 
     #[test]
     fn test_get_valid_how_many_not_an_int() {
 	let mut form = OrderForm::default();
-	form.how_many = "asdf".to_string();
+	form.lines[0].how_many = "asdf".to_string();
 
-	assert!(form.get_valid_how_many().is_err());
+	assert!(form.lines[0].get_valid_how_many().is_err());
     }
 
     #[test]
     fn test_get_valid_how_many_too_small() {
 	let mut form = OrderForm::default();
-	form.how_many = "-24".to_string();
+	form.lines[0].how_many = "-24".to_string();
 
-	assert!(form.get_valid_how_many().is_err());
+	assert!(form.lines[0].get_valid_how_many().is_err());
     }
 
     #[test]
     fn test_get_valid_how_many_too_big() {
 	let mut form = OrderForm::default();
-	form.how_many = "21442".to_string();
+	form.lines[0].how_many = "21442".to_string();
 
-	assert!(form.get_valid_how_many().is_err());
+	assert!(form.lines[0].get_valid_how_many().is_err());
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_get_valid_hired_on() {
 	let mut form = OrderForm::default();
 	form.hired_on = "2024-03-24".to_string();
@@ -335,6 +766,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_get_valid_hired_on_invalid_format() {
 	let mut form = OrderForm::default();
 	form.hired_on = "2024/03-asdf".to_string();
@@ -343,6 +775,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_get_valid_return_on() {
 	let mut form = OrderForm::default();
 	form.return_on = "2024-03-24".to_string();
@@ -358,6 +791,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_get_valid_return_on_invalid_format() {
 	let mut form = OrderForm::default();
 	form.return_on = "2024/03-asdf".to_string();
@@ -366,36 +800,449 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_form_create_order() {
 	let mut form = OrderForm::default();
 	form.customer_name = "Test".to_string();
 	form.receipt_number = "123".to_string();
-	form.item_hired = "Test Item".to_string();
-	form.how_many = "123".to_string();
-	form.hired_on = "1-1-1".to_string();
-	form.return_on = "1-1-2".to_string();
+	form.lines[0].item_hired = "Test Item".to_string();
+	form.lines[0].how_many = "123".to_string();
+	form.hired_on = "2025-03-23".to_string();
+	form.date_limits = test_limits();
+	form.return_on = "2025-03-24".to_string();
 
 	let con = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&con);
+	init_db_tables(&con).unwrap();
+	test_catalogue(&con);
 
 	assert!(form.create_order(&con).is_ok());
     }
 
     #[test]
+    fn test_form_create_order_reused_receipt() {
+	let mut form = OrderForm {
+	    customer_name: "Test".to_string(),
+	    receipt_number: "123".to_string(),
+	    ..Default::default()
+	};
+	form.lines[0].item_hired = "Test Item".to_string();
+	form.lines[0].how_many = "10".to_string();
+	form.hired_on = "2025-03-23".to_string();
+	form.date_limits = test_limits();
+	form.return_on = "2025-03-24".to_string();
+
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	test_catalogue(&con);
+
+	let order = form.create_order(&con).unwrap();
+	assert!(matches!(form.create_order(&con), Err(Error::NeedsConfirmation(_))));
+
+	// An order keeps its own receipt when it is edited
+	let mut edit_form = OrderForm::from_order(&order);
+	edit_form.date_limits = test_limits();
+	assert!(edit_form.update_order(&con, &order).is_ok());
+
+	form.receipt_reuse_confirmed = true;
+	assert!(form.create_order(&con).is_ok());
+	assert_eq!(Order::get_by_receipt_number(&con, 123).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_form_create_order_not_in_catalogue() {
+	let mut form = OrderForm {
+	    customer_name: "Test".to_string(),
+	    receipt_number: "123".to_string(),
+	    ..Default::default()
+	};
+	form.lines[0].item_hired = "Chiars".to_string();
+	form.lines[0].how_many = "10".to_string();
+	form.hired_on = "2025-03-23".to_string();
+	form.date_limits = test_limits();
+	form.return_on = "2025-03-25".to_string();
+
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	test_catalogue(&con);
+
+	assert!(matches!(form.create_order(&con), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn test_form_create_order_not_enough_stock() {
+	let mut form = OrderForm {
+	    customer_name: "Test".to_string(),
+	    receipt_number: "123".to_string(),
+	    ..Default::default()
+	};
+	form.lines[0].item_hired = "test item".to_string();
+	form.lines[0].how_many = "150".to_string();
+	form.hired_on = "2025-03-23".to_string();
+	form.date_limits = test_limits();
+	form.return_on = "2025-03-25".to_string();
+
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	test_catalogue(&con);
+
+	let order = form.create_order(&con).unwrap();
+	assert_eq!(order.lines[0].item_hired, "Test Item".to_string());
+
+	form.lines[0].how_many = "60".to_string();
+	assert!(matches!(form.create_order(&con), Err(Error::Validation(_))));
+
+	// The order being edited doesn't count against itself
+	let mut form = OrderForm::from_order(&order);
+	form.lines[0].how_many = "200".to_string();
+	assert!(form.update_order(&con, &order).is_ok());
+    }
+
+    #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_form_create_order_invalid() {
 	let mut form = OrderForm::default();
 	form.customer_name = "Test".to_string();
 	form.receipt_number = "12asdf3".to_string();
-	form.item_hired = "Test Item".to_string();
-	form.how_many = "123asdf".to_string();
+	form.lines[0].item_hired = "Test Item".to_string();
+	form.lines[0].how_many = "123asdf".to_string();
 	form.hired_on = "1-1-1".to_string();
 	form.return_on = "1-1-2".to_string();
 
 	let con = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&con);
+	init_db_tables(&con).unwrap();
 
 	assert!(form.create_order(&con).is_err());
     }
+
+    #[test]
+    fn test_form_from_order() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = Order::new(
+	    &con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	).unwrap();
+
+	let form = OrderForm::from_order(&order);
+
+	assert_eq!(form.editing_id, Some(order.id));
+	assert_eq!(form.customer_name, "Test Person".to_string());
+	assert_eq!(form.receipt_number, "15".to_string());
+	assert_eq!(form.lines[0].item_hired, "Test Item".to_string());
+	assert_eq!(form.lines[0].how_many, "26".to_string());
+	assert_eq!(form.hired_on, "2025-03-23".to_string());
+	assert_eq!(form.return_on, "2025-03-25".to_string());
+    }
+
+    #[test]
+    fn test_form_update_order() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	test_catalogue(&con);
+
+	let order = Order::new(
+	    &con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	).unwrap();
+
+	let mut form = OrderForm::from_order(&order);
+	form.lines[0].how_many = "80".to_string();
+
+	let updated = form.update_order(&con, &order).unwrap();
+
+	assert_eq!(updated.id, order.id);
+	assert_eq!(updated.raffle_number, order.raffle_number);
+	assert_eq!(updated.how_many(), 80);
+    }
+
+    #[test]
+    fn test_form_update_order_invalid() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	test_catalogue(&con);
+
+	let order = Order::new(
+	    &con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	).unwrap();
+
+	let mut form = OrderForm::from_order(&order);
+	form.lines[0].how_many = "asdf".to_string();
+
+	assert!(form.update_order(&con, &order).is_err());
+	assert_eq!(Order::get_by_id(&con, order.id).unwrap().how_many(), 26);
+    }
+
+    /// Makes a valid form hiring the given (item, how many) lines.
+    fn test_form_with_lines(lines: &[(&str, &str)]) -> OrderForm {
+	OrderForm {
+	    customer_name: "Test".to_string(),
+	    receipt_number: "123".to_string(),
+	    hired_on: "2025-03-23".to_string(),
+	    date_limits: test_limits(),
+	    return_on: "2025-03-25".to_string(),
+	    lines: lines.iter().map(|(item, how_many)| OrderLineForm {
+		item_hired: item.to_string(),
+		how_many: how_many.to_string(),
+		..Default::default()
+	    }).collect(),
+	    ..Default::default()
+	}
+    }
+
+    #[test]
+    fn test_form_add_and_remove_lines() {
+	let mut form = OrderForm::default();
+	assert_eq!(form.lines.len(), 1);
+
+	form.add_line();
+	form.lines[1].item_hired = "Tables".to_string();
+	assert_eq!(form.lines.len(), 2);
+
+	form.remove_line(0);
+	assert_eq!(form.lines.len(), 1);
+	assert_eq!(form.lines[0].item_hired, "Tables".to_string());
+
+	form.remove_line(0);
+	assert_eq!(form.get_visible_field_error("lines"), Some("At least one item is required".to_string()));
+    }
+
+    #[test]
+    fn test_form_duplicate_lines() {
+	let mut form = test_form_with_lines(&[("Test Item", "5"), ("test item", "5")]);
+
+	assert_eq!(form.get_visible_field_error("lines"), None);
+	form.show_all_errors();
+	assert!(form.get_visible_field_error("lines").is_some());
+	assert!(form.has_visible_errors());
+	assert_eq!(form.get_errors().len(), 1);
+    }
+
+    #[test]
+    fn test_form_get_visible_line_error() {
+	let mut form = test_form_with_lines(&[("Test Item", "5"), ("Other Item", "lots")]);
+	form.show_all_errors();
+
+	assert_eq!(form.get_visible_line_error(0, "how_many"), None);
+	assert!(form.get_visible_line_error(1, "how_many").is_some());
+	assert_eq!(form.get_visible_line_error(2, "how_many"), None);
+    }
+
+    #[test]
+    fn test_form_create_order_with_lines() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	test_catalogue(&con);
+	Item::new(&con, "Tables".to_string(), 10, 1, 1500).unwrap();
+
+	let order = test_form_with_lines(&[("test item", "20"), ("Tables", "4")]).create_order(&con).unwrap();
+	assert_eq!(order.items_hired(), "Test Item, Tables".to_string());
+
+	// There are only 6 tables left, so the whole order is refused
+	let form = test_form_with_lines(&[("Test Item", "20"), ("Tables", "7")]);
+	assert!(matches!(form.create_order(&con), Err(Error::Validation(_))));
+	assert_eq!(Order::get_all(&con).unwrap().len(), 1);
+
+	let form = OrderForm::from_order(&order);
+	assert_eq!(form.lines.len(), 2);
+	assert_eq!(form.lines[1].how_many, "4".to_string());
+    }
+
+    /// Makes a form with the given dates, checked against test_limits.
+    fn test_form_with_dates(hired_on: &str, return_on: &str) -> OrderForm {
+	OrderForm {
+	    hired_on: hired_on.to_string(),
+	    return_on: return_on.to_string(),
+	    hired_on_show_error: true,
+	    return_on_show_error: true,
+	    date_limits: test_limits(),
+	    ..Default::default()
+	}
+    }
+
+    #[test]
+    fn test_check_dates_valid() {
+	let form = test_form_with_dates("2025-03-23", "2025-03-25");
+
+	assert_eq!(form.check_dates(), Ok(()));
+	assert!(form.get_valid_dates().is_ok());
+    }
+
+    #[test]
+    fn test_check_dates_return_before_hire() {
+	let form = test_form_with_dates("2025-03-23", "2025-03-22");
+
+	assert!(matches!(form.check_dates(), Err(("return_on", _))));
+	assert!(form.get_visible_field_error("return_on").is_some());
+	assert!(form.get_visible_field_error("hired_on").is_none());
+    }
+
+    #[test]
+    fn test_check_dates_same_day_return() {
+	let form = test_form_with_dates("2025-03-23", "2025-03-23");
+
+	assert_eq!(form.check_dates(), Ok(()));
+    }
+
+    #[test]
+    fn test_check_dates_hire_too_long() {
+	let mut form = test_form_with_dates("2025-03-23", "2025-04-22");
+	assert_eq!(form.check_dates(), Ok(()));
+
+	form.return_on = "2025-04-23".to_string();
+	assert!(matches!(form.check_dates(), Err(("return_on", _))));
+
+	form.date_limits.max_hire_days = 31;
+	assert_eq!(form.check_dates(), Ok(()));
+    }
+
+    #[test]
+    fn test_check_dates_hire_too_long_ago() {
+	let mut form = test_form_with_dates("2025-02-18", "2025-02-20");
+	assert_eq!(form.check_dates(), Ok(()));
+
+	form.hired_on = "2025-02-17".to_string();
+	assert!(matches!(form.check_dates(), Err(("hired_on", _))));
+	assert!(form.get_visible_field_error("hired_on").is_some());
+	assert!(form.get_visible_field_error("return_on").is_none());
+    }
+
+    #[test]
+    fn test_check_dates_hire_too_far_ahead() {
+	let mut form = test_form_with_dates("2026-03-20", "2026-03-21");
+	assert_eq!(form.check_dates(), Ok(()));
+
+	form.hired_on = "2026-03-21".to_string();
+	form.return_on = "2026-03-22".to_string();
+	assert!(matches!(form.check_dates(), Err(("hired_on", _))));
+    }
+
+    #[test]
+    fn test_check_dates_edited_order_keeps_old_hire_date() {
+	let mut form = test_form_with_dates("2024-01-10", "2024-01-12");
+	form.saved_hired_on = NaiveDate::from_ymd_opt(2024, 1, 10);
+	assert_eq!(form.check_dates(), Ok(()));
+
+	// Moving it to another day long ago isn't allowed
+	form.hired_on = "2024-01-11".to_string();
+	assert!(matches!(form.check_dates(), Err(("hired_on", _))));
+    }
+
+    #[test]
+    fn test_check_dates_waits_for_valid_dates() {
+	let form = test_form_with_dates("2025-03-23", "soon");
+
+	assert_eq!(form.check_dates(), Ok(()));
+	assert_eq!(form.get_errors().len(), 5);
+	assert!(form.get_errors().contains(
+	    &"Return on date must be like 2025-03-18, today, +3d or next fri".to_string()
+	));
+    }
+
+    #[test]
+    fn test_parse_date() {
+	// A Thursday
+	let today = NaiveDate::from_ymd_opt(2025, 3, 20).unwrap();
+	let date = |day| NaiveDate::from_ymd_opt(2025, 3, day);
+
+	assert_eq!(parse_date("2025-03-18", today), date(18));
+	assert_eq!(parse_date(" Today ", today), date(20));
+	assert_eq!(parse_date("tomorrow", today), date(21));
+	assert_eq!(parse_date("yesterday", today), date(19));
+	assert_eq!(parse_date("+3d", today), date(23));
+	assert_eq!(parse_date("-2d", today), date(18));
+	assert_eq!(parse_date("+1w", today), date(27));
+	assert_eq!(parse_date("next fri", today), date(21));
+	assert_eq!(parse_date("monday", today), date(24));
+	assert_eq!(parse_date("next thu", today), date(27));
+    }
+
+    #[test]
+    fn test_parse_date_invalid() {
+	let today = NaiveDate::from_ymd_opt(2025, 3, 20).unwrap();
+
+	for input in ["", "soon", "+d", "+3", "+3y", "3d", "next", "next month", "2025-02-30"] {
+	    assert_eq!(parse_date(input, today), None, "{} should be invalid", input);
+	}
+	assert_eq!(parse_date("+99999999999999999d", today), None);
+    }
+
+    #[test]
+    fn test_parse_date_non_ascii() {
+	let today = NaiveDate::from_ymd_opt(2025, 3, 20).unwrap();
+
+	for input in ["+é", "+3é", "-€", "+3dé", "+é3d"] {
+	    assert_eq!(parse_date(input, today), None, "{} should be invalid", input);
+	}
+    }
+
+    #[test]
+    fn test_form_relative_dates() {
+	let form = test_form_with_dates("tomorrow", "+1w");
+
+	assert_eq!(form.get_valid_hired_on(), Ok(NaiveDate::from_ymd_opt(2025, 3, 21).unwrap()));
+	assert_eq!(form.get_valid_return_on(), Ok(NaiveDate::from_ymd_opt(2025, 3, 27).unwrap()));
+	assert_eq!(form.check_dates(), Ok(()));
+    }
+
+    #[test]
+    fn test_get_errors_includes_date_checks() {
+	let form = test_form_with_dates("2025-03-23", "2025-03-01");
+
+	assert!(form.get_errors().contains(&"Return on date can't be before the hired on date".to_string()));
+    }
+
+    #[test]
+    fn test_form_create_order_checks_dates() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	test_catalogue(&con);
+
+	let mut form = test_form_with_dates("2025-03-23", "2025-03-20");
+	form.customer_name = "Test".to_string();
+	form.receipt_number = "123".to_string();
+	form.lines[0].item_hired = "Test Item".to_string();
+	form.lines[0].how_many = "1".to_string();
+
+	assert!(matches!(form.create_order(&con), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn test_date_limits_load() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	MAX_HIRE_DAYS.set(&con, 7).unwrap();
+
+	let today = NaiveDate::from_ymd_opt(2025, 3, 20).unwrap();
+	let limits = DateLimits::load(&con, today).unwrap();
+
+	assert_eq!(limits.today, today);
+	assert_eq!(limits.max_hire_days, 7);
+	assert_eq!(limits.max_days_ago, MAX_DAYS_AGO.default);
+	assert_eq!(limits.max_days_ahead, MAX_DAYS_AHEAD.default);
+    }
 }
@@ -0,0 +1,369 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use rusqlite::Connection;
+use chrono::Local;
+use crate::order::{Order, OrderForm};
+use crate::order::form::{DateLimits, OrderLineForm};
+use crate::error::Error;
+
+/// The Order fields that a CSV column can be mapped to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportField {
+    CustomerName,
+    ReceiptNumber,
+    ItemHired,
+    HowMany,
+    HiredOn,
+    ReturnOn,
+}
+
+impl ImportField {
+    /// Every field, all of which need a column.
+    pub const ALL: [ImportField; 6] = [
+	ImportField::CustomerName,
+	ImportField::ReceiptNumber,
+	ImportField::ItemHired,
+	ImportField::HowMany,
+	ImportField::HiredOn,
+	ImportField::ReturnOn,
+    ];
+
+    /// Gets the name of the field shown to the user.
+    pub fn label(&self) -> &'static str {
+	match self {
+	    ImportField::CustomerName => "Customer Name",
+	    ImportField::ReceiptNumber => "Receipt Number",
+	    ImportField::ItemHired => "Item Hired",
+	    ImportField::HowMany => "How Many",
+	    ImportField::HiredOn => "Hired On",
+	    ImportField::ReturnOn => "Return On",
+	}
+    }
+
+    /// Works out which field a column is for from its header, ignoring case,
+    /// spaces and punctuation. Returns None for columns that aren't imported.
+    pub fn from_header(header: &str) -> Option<Self> {
+	let header: String = header
+	    .chars()
+	    .filter(|c| c.is_alphanumeric())
+	    .collect::<String>()
+	    .to_lowercase();
+
+	match header.as_str() {
+	    "customername" | "customer" | "name" => Some(ImportField::CustomerName),
+	    "receiptnumber" | "receiptno" | "receipt" => Some(ImportField::ReceiptNumber),
+	    "itemhired" | "item" => Some(ImportField::ItemHired),
+	    "howmany" | "quantity" | "qty" => Some(ImportField::HowMany),
+	    "hiredon" | "hiredate" | "from" => Some(ImportField::HiredOn),
+	    "returnon" | "returndate" | "to" => Some(ImportField::ReturnOn),
+	    _ => None,
+	}
+    }
+}
+
+/// Represents an order read from a CSV file being imported, and what is wrong
+/// with it. Rows with the same customer, receipt and dates are one order, with
+/// a line for each row.
+pub struct ImportRow {
+    // The line of the file the order's first row is on
+    pub line: u64,
+    pub form: OrderForm,
+    pub errors: Vec<String>,
+}
+
+/// Represents a CSV file that has been checked but not yet imported.
+pub struct ImportPreview {
+    pub rows: Vec<ImportRow>,
+}
+
+impl ImportPreview {
+    /// Reads and checks every row of a CSV file, grouping them into orders. The
+    /// file needs a header row with a column for every ImportField.
+    pub fn from_reader<R: Read>(connection: &Connection, reader: R) -> Result<Self, Error> {
+	let mut reader = csv::ReaderBuilder::new()
+	    .flexible(true)
+	    .trim(csv::Trim::All)
+	    .from_reader(reader);
+
+	let headers = reader.headers()?.clone();
+	let mut columns = Vec::new();
+	let mut missing = Vec::new();
+	for field in ImportField::ALL {
+	    match headers.iter().position(|h| ImportField::from_header(h) == Some(field)) {
+		Some(index) => columns.push((field, index)),
+		None => missing.push(field.label()),
+	    }
+	}
+
+	if !missing.is_empty() {
+	    return Err(Error::Validation(format!("The CSV file has no column for: {}", missing.join(", "))));
+	}
+
+	let date_limits = DateLimits::load(connection, Local::now().date_naive())?;
+
+	let mut rows: Vec<ImportRow> = Vec::new();
+	for record in reader.records() {
+	    let record = record?;
+	    let line = record.position().map(|p| p.line()).unwrap_or_default();
+
+	    let mut form = OrderForm {
+		date_limits,
+		..Default::default()
+	    };
+	    let mut order_line = OrderLineForm::default();
+	    for (field, index) in &columns {
+		let value = record.get(*index).unwrap_or_default().to_string();
+
+		match field {
+		    ImportField::CustomerName => form.customer_name = value,
+		    ImportField::ReceiptNumber => form.receipt_number = value,
+		    ImportField::ItemHired => order_line.item_hired = value,
+		    ImportField::HowMany => order_line.how_many = value,
+		    ImportField::HiredOn => form.hired_on = value,
+		    ImportField::ReturnOn => form.return_on = value,
+		}
+	    }
+
+	    let same_order = rows.iter_mut().find(|row| {
+		row.form.customer_name == form.customer_name
+		    && row.form.receipt_number == form.receipt_number
+		    && row.form.hired_on == form.hired_on
+		    && row.form.return_on == form.return_on
+	    });
+
+	    match same_order {
+		Some(row) => row.form.lines.push(order_line),
+		None => {
+		    form.lines = vec![order_line];
+		    rows.push(ImportRow { line, form, errors: Vec::new() });
+		},
+	    }
+	}
+
+	for row in rows.iter_mut() {
+	    row.errors = row.form.get_errors();
+	}
+
+	let mut preview = Self { rows };
+	preview.trial_run(connection)?;
+
+	Ok(preview)
+    }
+
+    /// Reads and checks every row of a CSV file on disk.
+    pub fn from_file(connection: &Connection, path: &Path) -> Result<Self, Error> {
+	Self::from_reader(connection, File::open(path)?)
+    }
+
+    /// Adds the rows without field errors in a transaction that is rolled back,
+    /// recording why any of them fail, e.g. the item isn't in the catalogue or
+    /// earlier rows have used up its stock.
+    fn trial_run(&mut self, connection: &Connection) -> Result<(), Error> {
+	let tx = connection.unchecked_transaction()?;
+
+	for row in self.rows.iter_mut().filter(|row| row.errors.is_empty()) {
+	    match row.form.create_order(&tx) {
+		Ok(_) => (),
+		Err(Error::Validation(message)) | Err(Error::NeedsConfirmation(message)) => {
+		    row.errors.push(message)
+		},
+		Err(e) => return Err(e),
+	    }
+	}
+
+	tx.rollback()?;
+
+	Ok(())
+    }
+
+    /// Gets the rows that can't be imported.
+    pub fn invalid_rows(&self) -> impl Iterator<Item = &ImportRow> {
+	self.rows.iter().filter(|row| !row.errors.is_empty())
+    }
+
+    /// Checks if every row can be imported.
+    pub fn is_valid(&self) -> bool {
+	self.invalid_rows().next().is_none()
+    }
+
+    /// Imports every row in a single transaction. If any row fails, nothing is imported.
+    pub fn commit(&self, connection: &Connection) -> Result<Vec<Order>, Error> {
+	if !self.is_valid() {
+	    return Err(Error::Validation("Fix the rows with errors before importing".to_string()));
+	}
+
+	let tx = connection.unchecked_transaction()?;
+
+	let mut orders = Vec::new();
+	for row in &self.rows {
+	    let order = row.form.create_order(&tx).map_err(|e| match e {
+		Error::Validation(message) => Error::Validation(format!("Line {}: {}", row.line, message)),
+		e => e,
+	    })?;
+	    orders.push(order);
+	}
+
+	tx.commit()?;
+
+	Ok(orders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+    use crate::item::Item;
+    use crate::order::export::export_csv;
+    use crate::settings::{MAX_DAYS_AGO, RAFFLE_NUMBER_MAX};
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    /// Makes a database with the items in VALID_CSV in the catalogue.
+    fn test_connection() -> Connection {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	// The tests hire in March 2025, which is long ago now
+	MAX_DAYS_AGO.set(&con, MAX_DAYS_AGO.max).unwrap();
+	Item::new(&con, "Chairs".to_string(), 100, 25, 250).unwrap();
+	Item::new(&con, "Tables".to_string(), 10, 1, 1500).unwrap();
+
+	con
+    }
+
+    const VALID_CSV: &str = "Customer,Receipt No.,Item,Qty,Hire Date,Return Date
+Alice Smith,1001,Chairs,40,2025-03-01,2025-03-03
+\"Jones, Bob\",1002,Tables,5,2025-03-10,2025-03-12
+";
+
+    #[test]
+    fn test_import_field_from_header() {
+	assert_eq!(ImportField::from_header("Customer Name"), Some(ImportField::CustomerName));
+	assert_eq!(ImportField::from_header("receipt_number"), Some(ImportField::ReceiptNumber));
+	assert_eq!(ImportField::from_header(" QTY "), Some(ImportField::HowMany));
+	assert_eq!(ImportField::from_header("Notes"), None);
+    }
+
+    #[test]
+    fn test_import_preview() {
+	let con = test_connection();
+	let preview = ImportPreview::from_reader(&con, VALID_CSV.as_bytes()).unwrap();
+
+	assert_eq!(preview.rows.len(), 2);
+	assert!(preview.is_valid());
+	assert_eq!(preview.rows[1].line, 3);
+	assert_eq!(preview.rows[1].form.customer_name, "Jones, Bob".to_string());
+	assert_eq!(preview.rows[1].form.lines[0].how_many, "5".to_string());
+    }
+
+    #[test]
+    fn test_import_preview_groups_lines() {
+	let con = test_connection();
+	let csv = format!("{}Alice Smith,1001,Tables,2,2025-03-01,2025-03-03\n", VALID_CSV);
+
+	let preview = ImportPreview::from_reader(&con, csv.as_bytes()).unwrap();
+	assert_eq!(preview.rows.len(), 2);
+	assert_eq!(preview.rows[0].form.lines.len(), 2);
+
+	let orders = preview.commit(&con).unwrap();
+	assert_eq!(orders[0].items_hired(), "Chairs, Tables".to_string());
+    }
+
+    #[test]
+    fn test_import_preview_missing_column() {
+	let con = test_connection();
+	let csv = "Customer,Item,Qty\nAlice Smith,Chairs,40\n";
+
+	let error = ImportPreview::from_reader(&con, csv.as_bytes()).err().unwrap().to_string();
+	assert!(error.contains("Receipt Number"));
+	assert!(error.contains("Hired On"));
+    }
+
+    #[test]
+    fn test_import_preview_row_errors() {
+	let con = test_connection();
+	let csv = "Customer,Receipt No.,Item,Qty,Hire Date,Return Date
+Alice Smith,1001,Chairs,40,2025-03-01,2025-03-03
+A,abc,Chairs,lots,2025-03-01,2025-03-03
+";
+	let preview = ImportPreview::from_reader(&con, csv.as_bytes()).unwrap();
+
+	assert!(!preview.is_valid());
+	let invalid: Vec<&ImportRow> = preview.invalid_rows().collect();
+	assert_eq!(invalid.len(), 1);
+	assert_eq!(invalid[0].line, 3);
+	assert_eq!(invalid[0].errors.len(), 3);
+    }
+
+    #[test]
+    fn test_import_preview_catalogue_and_stock() {
+	let con = test_connection();
+	let csv = "Customer,Receipt No.,Item,Qty,Hire Date,Return Date
+Alice Smith,1001,Tables,6,2025-03-01,2025-03-03
+Bob Jones,1002,Tables,6,2025-03-02,2025-03-04
+Carol King,1003,Chiars,6,2025-03-02,2025-03-04
+";
+	let preview = ImportPreview::from_reader(&con, csv.as_bytes()).unwrap();
+
+	let invalid: Vec<u64> = preview.invalid_rows().map(|row| row.line).collect();
+	assert_eq!(invalid, vec![3, 4]);
+	assert!(Order::get_all(&con).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_import_commit() {
+	let con = test_connection();
+
+	let preview = ImportPreview::from_reader(&con, VALID_CSV.as_bytes()).unwrap();
+	let orders = preview.commit(&con).unwrap();
+
+	assert_eq!(orders.len(), 2);
+	assert_eq!(Order::get_all(&con).unwrap().len(), 2);
+	assert_eq!(orders[0].hired_on, NaiveDate::from_ymd_opt(2025, 3, 1).unwrap());
+    }
+
+    #[test]
+    fn test_import_commit_invalid_imports_nothing() {
+	let con = test_connection();
+
+	let csv = format!("{}A,abc,Chairs,lots,2025-03-01,2025-03-03\n", VALID_CSV);
+	let preview = ImportPreview::from_reader(&con, csv.as_bytes()).unwrap();
+
+	assert!(preview.commit(&con).is_err());
+	assert!(Order::get_all(&con).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_import_commit_rolls_back() {
+	let con = test_connection();
+	let preview = ImportPreview::from_reader(&con, VALID_CSV.as_bytes()).unwrap();
+	assert!(preview.is_valid());
+
+	// Only one raffle number is left, so the second row fails
+	RAFFLE_NUMBER_MAX.set(&con, 0).unwrap();
+
+	assert!(preview.commit(&con).is_err());
+	assert!(Order::get_all(&con).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_export_then_import() {
+	let con = test_connection();
+
+	let orders = ImportPreview::from_reader(&con, VALID_CSV.as_bytes()).unwrap().commit(&con).unwrap();
+	let mut exported = Vec::new();
+	export_csv(&orders, &mut exported).unwrap();
+
+	let preview = ImportPreview::from_reader(&test_connection(), exported.as_slice()).unwrap();
+	assert!(preview.is_valid());
+	assert_eq!(preview.rows.len(), 2);
+	assert_eq!(preview.rows[0].form.customer_name, "Alice Smith".to_string());
+
+	// Importing them again would put their receipts on two orders each
+	let preview = ImportPreview::from_reader(&con, exported.as_slice()).unwrap();
+	assert_eq!(preview.invalid_rows().count(), 2);
+	assert!(preview.rows[0].errors[0].contains("already used"));
+    }
+}
@@ -0,0 +1,307 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use crate::error::Error;
+use crate::item::format_price;
+use crate::order::Order;
+use crate::settings::{LATE_FEE_CAP, LATE_FEE_GRACE_DAYS, LATE_FEE_PER_DAY, LATE_FEE_PER_ITEM};
+
+/// What orders that come back late are charged, from the settings. The days
+/// in the grace period aren't charged for, and only the days after it are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LateFeePolicy {
+    // Charged for each day late, in cents
+    pub per_day: i64,
+    // Charged for each item on the order for each day late, in cents
+    pub per_item: i64,
+    pub grace_days: i64,
+    // The most an order is charged, in cents, or 0 for no limit
+    pub cap: i64,
+}
+
+impl LateFeePolicy {
+    /// Loads the policy from the settings.
+    pub fn load(connection: &Connection) -> Result<Self, Error> {
+	Ok(Self {
+	    per_day: LATE_FEE_PER_DAY.get(connection)?,
+	    per_item: LATE_FEE_PER_ITEM.get(connection)?,
+	    grace_days: LATE_FEE_GRACE_DAYS.get(connection)?,
+	    cap: LATE_FEE_CAP.get(connection)?,
+	})
+    }
+
+    /// Gets the fee, in cents, for an order of `items` items that was due back
+    /// on `return_on` and came back on `returned_on`.
+    pub fn fee(&self, items: i64, return_on: NaiveDate, returned_on: NaiveDate) -> i64 {
+	let charged_days = (days_late(return_on, returned_on) - self.grace_days).max(0);
+	let per_day = self.per_day.saturating_add(self.per_item.saturating_mul(items.max(0)));
+	let fee = per_day.saturating_mul(charged_days);
+
+	match self.cap {
+	    0 => fee,
+	    cap => fee.min(cap),
+	}
+    }
+}
+
+/// Gets how many days after `return_on` an order came back, or 0 if it was on
+/// time.
+pub fn days_late(return_on: NaiveDate, returned_on: NaiveDate) -> i64 {
+    (returned_on - return_on).num_days().max(0)
+}
+
+/// Represents the late fee charged on an order, which staff can waive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LateFee {
+    pub id: i32,
+    pub order_id: i32,
+    pub days_late: i64,
+    // The fee, in cents
+    pub amount: i64,
+    pub charged_at: NaiveDateTime,
+    pub waived_at: Option<NaiveDateTime>,
+    pub waive_reason: Option<String>,
+}
+
+impl LateFee {
+    /// Charges the order the late fee the policy gives for it coming back at
+    /// `returned_at`. Gives None if it came back in time, or the fee is nothing.
+    pub fn charge(connection: &Connection, order: &Order, returned_at: NaiveDateTime) -> Result<Option<Self>, Error> {
+	let policy = LateFeePolicy::load(connection)?;
+	let returned_on = returned_at.date();
+	let amount = policy.fee(order.how_many() as i64, order.return_on, returned_on);
+
+	if amount == 0 {
+	    return Ok(None);
+	}
+
+	connection.execute(
+	    "INSERT INTO LateFee (order_id, days_late, amount, charged_at) VALUES (?1, ?2, ?3, ?4)",
+	    params![order.id, days_late(order.return_on, returned_on), amount, returned_at],
+	)?;
+
+	Self::get_for_order(connection, order.id)
+    }
+
+    /// Gets the late fee charged on an order, if it was charged one.
+    pub fn get_for_order(connection: &Connection, order_id: i32) -> Result<Option<Self>, Error> {
+	Ok(connection.query_row(
+	    "SELECT id, order_id, days_late, amount, charged_at, waived_at, waive_reason
+	     FROM LateFee WHERE order_id = ?1",
+	    [order_id],
+	    Self::from_row,
+	).optional()?)
+    }
+
+    /// Waives the fee, so the customer doesn't owe it, recording why.
+    pub fn waive(&self, connection: &Connection, reason: &str, waived_at: NaiveDateTime) -> Result<Self, Error> {
+	let reason = reason.trim();
+	if reason.is_empty() {
+	    return Err(Error::Validation("A reason is needed to waive a late fee".to_string()));
+	} else if reason.len() > 100 {
+	    return Err(Error::Validation("Reason must be less than 100 characters".to_string()));
+	} else if self.is_waived() {
+	    return Err(Error::Validation("The late fee has already been waived".to_string()));
+	}
+
+	connection.execute(
+	    "UPDATE LateFee SET waived_at = ?1, waive_reason = ?2 WHERE id = ?3",
+	    params![waived_at, reason, self.id],
+	)?;
+
+	Ok(Self {
+	    waived_at: Some(waived_at),
+	    waive_reason: Some(reason.to_string()),
+	    ..self.clone()
+	})
+    }
+
+    /// Checks if the fee has been waived.
+    pub fn is_waived(&self) -> bool {
+	self.waived_at.is_some()
+    }
+
+    /// Describes the fee, e.g. "Late fee for 3 day(s) late: $15.00".
+    pub fn description(&self) -> String {
+	let description = format!(
+	    "Late fee for {} day(s) late: {}",
+	    self.days_late,
+	    format_price(self.amount),
+	);
+
+	match &self.waive_reason {
+	    Some(reason) => format!("{} (waived: {})", description, reason),
+	    None => description,
+	}
+    }
+
+    fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
+	Ok(Self {
+	    id: row.get(0)?,
+	    order_id: row.get(1)?,
+	    days_late: row.get(2)?,
+	    amount: row.get(3)?,
+	    charged_at: row.get(4)?,
+	    waived_at: row.get(5)?,
+	    waive_reason: row.get(6)?,
+	})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+	NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn per_day(per_day: i64) -> LateFeePolicy {
+	LateFeePolicy { per_day, ..Default::default() }
+    }
+
+    #[test]
+    fn test_days_late() {
+	assert_eq!(days_late(ymd(2025, 3, 10), ymd(2025, 3, 13)), 3);
+	assert_eq!(days_late(ymd(2025, 3, 10), ymd(2025, 3, 10)), 0);
+	assert_eq!(days_late(ymd(2025, 3, 10), ymd(2025, 3, 8)), 0);
+    }
+
+    #[test]
+    fn test_days_late_month_edge() {
+	assert_eq!(days_late(ymd(2025, 1, 31), ymd(2025, 2, 2)), 2);
+	assert_eq!(days_late(ymd(2025, 4, 30), ymd(2025, 5, 1)), 1);
+	assert_eq!(days_late(ymd(2024, 12, 31), ymd(2025, 1, 2)), 2);
+    }
+
+    #[test]
+    fn test_days_late_leap_year() {
+	assert_eq!(days_late(ymd(2024, 2, 28), ymd(2024, 3, 1)), 2);
+	assert_eq!(days_late(ymd(2024, 2, 29), ymd(2024, 3, 1)), 1);
+	assert_eq!(days_late(ymd(2025, 2, 28), ymd(2025, 3, 1)), 1);
+	// 2100 isn't a leap year, as it is divisible by 100 but not 400
+	assert_eq!(days_late(ymd(2100, 2, 28), ymd(2100, 3, 1)), 1);
+	assert_eq!(days_late(ymd(2000, 2, 28), ymd(2000, 3, 1)), 2);
+    }
+
+    #[test]
+    fn test_fee_per_day() {
+	let policy = per_day(500);
+
+	assert_eq!(policy.fee(10, ymd(2025, 3, 10), ymd(2025, 3, 13)), 1500);
+	assert_eq!(policy.fee(10, ymd(2025, 3, 10), ymd(2025, 3, 10)), 0);
+	assert_eq!(policy.fee(10, ymd(2024, 2, 28), ymd(2024, 3, 1)), 1000);
+	assert_eq!(policy.fee(10, ymd(2025, 2, 28), ymd(2025, 3, 1)), 500);
+    }
+
+    #[test]
+    fn test_fee_per_item() {
+	let policy = LateFeePolicy { per_day: 500, per_item: 20, ..Default::default() };
+
+	// ($5 + 10 x $0.20) x 2 days
+	assert_eq!(policy.fee(10, ymd(2025, 1, 31), ymd(2025, 2, 2)), 1400);
+    }
+
+    #[test]
+    fn test_fee_grace_days() {
+	let policy = LateFeePolicy { per_day: 500, grace_days: 2, ..Default::default() };
+
+	assert_eq!(policy.fee(1, ymd(2025, 3, 10), ymd(2025, 3, 12)), 0);
+	assert_eq!(policy.fee(1, ymd(2025, 3, 10), ymd(2025, 3, 13)), 500);
+	assert_eq!(policy.fee(1, ymd(2024, 2, 27), ymd(2024, 3, 2)), 1000);
+    }
+
+    #[test]
+    fn test_fee_cap() {
+	let policy = LateFeePolicy { per_day: 500, cap: 1200, ..Default::default() };
+
+	assert_eq!(policy.fee(1, ymd(2025, 3, 10), ymd(2025, 3, 12)), 1000);
+	assert_eq!(policy.fee(1, ymd(2025, 3, 10), ymd(2025, 3, 13)), 1200);
+	assert_eq!(policy.fee(1, ymd(2024, 12, 1), ymd(2025, 3, 1)), 1200);
+    }
+
+    #[test]
+    fn test_fee_no_policy() {
+	assert_eq!(LateFeePolicy::default().fee(10, ymd(2025, 3, 10), ymd(2025, 4, 10)), 0);
+    }
+
+    #[test]
+    fn test_policy_load() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	LATE_FEE_PER_DAY.set(&con, 500).unwrap();
+	LATE_FEE_CAP.set(&con, 2000).unwrap();
+
+	assert_eq!(
+	    LateFeePolicy::load(&con).unwrap(),
+	    LateFeePolicy { per_day: 500, per_item: 0, grace_days: 0, cap: 2000 },
+	);
+    }
+
+    fn returned_order(con: &Connection, returned_on: NaiveDate) -> Order {
+	let order = Order::new(
+	    con,
+	    "Test Person".to_string(),
+	    1,
+	    vec![("Chairs".to_string(), 10)],
+	    ymd(2024, 2, 25),
+	    ymd(2024, 2, 28),
+	).unwrap();
+
+	order.mark_out(con).unwrap()
+	    .mark_returned(con, returned_on.and_hms_opt(9, 0, 0).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_charged_on_return() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	LATE_FEE_PER_DAY.set(&con, 500).unwrap();
+
+	let order = returned_order(&con, ymd(2024, 3, 1));
+	let fee = LateFee::get_for_order(&con, order.id).unwrap().unwrap();
+	assert_eq!((fee.days_late, fee.amount), (2, 1000));
+	assert_eq!(order.late_fee, 1000);
+	assert_eq!(order.balance(), 1000);
+	assert_eq!(fee.description(), "Late fee for 2 day(s) late: $10.00".to_string());
+    }
+
+    #[test]
+    fn test_not_charged_on_time() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	LATE_FEE_PER_DAY.set(&con, 500).unwrap();
+
+	let order = returned_order(&con, ymd(2024, 2, 28));
+	assert_eq!(LateFee::get_for_order(&con, order.id).unwrap(), None);
+	assert_eq!(order.late_fee, 0);
+    }
+
+    #[test]
+    fn test_waive() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	LATE_FEE_PER_DAY.set(&con, 500).unwrap();
+
+	let order = returned_order(&con, ymd(2024, 3, 1));
+	let fee = LateFee::get_for_order(&con, order.id).unwrap().unwrap();
+	let waived_at = ymd(2024, 3, 2).and_hms_opt(10, 0, 0).unwrap();
+
+	assert!(fee.waive(&con, " ", waived_at).is_err());
+	let fee = fee.waive(&con, "Storm closed the roads", waived_at).unwrap();
+	assert!(fee.is_waived());
+	assert!(fee.waive(&con, "Again", waived_at).is_err());
+
+	assert_eq!(LateFee::get_for_order(&con, order.id).unwrap(), Some(fee.clone()));
+	assert_eq!(Order::get_by_id(&con, order.id).unwrap().balance(), 0);
+	assert_eq!(
+	    fee.description(),
+	    "Late fee for 2 day(s) late: $10.00 (waived: Storm closed the roads)".to_string(),
+	);
+    }
+}
@@ -1,206 +1,863 @@
 pub mod table;
 pub mod form;
+pub mod status;
+pub mod query;
+pub mod export;
+pub mod import;
+pub mod undo;
+pub mod late_fee;
 
-use rusqlite::{Connection, Error};
-use chrono::NaiveDate;
+use std::collections::HashMap;
+use rusqlite::{params, Connection, Row};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use chrono::{Days, Local, NaiveDate, NaiveDateTime};
 use rand::prelude::*;
+use crate::customer::Customer;
+use crate::database::with_savepoint;
+use crate::error::Error;
+use crate::item::Item;
+use crate::packing::{pack, PackingLine};
+use crate::settings::{
+    DEFAULT_BOX_CAPACITY, DELETED_ORDER_RETENTION_DAYS, RAFFLE_NUMBER_MIN, RAFFLE_NUMBER_MAX, RECEIPT_NUMBER_START,
+};
 
 pub use self::form::OrderForm;
+pub use self::status::OrderStatus;
+pub use self::query::OrderQuery;
+pub use self::undo::UndoAction;
+pub use self::late_fee::LateFee;
 
-/// Represents an order in the database
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// The columns of an order, in the order Order::from_row reads them. What has
+/// been paid is added up from its payments, and a waived late fee isn't owed.
+const ORDER_COLUMNS: &str = "CustomerOrder.id, customer_id, Customer.name, receipt_number,
+    hired_on, return_on, raffle_number, status, returned_at, deleted_at, deposit, damage_charge,
+    (SELECT COALESCE(SUM(amount), 0) FROM Payment WHERE Payment.order_id = CustomerOrder.id),
+    (SELECT COALESCE(SUM(amount), 0) FROM LateFee
+     WHERE LateFee.order_id = CustomerOrder.id AND waived_at IS NULL),
+    CustomerOrder.boxes_needed";
+
+/// The tables an order is selected from, which give it its customer's name.
+/// Deleted orders are left out, so they only show up in the recently deleted list.
+const ORDER_TABLES: &str = "CustomerOrder JOIN Customer ON Customer.id = CustomerOrder.customer_id
+    AND CustomerOrder.deleted_at IS NULL";
+
+/// The tables deleted orders are selected from.
+const DELETED_ORDER_TABLES: &str = "CustomerOrder JOIN Customer ON Customer.id = CustomerOrder.customer_id
+    AND CustomerOrder.deleted_at IS NOT NULL";
+
+/// The columns of OrderLine, in the order OrderLine::from_row reads them.
+const LINE_COLUMNS: &str = "id, order_id, item_hired, how_many, boxes_needed, daily_rate";
+
+/// Selects the ids of orders deleted before ?1 that can be purged, which are
+/// those that didn't win a raffle draw.
+const PURGEABLE_ORDERS: &str = "SELECT id FROM CustomerOrder WHERE deleted_at < ?1
+    AND id NOT IN (SELECT winner_order_id FROM RaffleDraw)";
+
+/// How many times a new order is given a raffle number before giving up.
+const RAFFLE_NUMBER_TRIES: usize = 5;
+
+/// Represents an order in the database. The customer, receipt and dates are
+/// shared by every item on the order, which are its lines.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Order {
     pub id: i32,
+    pub customer_id: i32,
     pub customer_name: String,
     pub receipt_number: i64,
-    pub item_hired: String,
-    pub how_many: i32,
     pub hired_on: NaiveDate,
     pub return_on: NaiveDate,
-    pub boxes_needed: i32,
     pub raffle_number: i32,
+    pub status: OrderStatus,
+    pub returned_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
+    // The deposit taken while the items are out, in cents
+    pub deposit: i64,
+    // What the customer is charged for damage or losses, in cents
+    pub damage_charge: i64,
+    // What has been paid altogether, less refunds, in cents
+    pub paid: i64,
+    // The late fee charged when it came back, unless it was waived, in cents
+    pub late_fee: i64,
+    // How many boxes the items are packed in, where leftovers of different
+    // items can share a box
+    pub boxes_needed: i32,
+    pub lines: Vec<OrderLine>,
+}
+
+/// Represents one item hired on an order, and how many of it.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OrderLine {
+    pub id: i32,
+    pub order_id: i32,
+    pub item_hired: String,
+    pub how_many: i32,
+    pub boxes_needed: i32,
+    // The price to hire one of the item for a day, in cents, fixed when the
+    // line was added
+    pub daily_rate: i64,
+}
+
+impl OrderLine {
+    /// Builds an OrderLine from a row selected with LINE_COLUMNS.
+    fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
+	Ok(Self {
+	    id: row.get(0)?,
+	    order_id: row.get(1)?,
+	    item_hired: row.get(2)?,
+	    how_many: row.get(3)?,
+	    boxes_needed: row.get(4)?,
+	    daily_rate: row.get(5)?,
+	})
+    }
+
+    /// Gets what hiring the line costs for the given number of days, in cents.
+    pub fn charge(&self, days: i64) -> i64 {
+	self.daily_rate * self.how_many as i64 * days
+    }
+}
+
+/// How many orders match a query, and the items and boxes on them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OrderTotals {
+    pub orders: i64,
+    pub items: i64,
+    pub boxes: i64,
 }
 
 impl Order {
-    /// Creates the database table for Order in the given database if it doesn't exist.
-    pub fn init_table(connection: &Connection) -> Result<usize, Error> {
-	connection.execute(
-	    "CREATE TABLE IF NOT EXISTS CustomerOrder (
-                id             INTEGER PRIMARY KEY,
-                customer_name  TEXT NOT NULL,
-                receipt_number INTEGER NOT NULL,
-                item_hired     TEXT NOT NULL,
-                how_many       INTEGER NOT NULL,
-                hired_on       TEXT NOT NULL,
-                return_on      TEXT NOT NULL,
-                boxes_needed   INTEGER NOT NULL,
-                raffle_number  INTEGER NOT NULL
-            )",
-	    (),
-	)
-    }
-
-    /// Creates a new Order in the database.
+    /// Creates a new Order in the database, with a line for each (item, how
+    /// many) given.
     pub fn new(
 	connection: &Connection,
 	customer_name: String,
 	receipt_number: i64,
-	item_hired: String,
-	how_many: i32,
+	lines: Vec<(String, i32)>,
 	hired_on: NaiveDate,
 	return_on: NaiveDate,
     ) -> Result<Self, Error> {
-	let boxes_needed = boxes_needed(how_many);
+	if lines.is_empty() {
+	    return Err(Error::Validation("An order needs at least one item".to_string()));
+	}
+
+	// Another program using the database can take the same number between
+	// picking it and inserting the order, so a few tries are made
+	for _ in 0..RAFFLE_NUMBER_TRIES {
+	    let result = with_savepoint(connection, || {
+		let raffle_number = allocate_raffle_number(connection)?;
+		Self::insert(connection, &customer_name, receipt_number, &lines, hired_on, return_on, raffle_number)
+	    });
+
+	    match result {
+		Err(e) if is_raffle_number_taken(&e) => continue,
+		result => return result,
+	    }
+	}
+
+	Err(Error::Validation("Couldn't give the order a raffle number, try saving it again".to_string()))
+    }
+
+    /// Inserts a new order with its lines, which must be done in a savepoint.
+    fn insert(
+	connection: &Connection,
+	customer_name: &str,
+	receipt_number: i64,
+	lines: &[(String, i32)],
+	hired_on: NaiveDate,
+	return_on: NaiveDate,
+	raffle_number: i32,
+    ) -> Result<Self, Error> {
+	let customer = Customer::find_or_create(connection, customer_name)?;
 
-	let mut rng = rand::rng();
-	let raffle_number = rng.random_range(0..=1000);
-	
 	connection.execute(
 	    "INSERT INTO CustomerOrder (
-                customer_name, 
-                receipt_number, 
-                item_hired,   
-                how_many,       
-                hired_on,
-                return_on,  
-                boxes_needed,
-                raffle_number) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+		customer_id, 
+		receipt_number, 
+		hired_on,
+		return_on,  
+		raffle_number) VALUES (?1, ?2, ?3, ?4, ?5)",
 	    [
-		customer_name,
+		customer.id.to_string(),
 		receipt_number.to_string(),
-		item_hired,
-		how_many.to_string(),
 		hired_on.format("%Y-%m-%d").to_string(),
 		return_on.format("%Y-%m-%d").to_string(),
-		boxes_needed.to_string(),
 		raffle_number.to_string(),
 	    ],
 	)?;
 
-	Self::get_by_id(connection, connection.last_insert_rowid() as i32)
+	let id = connection.last_insert_rowid() as i32;
+	insert_lines(connection, id, lines, &[])?;
+
+	Self::get_by_id(connection, id)
     }
 
     /// Retrieves an order from the database by its id.
     pub fn get_by_id(connection: &Connection, id: i32) -> Result<Self, Error> {
 	let mut stmt = connection.prepare(
-	    "SELECT * FROM CustomerOrder WHERE id = ?1;"
+	    format!("SELECT {} FROM {} WHERE CustomerOrder.id = ?1;", ORDER_COLUMNS, ORDER_TABLES).as_str()
 	)?;
 
-	stmt.query_row([id], |row| {
-	    let hired_on: String = row.get(5).unwrap();
-	    let return_on: String = row.get(6).unwrap();
-	    
-	    Ok(Self {
-		id: row.get(0).unwrap(),
-		customer_name: row.get(1).unwrap(),
-		receipt_number: row.get(2).unwrap(),
-		item_hired: row.get(3).unwrap(),
-		how_many: row.get(4).unwrap(),
-		hired_on: NaiveDate::parse_from_str(hired_on.as_str(), "%Y-%m-%d").unwrap(),
-		return_on: NaiveDate::parse_from_str(return_on.as_str(), "%Y-%m-%d").unwrap(),
-		boxes_needed: row.get(7).unwrap(),
-		raffle_number: row.get(8).unwrap(),
-	    })
-	})
+	let mut order = stmt.query_row([id], Self::from_row)?;
+
+	let mut stmt = connection.prepare(
+	    format!("SELECT {} FROM OrderLine WHERE order_id = ?1 ORDER BY id", LINE_COLUMNS).as_str()
+	)?;
+	order.lines = stmt.query_map([id], OrderLine::from_row)?.collect::<Result<_, _>>()?;
+
+	Ok(order)
     }
 
     /// Gets all the orders in the database.
-    pub fn get_all(connection: &Connection) -> Vec<Self> {
-	let mut stmt = connection.prepare("SELECT * FROM CustomerOrder").unwrap();
-	
-	stmt.query_map([], |row| {
-	    let hired_on: String = row.get(5).unwrap();
-	    let return_on: String = row.get(6).unwrap();
-	    
-	    Ok(Self {
-		id: row.get(0).unwrap(),
-		customer_name: row.get(1).unwrap(),
-		receipt_number: row.get(2).unwrap(),
-		item_hired: row.get(3).unwrap(),
-		how_many: row.get(4).unwrap(),
-		hired_on: NaiveDate::parse_from_str(hired_on.as_str(), "%Y-%m-%d").unwrap(),
-		return_on: NaiveDate::parse_from_str(return_on.as_str(), "%Y-%m-%d").unwrap(),
-		boxes_needed: row.get(7).unwrap(),
-		raffle_number: row.get(8).unwrap(),
-	    })
-	}).unwrap().map(|o| o.unwrap()).collect()
-    }
-
-    /// Deletes an order from the database.
-    pub fn delete(self, connection: &Connection) -> Result<usize, Error> {
+    pub fn get_all(connection: &Connection) -> Result<Vec<Self>, Error> {
+	let mut stmt = connection.prepare(
+	    format!("SELECT {} FROM {}", ORDER_COLUMNS, ORDER_TABLES).as_str()
+	)?;
+
+	let orders = stmt.query_map([], Self::from_row)?.collect::<Result<_, _>>()?;
+
+	Self::with_lines(connection, orders)
+    }
+
+    /// Gets the orders matching the query, filtered and sorted by the database.
+    pub fn query(connection: &Connection, query: &OrderQuery) -> Result<Vec<Self>, Error> {
+	Self::query_as_of(connection, query, Local::now().date_naive())
+    }
+
+    /// Gets the orders matching the query as of `today`, which is used to work
+    /// out which orders are overdue.
+    pub fn query_as_of(connection: &Connection, query: &OrderQuery, today: NaiveDate) -> Result<Vec<Self>, Error> {
+	let (clauses, params) = query.to_sql(today);
+	let mut stmt = connection.prepare(
+	    format!("SELECT {} FROM {}{}", ORDER_COLUMNS, ORDER_TABLES, clauses).as_str()
+	)?;
+
+	let orders = stmt.query_map(
+	    rusqlite::params_from_iter(params.iter()),
+	    Self::from_row,
+	)?.collect::<Result<_, _>>()?;
+
+	Self::with_lines(connection, orders)
+    }
+
+    /// Counts the orders matching the query, and adds up the items and boxes
+    /// on them, all in the database. `today` is used to work out which orders
+    /// are overdue.
+    pub fn totals(connection: &Connection, query: &OrderQuery, today: NaiveDate) -> Result<OrderTotals, Error> {
+	let (clauses, params) = query.where_sql(today);
+
+	Ok(connection.query_row(
+	    format!(
+		"SELECT COUNT(*), COALESCE(SUM(Lines.how_many), 0), COALESCE(SUM(CustomerOrder.boxes_needed), 0)
+		 FROM {} LEFT JOIN (
+		     SELECT order_id, SUM(how_many) AS how_many
+		     FROM OrderLine GROUP BY order_id
+		 ) AS Lines ON Lines.order_id = CustomerOrder.id{}",
+		ORDER_TABLES,
+		clauses,
+	    ).as_str(),
+	    rusqlite::params_from_iter(params.iter()),
+	    |row| Ok(OrderTotals { orders: row.get(0)?, items: row.get(1)?, boxes: row.get(2)? }),
+	)?)
+    }
+
+    /// Gets every order with the given receipt number, oldest first.
+    pub fn get_by_receipt_number(connection: &Connection, receipt_number: i64) -> Result<Vec<Self>, Error> {
+	let mut stmt = connection.prepare(
+	    format!(
+		"SELECT {} FROM {} WHERE receipt_number = ?1 ORDER BY CustomerOrder.id",
+		ORDER_COLUMNS,
+		ORDER_TABLES,
+	    ).as_str()
+	)?;
+
+	let orders = stmt.query_map([receipt_number], Self::from_row)?.collect::<Result<_, _>>()?;
+
+	Self::with_lines(connection, orders)
+    }
+
+    /// Gets the receipt number to give the next order, which follows on from
+    /// the highest one used, starting from the configured first number.
+    pub fn next_receipt_number(connection: &Connection) -> Result<i64, Error> {
+	let start = RECEIPT_NUMBER_START.get(connection)?;
+	let highest: Option<i64> = connection.query_row(
+	    "SELECT MAX(receipt_number) FROM CustomerOrder",
+	    [],
+	    |row| row.get(0),
+	)?;
+
+	match highest {
+	    Some(highest) => highest.checked_add(1)
+		.map(|next| next.max(start))
+		.ok_or_else(|| Error::Validation("There are no receipt numbers left to give out".to_string())),
+	    None => Ok(start),
+	}
+    }
+
+    /// Fills in the lines of the given orders, which are loaded without them.
+    /// Only the lines of those orders are read, a chunk of ids at a time so
+    /// the query stays under SQLite's limit on parameters.
+    fn with_lines(connection: &Connection, mut orders: Vec<Self>) -> Result<Vec<Self>, Error> {
+	let ids: Vec<i32> = orders.iter().map(|order| order.id).collect();
+
+	let mut lines: HashMap<i32, Vec<OrderLine>> = HashMap::new();
+	for chunk in ids.chunks(500) {
+	    let placeholders = vec!["?"; chunk.len()].join(", ");
+	    let mut stmt = connection.prepare(format!(
+		"SELECT {} FROM OrderLine WHERE order_id IN ({}) ORDER BY id",
+		LINE_COLUMNS,
+		placeholders,
+	    ).as_str())?;
+
+	    for line in stmt.query_map(rusqlite::params_from_iter(chunk), OrderLine::from_row)? {
+		let line = line?;
+		lines.entry(line.order_id).or_default().push(line);
+	    }
+	}
+
+	for order in orders.iter_mut() {
+	    order.lines = lines.remove(&order.id).unwrap_or_default();
+	}
+
+	Ok(orders)
+    }
+
+    /// Gets the names of every item that has been hired, in alphabetical order.
+    pub fn get_item_names(connection: &Connection) -> Result<Vec<String>, Error> {
+	let mut stmt = connection.prepare(
+	    "SELECT DISTINCT item_hired FROM OrderLine ORDER BY item_hired COLLATE NOCASE"
+	)?;
+
+	let names = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+
+	Ok(names)
+    }
+
+    /// Builds an Order from a row selected with ORDER_COLUMNS, without its
+    /// lines. Corrupt values give a conversion error rather than a panic.
+    fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
+	Ok(Self {
+	    id: row.get(0)?,
+	    customer_id: row.get(1)?,
+	    customer_name: row.get(2)?,
+	    receipt_number: row.get(3)?,
+	    hired_on: row.get(4)?,
+	    return_on: row.get(5)?,
+	    raffle_number: row.get(6)?,
+	    status: row.get(7)?,
+	    returned_at: row.get(8)?,
+	    deleted_at: row.get(9)?,
+	    deposit: row.get(10)?,
+	    damage_charge: row.get(11)?,
+	    paid: row.get(12)?,
+	    late_fee: row.get(13)?,
+	    boxes_needed: row.get(14)?,
+	    lines: Vec::new(),
+	})
+    }
+
+    /// Updates an order in the database, replacing its lines with the (item,
+    /// how many) given, and leaving its id and raffle number untouched.
+    pub fn update(
+	&self,
+	connection: &Connection,
+	customer_name: String,
+	receipt_number: i64,
+	lines: Vec<(String, i32)>,
+	hired_on: NaiveDate,
+	return_on: NaiveDate,
+    ) -> Result<Self, Error> {
+	if lines.is_empty() {
+	    return Err(Error::Validation("An order needs at least one item".to_string()));
+	}
+
+	with_savepoint(connection, || {
+	    let customer = Customer::find_or_create(connection, &customer_name)?;
+
+	    connection.execute(
+		"UPDATE CustomerOrder SET
+		    customer_id = ?1,
+		    receipt_number = ?2,
+		    hired_on = ?3,
+		    return_on = ?4
+		 WHERE id = ?5",
+		[
+		    customer.id.to_string(),
+		    receipt_number.to_string(),
+		    hired_on.format("%Y-%m-%d").to_string(),
+		    return_on.format("%Y-%m-%d").to_string(),
+		    self.id.to_string(),
+		],
+	    )?;
+
+	    connection.execute("DELETE FROM OrderLine WHERE order_id = ?1", [self.id])?;
+	    insert_lines(connection, self.id, &lines, &self.lines)?;
+
+	    Self::get_by_id(connection, self.id)
+	})
+    }
+
+    /// Gets how many items are hired on the order altogether.
+    pub fn how_many(&self) -> i32 {
+	self.lines.iter().map(|line| line.how_many).sum()
+    }
+
+    /// Gets the items hired on the order, e.g. "Chairs, Tables".
+    pub fn items_hired(&self) -> String {
+	self.lines.iter()
+	    .map(|line| line.item_hired.clone())
+	    .collect::<Vec<String>>()
+	    .join(", ")
+    }
+
+    /// Gets how many days the order is hired for, counting a same-day hire as
+    /// one day.
+    pub fn hire_days(&self) -> i64 {
+	(self.return_on - self.hired_on).num_days().max(1)
+    }
+
+    /// Gets what hiring the order's items costs, in cents. A cancelled order
+    /// isn't charged for.
+    pub fn hire_charge(&self) -> i64 {
+	if self.status == OrderStatus::Cancelled {
+	    return 0;
+	}
+
+	self.lines.iter().map(|line| line.charge(self.hire_days())).sum()
+    }
+
+    /// Gets what the customer should have paid, in cents, including any late
+    /// fee. The deposit is only owed while the order is still open, and is
+    /// given back once it is returned or cancelled.
+    pub fn amount_due(&self) -> i64 {
+	let deposit = if self.status.is_open() { self.deposit } else { 0 };
+
+	self.hire_charge() + self.damage_charge + self.late_fee + deposit
+    }
+
+    /// Gets what the customer still owes, in cents. It is negative when they
+    /// are owed a refund, e.g. of their deposit.
+    pub fn balance(&self) -> i64 {
+	self.amount_due() - self.paid
+    }
+
+    /// Sets the deposit and damage charge on the order, in cents.
+    pub fn set_charges(&self, connection: &Connection, deposit: i64, damage_charge: i64) -> Result<Self, Error> {
+	if deposit < 0 || damage_charge < 0 {
+	    return Err(Error::Validation("Charges can't be negative".to_string()));
+	}
+
+	connection.execute(
+	    "UPDATE CustomerOrder SET deposit = ?1, damage_charge = ?2 WHERE id = ?3",
+	    params![deposit, damage_charge, self.id],
+	)?;
+
+	Self::get_by_id(connection, self.id)
+    }
+
+    /// Checks if the order should have come back by the given day but hasn't.
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+	self.status.is_open() && self.return_on < today
+    }
+
+    /// Marks the order as picked up by the customer.
+    pub fn mark_out(&self, connection: &Connection) -> Result<Self, Error> {
+	self.set_status(connection, OrderStatus::Out, None)
+    }
+
+    /// Marks the order as returned, recording when it came back, and charges
+    /// it a late fee if it came back after its return date.
+    pub fn mark_returned(
+	&self,
+	connection: &Connection,
+	returned_at: NaiveDateTime,
+    ) -> Result<Self, Error> {
+	with_savepoint(connection, || {
+	    self.set_status(connection, OrderStatus::Returned, Some(returned_at))?;
+	    LateFee::charge(connection, self, returned_at)?;
+
+	    Self::get_by_id(connection, self.id)
+	})
+    }
+
+    /// Marks the order as cancelled.
+    pub fn cancel(&self, connection: &Connection) -> Result<Self, Error> {
+	self.set_status(connection, OrderStatus::Cancelled, None)
+    }
+
+    /// Moves the order to a new status, if the transition is allowed.
+    fn set_status(
+	&self,
+	connection: &Connection,
+	status: OrderStatus,
+	returned_at: Option<NaiveDateTime>,
+    ) -> Result<Self, Error> {
+	if !self.status.can_transition_to(status) {
+	    return Err(Error::Validation(format!(
+		"An order that is {} can't be marked {}",
+		self.status,
+		status,
+	    )));
+	}
+
 	connection.execute(
-	    "DELETE FROM CustomerOrder WHERE id = ?1", [self.id]
-	)
+	    "UPDATE CustomerOrder SET status = ?1, returned_at = ?2 WHERE id = ?3",
+	    params![
+		status,
+		returned_at.map(|r| r.format("%Y-%m-%d %H:%M:%S").to_string()),
+		self.id,
+	    ],
+	)?;
+
+	Self::get_by_id(connection, self.id)
+    }
+
+    /// Deletes an order, keeping it in the database so it can be restored
+    /// until it is purged.
+    pub fn delete(self, connection: &Connection, deleted_at: NaiveDateTime) -> Result<usize, Error> {
+	Ok(connection.execute(
+	    "UPDATE CustomerOrder SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+	    params![deleted_at.format("%Y-%m-%d %H:%M:%S").to_string(), self.id],
+	)?)
+    }
+
+    /// Gets the orders that have been deleted but not purged yet, most
+    /// recently deleted first.
+    pub fn get_deleted(connection: &Connection) -> Result<Vec<Self>, Error> {
+	let mut stmt = connection.prepare(
+	    format!(
+		"SELECT {} FROM {} ORDER BY deleted_at DESC, CustomerOrder.id DESC",
+		ORDER_COLUMNS,
+		DELETED_ORDER_TABLES,
+	    ).as_str()
+	)?;
+
+	let orders = stmt.query_map([], Self::from_row)?.collect::<Result<_, _>>()?;
+
+	Self::with_lines(connection, orders)
+    }
+
+    /// Brings back a deleted order that hasn't been purged yet.
+    pub fn restore(connection: &Connection, id: i32) -> Result<Self, Error> {
+	let restored = connection.execute(
+	    "UPDATE CustomerOrder SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+	    [id],
+	)?;
+
+	if restored == 0 {
+	    return Err(Error::Validation(format!("Order {} isn't in the recently deleted orders", id)));
+	}
+
+	Self::get_by_id(connection, id)
+    }
+
+    /// Removes the orders deleted longer ago than the retention setting allows,
+    /// along with their lines, payments, late fees, reminders and emails, for
+    /// good. Orders that won a raffle draw are kept, so the draw history can
+    /// still name them and replay the draw. Gives how many were purged.
+    pub fn purge_deleted(connection: &Connection, now: NaiveDateTime) -> Result<usize, Error> {
+	let days = DELETED_ORDER_RETENTION_DAYS.get(connection)?;
+	let Some(cutoff) = now.checked_sub_days(Days::new(days as u64)) else {
+	    return Ok(0);
+	};
+	let cutoff = cutoff.format("%Y-%m-%d %H:%M:%S").to_string();
+
+	with_savepoint(connection, || {
+	    for table in ["LateFee", "Payment", "Reminder", "OutgoingEmail", "OrderLine"] {
+		connection.execute(
+		    format!("DELETE FROM {} WHERE order_id IN ({})", table, PURGEABLE_ORDERS).as_str(),
+		    [&cutoff],
+		)?;
+	    }
+
+	    Ok(connection.execute(
+		format!("DELETE FROM CustomerOrder WHERE id IN ({})", PURGEABLE_ORDERS).as_str(),
+		[&cutoff],
+	    )?)
+	})
     }
 }
 
-/// Gets how many boxes needed to store the given amount of items
-fn boxes_needed(items: i32) -> i32 {
-    (items + 25 - 1) / 25
+impl ToSql for OrderStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+	Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for OrderStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+	let status = value.as_str()?;
+
+	OrderStatus::parse(status).ok_or_else(|| {
+	    FromSqlError::Other(format!("Unknown order status: {}", status).into())
+	})
+    }
+}
+
+/// Picks a random raffle number in the configured range that no other order has.
+fn allocate_raffle_number(connection: &Connection) -> Result<i32, Error> {
+    let min = RAFFLE_NUMBER_MIN.get(connection)?;
+    let max = RAFFLE_NUMBER_MAX.get(connection)?;
+
+    let mut stmt = connection.prepare(
+	"SELECT DISTINCT raffle_number FROM CustomerOrder
+	 WHERE raffle_number BETWEEN ?1 AND ?2 ORDER BY raffle_number"
+    )?;
+    let used: Vec<i64> = stmt.query_map([min, max], |row| row.get(0))?
+	.collect::<Result<_, _>>()?;
+
+    let unused = (max - min + 1) - used.len() as i64;
+    if unused <= 0 {
+	return Err(Error::Validation(format!(
+	    "Every raffle number from {} to {} has been given out. Widen the range in Settings",
+	    min,
+	    max,
+	)));
+    }
+
+    let index = rand::rng().random_range(0..unused);
+
+    Ok(nth_unused_number(min, &used, index) as i32)
+}
+
+/// Checks if an insert failed because its raffle number was already given
+/// to another order.
+fn is_raffle_number_taken(error: &Error) -> bool {
+    match error {
+	Error::Database(rusqlite::Error::SqliteFailure(e, Some(message))) => {
+	    e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE
+		&& message.contains("CustomerOrder.raffle_number")
+	},
+	_ => false,
+    }
+}
+
+/// Gets the index'th number counting up from min, skipping the sorted used numbers.
+fn nth_unused_number(min: i64, used: &[i64], index: i64) -> i64 {
+    let mut number = min + index;
+
+    for &u in used {
+	if u <= number {
+	    number += 1;
+	} else {
+	    break;
+	}
+    }
+
+    number
+}
+
+/// Adds a line to the order for each (item, how many), counting the boxes each
+/// needs on its own and the boxes the order needs once they are packed
+/// together. Items that were already on the order keep the rate they were
+/// hired at, and new ones are charged the catalogue's price.
+fn insert_lines(
+    connection: &Connection,
+    order_id: i32,
+    lines: &[(String, i32)],
+    previous: &[OrderLine],
+) -> Result<(), Error> {
+    for (item_hired, how_many) in lines {
+	let daily_rate = match previous.iter().find(|line| line.item_hired.eq_ignore_ascii_case(item_hired)) {
+	    Some(line) => line.daily_rate,
+	    None => daily_rate_for(connection, item_hired)?,
+	};
+
+	connection.execute(
+	    "INSERT INTO OrderLine (order_id, item_hired, how_many, boxes_needed, daily_rate)
+	     VALUES (?1, ?2, ?3, ?4, ?5)",
+	    params![
+		order_id,
+		item_hired,
+		how_many,
+		boxes_needed(*how_many, box_capacity_for(connection, item_hired)?),
+		daily_rate,
+	    ],
+	)?;
+    }
+
+    count_boxes(connection, order_id)
+}
+
+/// Packs the order's lines the same way the packing plan does, and stores how
+/// many boxes that takes on the order.
+pub fn count_boxes(connection: &Connection, order_id: i32) -> Result<(), Error> {
+    let mut stmt = connection.prepare("SELECT item_hired, how_many FROM OrderLine WHERE order_id = ?1 ORDER BY id")?;
+    let lines: Vec<(String, i32)> = stmt.query_map([order_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+	.collect::<Result<_, _>>()?;
+
+    let lines = lines.into_iter()
+	.map(|(item, quantity)| Ok(PackingLine { box_capacity: box_capacity_for(connection, &item)?, item, quantity }))
+	.collect::<Result<Vec<PackingLine>, Error>>()?;
+
+    connection.execute(
+	"UPDATE CustomerOrder SET boxes_needed = ?1 WHERE id = ?2",
+	params![pack(&lines).len() as i32, order_id],
+    )?;
+
+    Ok(())
+}
+
+/// Counts the boxes of the orders that haven't been counted yet, which are
+/// those from before the count was kept.
+pub fn count_uncounted_boxes(connection: &Connection) -> Result<(), Error> {
+    let mut stmt = connection.prepare("SELECT id FROM CustomerOrder WHERE boxes_needed IS NULL")?;
+    let ids: Vec<i32> = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+
+    for id in ids {
+	count_boxes(connection, id)?;
+    }
+
+    Ok(())
+}
+
+/// Gets the price to hire one of an item for a day, in cents, which is set on
+/// the item in the catalogue, or nothing if it isn't in the catalogue.
+fn daily_rate_for(connection: &Connection, item_hired: &str) -> Result<i64, Error> {
+    Ok(Item::get_by_name(connection, item_hired)?.map(|item| item.hire_price).unwrap_or(0))
+}
+
+/// Gets how many of an item fit in a box, which is set on the item in the
+/// catalogue, or the default setting if it isn't in the catalogue.
+pub fn box_capacity_for(connection: &Connection, item_hired: &str) -> Result<i32, Error> {
+    match Item::get_by_name(connection, item_hired)? {
+	Some(item) => Ok(item.box_capacity),
+	None => Ok(DEFAULT_BOX_CAPACITY.get(connection)? as i32),
+    }
+}
+
+/// Gets how many boxes needed to store the given amount of items, when
+/// box_capacity of them fit in each box
+pub fn boxes_needed(items: i32, box_capacity: i32) -> i32 {
+    let box_capacity = box_capacity.max(1);
+
+    (items.max(0) + box_capacity - 1) / box_capacity
 }
 
 #[cfg(test)]
 mod tests {
     use crate::database::init_db_tables;
+    use crate::packing::PackingPlan;
 
     use super::*;
 
     #[test]
-    fn test_init_order_table() {
+    fn test_new_order() {
 	let con = Connection::open_in_memory().unwrap();
 
-	assert!(Order::init_table(&con).is_ok());
+	init_db_tables(&con).unwrap();
+
+	assert!(Order::new(
+	    &con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).is_ok());
     }
 
     #[test]
-    fn test_new_order() {
+    fn test_new_order_unique_raffle_numbers() {
 	let con = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&con);
+	init_db_tables(&con).unwrap();
+	RAFFLE_NUMBER_MIN.set(&con, 1).unwrap();
+	RAFFLE_NUMBER_MAX.set(&con, 50).unwrap();
 
-	assert!(Order::new(
+	let mut numbers: Vec<i32> = (0..50).map(|_| test_order(&con).raffle_number).collect();
+	numbers.sort();
+
+	assert_eq!(numbers, (1..=50).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn test_new_order_raffle_numbers_used_up() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	RAFFLE_NUMBER_MIN.set(&con, 5).unwrap();
+	RAFFLE_NUMBER_MAX.set(&con, 6).unwrap();
+
+	test_order(&con);
+	test_order(&con);
+
+	let order = Order::new(
 	    &con,
 	    "Test Person".to_string(),
 	    15,
-	    "Test Item".to_string(),
-	    26,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
 	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	);
+	assert!(matches!(order, Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn test_insert_taken_raffle_number() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	let order = test_order(&con);
+	let lines = vec![("Test Item".to_string(), 26)];
+
+	let error = Order::insert(
+	    &con,
+	    "Other Person",
+	    16,
+	    &lines,
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
 	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
-	).is_ok());
+	    order.raffle_number,
+	).unwrap_err();
+
+	assert!(is_raffle_number_taken(&error));
+	assert!(!is_raffle_number_taken(&Error::Validation("Taken".to_string())));
+    }
+
+    #[test]
+    fn test_nth_unused_number() {
+	assert_eq!(nth_unused_number(0, &[], 3), 3);
+	assert_eq!(nth_unused_number(0, &[1, 2], 0), 0);
+	assert_eq!(nth_unused_number(0, &[1, 2], 1), 3);
+	assert_eq!(nth_unused_number(10, &[10, 11, 13], 1), 14);
     }
 
     #[test]
     fn test_boxes_needed() {
-	assert_eq!(boxes_needed(26), 2);
+	assert_eq!(boxes_needed(26, 25), 2);
     }
     
     #[test]
     fn test_boxes_needed_zero() {
-	assert_eq!(boxes_needed(0), 0);
+	assert_eq!(boxes_needed(0, 25), 0);
     }
 
     #[test]
     fn test_boxes_needed_exact() {
-	assert_eq!(boxes_needed(30), 2);
+	assert_eq!(boxes_needed(30, 25), 2);
+    }
+
+    #[test]
+    fn test_boxes_needed_per_item() {
+	assert_eq!(boxes_needed(30, 10), 3);
+	assert_eq!(boxes_needed(30, 0), 30);
     }
 
     #[test]
     fn test_boxes_needed_negative() {
-	assert_eq!(boxes_needed(-26), 0);
+	assert_eq!(boxes_needed(-26, 25), 0);
     }
 
     #[test]
     fn test_get_order_by_id() {
 	let con = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&con);
+	init_db_tables(&con).unwrap();
 
 	let order = Order::new(
 	    &con,
 	    "Test Person".to_string(),
 	    15,
-	    "Test Item".to_string(),
-	    26,
+	    vec![("Test Item".to_string(), 26)],
 	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
 	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
 	).unwrap();
@@ -212,7 +869,7 @@ mod tests {
     fn test_get_order_by_id_doesnt_exist() {
 	let con = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&con);
+	init_db_tables(&con).unwrap();
 	
 	assert!(Order::get_by_id(&con, 1132).is_err());
     }
@@ -221,40 +878,366 @@ mod tests {
     fn test_order_get_all() {
 	let con = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&con);
+	init_db_tables(&con).unwrap();
 
 	let order = Order::new(
 	    &con,
 	    "Test Person".to_string(),
 	    15,
-	    "Test Item".to_string(),
-	    26,
+	    vec![("Test Item".to_string(), 26)],
 	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
 	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
 	).unwrap();
 
-	let orders = Order::get_all(&con);
+	let orders = Order::get_all(&con).unwrap();
 	
 	assert!(!orders.is_empty());
 	assert_eq!(orders[0], order);
     }
 
+    #[test]
+    fn test_order_get_all_corrupt_date() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	test_order(&con);
+	con.execute("UPDATE CustomerOrder SET hired_on = '23/03/2025'", []).unwrap();
+
+	assert!(matches!(Order::get_all(&con), Err(Error::Parse(_))));
+	assert!(matches!(Order::get_by_id(&con, 1), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_order_update() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = Order::new(
+	    &con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap();
+
+	let updated = order.update(
+	    &con,
+	    "Other Person".to_string(),
+	    16,
+	    vec![("Other Item".to_string(), 51)],
+	    NaiveDate::from_ymd_opt(2025, 3, 24).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 26).unwrap(),
+	).unwrap();
+
+	assert_eq!(updated.id, order.id);
+	assert_eq!(updated.raffle_number, order.raffle_number);
+	assert_eq!(updated.customer_name, "Other Person".to_string());
+	assert_eq!(updated.how_many(), 51);
+	assert_eq!(updated.boxes_needed, 3);
+	assert_eq!(updated.lines.len(), 1);
+	assert_eq!(Order::get_by_id(&con, order.id).unwrap(), updated);
+    }
+
+    /// Makes an order for the tests that returns on 2025-03-23.
+    fn test_order(con: &Connection) -> Order {
+	Order::new(
+	    con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap()
+    }
+
+    #[test]
+    fn test_new_order_is_booked() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con);
+
+	assert_eq!(order.status, OrderStatus::Booked);
+	assert_eq!(order.returned_at, None);
+    }
+
+    #[test]
+    fn test_order_mark_out_and_returned() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let returned_at = NaiveDate::from_ymd_opt(2025, 3, 22).unwrap().and_hms_opt(14, 30, 0).unwrap();
+	let order = test_order(&con).mark_out(&con).unwrap();
+	assert_eq!(order.status, OrderStatus::Out);
+
+	let order = order.mark_returned(&con, returned_at).unwrap();
+	assert_eq!(order.status, OrderStatus::Returned);
+	assert_eq!(order.returned_at, Some(returned_at));
+	assert_eq!(Order::get_by_id(&con, order.id).unwrap(), order);
+    }
+
+    #[test]
+    fn test_order_cancel() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con).cancel(&con).unwrap();
+
+	assert_eq!(order.status, OrderStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_order_invalid_status_change() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let returned_at = NaiveDate::from_ymd_opt(2025, 3, 22).unwrap().and_hms_opt(14, 30, 0).unwrap();
+	let order = test_order(&con);
+
+	assert!(matches!(
+	    order.mark_returned(&con, returned_at),
+	    Err(Error::Validation(_))
+	));
+	assert_eq!(Order::get_by_id(&con, order.id).unwrap().status, OrderStatus::Booked);
+    }
+
+    #[test]
+    fn test_order_is_overdue() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con).mark_out(&con).unwrap();
+
+	assert!(!order.is_overdue(NaiveDate::from_ymd_opt(2025, 3, 23).unwrap()));
+	assert!(order.is_overdue(NaiveDate::from_ymd_opt(2025, 3, 24).unwrap()));
+    }
+
+    #[test]
+    fn test_order_returned_is_not_overdue() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let returned_at = NaiveDate::from_ymd_opt(2025, 3, 25).unwrap().and_hms_opt(9, 0, 0).unwrap();
+	let order = test_order(&con)
+	    .mark_out(&con).unwrap()
+	    .mark_returned(&con, returned_at).unwrap();
+
+	assert!(!order.is_overdue(NaiveDate::from_ymd_opt(2025, 3, 26).unwrap()));
+    }
+
+    #[test]
+    fn test_new_order_with_lines() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	Item::new(&con, "Chairs".to_string(), 100, 20, 250).unwrap();
+
+	let order = Order::new(
+	    &con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Chairs".to_string(), 30), ("Tablecloths".to_string(), 10)],
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	).unwrap();
+
+	assert_eq!(order.lines.len(), 2);
+	assert_eq!(order.lines[0].boxes_needed, 2);
+	assert_eq!(order.lines[1].boxes_needed, 1);
+	assert_eq!(order.how_many(), 40);
+	assert_eq!(order.boxes_needed, 2);
+	assert_eq!(order.items_hired(), "Chairs, Tablecloths".to_string());
+	assert_eq!(Order::get_all(&con).unwrap(), vec![order]);
+    }
+
+    #[test]
+    fn test_new_order_packs_items_together() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	Item::new(&con, "Chairs".to_string(), 100, 25, 250).unwrap();
+	Item::new(&con, "Tablecloths".to_string(), 100, 25, 100).unwrap();
+
+	let order = Order::new(
+	    &con,
+	    "Test Person".to_string(),
+	    15,
+	    vec![("Chairs".to_string(), 10), ("Tablecloths".to_string(), 5)],
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	).unwrap();
+
+	assert_eq!(order.lines[0].boxes_needed, 1);
+	assert_eq!(order.lines[1].boxes_needed, 1);
+	assert_eq!(order.boxes_needed, 1);
+	let totals = Order::totals(&con, &OrderQuery::default(), NaiveDate::from_ymd_opt(2025, 3, 23).unwrap()).unwrap();
+	assert_eq!(totals.boxes, 1);
+
+	let plan = PackingPlan::build(&con, order.hired_on, order.hired_on).unwrap();
+	assert_eq!(plan.days[0].total_boxes(), order.boxes_needed as usize);
+    }
+
+    #[test]
+    fn test_lines_loaded_for_many_orders() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	// More orders than fit in one chunk of ids
+	for i in 1..=501 {
+	    Order::new(
+		&con,
+		format!("Person {}", i),
+		i,
+		vec![("Chairs".to_string(), i as i32)],
+		NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+		NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	    ).unwrap();
+	}
+
+	let orders = Order::get_all(&con).unwrap();
+	assert_eq!(orders.len(), 501);
+	assert!(orders.iter().all(|order| order.lines.len() == 1));
+	assert!(orders.iter().all(|order| order.how_many() as i64 == order.receipt_number));
+
+	let query = OrderQuery { search: "Person 501".to_string(), ..Default::default() };
+	let orders = Order::query(&con, &query).unwrap();
+	assert_eq!(orders.len(), 1);
+	assert_eq!(orders[0].how_many(), 501);
+    }
+
+    #[test]
+    fn test_new_order_without_lines() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = Order::new(
+	    &con,
+	    "Test Person".to_string(),
+	    15,
+	    Vec::new(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	);
+
+	assert!(matches!(order, Err(Error::Validation(_))));
+	assert!(Order::get_all(&con).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_order_update_replaces_lines() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con);
+	let updated = order.update(
+	    &con,
+	    order.customer_name.clone(),
+	    order.receipt_number,
+	    vec![("Chairs".to_string(), 5), ("Tables".to_string(), 2)],
+	    order.hired_on,
+	    order.return_on,
+	).unwrap();
+
+	let items: Vec<&str> = updated.lines.iter().map(|line| line.item_hired.as_str()).collect();
+	assert_eq!(items, vec!["Chairs", "Tables"]);
+	assert_eq!(Order::get_item_names(&con).unwrap(), vec!["Chairs".to_string(), "Tables".to_string()]);
+    }
+
     #[test]
     fn test_order_delete() {
 	let con = Connection::open_in_memory().unwrap();
 
-	init_db_tables(&con);
+	init_db_tables(&con).unwrap();
 
 	let order = Order::new(
 	    &con,
 	    "Test Person".to_string(),
 	    15,
-	    "Test Item".to_string(),
-	    26,
+	    vec![("Test Item".to_string(), 26)],
 	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
 	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
 	).unwrap();
 
-	assert!(order.delete(&con).is_ok());
+	let deleted_at = NaiveDate::from_ymd_opt(2025, 3, 24).unwrap().and_hms_opt(9, 0, 0).unwrap();
+	assert_eq!(order.clone().delete(&con, deleted_at).unwrap(), 1);
+
+	assert!(Order::get_all(&con).unwrap().is_empty());
+	assert!(Order::get_by_id(&con, order.id).is_err());
+	assert_eq!(Order::get_deleted(&con).unwrap()[0].deleted_at, Some(deleted_at));
+	assert_eq!(order.delete(&con, deleted_at).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_order_restore() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con);
+	let deleted_at = NaiveDate::from_ymd_opt(2025, 3, 24).unwrap().and_hms_opt(9, 0, 0).unwrap();
+	order.clone().delete(&con, deleted_at).unwrap();
+
+	assert_eq!(Order::restore(&con, order.id).unwrap(), order);
+	assert!(Order::get_deleted(&con).unwrap().is_empty());
+	assert!(matches!(Order::restore(&con, order.id), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn test_order_purge_deleted() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+	DELETED_ORDER_RETENTION_DAYS.set(&con, 7).unwrap();
+
+	let old = test_order(&con);
+	let recent = test_order(&con);
+	let day = |day| NaiveDate::from_ymd_opt(2025, 3, day).unwrap().and_hms_opt(9, 0, 0).unwrap();
+	old.delete(&con, day(1)).unwrap();
+	recent.clone().delete(&con, day(5)).unwrap();
+
+	assert_eq!(Order::purge_deleted(&con, day(10)).unwrap(), 1);
+
+	let deleted: Vec<i32> = Order::get_deleted(&con).unwrap().into_iter().map(|o| o.id).collect();
+	assert_eq!(deleted, vec![recent.id]);
+	let lines: i32 = con.query_row("SELECT COUNT(*) FROM OrderLine", [], |row| row.get(0)).unwrap();
+	assert_eq!(lines, 1);
+    }
+
+    #[test]
+    fn test_order_get_by_receipt_number() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con);
+
+	assert_eq!(Order::get_by_receipt_number(&con, 15).unwrap(), vec![order]);
+	assert!(Order::get_by_receipt_number(&con, 16).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_order_next_receipt_number() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	assert_eq!(Order::next_receipt_number(&con).unwrap(), 1);
+
+	test_order(&con);
+	assert_eq!(Order::next_receipt_number(&con).unwrap(), 16);
+
+	RECEIPT_NUMBER_START.set(&con, 1000).unwrap();
+	assert_eq!(Order::next_receipt_number(&con).unwrap(), 1000);
     }
 }
@@ -0,0 +1,399 @@
+use std::fmt;
+use chrono::NaiveDate;
+use rusqlite::ToSql;
+use crate::order::OrderStatus;
+
+/// The fields the orders list can be sorted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderSortField {
+    CustomerName,
+    ReceiptNumber,
+    ItemHired,
+    HowMany,
+    HiredOn,
+    ReturnOn,
+    BoxesNeeded,
+    RaffleNumber,
+    Status,
+}
+
+impl OrderSortField {
+    /// Gets the SQL expression to sort by. Orders are sorted by the first of
+    /// their items, and by the total of their lines.
+    fn sql(&self) -> &'static str {
+	match self {
+	    OrderSortField::CustomerName => "Customer.name COLLATE NOCASE",
+	    OrderSortField::ReceiptNumber => "receipt_number",
+	    OrderSortField::ItemHired => {
+		"(SELECT MIN(item_hired COLLATE NOCASE) FROM OrderLine WHERE order_id = CustomerOrder.id)"
+	    },
+	    OrderSortField::HowMany => {
+		"(SELECT SUM(how_many) FROM OrderLine WHERE order_id = CustomerOrder.id)"
+	    },
+	    OrderSortField::HiredOn => "hired_on",
+	    OrderSortField::ReturnOn => "return_on",
+	    OrderSortField::BoxesNeeded => "CustomerOrder.boxes_needed",
+	    OrderSortField::RaffleNumber => "raffle_number",
+	    OrderSortField::Status => "status",
+	}
+    }
+}
+
+/// The direction to sort in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+impl SortDirection {
+    /// Gets the opposite direction.
+    pub fn reversed(&self) -> Self {
+	match self {
+	    SortDirection::Ascending => SortDirection::Descending,
+	    SortDirection::Descending => SortDirection::Ascending,
+	}
+    }
+}
+
+/// What status to filter the orders by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusFilter {
+    Is(OrderStatus),
+    /// Orders that aren't back, and should have been.
+    Overdue,
+    /// Every order except the cancelled ones.
+    NotCancelled,
+}
+
+impl StatusFilter {
+    /// Every filter, in the order they are shown to the user.
+    pub const ALL: [StatusFilter; 6] = [
+	StatusFilter::Is(OrderStatus::Booked),
+	StatusFilter::Is(OrderStatus::Out),
+	StatusFilter::Is(OrderStatus::Returned),
+	StatusFilter::Is(OrderStatus::Cancelled),
+	StatusFilter::Overdue,
+	StatusFilter::NotCancelled,
+    ];
+}
+
+impl fmt::Display for StatusFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    StatusFilter::Is(status) => write!(f, "{}", status),
+	    StatusFilter::Overdue => write!(f, "Overdue"),
+	    StatusFilter::NotCancelled => write!(f, "Not cancelled"),
+	}
+    }
+}
+
+/// Describes which orders to get from the database, and in what order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OrderQuery {
+    /// Text to look for in the customer name, receipt number or item hired.
+    pub search: String,
+    /// Only orders whose hire overlaps these dates (inclusive).
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub status: Option<StatusFilter>,
+    /// Only orders with a line for this item.
+    pub item: Option<String>,
+    /// Only orders picked up on, or due back on, a day in these dates (inclusive).
+    pub hired_between: Option<(NaiveDate, NaiveDate)>,
+    pub returning_between: Option<(NaiveDate, NaiveDate)>,
+    pub sort: Option<(OrderSortField, SortDirection)>,
+}
+
+impl OrderQuery {
+    /// Builds the WHERE and ORDER BY clauses for the query, along with the
+    /// parameters they use. `today` is used to work out which orders are overdue.
+    pub fn to_sql(&self, today: NaiveDate) -> (String, Vec<Box<dyn ToSql>>) {
+	let (mut sql, params) = self.where_sql(today);
+
+	// The id keeps rows with equal sort values in a stable order
+	match self.sort {
+	    Some((field, SortDirection::Ascending)) => {
+		sql.push_str(&format!(" ORDER BY {} ASC, CustomerOrder.id ASC", field.sql()));
+	    },
+	    Some((field, SortDirection::Descending)) => {
+		sql.push_str(&format!(" ORDER BY {} DESC, CustomerOrder.id DESC", field.sql()));
+	    },
+	    None => sql.push_str(" ORDER BY CustomerOrder.id ASC"),
+	}
+
+	(sql, params)
+    }
+
+    /// Builds just the WHERE clause for the query, e.g. to count the orders
+    /// it matches, along with the parameters it uses.
+    pub fn where_sql(&self, today: NaiveDate) -> (String, Vec<Box<dyn ToSql>>) {
+	let mut conditions: Vec<String> = Vec::new();
+	let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+
+	let search = self.search.trim();
+	if !search.is_empty() {
+	    params.push(Box::new(format!("%{}%", escape_like(search))));
+	    let n = params.len();
+	    conditions.push(format!(
+		"(Customer.name LIKE ?{n} ESCAPE '\\'
+		  OR EXISTS (SELECT 1 FROM OrderLine WHERE order_id = CustomerOrder.id
+			     AND item_hired LIKE ?{n} ESCAPE '\\')
+		  OR CAST(receipt_number AS TEXT) LIKE ?{n} ESCAPE '\\')"
+	    ));
+	}
+
+	if let Some(from) = self.from {
+	    params.push(Box::new(from.format("%Y-%m-%d").to_string()));
+	    conditions.push(format!("return_on >= ?{}", params.len()));
+	}
+
+	if let Some(to) = self.to {
+	    params.push(Box::new(to.format("%Y-%m-%d").to_string()));
+	    conditions.push(format!("hired_on <= ?{}", params.len()));
+	}
+
+	match self.status {
+	    Some(StatusFilter::Is(status)) => {
+		params.push(Box::new(status));
+		conditions.push(format!("status = ?{}", params.len()));
+	    },
+	    Some(StatusFilter::Overdue) => {
+		params.push(Box::new(today.format("%Y-%m-%d").to_string()));
+		conditions.push(format!(
+		    "status IN ('Booked', 'Out') AND return_on < ?{}",
+		    params.len()
+		));
+	    },
+	    Some(StatusFilter::NotCancelled) => conditions.push("status != 'Cancelled'".to_string()),
+	    None => (),
+	}
+
+	for (column, between) in [("hired_on", self.hired_between), ("return_on", self.returning_between)] {
+	    if let Some((first, last)) = between {
+		params.push(Box::new(first.format("%Y-%m-%d").to_string()));
+		params.push(Box::new(last.format("%Y-%m-%d").to_string()));
+		conditions.push(format!("{} BETWEEN ?{} AND ?{}", column, params.len() - 1, params.len()));
+	    }
+	}
+
+	if let Some(item) = &self.item {
+	    params.push(Box::new(item.clone()));
+	    conditions.push(format!(
+		"EXISTS (SELECT 1 FROM OrderLine WHERE order_id = CustomerOrder.id
+			 AND item_hired = ?{} COLLATE NOCASE)",
+		params.len()
+	    ));
+	}
+
+	let mut sql = String::new();
+	if !conditions.is_empty() {
+	    sql.push_str(" WHERE ");
+	    sql.push_str(&conditions.join(" AND "));
+	}
+
+	(sql, params)
+    }
+}
+
+/// Escapes the wildcards in text to be used in a LIKE pattern.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+    use crate::order::Order;
+    use rusqlite::Connection;
+
+    use super::*;
+
+    /// Makes a database with a few orders in it to query.
+    fn test_database() -> Connection {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	for (name, receipt, item, how_many, hired_on, return_on) in [
+	    ("Alice Smith", 1001, "Chairs", 40, (2025, 3, 1), (2025, 3, 3)),
+	    ("Bob Jones", 1002, "Tables", 5, (2025, 3, 10), (2025, 3, 12)),
+	    ("Carol 100%", 2001, "Chairs", 10, (2025, 4, 1), (2025, 4, 2)),
+	] {
+	    Order::new(
+		&con,
+		name.to_string(),
+		receipt,
+		vec![(item.to_string(), how_many)],
+		NaiveDate::from_ymd_opt(hired_on.0, hired_on.1, hired_on.2).unwrap(),
+		NaiveDate::from_ymd_opt(return_on.0, return_on.1, return_on.2).unwrap(),
+	    ).unwrap();
+	}
+
+	con
+    }
+
+    /// Gets the customer names of the orders matching the query.
+    fn names(con: &Connection, query: &OrderQuery) -> Vec<String> {
+	Order::query(con, query).unwrap().into_iter().map(|o| o.customer_name).collect()
+    }
+
+    #[test]
+    fn test_query_default_is_everything_in_insertion_order() {
+	let con = test_database();
+
+	assert_eq!(
+	    names(&con, &OrderQuery::default()),
+	    vec!["Alice Smith", "Bob Jones", "Carol 100%"],
+	);
+    }
+
+    #[test]
+    fn test_query_search_customer_name() {
+	let con = test_database();
+	let query = OrderQuery { search: "bob".to_string(), ..Default::default() };
+
+	assert_eq!(names(&con, &query), vec!["Bob Jones"]);
+    }
+
+    #[test]
+    fn test_query_search_receipt_number() {
+	let con = test_database();
+	let query = OrderQuery { search: "100".to_string(), ..Default::default() };
+
+	assert_eq!(names(&con, &query), vec!["Alice Smith", "Bob Jones", "Carol 100%"]);
+
+	let query = OrderQuery { search: "2001".to_string(), ..Default::default() };
+
+	assert_eq!(names(&con, &query), vec!["Carol 100%"]);
+    }
+
+    #[test]
+    fn test_query_search_item() {
+	let con = test_database();
+	let query = OrderQuery { search: "table".to_string(), ..Default::default() };
+
+	assert_eq!(names(&con, &query), vec!["Bob Jones"]);
+    }
+
+    #[test]
+    fn test_query_search_escapes_wildcards() {
+	let con = test_database();
+	let query = OrderQuery { search: "0%".to_string(), ..Default::default() };
+
+	assert_eq!(names(&con, &query), vec!["Carol 100%"]);
+    }
+
+    #[test]
+    fn test_query_date_range() {
+	let con = test_database();
+	let query = OrderQuery {
+	    from: NaiveDate::from_ymd_opt(2025, 3, 2),
+	    to: NaiveDate::from_ymd_opt(2025, 3, 10),
+	    ..Default::default()
+	};
+
+	assert_eq!(names(&con, &query), vec!["Alice Smith", "Bob Jones"]);
+    }
+
+    #[test]
+    fn test_query_item() {
+	let con = test_database();
+	let query = OrderQuery { item: Some("Chairs".to_string()), ..Default::default() };
+
+	assert_eq!(names(&con, &query), vec!["Alice Smith", "Carol 100%"]);
+    }
+
+    #[test]
+    fn test_query_item_on_any_line() {
+	let con = test_database();
+	let order = Order::get_by_id(&con, 2).unwrap();
+	order.update(
+	    &con,
+	    order.customer_name.clone(),
+	    order.receipt_number,
+	    vec![("Tables".to_string(), 5), ("Chairs".to_string(), 50)],
+	    order.hired_on,
+	    order.return_on,
+	).unwrap();
+
+	let query = OrderQuery { item: Some("Chairs".to_string()), ..Default::default() };
+	assert_eq!(names(&con, &query), vec!["Alice Smith", "Bob Jones", "Carol 100%"]);
+
+	let query = OrderQuery {
+	    sort: Some((OrderSortField::HowMany, SortDirection::Descending)),
+	    ..Default::default()
+	};
+	assert_eq!(names(&con, &query), vec!["Bob Jones", "Alice Smith", "Carol 100%"]);
+    }
+
+    #[test]
+    fn test_query_status() {
+	let con = test_database();
+	Order::get_by_id(&con, 2).unwrap().cancel(&con).unwrap();
+
+	let query = OrderQuery {
+	    status: Some(StatusFilter::Is(OrderStatus::Cancelled)),
+	    ..Default::default()
+	};
+	assert_eq!(names(&con, &query), vec!["Bob Jones"]);
+
+	// The test orders were all due back in 2025
+	let query = OrderQuery { status: Some(StatusFilter::Overdue), ..Default::default() };
+	assert_eq!(names(&con, &query), vec!["Alice Smith", "Carol 100%"]);
+
+	let query = OrderQuery { status: Some(StatusFilter::NotCancelled), ..Default::default() };
+	assert_eq!(names(&con, &query), vec!["Alice Smith", "Carol 100%"]);
+    }
+
+    #[test]
+    fn test_query_hired_and_returning_between() {
+	let con = test_database();
+	let query = OrderQuery {
+	    hired_between: Some((NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(), NaiveDate::from_ymd_opt(2025, 3, 10).unwrap())),
+	    ..Default::default()
+	};
+	assert_eq!(names(&con, &query), vec!["Alice Smith", "Bob Jones"]);
+
+	let query = OrderQuery {
+	    returning_between: Some((NaiveDate::from_ymd_opt(2025, 3, 3).unwrap(), NaiveDate::from_ymd_opt(2025, 3, 3).unwrap())),
+	    ..Default::default()
+	};
+	assert_eq!(names(&con, &query), vec!["Alice Smith"]);
+    }
+
+    #[test]
+    fn test_query_sort() {
+	let con = test_database();
+	let query = OrderQuery {
+	    sort: Some((OrderSortField::HowMany, SortDirection::Ascending)),
+	    ..Default::default()
+	};
+	assert_eq!(names(&con, &query), vec!["Bob Jones", "Carol 100%", "Alice Smith"]);
+
+	let query = OrderQuery {
+	    sort: Some((OrderSortField::CustomerName, SortDirection::Descending)),
+	    ..Default::default()
+	};
+	assert_eq!(names(&con, &query), vec!["Carol 100%", "Bob Jones", "Alice Smith"]);
+    }
+
+    #[test]
+    fn test_query_filters_combine() {
+	let con = test_database();
+	let query = OrderQuery {
+	    search: "a".to_string(),
+	    item: Some("Chairs".to_string()),
+	    from: NaiveDate::from_ymd_opt(2025, 3, 15),
+	    ..Default::default()
+	};
+
+	assert_eq!(names(&con, &query), vec!["Carol 100%"]);
+    }
+
+    #[test]
+    fn test_sort_direction_reversed() {
+	assert_eq!(SortDirection::Ascending.reversed(), SortDirection::Descending);
+	assert_eq!(SortDirection::Descending.reversed(), SortDirection::Ascending);
+    }
+}
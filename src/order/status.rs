@@ -0,0 +1,92 @@
+use std::fmt;
+
+/// Represents where an order is in its lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OrderStatus {
+    Booked,
+    Out,
+    Returned,
+    Cancelled,
+}
+
+impl OrderStatus {
+    /// Gets the value stored in the database for the status.
+    pub fn as_str(&self) -> &'static str {
+	match self {
+	    OrderStatus::Booked => "Booked",
+	    OrderStatus::Out => "Out",
+	    OrderStatus::Returned => "Returned",
+	    OrderStatus::Cancelled => "Cancelled",
+	}
+    }
+
+    /// Parses a status stored in the database, or None if it is unknown.
+    pub fn parse(status: &str) -> Option<Self> {
+	match status {
+	    "Booked" => Some(OrderStatus::Booked),
+	    "Out" => Some(OrderStatus::Out),
+	    "Returned" => Some(OrderStatus::Returned),
+	    "Cancelled" => Some(OrderStatus::Cancelled),
+	    _ => None,
+	}
+    }
+
+    /// Checks if an order with this status is allowed to move to the next status.
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+	matches!(
+	    (self, next),
+	    (OrderStatus::Booked, OrderStatus::Out)
+		| (OrderStatus::Out, OrderStatus::Returned)
+		| (OrderStatus::Booked, OrderStatus::Cancelled)
+	)
+    }
+
+    /// Checks if the items for an order with this status are still to come back.
+    pub fn is_open(&self) -> bool {
+	matches!(self, OrderStatus::Booked | OrderStatus::Out)
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_parse_round_trip() {
+	for status in [
+	    OrderStatus::Booked,
+	    OrderStatus::Out,
+	    OrderStatus::Returned,
+	    OrderStatus::Cancelled,
+	] {
+	    assert_eq!(OrderStatus::parse(status.as_str()), Some(status));
+	}
+    }
+
+    #[test]
+    fn test_status_parse_unknown() {
+	assert_eq!(OrderStatus::parse("Lost"), None);
+    }
+
+    #[test]
+    fn test_status_transitions() {
+	assert!(OrderStatus::Booked.can_transition_to(OrderStatus::Out));
+	assert!(OrderStatus::Out.can_transition_to(OrderStatus::Returned));
+	assert!(OrderStatus::Booked.can_transition_to(OrderStatus::Cancelled));
+    }
+
+    #[test]
+    fn test_status_invalid_transitions() {
+	assert!(!OrderStatus::Booked.can_transition_to(OrderStatus::Returned));
+	assert!(!OrderStatus::Out.can_transition_to(OrderStatus::Booked));
+	assert!(!OrderStatus::Out.can_transition_to(OrderStatus::Cancelled));
+	assert!(!OrderStatus::Returned.can_transition_to(OrderStatus::Out));
+	assert!(!OrderStatus::Cancelled.can_transition_to(OrderStatus::Booked));
+    }
+}
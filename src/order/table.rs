@@ -1,19 +1,53 @@
-use iced::{Element, Length, Theme, Renderer};
-use iced::widget::{container, text, button};
+use std::collections::HashSet;
+use iced::{Element, Length, Theme, Renderer, Color};
+use iced::widget::{container, text, button, row};
 use iced_table::table;
+use chrono::Local;
 use crate::app::Message;
-use crate::order::Order;
+use crate::order::{Order, OrderLine, OrderStatus};
+use crate::order::query::{OrderSortField, SortDirection};
+use crate::payment::describe_balance;
+
+/// Represents a row of the orders and raffle table, which is either an order
+/// or, under an expanded order, one of its lines.
+pub enum OrderRow {
+    Order { order: Order, expanded: bool },
+    Line(OrderLine),
+}
+
+impl OrderRow {
+    /// Builds the rows for the orders, putting the lines of the expanded
+    /// orders under them.
+    pub fn build(orders: &[Order], expanded: &HashSet<i32>) -> Vec<Self> {
+	let mut rows = Vec::new();
+
+	for order in orders {
+	    let is_expanded = expanded.contains(&order.id);
+
+	    rows.push(OrderRow::Order { order: order.clone(), expanded: is_expanded });
+	    if is_expanded {
+		rows.extend(order.lines.iter().cloned().map(OrderRow::Line));
+	    }
+	}
+
+	rows
+    }
+}
 
 /// Represents a column for the orders and raffle table.
 pub struct OrderColumn {
     pub kind: OrderColumnKind,
     pub width: f32,
     pub resize_offset: Option<f32>,
+    // Whether clicking the header sorts the table, and which way it is sorted
+    pub sortable: bool,
+    pub sort: Option<SortDirection>,
 }
 
 impl OrderColumn {
     pub fn new(kind: OrderColumnKind) -> Self {
 	let width = match kind {
+	    OrderColumnKind::Expand => 40.0,
 	    OrderColumnKind::CustomerName => 400.0,
 	    OrderColumnKind::RecieptNumber => 120.0,
 	    OrderColumnKind::ItemHired => 150.0,
@@ -22,6 +56,11 @@ impl OrderColumn {
 	    OrderColumnKind::ReturnOn => 100.0,
 	    OrderColumnKind::BoxesNeeded => 60.0,
 	    OrderColumnKind::RaffleNumber => 150.0,
+	    OrderColumnKind::Status => 100.0,
+	    OrderColumnKind::StatusAction => 220.0,
+	    OrderColumnKind::Paid => 130.0,
+	    OrderColumnKind::Docket => 100.0,
+	    OrderColumnKind::Edit => 100.0,
 	    OrderColumnKind::Delete => 100.0,
 	};
 
@@ -29,12 +68,34 @@ impl OrderColumn {
 	    kind,
 	    width,
 	    resize_offset: None,
+	    sortable: false,
+	    sort: None,
 	}
     }
+
+    /// Makes the column sort the table when its header is clicked.
+    pub fn sortable(mut self) -> Self {
+	self.sortable = true;
+	self
+    }
+
+    /// Gets the cell for a line shown under its order, which only fills the
+    /// columns about the item.
+    fn line_cell<'a>(&self, line: &'a OrderLine) -> Element<'a, Message> {
+	let content = match self.kind {
+	    OrderColumnKind::ItemHired => text(line.item_hired.clone()),
+	    OrderColumnKind::HowMany => text(line.how_many.to_string()),
+	    OrderColumnKind::BoxesNeeded => text(line.boxes_needed.to_string()),
+	    _ => text(""),
+	};
+
+	container(content).width(Length::Fill).center_y(32).into()
+    }
 }
 
 /// Represents what the field the column corrosponds to.
 pub enum OrderColumnKind {
+    Expand,
     CustomerName,
     RecieptNumber,
     ItemHired,
@@ -43,14 +104,43 @@ pub enum OrderColumnKind {
     ReturnOn,
     BoxesNeeded,
     RaffleNumber,
+    Status,
+    StatusAction,
+    Paid,
+    Docket,
+    Edit,
     Delete,
 }
 
+impl OrderColumnKind {
+    /// Gets the field the column sorts by, or None if it can't be sorted.
+    pub fn sort_field(&self) -> Option<OrderSortField> {
+	match self {
+	    OrderColumnKind::CustomerName => Some(OrderSortField::CustomerName),
+	    OrderColumnKind::RecieptNumber => Some(OrderSortField::ReceiptNumber),
+	    OrderColumnKind::ItemHired => Some(OrderSortField::ItemHired),
+	    OrderColumnKind::HowMany => Some(OrderSortField::HowMany),
+	    OrderColumnKind::HiredOn => Some(OrderSortField::HiredOn),
+	    OrderColumnKind::ReturnOn => Some(OrderSortField::ReturnOn),
+	    OrderColumnKind::BoxesNeeded => Some(OrderSortField::BoxesNeeded),
+	    OrderColumnKind::RaffleNumber => Some(OrderSortField::RaffleNumber),
+	    OrderColumnKind::Status => Some(OrderSortField::Status),
+	    OrderColumnKind::Expand
+		| OrderColumnKind::StatusAction
+		| OrderColumnKind::Paid
+		| OrderColumnKind::Docket
+		| OrderColumnKind::Edit
+		| OrderColumnKind::Delete => None,
+	}
+    }
+}
+
 impl<'a> table::Column<'a, Message, Theme, Renderer> for OrderColumn {
-    type Row = Order;
+    type Row = OrderRow;
     
     fn header(&'a self, _col_size: usize) -> Element<'a, Message> {
 	let content =  match self.kind {
+	    OrderColumnKind::Expand => "",
 	    OrderColumnKind::CustomerName => "Customer Name",
 	    OrderColumnKind::RecieptNumber => "Reciept No.",
 	    OrderColumnKind::ItemHired => "Item Hired",
@@ -59,24 +149,98 @@ impl<'a> table::Column<'a, Message, Theme, Renderer> for OrderColumn {
 	    OrderColumnKind::ReturnOn => "Return On",
 	    OrderColumnKind::BoxesNeeded => "Boxes",
 	    OrderColumnKind::RaffleNumber => "Raffle Number",
+	    OrderColumnKind::Status => "Status",
+	    OrderColumnKind::StatusAction => "",
+	    OrderColumnKind::Paid => "Paid",
+	    OrderColumnKind::Docket => "",
+	    OrderColumnKind::Edit => "",
 	    OrderColumnKind::Delete => "",
 	};
 
-	container(text(content)).center_y(24).into()
+	let header: Element<_> = match self.kind.sort_field() {
+	    Some(field) if self.sortable => {
+		let arrow = match self.sort {
+		    Some(SortDirection::Ascending) => " ▲",
+		    Some(SortDirection::Descending) => " ▼",
+		    None => "",
+		};
+
+		button(text(format!("{}{}", content, arrow)))
+		    .on_press(Message::SortOrders(field))
+		    .style(button::text)
+		    .padding(0)
+		    .into()
+	    },
+	    _ => text(content).into(),
+	};
+
+	container(header).center_y(24).into()
     }
 
-    fn cell(&'a self, _col_index: usize, _row_index: usize, row: &'a Order) -> Element<'a, Message> {
+    fn cell(&'a self, _col_index: usize, _row_index: usize, row: &'a OrderRow) -> Element<'a, Message> {
+	let (row, expanded) = match row {
+	    OrderRow::Order { order, expanded } => (order, *expanded),
+	    OrderRow::Line(line) => return self.line_cell(line),
+	};
+
 	let content: Element<_> = match self.kind {
+	    OrderColumnKind::Expand => {
+		if row.lines.len() > 1 {
+		    button(text(if expanded { "▼" } else { "▶" }))
+			.on_press(Message::ToggleOrderLines(row.id))
+			.style(button::text)
+			.into()
+		} else {
+		    text("").into()
+		}
+	    },
 	    OrderColumnKind::CustomerName => text(row.customer_name.clone()).into(),
 	    OrderColumnKind::RecieptNumber => text(row.receipt_number.to_string()).into(),
-	    OrderColumnKind::ItemHired => text(row.item_hired.clone()).into(),
-	    OrderColumnKind::HowMany => text(row.how_many.to_string()).into(),
+	    OrderColumnKind::ItemHired => match row.lines.as_slice() {
+		[line] => text(line.item_hired.clone()).into(),
+		lines => text(format!("{} items", lines.len())).into(),
+	    },
+	    OrderColumnKind::HowMany => text(row.how_many().to_string()).into(),
 	    OrderColumnKind::HiredOn => text(row.hired_on.to_string()).into(),
 	    OrderColumnKind::ReturnOn => text(row.return_on.to_string()).into(),
 	    OrderColumnKind::BoxesNeeded => text(row.boxes_needed.to_string()).into(),
 	    OrderColumnKind::RaffleNumber => text(row.raffle_number.to_string()).into(),
+	    OrderColumnKind::Status => {
+		if row.is_overdue(Local::now().date_naive()) {
+		    text("Overdue").color(Color::from_rgb(255.0, 0.0, 0.0)).into()
+		} else {
+		    text(row.status.to_string()).into()
+		}
+	    },
+	    OrderColumnKind::StatusAction => match row.status {
+		OrderStatus::Booked => row![
+		    button(text("Mark out")).on_press(Message::MarkOrderOut(row.id)),
+		    button(text("Cancel")).on_press(Message::CancelOrder(row.id)),
+		].spacing(5).into(),
+		OrderStatus::Out => button(text("Mark returned"))
+		    .on_press(Message::MarkOrderReturned(row.id))
+		    .into(),
+		OrderStatus::Returned | OrderStatus::Cancelled => text("").into(),
+	    },
+	    OrderColumnKind::Paid => button(text(describe_balance(row.balance())))
+		.on_press(Message::ViewPayments(row.id))
+		.style(button::text)
+		.into(),
+	    OrderColumnKind::Docket => button(text("Docket"))
+		.on_press(Message::SaveDocket(row.id))
+		.into(),
+	    OrderColumnKind::Edit => button(text("Edit"))
+		.on_press(Message::EditOrder(row.id))
+		.into(),
 	    OrderColumnKind::Delete => button(text("Delete"))
-		.on_press(Message::DeleteOrder(row.id))
+		.on_press(Message::AskToConfirm(
+		    format!(
+			"Delete order {} for {}? It can be restored from Recently deleted.",
+			row.id,
+			row.customer_name,
+		    ),
+		    Box::new(Message::DeleteOrder(row.id)),
+		))
 		.into(),
 	};
 
@@ -0,0 +1,149 @@
+use chrono::NaiveDateTime;
+use rusqlite::Connection;
+use crate::database::with_savepoint;
+use crate::error::Error;
+use crate::order::Order;
+
+/// Represents something done to orders that can be undone, with what is
+/// needed to undo it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UndoAction {
+    /// An order was deleted, and can be restored.
+    Deleted(i32),
+    /// An order was edited, and this is how it was before.
+    Edited(Order),
+    /// Orders were imported together, and can be deleted again.
+    Imported(Vec<i32>),
+}
+
+impl UndoAction {
+    /// Describes what would be undone, e.g. "delete order 5".
+    pub fn description(&self) -> String {
+	match self {
+	    UndoAction::Deleted(id) => format!("delete order {}", id),
+	    UndoAction::Edited(order) => format!("edit order {}", order.id),
+	    UndoAction::Imported(ids) => format!("import of {} order(s)", ids.len()),
+	}
+    }
+
+    /// Undoes the action. Imported orders are deleted as of `now`, so they can
+    /// be restored if the undo was a mistake too.
+    pub fn undo(&self, connection: &Connection, now: NaiveDateTime) -> Result<(), Error> {
+	match self {
+	    UndoAction::Deleted(id) => {
+		Order::restore(connection, *id)?;
+	    },
+	    UndoAction::Edited(before) => {
+		let lines = before.lines.iter()
+		    .map(|line| (line.item_hired.clone(), line.how_many))
+		    .collect();
+
+		before.update(
+		    connection,
+		    before.customer_name.clone(),
+		    before.receipt_number,
+		    lines,
+		    before.hired_on,
+		    before.return_on,
+		)?;
+	    },
+	    UndoAction::Imported(ids) => {
+		with_savepoint(connection, || {
+		    for id in ids {
+			match Order::get_by_id(connection, *id) {
+			    // Already deleted since it was imported
+			    Err(Error::Database(rusqlite::Error::QueryReturnedNoRows)) => (),
+			    order => {
+				order?.delete(connection, now)?;
+			    },
+			}
+		    }
+
+		    Ok(())
+		})?;
+	    },
+	}
+
+	Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn test_now() -> NaiveDateTime {
+	NaiveDate::from_ymd_opt(2025, 3, 20).unwrap().and_hms_opt(9, 0, 0).unwrap()
+    }
+
+    fn test_order(con: &Connection, receipt_number: i64) -> Order {
+	Order::new(
+	    con,
+	    "Test Person".to_string(),
+	    receipt_number,
+	    vec![("Test Item".to_string(), 26)],
+	    NaiveDate::from_ymd_opt(2025, 3, 20).unwrap(),
+	    NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+	).unwrap()
+    }
+
+    #[test]
+    fn test_undo_delete() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con, 15);
+	order.clone().delete(&con, test_now()).unwrap();
+
+	UndoAction::Deleted(order.id).undo(&con, test_now()).unwrap();
+
+	assert_eq!(Order::get_by_id(&con, order.id).unwrap(), order);
+	assert!(UndoAction::Deleted(order.id).undo(&con, test_now()).is_err());
+    }
+
+    #[test]
+    fn test_undo_edit() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let before = test_order(&con, 15);
+	before.update(
+	    &con,
+	    "Someone Else".to_string(),
+	    16,
+	    vec![("Other Item".to_string(), 3), ("Test Item".to_string(), 1)],
+	    before.hired_on,
+	    before.return_on,
+	).unwrap();
+
+	UndoAction::Edited(before.clone()).undo(&con, test_now()).unwrap();
+
+	let after = Order::get_by_id(&con, before.id).unwrap();
+	assert_eq!(after.customer_name, before.customer_name);
+	assert_eq!(after.receipt_number, 15);
+	assert_eq!(after.items_hired(), "Test Item".to_string());
+	assert_eq!(after.how_many(), 26);
+    }
+
+    #[test]
+    fn test_undo_import() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let kept = test_order(&con, 14);
+	let first = test_order(&con, 15);
+	let second = test_order(&con, 16);
+	second.clone().delete(&con, test_now()).unwrap();
+
+	UndoAction::Imported(vec![first.id, second.id]).undo(&con, test_now()).unwrap();
+
+	assert_eq!(Order::get_all(&con).unwrap(), vec![kept]);
+	assert_eq!(Order::get_deleted(&con).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,330 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::Write;
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use crate::error::Error;
+use crate::item::Item;
+use crate::order::{Order, OrderStatus};
+use crate::settings::DEFAULT_BOX_CAPACITY;
+
+/// How many days the packing plan covers, unless asked for more or less.
+pub const PACKING_PLAN_DAYS: u64 = 7;
+
+/// Represents some of an item that has to go in boxes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackingLine {
+    pub item: String,
+    pub quantity: i32,
+    pub box_capacity: i32,
+}
+
+/// Represents one box to prepare, and what goes in it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedBox {
+    pub contents: Vec<(String, i32)>,
+}
+
+impl PackedBox {
+    /// Gets the type of box, which is the item it holds, or Mixed if it holds
+    /// more than one item.
+    pub fn kind(&self) -> String {
+	match self.contents.as_slice() {
+	    [(item, _)] => item.clone(),
+	    _ => "Mixed".to_string(),
+	}
+    }
+
+    /// Describes what is in the box, e.g. "10 Chairs, 5 Tablecloths".
+    pub fn describe(&self) -> String {
+	self.contents.iter()
+	    .map(|(item, quantity)| format!("{} {}", quantity, item))
+	    .collect::<Vec<String>>()
+	    .join(", ")
+    }
+}
+
+/// Works out the boxes needed for the lines of one pickup. Each item fills
+/// whole boxes of its own first, then the leftovers share boxes where they fit.
+pub fn pack(lines: &[PackingLine]) -> Vec<PackedBox> {
+    let mut boxes = Vec::new();
+    let mut leftovers = Vec::new();
+
+    for line in lines.iter().filter(|line| line.quantity > 0) {
+	let capacity = line.box_capacity.max(1);
+
+	for _ in 0..line.quantity / capacity {
+	    boxes.push(PackedBox { contents: vec![(line.item.clone(), capacity)] });
+	}
+
+	if line.quantity % capacity > 0 {
+	    leftovers.push((line.item.clone(), line.quantity % capacity, capacity));
+	}
+    }
+
+    // Packing the biggest leftovers first wastes the least space
+    leftovers.sort_by(|(_, a, a_capacity), (_, b, b_capacity)| {
+	compare_fractions((*b as i64, *b_capacity as i64), (*a as i64, *a_capacity as i64))
+    });
+
+    // How full each shared box is, as a fraction
+    let mut shared: Vec<(PackedBox, (i64, i64))> = Vec::new();
+
+    for (item, quantity, capacity) in leftovers {
+	let space = (quantity as i64, capacity as i64);
+
+	let open = shared.iter_mut()
+	    .find(|(_, filled)| compare_fractions(add_fractions(*filled, space), (1, 1)) != Ordering::Greater);
+
+	match open {
+	    Some((packed, filled)) => {
+		packed.contents.push((item, quantity));
+		*filled = add_fractions(*filled, space);
+	    },
+	    None => shared.push((PackedBox { contents: vec![(item, quantity)] }, space)),
+	}
+    }
+
+    boxes.extend(shared.into_iter().map(|(packed, _)| packed));
+
+    boxes
+}
+
+/// Adds two fractions, each given as (numerator, denominator).
+fn add_fractions(a: (i64, i64), b: (i64, i64)) -> (i64, i64) {
+    let numerator = a.0 * b.1 + b.0 * a.1;
+    let denominator = a.1 * b.1;
+    let divisor = gcd(numerator, denominator);
+
+    (numerator / divisor, denominator / divisor)
+}
+
+/// Compares two fractions, each given as (numerator, denominator).
+fn compare_fractions(a: (i64, i64), b: (i64, i64)) -> Ordering {
+    (a.0 * b.1).cmp(&(b.0 * a.1))
+}
+
+/// Gets the greatest common divisor of two numbers.
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a.abs().max(1) } else { gcd(b, a % b) }
+}
+
+/// Represents the boxes to prepare for one customer's pickup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PickupPlan {
+    pub customer_name: String,
+    pub receipt_number: i64,
+    pub boxes: Vec<PackedBox>,
+}
+
+/// Represents the boxes to prepare for every pickup on one day.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DayPlan {
+    pub day: NaiveDate,
+    pub pickups: Vec<PickupPlan>,
+}
+
+impl DayPlan {
+    /// Counts the boxes of each type needed on the day, in alphabetical order
+    /// with Mixed boxes last.
+    pub fn box_counts(&self) -> Vec<(String, usize)> {
+	let mut counts: HashMap<String, usize> = HashMap::new();
+
+	for packed in self.pickups.iter().flat_map(|pickup| pickup.boxes.iter()) {
+	    *counts.entry(packed.kind()).or_insert(0) += 1;
+	}
+
+	let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+	counts.sort_by_key(|(kind, _)| (kind == "Mixed", kind.clone()));
+
+	counts
+    }
+
+    /// Gets how many boxes are needed on the day altogether.
+    pub fn total_boxes(&self) -> usize {
+	self.pickups.iter().map(|pickup| pickup.boxes.len()).sum()
+    }
+}
+
+/// Represents the boxes to prepare for the pickups over a range of days.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackingPlan {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub days: Vec<DayPlan>,
+}
+
+impl PackingPlan {
+    /// Plans the boxes for the booked orders picked up from `from` to `to`.
+    /// The lines of an order, and of any other orders with the same customer,
+    /// receipt and pickup day, are picked up together so their items can share boxes.
+    pub fn build(connection: &Connection, from: NaiveDate, to: NaiveDate) -> Result<Self, Error> {
+	let default_capacity = DEFAULT_BOX_CAPACITY.get(connection)? as i32;
+	let capacities: HashMap<String, i32> = Item::get_all(connection)?
+	    .into_iter()
+	    .map(|item| (item.name.to_lowercase(), item.box_capacity))
+	    .collect();
+
+	let mut orders: Vec<Order> = Order::get_all(connection)?
+	    .into_iter()
+	    .filter(|order| order.status == OrderStatus::Booked)
+	    .filter(|order| from <= order.hired_on && order.hired_on <= to)
+	    .collect();
+	orders.sort_by(|a, b| {
+	    (a.hired_on, &a.customer_name, a.receipt_number)
+		.cmp(&(b.hired_on, &b.customer_name, b.receipt_number))
+	});
+
+	let mut days: Vec<DayPlan> = Vec::new();
+	let mut lines: Vec<PackingLine> = Vec::new();
+
+	for (index, order) in orders.iter().enumerate() {
+	    lines.extend(order.lines.iter().map(|line| PackingLine {
+		item: line.item_hired.clone(),
+		quantity: line.how_many,
+		box_capacity: *capacities.get(&line.item_hired.to_lowercase()).unwrap_or(&default_capacity),
+	    }));
+
+	    // Keep collecting lines until the next order is a different pickup
+	    let next = orders.get(index + 1);
+	    if next.is_some_and(|next| {
+		(next.hired_on, &next.customer_name, next.receipt_number)
+		    == (order.hired_on, &order.customer_name, order.receipt_number)
+	    }) {
+		continue;
+	    }
+
+	    let pickup = PickupPlan {
+		customer_name: order.customer_name.clone(),
+		receipt_number: order.receipt_number,
+		boxes: pack(&lines),
+	    };
+	    lines.clear();
+
+	    match days.last_mut() {
+		Some(day) if day.day == order.hired_on => day.pickups.push(pickup),
+		_ => days.push(DayPlan { day: order.hired_on, pickups: vec![pickup] }),
+	    }
+	}
+
+	Ok(Self { from, to, days })
+    }
+
+    /// Gets how many boxes are needed over every day of the plan.
+    pub fn total_boxes(&self) -> usize {
+	self.days.iter().map(|day| day.total_boxes()).sum()
+    }
+
+    /// Formats the plan as plain text, ready to be printed.
+    pub fn to_text(&self) -> String {
+	let mut text = format!(
+	    "Packing plan for {} to {}\n",
+	    self.from.format("%Y-%m-%d"),
+	    self.to.format("%Y-%m-%d"),
+	);
+
+	if self.days.is_empty() {
+	    text.push_str("\nNo pickups booked.\n");
+	}
+
+	for day in &self.days {
+	    let counts: Vec<String> = day.box_counts()
+		.iter()
+		.map(|(kind, count)| format!("{} x{}", kind, count))
+		.collect();
+
+	    let _ = writeln!(text, "\n{} - {} box(es)", day.day.format("%A %Y-%m-%d"), day.total_boxes());
+	    let _ = writeln!(text, "  Boxes: {}", counts.join(", "));
+
+	    for pickup in &day.pickups {
+		let _ = writeln!(text, "  {} (receipt {})", pickup.customer_name, pickup.receipt_number);
+
+		for (number, packed) in pickup.boxes.iter().enumerate() {
+		    let _ = writeln!(text, "    Box {}: {}", number + 1, packed.describe());
+		}
+	    }
+	}
+
+	text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn line(item: &str, quantity: i32, box_capacity: i32) -> PackingLine {
+	PackingLine { item: item.to_string(), quantity, box_capacity }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+	NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_pack_single_item() {
+	let boxes = pack(&[line("Chairs", 60, 25)]);
+
+	assert_eq!(boxes.len(), 3);
+	assert_eq!(boxes[2].contents, vec![("Chairs".to_string(), 10)]);
+	assert_eq!(boxes[2].kind(), "Chairs".to_string());
+    }
+
+    #[test]
+    fn test_pack_shares_leftovers() {
+	// Half a box of chairs and a third of a box of tablecloths fit together,
+	// but the 3/4 box of tables doesn't fit with either
+	let boxes = pack(&[line("Chairs", 10, 20), line("Tablecloths", 10, 30), line("Tables", 3, 4)]);
+
+	assert_eq!(boxes.len(), 2);
+	assert_eq!(boxes[0].contents, vec![("Tables".to_string(), 3)]);
+	assert_eq!(boxes[1].describe(), "10 Chairs, 10 Tablecloths".to_string());
+	assert_eq!(boxes[1].kind(), "Mixed".to_string());
+    }
+
+    #[test]
+    fn test_pack_exactly_full_shared_box() {
+	let boxes = pack(&[line("Cups", 1, 3), line("Plates", 2, 3)]);
+
+	assert_eq!(boxes.len(), 1);
+    }
+
+    #[test]
+    fn test_pack_nothing() {
+	assert!(pack(&[line("Chairs", 0, 25)]).is_empty());
+    }
+
+    #[test]
+    fn test_packing_plan() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	Item::new(&con, "Chairs".to_string(), 200, 20, 250).unwrap();
+	Item::new(&con, "Tablecloths".to_string(), 200, 30, 500).unwrap();
+
+	let new_order = |customer: &str, receipt: i64, lines: &[(&str, i32)], day: u32| {
+	    let lines = lines.iter().map(|(item, how_many)| (item.to_string(), *how_many)).collect();
+	    Order::new(&con, customer.to_string(), receipt, lines, date(day), date(day + 2)).unwrap()
+	};
+	new_order("Alice Smith", 1, &[("Chairs", 50), ("Tablecloths", 10)], 1);
+	new_order("Bob Jones", 2, &[("Chairs", 5)], 1);
+	// Orders on the same receipt are picked up together
+	new_order("Bob Jones", 2, &[("Tablecloths", 15)], 1);
+	new_order("Carol White", 3, &[("Chairs", 5)], 2).cancel(&con).unwrap();
+	new_order("Dave Brown", 4, &[("Chairs", 5)], 10);
+
+	let plan = PackingPlan::build(&con, date(1), date(7)).unwrap();
+
+	assert_eq!(plan.days.len(), 1);
+	assert_eq!(plan.days[0].pickups.len(), 2);
+	assert_eq!(plan.days[0].total_boxes(), 4);
+	assert_eq!(
+	    plan.days[0].box_counts(),
+	    vec![("Chairs".to_string(), 2), ("Mixed".to_string(), 2)],
+	);
+	assert!(plan.to_text().contains("Box 3: 10 Chairs, 10 Tablecloths"));
+    }
+}
@@ -0,0 +1,297 @@
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use crate::error::Error;
+use crate::helpers::Form;
+use crate::item::parse_price;
+use crate::order::Order;
+use crate::order::form::parse_date;
+use crate::payment::{Payment, PaymentMethod};
+
+/// The most that can be paid or charged at once, in cents.
+const MAX_AMOUNT: i64 = 10_000_000;
+
+/// Represents a form to record a payment towards an order, or a refund
+pub struct PaymentForm {
+    pub amount: String,
+    pub amount_show_error: bool,
+    // Whether the money was given back to the customer
+    pub refund: bool,
+    pub method: PaymentMethod,
+    pub paid_on: String,
+    pub paid_on_show_error: bool,
+    pub note: String,
+    pub note_show_error: bool,
+    // The day dates like "today" are worked out from
+    pub today: NaiveDate,
+}
+
+impl PaymentForm {
+    /// Makes an empty form, for a payment made today.
+    pub fn new(today: NaiveDate) -> Self {
+	Self {
+	    amount: String::new(),
+	    amount_show_error: false,
+	    refund: false,
+	    method: PaymentMethod::default(),
+	    paid_on: today.format("%Y-%m-%d").to_string(),
+	    paid_on_show_error: false,
+	    note: String::new(),
+	    note_show_error: false,
+	    today,
+	}
+    }
+
+    /// Gets amount in cents, negative for a refund, or Err if it is invalid.
+    fn get_valid_amount(&self) -> Result<i64, String> {
+	match parse_price(&self.amount) {
+	    Some(0) => Err("Amount must be more than $0".to_string()),
+	    Some(cents) if cents > MAX_AMOUNT => Err("Amount must not be more than $100000".to_string()),
+	    Some(cents) if self.refund => Ok(-cents),
+	    Some(cents) => Ok(cents),
+	    None => Err("Amount must be in dollars, e.g. 12.50".to_string()),
+	}
+    }
+
+    /// Gets paid_on as a date, or Err if it is invalid.
+    fn get_valid_paid_on(&self) -> Result<NaiveDate, String> {
+	match parse_date(&self.paid_on, self.today) {
+	    Some(date) if date > self.today => Err("Payment date can't be in the future".to_string()),
+	    Some(date) => Ok(date),
+	    None => Err("Payment date must be formatted as YYYY-MM-DD e.g. 2025-03-18".to_string()),
+	}
+    }
+
+    /// Gets note, or Err if it is invalid.
+    fn get_valid_note(&self) -> Result<String, String> {
+	if self.note.len() > 100 {
+	    return Err("Note must be less than 100 characters".to_string());
+	}
+
+	Ok(self.note.trim().to_string())
+    }
+
+    /// Gets the error for a given field if its corresponding *_show_error is set to true.
+    pub fn get_visible_field_error(&self, field: &str) -> Option<String> {
+	match field {
+	    "amount" if self.amount_show_error => {
+		if let Err(e) = self.get_valid_amount() {
+		    return Some(e);
+		}
+	    },
+	    "paid_on" if self.paid_on_show_error => {
+		if let Err(e) = self.get_valid_paid_on() {
+		    return Some(e);
+		}
+	    },
+	    "note" if self.note_show_error => {
+		if let Err(e) = self.get_valid_note() {
+		    return Some(e);
+		}
+	    },
+	    _ => (),
+	};
+
+	None
+    }
+
+    /// Records the payment against the order if all fields of the form are valid.
+    pub fn save(&self, connection: &Connection, order_id: i32) -> Result<Payment, Error> {
+	Payment::new(
+	    connection,
+	    order_id,
+	    self.get_valid_amount().map_err(Error::Validation)?,
+	    self.method,
+	    self.get_valid_paid_on().map_err(Error::Validation)?,
+	    self.get_valid_note().map_err(Error::Validation)?,
+	)
+    }
+}
+
+impl Form for PaymentForm {
+    /// Sets every *_show_error to true, so all invalid fields show their errors.
+    fn show_all_errors(&mut self) {
+	self.amount_show_error = true;
+	self.paid_on_show_error = true;
+	self.note_show_error = true;
+    }
+
+    /// Checks if any field of the form is showing an error.
+    fn has_visible_errors(&self) -> bool {
+	["amount", "paid_on", "note"]
+	    .iter()
+	    .any(|field| self.get_visible_field_error(field).is_some())
+    }
+}
+
+/// Represents a form to change the deposit and damage charge on an order
+#[derive(Default)]
+pub struct ChargesForm {
+    pub deposit: String,
+    pub deposit_show_error: bool,
+    pub damage_charge: String,
+    pub damage_charge_show_error: bool,
+}
+
+impl ChargesForm {
+    /// Makes a form filled in with the order's charges.
+    pub fn from_order(order: &Order) -> Self {
+	Self {
+	    deposit: format!("{}.{:02}", order.deposit / 100, order.deposit % 100),
+	    damage_charge: format!("{}.{:02}", order.damage_charge / 100, order.damage_charge % 100),
+	    ..Default::default()
+	}
+    }
+
+    /// Gets deposit in cents, or Err if it is invalid.
+    fn get_valid_deposit(&self) -> Result<i64, String> {
+	match parse_price(&self.deposit) {
+	    Some(cents) if cents > MAX_AMOUNT => Err("Deposit must not be more than $100000".to_string()),
+	    Some(cents) => Ok(cents),
+	    None => Err("Deposit must be in dollars, e.g. 50.00".to_string()),
+	}
+    }
+
+    /// Gets damage_charge in cents, or Err if it is invalid.
+    fn get_valid_damage_charge(&self) -> Result<i64, String> {
+	match parse_price(&self.damage_charge) {
+	    Some(cents) if cents > MAX_AMOUNT => Err("Damage charge must not be more than $100000".to_string()),
+	    Some(cents) => Ok(cents),
+	    None => Err("Damage charge must be in dollars, e.g. 12.50".to_string()),
+	}
+    }
+
+    /// Gets the error for a given field if its corresponding *_show_error is set to true.
+    pub fn get_visible_field_error(&self, field: &str) -> Option<String> {
+	match field {
+	    "deposit" if self.deposit_show_error => {
+		if let Err(e) = self.get_valid_deposit() {
+		    return Some(e);
+		}
+	    },
+	    "damage_charge" if self.damage_charge_show_error => {
+		if let Err(e) = self.get_valid_damage_charge() {
+		    return Some(e);
+		}
+	    },
+	    _ => (),
+	};
+
+	None
+    }
+
+    /// Sets the charges on the order if all fields of the form are valid.
+    pub fn save(&self, connection: &Connection, order: &Order) -> Result<Order, Error> {
+	order.set_charges(
+	    connection,
+	    self.get_valid_deposit().map_err(Error::Validation)?,
+	    self.get_valid_damage_charge().map_err(Error::Validation)?,
+	)
+    }
+}
+
+impl Form for ChargesForm {
+    /// Sets every *_show_error to true, so all invalid fields show their errors.
+    fn show_all_errors(&mut self) {
+	self.deposit_show_error = true;
+	self.damage_charge_show_error = true;
+    }
+
+    /// Checks if any field of the form is showing an error.
+    fn has_visible_errors(&self) -> bool {
+	["deposit", "damage_charge"]
+	    .iter()
+	    .any(|field| self.get_visible_field_error(field).is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn today() -> NaiveDate {
+	NaiveDate::from_ymd_opt(2025, 3, 20).unwrap()
+    }
+
+    fn test_order(con: &Connection) -> Order {
+	Order::new(
+	    con,
+	    "Test Person".to_string(),
+	    1,
+	    vec![("Chairs".to_string(), 10)],
+	    today(),
+	    today(),
+	).unwrap()
+    }
+
+    #[test]
+    fn test_get_valid_amount() {
+	let mut form = PaymentForm::new(today());
+	form.amount = "12.50".to_string();
+	assert_eq!(form.get_valid_amount(), Ok(1250));
+
+	form.refund = true;
+	assert_eq!(form.get_valid_amount(), Ok(-1250));
+    }
+
+    #[test]
+    fn test_get_valid_amount_invalid() {
+	let mut form = PaymentForm::new(today());
+
+	for amount in ["", "0", "-5", "1.234", "100000.01", "lots"] {
+	    form.amount = amount.to_string();
+	    assert!(form.get_valid_amount().is_err());
+	}
+    }
+
+    #[test]
+    fn test_get_valid_paid_on() {
+	let mut form = PaymentForm::new(today());
+	assert_eq!(form.get_valid_paid_on(), Ok(today()));
+
+	form.paid_on = "yesterday".to_string();
+	assert_eq!(form.get_valid_paid_on(), Ok(NaiveDate::from_ymd_opt(2025, 3, 19).unwrap()));
+
+	form.paid_on = "tomorrow".to_string();
+	assert!(form.get_valid_paid_on().is_err());
+    }
+
+    #[test]
+    fn test_form_save() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con);
+	let mut form = PaymentForm::new(today());
+	form.amount = "$20".to_string();
+	form.method = PaymentMethod::Card;
+	form.note = " Deposit ".to_string();
+
+	let payment = form.save(&con, order.id).unwrap();
+	assert_eq!(payment.amount, 2000);
+	assert_eq!(payment.method, PaymentMethod::Card);
+	assert_eq!(payment.paid_on, today());
+	assert_eq!(payment.note, "Deposit".to_string());
+    }
+
+    #[test]
+    fn test_charges_form_save() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con);
+	let mut form = ChargesForm::from_order(&order);
+	assert_eq!(form.deposit, "0.00".to_string());
+
+	form.deposit = "50".to_string();
+	form.damage_charge = "12.5".to_string();
+	let order = form.save(&con, &order).unwrap();
+	assert_eq!((order.deposit, order.damage_charge), (5000, 1250));
+
+	form.damage_charge = "-1".to_string();
+	assert!(form.save(&con, &order).is_err());
+    }
+}
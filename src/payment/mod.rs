@@ -0,0 +1,296 @@
+pub mod form;
+
+use std::fmt;
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, Row};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use crate::error::Error;
+use crate::item::format_price;
+use crate::order::Order;
+
+pub use self::form::{ChargesForm, PaymentForm};
+
+/// How a payment was made.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PaymentMethod {
+    #[default]
+    Cash,
+    Card,
+    BankTransfer,
+    Other,
+}
+
+impl PaymentMethod {
+    /// Every method, in the order they are offered.
+    pub const ALL: [PaymentMethod; 4] = [
+	PaymentMethod::Cash,
+	PaymentMethod::Card,
+	PaymentMethod::BankTransfer,
+	PaymentMethod::Other,
+    ];
+
+    /// Gets the value stored in the database for the method.
+    pub fn as_str(&self) -> &'static str {
+	match self {
+	    PaymentMethod::Cash => "Cash",
+	    PaymentMethod::Card => "Card",
+	    PaymentMethod::BankTransfer => "Bank transfer",
+	    PaymentMethod::Other => "Other",
+	}
+    }
+
+    /// Parses a method, ignoring case, or None if it is unknown.
+    pub fn parse(method: &str) -> Option<Self> {
+	PaymentMethod::ALL.into_iter().find(|m| m.as_str().eq_ignore_ascii_case(method.trim()))
+    }
+}
+
+impl fmt::Display for PaymentMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql for PaymentMethod {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+	Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for PaymentMethod {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+	let method = value.as_str()?;
+
+	PaymentMethod::parse(method).ok_or_else(|| {
+	    FromSqlError::Other(format!("Unknown payment method: {}", method).into())
+	})
+    }
+}
+
+/// Represents money paid towards an order, or given back when the amount is
+/// negative.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Payment {
+    pub id: i32,
+    pub order_id: i32,
+    // How much was paid, in cents. Refunds are negative
+    pub amount: i64,
+    pub method: PaymentMethod,
+    pub paid_on: NaiveDate,
+    pub note: String,
+}
+
+impl Payment {
+    /// Records a payment towards an order.
+    pub fn new(
+	connection: &Connection,
+	order_id: i32,
+	amount: i64,
+	method: PaymentMethod,
+	paid_on: NaiveDate,
+	note: String,
+    ) -> Result<Self, Error> {
+	if amount == 0 {
+	    return Err(Error::Validation("A payment needs an amount".to_string()));
+	}
+
+	// Checks the order is there and hasn't been deleted
+	Order::get_by_id(connection, order_id)?;
+
+	connection.execute(
+	    "INSERT INTO Payment (order_id, amount, method, paid_on, note) VALUES (?1, ?2, ?3, ?4, ?5)",
+	    params![order_id, amount, method, paid_on, note],
+	)?;
+
+	Ok(Self { id: connection.last_insert_rowid() as i32, order_id, amount, method, paid_on, note })
+    }
+
+    /// Gets the payments made towards an order, oldest first.
+    pub fn get_for_order(connection: &Connection, order_id: i32) -> Result<Vec<Self>, Error> {
+	let mut stmt = connection.prepare(
+	    "SELECT id, order_id, amount, method, paid_on, note FROM Payment
+	     WHERE order_id = ?1 ORDER BY paid_on, id"
+	)?;
+
+	let payments = stmt.query_map([order_id], Self::from_row)?.collect::<Result<_, _>>()?;
+
+	Ok(payments)
+    }
+
+    /// Removes a payment that was recorded by mistake.
+    pub fn delete(connection: &Connection, id: i32) -> Result<(), Error> {
+	match connection.execute("DELETE FROM Payment WHERE id = ?1", [id])? {
+	    0 => Err(Error::Validation(format!("There is no payment {}", id))),
+	    _ => Ok(()),
+	}
+    }
+
+    fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
+	Ok(Self {
+	    id: row.get(0)?,
+	    order_id: row.get(1)?,
+	    amount: row.get(2)?,
+	    method: row.get(3)?,
+	    paid_on: row.get(4)?,
+	    note: row.get(5)?,
+	})
+    }
+}
+
+/// Describes what is left to pay on an order, e.g. "Owes $12.50".
+pub fn describe_balance(balance: i64) -> String {
+    match balance {
+	0 => "Paid".to_string(),
+	b if b > 0 => format!("Owes {}", format_price(b)),
+	b => format!("Refund {}", format_price(-b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use crate::database::init_db_tables;
+    use crate::item::Item;
+    use crate::order::OrderStatus;
+
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+	NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    fn returned_at() -> NaiveDateTime {
+	date(25).and_hms_opt(10, 0, 0).unwrap()
+    }
+
+    /// Makes an order for 10 chairs at $2.50 a day, hired for 3 days.
+    fn test_order(con: &Connection) -> Order {
+	Item::new(con, "Chairs".to_string(), 100, 25, 250).unwrap();
+
+	Order::new(
+	    con,
+	    "Test Person".to_string(),
+	    1,
+	    vec![("Chairs".to_string(), 10)],
+	    date(20),
+	    date(23),
+	).unwrap()
+    }
+
+    fn test_connection() -> Connection {
+	let con = Connection::open_in_memory().unwrap();
+	init_db_tables(&con).unwrap();
+	con
+    }
+
+    #[test]
+    fn test_hire_charge() {
+	let con = test_connection();
+	let order = test_order(&con);
+
+	assert_eq!(order.lines[0].daily_rate, 250);
+	assert_eq!(order.hire_days(), 3);
+	assert_eq!(order.hire_charge(), 7500);
+	assert_eq!(order.balance(), 7500);
+    }
+
+    #[test]
+    fn test_line_keeps_rate_when_edited() {
+	let con = test_connection();
+	let order = test_order(&con);
+	let mut item = Item::get_by_name(&con, "Chairs").unwrap().unwrap();
+	item = item.update(&con, item.name.clone(), item.total_stock, item.box_capacity, 300).unwrap();
+
+	let order = order.update(
+	    &con,
+	    order.customer_name.clone(),
+	    order.receipt_number,
+	    vec![("chairs".to_string(), 20)],
+	    date(20),
+	    date(21),
+	).unwrap();
+
+	assert_eq!(item.hire_price, 300);
+	assert_eq!(order.lines[0].daily_rate, 250);
+	assert_eq!(order.hire_charge(), 5000);
+    }
+
+    #[test]
+    fn test_payments_and_balance() {
+	let con = test_connection();
+	let order = test_order(&con).set_charges(&con, 5000, 0).unwrap();
+	assert_eq!(order.balance(), 12500);
+
+	Payment::new(&con, order.id, 10000, PaymentMethod::Cash, date(20), String::new()).unwrap();
+	Payment::new(&con, order.id, 2500, PaymentMethod::Card, date(20), "Deposit".to_string()).unwrap();
+
+	let order = Order::get_by_id(&con, order.id).unwrap();
+	assert_eq!(order.paid, 12500);
+	assert_eq!(order.balance(), 0);
+
+	// The deposit is owed back once the order is returned
+	let order = order.mark_out(&con).unwrap().mark_returned(&con, returned_at()).unwrap();
+	assert_eq!(order.balance(), -5000);
+
+	let order = order.set_charges(&con, 5000, 2000).unwrap();
+	assert_eq!(order.balance(), -3000);
+
+	Payment::new(&con, order.id, -3000, PaymentMethod::Cash, date(25), "Deposit back".to_string()).unwrap();
+	let order = Order::get_by_id(&con, order.id).unwrap();
+	assert_eq!(order.balance(), 0);
+	assert_eq!(Payment::get_for_order(&con, order.id).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_cancelled_order_owes_nothing() {
+	let con = test_connection();
+	let order = test_order(&con).set_charges(&con, 5000, 0).unwrap();
+
+	let order = order.cancel(&con).unwrap();
+
+	assert_eq!(order.status, OrderStatus::Cancelled);
+	assert_eq!(order.balance(), 0);
+    }
+
+    #[test]
+    fn test_set_charges_negative() {
+	let con = test_connection();
+	let order = test_order(&con);
+
+	assert!(order.set_charges(&con, -1, 0).is_err());
+    }
+
+    #[test]
+    fn test_payment_zero() {
+	let con = test_connection();
+	let order = test_order(&con);
+
+	assert!(Payment::new(&con, order.id, 0, PaymentMethod::Cash, date(20), String::new()).is_err());
+    }
+
+    #[test]
+    fn test_delete_payment() {
+	let con = test_connection();
+	let order = test_order(&con);
+	let payment = Payment::new(&con, order.id, 100, PaymentMethod::Other, date(20), String::new()).unwrap();
+
+	Payment::delete(&con, payment.id).unwrap();
+
+	assert_eq!(Payment::get_for_order(&con, order.id).unwrap(), vec![]);
+	assert!(Payment::delete(&con, payment.id).is_err());
+    }
+
+    #[test]
+    fn test_payment_method_parse() {
+	assert_eq!(PaymentMethod::parse("bank transfer"), Some(PaymentMethod::BankTransfer));
+	assert_eq!(PaymentMethod::parse("Card"), Some(PaymentMethod::Card));
+	assert_eq!(PaymentMethod::parse("cheque"), None);
+    }
+
+    #[test]
+    fn test_describe_balance() {
+	assert_eq!(describe_balance(0), "Paid".to_string());
+	assert_eq!(describe_balance(1250), "Owes $12.50".to_string());
+	assert_eq!(describe_balance(-500), "Refund $5.00".to_string());
+    }
+}
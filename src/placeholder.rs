@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+/// Checks every {placeholder} in the line is one of `names`.
+pub fn check_placeholders(line: &str, names: &[&str]) -> Result<(), String> {
+    let mut rest = line;
+
+    while let Some(start) = rest.find('{') {
+	let Some(length) = rest[start..].find('}') else {
+	    return Err("a { isn't closed with }".to_string());
+	};
+	let name = &rest[start + 1..start + length];
+	if !names.contains(&name) {
+	    return Err(format!("{{{}}} isn't a placeholder", name));
+	}
+	rest = &rest[start + length + 1..];
+    }
+
+    Ok(())
+}
+
+/// Fills in the placeholders in the line, or gives None if it has
+/// placeholders and they are all empty.
+pub fn fill(line: &str, values: &HashMap<&str, String>) -> Option<String> {
+    let mut filled = line.to_string();
+    let mut any_placeholders = false;
+    let mut any_values = false;
+
+    for (name, value) in values {
+	let placeholder = format!("{{{}}}", name);
+	if filled.contains(&placeholder) {
+	    any_placeholders = true;
+	    any_values |= !value.is_empty();
+	    filled = filled.replace(&placeholder, value);
+	}
+    }
+
+    if any_placeholders && !any_values {
+	return None;
+    }
+
+    Some(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_placeholders() {
+	let names = ["customer_name"];
+
+	assert_eq!(check_placeholders("Hi {customer_name}", &names), Ok(()));
+	assert_eq!(check_placeholders("Hi {name}", &names), Err("{name} isn't a placeholder".to_string()));
+	assert!(check_placeholders("Hi {customer_name", &names).is_err());
+    }
+
+    #[test]
+    fn test_fill() {
+	let values = HashMap::from([
+	    ("customer_name", "Test Person".to_string()),
+	    ("customer_phone", String::new()),
+	]);
+
+	assert_eq!(fill("Hi {customer_name}", &values), Some("Hi Test Person".to_string()));
+	assert_eq!(fill("Phone: {customer_phone}", &values), None);
+	assert_eq!(fill("", &values), Some(String::new()));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::raffle::RaffleDraw;
+use crate::error::Error;
+use crate::helpers::Form;
+use rusqlite::Connection;
+use chrono::NaiveDateTime;
+
+/// Represents a form to draw the raffle
+pub struct RaffleDrawForm {
+    pub prize_name: String,
+    pub prize_name_show_error: bool,
+    pub winners: String,
+    pub winners_show_error: bool,
+}
+
+impl Default for RaffleDrawForm {
+    fn default() -> Self {
+	Self {
+	    prize_name: String::new(),
+	    prize_name_show_error: false,
+	    winners: "1".to_string(),
+	    winners_show_error: false,
+	}
+    }
+}
+
+impl RaffleDrawForm {
+    /// Gets prize_name, or Err if it is invalid.
+    fn get_valid_prize_name(&self) -> Result<String, String> {
+	if self.prize_name.is_empty() {
+            return Err("Prize name is required".to_string());
+	} else if self.prize_name.len() > 50 {
+	    return Err("Prize name must be less than 50 characters".to_string());
+	}
+
+	Ok(self.prize_name.clone())
+    }
+
+    /// Gets winners as an integer, or Err if it is invalid.
+    fn get_valid_winners(&self) -> Result<usize, String> {
+	if self.winners.is_empty() {
+            return Err("Number of winners is required".to_string());
+	}
+
+	match self.winners.parse::<usize>() {
+	    Ok(num) => {
+		if num < 1 {
+		    return Err("Number of winners must be at least 1".to_string());
+		} else if num > 20 {
+		    return Err("Number of winners must not be more than 20".to_string());
+		}
+
+		Ok(num)
+	    },
+	    Err(_) => {
+		Err("Number of winners must be an integer".to_string())
+	    },
+	}
+    }
+
+    /// Gets the error for a given field if its corresponding *_show_error is set to true.
+    pub fn get_visible_field_error(&self, field: &str) -> Option<String> {
+	match field {
+	    "prize_name" if self.prize_name_show_error => {
+		if let Err(e) = self.get_valid_prize_name() {
+		    return Some(e);
+		}
+	    },
+	    "winners" if self.winners_show_error => {
+		if let Err(e) = self.get_valid_winners() {
+		    return Some(e);
+		}
+	    },
+	    _ => (),
+	};
+
+	None
+    }
+
+    /// Draws the raffle if all fields of the form are valid.
+    pub fn draw(&self, connection: &Connection, drawn_at: NaiveDateTime) -> Result<Vec<RaffleDraw>, Error> {
+	RaffleDraw::draw(
+	    connection,
+	    self.get_valid_prize_name().map_err(Error::Validation)?,
+	    self.get_valid_winners().map_err(Error::Validation)?,
+	    drawn_at,
+	)
+    }
+}
+
+impl Form for RaffleDrawForm {
+    /// Sets every *_show_error to true, so all invalid fields show their errors.
+    fn show_all_errors(&mut self) {
+	self.prize_name_show_error = true;
+	self.winners_show_error = true;
+    }
+
+    /// Checks if any field of the form is showing an error.
+    fn has_visible_errors(&self) -> bool {
+	["prize_name", "winners"]
+	    .iter()
+	    .any(|field| self.get_visible_field_error(field).is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::database::init_db_tables;
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn test_get_valid_prize_name() {
+	let form = RaffleDrawForm {
+	    prize_name: "Hamper".to_string(),
+	    ..Default::default()
+	};
+
+	assert!(form.get_valid_prize_name().is_ok());
+    }
+
+    #[test]
+    fn test_get_valid_prize_name_empty() {
+	let form = RaffleDrawForm::default();
+
+	assert!(form.get_valid_prize_name().is_err());
+    }
+
+    #[test]
+    fn test_get_valid_winners() {
+	let form = RaffleDrawForm::default();
+
+	assert_eq!(form.get_valid_winners(), Ok(1));
+    }
+
+    #[test]
+    fn test_get_valid_winners_invalid() {
+	let mut form = RaffleDrawForm::default();
+
+	for winners in ["", "0", "21", "-1", "one"] {
+	    form.winners = winners.to_string();
+	    assert!(form.get_valid_winners().is_err());
+	}
+    }
+
+    #[test]
+    fn test_form_draw_no_entrants() {
+	let form = RaffleDrawForm {
+	    prize_name: "Hamper".to_string(),
+	    ..Default::default()
+	};
+
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let drawn_at = NaiveDate::from_ymd_opt(2025, 4, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+	assert!(form.draw(&con, drawn_at).is_err());
+    }
+}
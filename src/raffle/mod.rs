@@ -0,0 +1,322 @@
+pub mod form;
+pub mod table;
+
+use rusqlite::{params, Connection, Row};
+use chrono::NaiveDateTime;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use crate::error::Error;
+
+pub use self::form::RaffleDrawForm;
+
+/// Represents one winner picked in a raffle draw. A draw with several winners
+/// stores a RaffleDraw for each, sharing the same time and seed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RaffleDraw {
+    pub id: i32,
+    pub drawn_at: NaiveDateTime,
+    pub prize_name: String,
+    pub winner_order_id: i32,
+    pub seed: i64,
+    // Which winner of the draw this is, starting at 1
+    pub position: i32,
+    // The winning order's details, or None if it is no longer in the database
+    pub winner_name: Option<String>,
+    pub winner_raffle_number: Option<i32>,
+}
+
+impl RaffleDraw {
+    /// Draws winners out of every order that isn't cancelled and hasn't won
+    /// before, and records them in the database.
+    pub fn draw(
+	connection: &Connection,
+	prize_name: String,
+	winners: usize,
+	drawn_at: NaiveDateTime,
+    ) -> Result<Vec<Self>, Error> {
+	let tx = connection.unchecked_transaction()?;
+
+	let entrants = get_entrants(&tx, None, drawn_at)?;
+	if winners > entrants.len() {
+	    return Err(Error::Validation(format!(
+		"Can't draw {} winner(s), only {} order(s) can still win",
+		winners,
+		entrants.len(),
+	    )));
+	}
+
+	// Kept positive so it fits in an SQLite INTEGER
+	let seed = rand::rng().random_range(0..=i64::MAX);
+
+	let mut ids = Vec::new();
+	for (position, winner) in pick_winners(&entrants, seed, winners).into_iter().enumerate() {
+	    tx.execute(
+		"INSERT INTO RaffleDraw (
+                    drawn_at,
+                    prize_name,
+                    winner_order_id,
+                    seed,
+                    position) VALUES (?1, ?2, ?3, ?4, ?5)",
+		params![
+		    drawn_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+		    prize_name,
+		    winner,
+		    seed,
+		    position as i32 + 1,
+		],
+	    )?;
+	    ids.push(tx.last_insert_rowid() as i32);
+	}
+
+	tx.commit()?;
+
+	ids.into_iter()
+	    .map(|id| Self::get_by_id(connection, id))
+	    .collect()
+    }
+
+    /// Retrieves a draw from the database by its id.
+    pub fn get_by_id(connection: &Connection, id: i32) -> Result<Self, Error> {
+	let mut stmt = connection.prepare(
+	    format!("{} WHERE RaffleDraw.id = ?1", DRAW_SELECT).as_str()
+	)?;
+
+	Ok(stmt.query_row([id], Self::from_row)?)
+    }
+
+    /// Gets every draw, newest first.
+    pub fn get_all(connection: &Connection) -> Result<Vec<Self>, Error> {
+	let mut stmt = connection.prepare(
+	    format!("{} ORDER BY RaffleDraw.id DESC", DRAW_SELECT).as_str()
+	)?;
+
+	let draws = stmt.query_map([], Self::from_row)?.collect::<Result<_, _>>()?;
+
+	Ok(draws)
+    }
+
+    /// Re-runs the draw this winner came from using its stored seed, returning
+    /// the order ids it picks. These match the recorded winners as long as no
+    /// entrant has been added, cancelled or purged since. Orders deleted after
+    /// the draw still count, as they were entrants when it was drawn.
+    pub fn replay(&self, connection: &Connection) -> Result<Vec<i32>, Error> {
+	let (first_id, winners): (i32, usize) = connection.query_row(
+	    "SELECT MIN(id), COUNT(*) FROM RaffleDraw WHERE seed = ?1 AND drawn_at = ?2",
+	    params![self.seed, self.drawn_at.format("%Y-%m-%d %H:%M:%S").to_string()],
+	    |row| Ok((row.get(0)?, row.get(1)?)),
+	)?;
+
+	let entrants = get_entrants(connection, Some(first_id), self.drawn_at)?;
+
+	Ok(pick_winners(&entrants, self.seed, winners))
+    }
+
+    /// Gets the order ids recorded as winners of the draw this winner came from.
+    pub fn get_draw_winners(&self, connection: &Connection) -> Result<Vec<i32>, Error> {
+	let mut stmt = connection.prepare(
+	    "SELECT winner_order_id FROM RaffleDraw
+             WHERE seed = ?1 AND drawn_at = ?2 ORDER BY position"
+	)?;
+
+	let winners = stmt.query_map(
+	    params![self.seed, self.drawn_at.format("%Y-%m-%d %H:%M:%S").to_string()],
+	    |row| row.get(0),
+	)?.collect::<Result<_, _>>()?;
+
+	Ok(winners)
+    }
+
+    /// Builds a RaffleDraw from a row selected with DRAW_SELECT.
+    fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
+	Ok(Self {
+	    id: row.get(0)?,
+	    drawn_at: row.get(1)?,
+	    prize_name: row.get(2)?,
+	    winner_order_id: row.get(3)?,
+	    seed: row.get(4)?,
+	    position: row.get(5)?,
+	    winner_name: row.get(6)?,
+	    winner_raffle_number: row.get(7)?,
+	})
+    }
+}
+
+/// Selects draws along with the details of the winning order.
+const DRAW_SELECT: &str = "SELECT RaffleDraw.id, drawn_at, prize_name, winner_order_id, seed,
+	position, Customer.name, CustomerOrder.raffle_number
+    FROM RaffleDraw LEFT JOIN CustomerOrder ON CustomerOrder.id = winner_order_id
+    LEFT JOIN Customer ON Customer.id = CustomerOrder.customer_id";
+
+/// Gets the ids of the orders that can win a draw at `drawn_at`, in id order.
+/// When `before_draw` is given, only winners from draws recorded before it are
+/// left out.
+fn get_entrants(
+    connection: &Connection,
+    before_draw: Option<i32>,
+    drawn_at: NaiveDateTime,
+) -> Result<Vec<i32>, Error> {
+    let mut stmt = connection.prepare(
+	"SELECT id FROM CustomerOrder
+	 WHERE status != 'Cancelled' AND (deleted_at IS NULL OR deleted_at > ?2)
+         AND id NOT IN (SELECT winner_order_id FROM RaffleDraw WHERE ?1 IS NULL OR id < ?1)
+         ORDER BY id"
+    )?;
+
+    let entrants = stmt.query_map(
+	params![before_draw, drawn_at.format("%Y-%m-%d %H:%M:%S").to_string()],
+	|row| row.get(0),
+    )?.collect::<Result<_, _>>()?;
+
+    Ok(entrants)
+}
+
+/// Picks `count` different winners out of the entrants using the seed. The
+/// same entrants and seed always give the same winners. ChaCha8 and raw u64s
+/// are used, rather than rand's helpers, so that updating rand can't change
+/// the result of old draws.
+pub fn pick_winners(entrants: &[i32], seed: i64, count: usize) -> Vec<i32> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed as u64);
+    let mut remaining = entrants.to_vec();
+    let mut winners = Vec::new();
+
+    while winners.len() < count && !remaining.is_empty() {
+	let index = (rng.next_u64() % remaining.len() as u64) as usize;
+	winners.push(remaining.remove(index));
+    }
+
+    winners
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+    use crate::order::Order;
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    /// Makes a database with the given number of orders in it.
+    fn test_database(orders: i32) -> Connection {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	for i in 0..orders {
+	    Order::new(
+		&con,
+		format!("Person {}", i),
+		i as i64,
+		vec![("Test Item".to_string(), 5)],
+		NaiveDate::from_ymd_opt(2025, 3, 23).unwrap(),
+		NaiveDate::from_ymd_opt(2025, 3, 25).unwrap(),
+	    ).unwrap();
+	}
+
+	con
+    }
+
+    fn drawn_at() -> NaiveDateTime {
+	NaiveDate::from_ymd_opt(2025, 4, 1).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_pick_winners_same_seed_same_winners() {
+	let entrants: Vec<i32> = (1..=50).collect();
+
+	assert_eq!(pick_winners(&entrants, 1234, 3), pick_winners(&entrants, 1234, 3));
+    }
+
+    #[test]
+    fn test_pick_winners_are_unique_entrants() {
+	let entrants: Vec<i32> = (1..=10).collect();
+	let mut winners = pick_winners(&entrants, 99, 10);
+
+	winners.sort();
+	assert_eq!(winners, entrants);
+    }
+
+    #[test]
+    fn test_pick_winners_not_enough_entrants() {
+	assert_eq!(pick_winners(&[7], 5, 3), vec![7]);
+	assert!(pick_winners(&[], 5, 3).is_empty());
+    }
+
+    #[test]
+    fn test_draw() {
+	let con = test_database(5);
+
+	let draws = RaffleDraw::draw(&con, "Hamper".to_string(), 2, drawn_at()).unwrap();
+
+	assert_eq!(draws.len(), 2);
+	assert_eq!(draws[0].position, 1);
+	assert_eq!(draws[1].position, 2);
+	assert_eq!(draws[0].seed, draws[1].seed);
+	assert_ne!(draws[0].winner_order_id, draws[1].winner_order_id);
+	assert_eq!(draws[0].prize_name, "Hamper".to_string());
+	assert!(draws[0].winner_name.is_some());
+	assert_eq!(RaffleDraw::get_all(&con).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_draw_excludes_cancelled_and_past_winners() {
+	let con = test_database(3);
+
+	Order::get_by_id(&con, 1).unwrap().cancel(&con).unwrap();
+	let first = RaffleDraw::draw(&con, "First".to_string(), 1, drawn_at()).unwrap();
+	let second = RaffleDraw::draw(&con, "Second".to_string(), 1, drawn_at()).unwrap();
+
+	let mut winners = vec![first[0].winner_order_id, second[0].winner_order_id];
+	winners.sort();
+	assert_eq!(winners, vec![2, 3]);
+
+	assert!(matches!(
+	    RaffleDraw::draw(&con, "Third".to_string(), 1, drawn_at()),
+	    Err(Error::Validation(_))
+	));
+    }
+
+    #[test]
+    fn test_draw_replay() {
+	let con = test_database(20);
+
+	RaffleDraw::draw(&con, "First".to_string(), 2, drawn_at()).unwrap();
+	let draws = RaffleDraw::draw(&con, "Second".to_string(), 3, drawn_at()).unwrap();
+
+	let recorded: Vec<i32> = draws.iter().map(|d| d.winner_order_id).collect();
+	assert_eq!(draws[1].get_draw_winners(&con).unwrap(), recorded);
+	assert_eq!(draws[1].replay(&con).unwrap(), recorded);
+    }
+
+    #[test]
+    fn test_purge_keeps_deleted_winner() {
+	let con = test_database(5);
+	let deleted_at = drawn_at() + chrono::Days::new(1);
+
+	let draws = RaffleDraw::draw(&con, "Hamper".to_string(), 2, drawn_at()).unwrap();
+	let recorded: Vec<i32> = draws.iter().map(|d| d.winner_order_id).collect();
+	let loser = (1..=5).find(|id| !recorded.contains(id)).unwrap();
+	for id in [recorded[0], loser] {
+	    Order::get_by_id(&con, id).unwrap().delete(&con, deleted_at).unwrap();
+	}
+
+	assert_eq!(Order::purge_deleted(&con, deleted_at + chrono::Days::new(365)).unwrap(), 1);
+
+	let draw = RaffleDraw::get_by_id(&con, draws[0].id).unwrap();
+	assert_eq!(draw.winner_name, Some(format!("Person {}", recorded[0] - 1)));
+	assert!(draw.winner_raffle_number.is_some());
+	assert_eq!(draw.get_draw_winners(&con).unwrap(), recorded);
+	assert_eq!(Order::get_deleted(&con).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_draw_replay_after_winner_deleted() {
+	let con = test_database(20);
+
+	let draws = RaffleDraw::draw(&con, "Hamper".to_string(), 3, drawn_at()).unwrap();
+	let recorded: Vec<i32> = draws.iter().map(|d| d.winner_order_id).collect();
+	Order::get_by_id(&con, recorded[1]).unwrap()
+	    .delete(&con, drawn_at() + chrono::Days::new(1)).unwrap();
+
+	assert_eq!(draws[0].replay(&con).unwrap(), recorded);
+    }
+}
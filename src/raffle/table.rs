@@ -0,0 +1,87 @@
+use iced::{Element, Length, Theme, Renderer};
+use iced::widget::{container, text, button};
+use iced_table::table;
+use crate::app::Message;
+use crate::raffle::RaffleDraw;
+
+/// Represents a column for the past raffle draws table.
+pub struct RaffleDrawColumn {
+    pub kind: RaffleDrawColumnKind,
+    pub width: f32,
+    pub resize_offset: Option<f32>,
+}
+
+impl RaffleDrawColumn {
+    pub fn new(kind: RaffleDrawColumnKind) -> Self {
+	let width = match kind {
+	    RaffleDrawColumnKind::DrawnAt => 160.0,
+	    RaffleDrawColumnKind::PrizeName => 200.0,
+	    RaffleDrawColumnKind::Winner => 200.0,
+	    RaffleDrawColumnKind::RaffleNumber => 120.0,
+	    RaffleDrawColumnKind::Seed => 180.0,
+	    RaffleDrawColumnKind::Verify => 100.0,
+	};
+
+	Self {
+	    kind,
+	    width,
+	    resize_offset: None,
+	}
+    }
+}
+
+/// Represents what the field the column corrosponds to.
+pub enum RaffleDrawColumnKind {
+    DrawnAt,
+    PrizeName,
+    Winner,
+    RaffleNumber,
+    Seed,
+    Verify,
+}
+
+impl<'a> table::Column<'a, Message, Theme, Renderer> for RaffleDrawColumn {
+    type Row = RaffleDraw;
+
+    fn header(&'a self, _col_size: usize) -> Element<'a, Message> {
+	let content = match self.kind {
+	    RaffleDrawColumnKind::DrawnAt => "Drawn At",
+	    RaffleDrawColumnKind::PrizeName => "Prize",
+	    RaffleDrawColumnKind::Winner => "Winner",
+	    RaffleDrawColumnKind::RaffleNumber => "Raffle Number",
+	    RaffleDrawColumnKind::Seed => "Seed",
+	    RaffleDrawColumnKind::Verify => "",
+	};
+
+	container(text(content)).center_y(24).into()
+    }
+
+    fn cell(&'a self, _col_index: usize, _row_index: usize, row: &'a RaffleDraw) -> Element<'a, Message> {
+	let content: Element<_> = match self.kind {
+	    RaffleDrawColumnKind::DrawnAt => text(row.drawn_at.format("%Y-%m-%d %H:%M").to_string()).into(),
+	    RaffleDrawColumnKind::PrizeName => text(row.prize_name.clone()).into(),
+	    RaffleDrawColumnKind::Winner => match &row.winner_name {
+		Some(name) => text(name.clone()).into(),
+		None => text(format!("Deleted order #{}", row.winner_order_id)).into(),
+	    },
+	    RaffleDrawColumnKind::RaffleNumber => match row.winner_raffle_number {
+		Some(number) => text(number.to_string()).into(),
+		None => text("").into(),
+	    },
+	    RaffleDrawColumnKind::Seed => text(row.seed.to_string()).into(),
+	    RaffleDrawColumnKind::Verify => button(text("Re-run"))
+		.on_press(Message::VerifyRaffleDraw(row.id))
+		.into(),
+	};
+
+	container(content).width(Length::Fill).center_y(32).into()
+    }
+
+    fn width(&self) -> f32 {
+	self.width
+    }
+
+    fn resize_offset(&self) -> Option<f32> {
+	self.resize_offset
+    }
+}
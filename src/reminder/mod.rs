@@ -0,0 +1,533 @@
+pub mod template;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::{params, Connection, Row};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use crate::customer::Customer;
+use crate::database::db_dir;
+use crate::error::Error;
+use crate::order::{Order, OrderQuery};
+use crate::order::query::{OrderSortField, SortDirection, StatusFilter};
+use crate::settings::REMINDER_INTERVAL_DAYS;
+
+use self::template::{ReminderTemplate, DEFAULT_OVERDUE_TEMPLATE, DEFAULT_PICKUP_TEMPLATE, DEFAULT_RETURN_TEMPLATE};
+
+/// How dates are written in a reminder, e.g. "Thursday 20 March 2025".
+const DATE_FORMAT: &str = "%A %-d %B %Y";
+
+/// What a reminder is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReminderKind {
+    /// An order is about to be picked up.
+    Pickup,
+    /// An order is about to be due back.
+    Return,
+    /// An order should have come back and hasn't.
+    Overdue,
+}
+
+impl ReminderKind {
+    /// Gets the value stored in the database for the kind.
+    pub fn as_str(&self) -> &'static str {
+	match self {
+	    ReminderKind::Pickup => "Pickup",
+	    ReminderKind::Return => "Return",
+	    ReminderKind::Overdue => "Overdue",
+	}
+    }
+
+    /// Parses a kind stored in the database, or None if it is unknown.
+    pub fn parse(kind: &str) -> Option<Self> {
+	match kind {
+	    "Pickup" => Some(ReminderKind::Pickup),
+	    "Return" => Some(ReminderKind::Return),
+	    "Overdue" => Some(ReminderKind::Overdue),
+	    _ => None,
+	}
+    }
+
+    /// Gets the name of the template file for the kind, kept next to the
+    /// database.
+    pub fn template_file_name(&self) -> &'static str {
+	match self {
+	    ReminderKind::Pickup => "pickup-reminder-template.txt",
+	    ReminderKind::Return => "return-reminder-template.txt",
+	    ReminderKind::Overdue => "reminder-template.txt",
+	}
+    }
+
+    /// Gets the template used when there isn't one next to the database.
+    pub fn default_template(&self) -> &'static str {
+	match self {
+	    ReminderKind::Pickup => DEFAULT_PICKUP_TEMPLATE,
+	    ReminderKind::Return => DEFAULT_RETURN_TEMPLATE,
+	    ReminderKind::Overdue => DEFAULT_OVERDUE_TEMPLATE,
+	}
+    }
+}
+
+impl fmt::Display for ReminderKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql for ReminderKind {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+	Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ReminderKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+	let kind = value.as_str()?;
+
+	ReminderKind::parse(kind).ok_or_else(|| {
+	    FromSqlError::Other(format!("Unknown reminder kind: {}", kind).into())
+	})
+    }
+}
+
+/// How a reminder was sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReminderMethod {
+    /// An email saved to a file, to be opened and sent from an email program.
+    EmailFile,
+    /// A plain-text file, to be read out over the phone or printed and posted.
+    TextFile,
+    /// An email sent by the app.
+    Email,
+}
+
+impl ReminderMethod {
+    /// The ways reminders can be written to files.
+    pub const FILES: [ReminderMethod; 2] = [ReminderMethod::EmailFile, ReminderMethod::TextFile];
+
+    /// Gets the value stored in the database for the method.
+    pub fn as_str(&self) -> &'static str {
+	match self {
+	    ReminderMethod::EmailFile => "Email file",
+	    ReminderMethod::TextFile => "Text file",
+	    ReminderMethod::Email => "Email",
+	}
+    }
+
+    /// Parses a method stored in the database, or None if it is unknown.
+    pub fn parse(method: &str) -> Option<Self> {
+	match method {
+	    "Email file" => Some(ReminderMethod::EmailFile),
+	    "Text file" => Some(ReminderMethod::TextFile),
+	    "Email" => Some(ReminderMethod::Email),
+	    _ => None,
+	}
+    }
+}
+
+impl fmt::Display for ReminderMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql for ReminderMethod {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+	Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ReminderMethod {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+	let method = value.as_str()?;
+
+	ReminderMethod::parse(method).ok_or_else(|| {
+	    FromSqlError::Other(format!("Unknown reminder method: {}", method).into())
+	})
+    }
+}
+
+/// Represents a reminder sent to a customer about an order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reminder {
+    pub id: i32,
+    pub order_id: i32,
+    pub kind: ReminderKind,
+    pub sent_at: NaiveDateTime,
+    pub method: ReminderMethod,
+}
+
+impl Reminder {
+    /// Records that a reminder about the order was sent.
+    pub fn record(
+	connection: &Connection,
+	order_id: i32,
+	kind: ReminderKind,
+	sent_at: NaiveDateTime,
+	method: ReminderMethod,
+    ) -> Result<Self, Error> {
+	connection.execute(
+	    "INSERT INTO Reminder (order_id, kind, sent_at, method) VALUES (?1, ?2, ?3, ?4)",
+	    params![order_id, kind, sent_at, method],
+	)?;
+
+	Ok(Self { id: connection.last_insert_rowid() as i32, order_id, kind, sent_at, method })
+    }
+
+    /// Gets the reminders sent about the order, newest first.
+    pub fn get_for_order(connection: &Connection, order_id: i32) -> Result<Vec<Self>, Error> {
+	let mut stmt = connection.prepare(
+	    "SELECT id, order_id, kind, sent_at, method FROM Reminder
+	     WHERE order_id = ?1 ORDER BY sent_at DESC, id DESC"
+	)?;
+
+	let reminders = stmt.query_map([order_id], Self::from_row)?.collect::<Result<_, _>>()?;
+
+	Ok(reminders)
+    }
+
+    fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
+	Ok(Self {
+	    id: row.get(0)?,
+	    order_id: row.get(1)?,
+	    kind: row.get(2)?,
+	    sent_at: row.get(3)?,
+	    method: row.get(4)?,
+	})
+    }
+}
+
+/// Represents an order that should have come back and hasn't, and who to
+/// chase about it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverdueOrder {
+    pub order: Order,
+    pub customer: Customer,
+    pub days_overdue: i64,
+    pub last_reminded: Option<NaiveDateTime>,
+}
+
+impl OverdueOrder {
+    /// Gets every order that was due back before `today` and isn't back, most
+    /// overdue first.
+    pub fn get_all(connection: &Connection, today: NaiveDate) -> Result<Vec<Self>, Error> {
+	let query = OrderQuery {
+	    status: Some(StatusFilter::Overdue),
+	    sort: Some((OrderSortField::ReturnOn, SortDirection::Ascending)),
+	    ..Default::default()
+	};
+	let orders = Order::query_as_of(connection, &query, today)?;
+
+	let customers: HashMap<i32, Customer> = Customer::get_all(connection)?
+	    .into_iter()
+	    .map(|customer| (customer.id, customer))
+	    .collect();
+	let mut stmt = connection.prepare(
+	    "SELECT order_id, MAX(sent_at) FROM Reminder WHERE kind = ?1 GROUP BY order_id"
+	)?;
+	let last_reminded: HashMap<i32, NaiveDateTime> = stmt
+	    .query_map([ReminderKind::Overdue], |row| Ok((row.get(0)?, row.get(1)?)))?
+	    .collect::<Result<_, _>>()?;
+
+	orders.into_iter().map(|order| {
+	    let customer = customers.get(&order.customer_id).cloned()
+		.ok_or(Error::Database(rusqlite::Error::QueryReturnedNoRows))?;
+
+	    Ok(Self {
+		days_overdue: (today - order.return_on).num_days(),
+		last_reminded: last_reminded.get(&order.id).copied(),
+		customer,
+		order,
+	    })
+	}).collect()
+    }
+
+    /// Checks if the customer hasn't been reminded about the order in the
+    /// last `interval_days` days.
+    pub fn needs_reminder(&self, now: NaiveDateTime, interval_days: i64) -> bool {
+	match self.last_reminded {
+	    Some(last_reminded) => (now - last_reminded).num_days() >= interval_days,
+	    None => true,
+	}
+    }
+
+}
+
+/// Gets the value of each placeholder in a reminder template, for a reminder
+/// about the order sent on `today`.
+pub fn reminder_values(order: &Order, customer: &Customer, today: NaiveDate) -> HashMap<&'static str, String> {
+    let items: Vec<String> = order.lines.iter()
+	.map(|line| format!("  {} x {}", line.how_many, line.item_hired))
+	.collect();
+
+    HashMap::from([
+	("customer_name", customer.name.clone()),
+	("customer_phone", customer.phone.clone()),
+	("customer_email", customer.email.clone()),
+	("order_id", order.id.to_string()),
+	("receipt_number", order.receipt_number.to_string()),
+	("hired_on", order.hired_on.format(DATE_FORMAT).to_string()),
+	("return_on", order.return_on.format(DATE_FORMAT).to_string()),
+	("days_overdue", (today - order.return_on).num_days().max(0).to_string()),
+	("items", items.join("\n")),
+	("total_items", order.how_many().to_string()),
+	("today", today.format(DATE_FORMAT).to_string()),
+    ])
+}
+
+/// Represents a reminder that was written to a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WrittenReminder {
+    pub order_id: i32,
+    pub customer_name: String,
+    pub path: PathBuf,
+    pub method: ReminderMethod,
+}
+
+/// Gets the template for the kind of reminder next to the database, writing
+/// the default one there first if there isn't one. A database only in memory
+/// uses the default.
+pub fn load_template(connection: &Connection, kind: ReminderKind) -> Result<ReminderTemplate, Error> {
+    let Some(dir) = db_dir(connection) else {
+	return ReminderTemplate::parse(kind.default_template()).map_err(Error::Parse);
+    };
+    let path = dir.join(kind.template_file_name());
+
+    let template = match fs::read_to_string(&path) {
+	Ok(template) => template,
+	Err(e) if e.kind() == io::ErrorKind::NotFound => {
+	    fs::write(&path, kind.default_template())?;
+	    kind.default_template().to_string()
+	},
+	Err(e) => return Err(e.into()),
+    };
+
+    ReminderTemplate::parse(&template)
+	.map_err(|message| Error::Parse(format!("{}, {}", path.display(), message)))
+}
+
+/// Gets where reminders are written if no folder is given, which is the
+/// reminders folder next to the database.
+pub fn default_reminder_dir(connection: &Connection) -> PathBuf {
+    match db_dir(connection) {
+	Some(dir) => dir.join("reminders"),
+	None => std::env::temp_dir().join("reminders"),
+    }
+}
+
+/// Writes a reminder for each of the orders whose customer hasn't been
+/// reminded lately, into `dir` or the default folder if None, and records
+/// that they were sent. Customers with no email address get a text file even
+/// if an email is asked for. Gives back the reminders written.
+pub fn write_reminders(
+    connection: &Connection,
+    overdue: &[OverdueOrder],
+    method: ReminderMethod,
+    dir: Option<&Path>,
+    now: NaiveDateTime,
+) -> Result<Vec<WrittenReminder>, Error> {
+    let template = load_template(connection, ReminderKind::Overdue)?;
+    let interval_days = REMINDER_INTERVAL_DAYS.get(connection)?;
+    let dir = match dir {
+	Some(dir) => dir.to_path_buf(),
+	None => default_reminder_dir(connection),
+    };
+    fs::create_dir_all(&dir)?;
+
+    let mut written = Vec::new();
+    for overdue_order in overdue.iter().filter(|overdue_order| overdue_order.needs_reminder(now, interval_days)) {
+	let customer = &overdue_order.customer;
+	let (subject, body) = template.render(&reminder_values(&overdue_order.order, customer, now.date()));
+	let (method, extension, contents) = match method {
+	    ReminderMethod::EmailFile if !customer.email.is_empty() => {
+		(method, "eml", email_file(customer, &subject, &body))
+	    },
+	    _ => (ReminderMethod::TextFile, "txt", text_file(customer, &subject, &body)),
+	};
+	let path = dir.join(format!(
+	    "reminder-{}-{}-{}.{}",
+	    overdue_order.order.receipt_number,
+	    overdue_order.order.id,
+	    now.format("%Y-%m-%d"),
+	    extension,
+	));
+	fs::write(&path, contents)?;
+	Reminder::record(connection, overdue_order.order.id, ReminderKind::Overdue, now, method)?;
+
+	written.push(WrittenReminder {
+	    order_id: overdue_order.order.id,
+	    customer_name: customer.name.clone(),
+	    path,
+	    method,
+	});
+    }
+
+    Ok(written)
+}
+
+/// Makes an email that opens as a draft ready to send in most email programs.
+fn email_file(customer: &Customer, subject: &str, body: &str) -> String {
+    let name = customer.name.replace('\\', "\\\\").replace('"', "\\\"");
+    let headers = [
+	format!("To: \"{}\" <{}>", name, customer.email),
+	format!("Subject: {}", subject),
+	"X-Unsent: 1".to_string(),
+	"MIME-Version: 1.0".to_string(),
+	"Content-Type: text/plain; charset=utf-8".to_string(),
+	"Content-Transfer-Encoding: 8bit".to_string(),
+    ];
+
+    // Email lines end with CRLF
+    format!("{}\r\n\r\n{}\r\n", headers.join("\r\n"), body.replace('\n', "\r\n"))
+}
+
+/// Makes a reminder to read out or print, with how to get in touch at the top.
+fn text_file(customer: &Customer, subject: &str, body: &str) -> String {
+    let contact: Vec<&str> = [customer.phone.as_str(), customer.email.as_str(), customer.address.as_str()]
+	.into_iter()
+	.filter(|detail| !detail.is_empty())
+	.collect();
+    let to = match contact.is_empty() {
+	true => customer.name.clone(),
+	false => format!("{} ({})", customer.name, contact.join(", ")),
+    };
+
+    format!("To: {}\nSubject: {}\n\n{}\n", to, subject, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Days;
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+	NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    fn test_order(con: &Connection, customer_name: &str, hired_on: NaiveDate, return_on: NaiveDate) -> Order {
+	Order::new(
+	    con,
+	    customer_name.to_string(),
+	    15,
+	    vec![("Chairs".to_string(), 10), ("Tables".to_string(), 2)],
+	    hired_on,
+	    return_on,
+	).unwrap()
+    }
+
+    #[test]
+    fn test_get_all() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let today = date(3, 20);
+	let late = test_order(&con, "Late Person", date(3, 10), date(3, 18)).mark_out(&con).unwrap();
+	let later = test_order(&con, "Later Person", date(3, 1), date(3, 5)).mark_out(&con).unwrap();
+	test_order(&con, "Due Person", date(3, 18), today).mark_out(&con).unwrap();
+	test_order(&con, "Back Person", date(3, 1), date(3, 5))
+	    .mark_out(&con).unwrap()
+	    .mark_returned(&con, date(3, 6).and_hms_opt(9, 0, 0).unwrap()).unwrap();
+	let reminded_at = date(3, 19).and_hms_opt(10, 0, 0).unwrap();
+	Reminder::record(&con, later.id, ReminderKind::Overdue, reminded_at, ReminderMethod::TextFile).unwrap();
+	// Reminding them it was due back doesn't count
+	Reminder::record(&con, late.id, ReminderKind::Return, reminded_at, ReminderMethod::Email).unwrap();
+
+	let overdue = OverdueOrder::get_all(&con, today).unwrap();
+
+	assert_eq!(overdue.len(), 2);
+	assert_eq!(overdue[0].order, later);
+	assert_eq!(overdue[0].days_overdue, 15);
+	assert_eq!(overdue[0].last_reminded, Some(reminded_at));
+	assert_eq!(overdue[1].order, late);
+	assert_eq!(overdue[1].customer.name, "Late Person");
+	assert_eq!(overdue[1].last_reminded, None);
+    }
+
+    #[test]
+    fn test_needs_reminder() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	test_order(&con, "Late Person", date(3, 10), date(3, 18));
+	let mut overdue = OverdueOrder::get_all(&con, date(3, 20)).unwrap().remove(0);
+	let now = date(3, 20).and_hms_opt(12, 0, 0).unwrap();
+
+	assert!(overdue.needs_reminder(now, 7));
+	overdue.last_reminded = Some(now - Days::new(6));
+	assert!(!overdue.needs_reminder(now, 7));
+	overdue.last_reminded = Some(now - Days::new(7));
+	assert!(overdue.needs_reminder(now, 7));
+    }
+
+    #[test]
+    fn test_write_reminders() {
+	let con = Connection::open_in_memory().unwrap();
+	let dir = std::env::temp_dir().join(format!("as91896-reminders-{}", std::process::id()));
+
+	init_db_tables(&con).unwrap();
+
+	let emailed = test_order(&con, "Emailed Person", date(3, 10), date(3, 18));
+	let customer = Customer::get_by_id(&con, emailed.customer_id).unwrap();
+	customer.update(
+	    &con,
+	    customer.name.clone(),
+	    "021 123 4567".to_string(),
+	    "emailed@example.com".to_string(),
+	    String::new(),
+	    String::new(),
+	).unwrap();
+	let phoned = test_order(&con, "Phoned Person", date(3, 10), date(3, 17));
+	let now = date(3, 20).and_hms_opt(12, 0, 0).unwrap();
+
+	let overdue = OverdueOrder::get_all(&con, now.date()).unwrap();
+	let written = write_reminders(&con, &overdue, ReminderMethod::EmailFile, Some(&dir), now).unwrap();
+
+	assert_eq!(written.len(), 2);
+	assert_eq!(written[0].order_id, phoned.id);
+	assert_eq!(written[0].method, ReminderMethod::TextFile);
+	assert_eq!(written[1].method, ReminderMethod::EmailFile);
+
+	let text = fs::read_to_string(&written[0].path).unwrap();
+	assert!(text.starts_with("To: Phoned Person\nSubject: Your hire is overdue (receipt 15)\n\nHi Phoned Person,"));
+	assert!(text.contains("which is 3 day(s) ago"));
+	assert!(text.contains("  10 x Chairs\n  2 x Tables\n"));
+
+	let email = fs::read_to_string(&written[1].path).unwrap();
+	assert!(email.starts_with("To: \"Emailed Person\" <emailed@example.com>\r\nSubject: Your hire is overdue"));
+	assert!(email.contains("\r\n\r\nHi Emailed Person,\r\n"));
+
+	assert_eq!(Reminder::get_for_order(&con, emailed.id).unwrap()[0].method, ReminderMethod::EmailFile);
+
+	// Nobody is reminded twice within the interval
+	let overdue = OverdueOrder::get_all(&con, now.date()).unwrap();
+	let later = now + Days::new(REMINDER_INTERVAL_DAYS.default as u64 - 1);
+	assert!(write_reminders(&con, &overdue, ReminderMethod::EmailFile, Some(&dir), later).unwrap().is_empty());
+	let later = now + Days::new(REMINDER_INTERVAL_DAYS.default as u64);
+	assert_eq!(write_reminders(&con, &overdue, ReminderMethod::TextFile, Some(&dir), later).unwrap().len(), 2);
+	assert_eq!(Reminder::get_for_order(&con, phoned.id).unwrap().len(), 2);
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_purge_removes_reminders() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let order = test_order(&con, "Late Person", date(3, 10), date(3, 18));
+	let now = date(3, 20).and_hms_opt(12, 0, 0).unwrap();
+	Reminder::record(&con, order.id, ReminderKind::Overdue, now, ReminderMethod::EmailFile).unwrap();
+	order.clone().delete(&con, now).unwrap();
+
+	assert_eq!(Order::purge_deleted(&con, now + Days::new(365)).unwrap(), 1);
+	assert!(Reminder::get_for_order(&con, order.id).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use crate::placeholder::{check_placeholders, fill};
+
+/// The templates used when there isn't one next to the database, which are
+/// written there so they can be changed.
+pub const DEFAULT_OVERDUE_TEMPLATE: &str = "\
+# The wording of the reminders for overdue orders. Change it, then write or
+# send the reminders again to see the change.
+#
+# The first line is the subject, and the rest is the message. A line whose
+# {placeholders} are all empty, like a customer with no phone number, is left
+# out. The placeholders are:
+#   {customer_name} {customer_phone} {customer_email} {order_id}
+#   {receipt_number} {hired_on} {return_on} {days_overdue} {items}
+#   {total_items} {today}
+# {items} is a list of the items on the order, one on each line.
+# Lines starting with # are ignored.
+
+Subject: Your hire is overdue (receipt {receipt_number})
+
+Hi {customer_name},
+
+The items you hired from us on {hired_on} were due back on {return_on},
+which is {days_overdue} day(s) ago. They are:
+
+{items}
+
+Please bring them back as soon as you can, or let us know if there is a
+problem. If you have already returned them, thank you, and please ignore this
+message.
+";
+
+pub const DEFAULT_PICKUP_TEMPLATE: &str = "\
+# The wording of the reminders emailed before orders are picked up.
+#
+# The first line is the subject, and the rest is the message. A line whose
+# {placeholders} are all empty, like a customer with no phone number, is left
+# out. The placeholders are:
+#   {customer_name} {customer_phone} {customer_email} {order_id}
+#   {receipt_number} {hired_on} {return_on} {days_overdue} {items}
+#   {total_items} {today}
+# {items} is a list of the items on the order, one on each line.
+# Lines starting with # are ignored.
+
+Subject: Your hire is ready to pick up on {hired_on} (receipt {receipt_number})
+
+Hi {customer_name},
+
+Just a reminder that your hire is ready to pick up on {hired_on}. It is:
+
+{items}
+
+Please bring your receipt, number {receipt_number}. The items are due back on
+{return_on}.
+";
+
+pub const DEFAULT_RETURN_TEMPLATE: &str = "\
+# The wording of the reminders emailed before orders are due back.
+#
+# The first line is the subject, and the rest is the message. A line whose
+# {placeholders} are all empty, like a customer with no phone number, is left
+# out. The placeholders are:
+#   {customer_name} {customer_phone} {customer_email} {order_id}
+#   {receipt_number} {hired_on} {return_on} {days_overdue} {items}
+#   {total_items} {today}
+# {items} is a list of the items on the order, one on each line.
+# Lines starting with # are ignored.
+
+Subject: Your hire is due back on {return_on} (receipt {receipt_number})
+
+Hi {customer_name},
+
+Just a reminder that the items you hired from us are due back on {return_on}.
+They are:
+
+{items}
+
+Please return them in the boxes they came in. Thank you for hiring from us.
+";
+
+/// The placeholders that can be used in a template.
+pub const PLACEHOLDERS: [&str; 11] = [
+    "customer_name",
+    "customer_phone",
+    "customer_email",
+    "order_id",
+    "receipt_number",
+    "hired_on",
+    "return_on",
+    "days_overdue",
+    "items",
+    "total_items",
+    "today",
+];
+
+/// Represents the wording of a reminder, read from a template file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReminderTemplate {
+    pub subject: String,
+    pub body: Vec<String>,
+}
+
+impl ReminderTemplate {
+    /// Reads a template, which starts with its subject line.
+    pub fn parse(template: &str) -> Result<Self, String> {
+	let mut subject = None;
+	let mut body = Vec::new();
+
+	for (number, line) in template.lines().enumerate() {
+	    let error = |message: String| format!("line {}: {}", number + 1, message);
+	    let line = line.trim_end();
+
+	    // Blank lines around the subject would only push the message down
+	    if line.starts_with('#') || (line.is_empty() && body.is_empty()) {
+		continue;
+	    }
+	    check_placeholders(line, &PLACEHOLDERS).map_err(error)?;
+
+	    if subject.is_some() {
+		body.push(line.to_string());
+		continue;
+	    }
+	    match line.strip_prefix("Subject:") {
+		Some(line) => subject = Some(line.trim().to_string()),
+		None => return Err(error("the first line must be the Subject:".to_string())),
+	    }
+	}
+
+	while body.last().is_some_and(|line| line.is_empty()) {
+	    body.pop();
+	}
+
+	match subject {
+	    Some(subject) if !subject.is_empty() => Ok(Self { subject, body }),
+	    _ => Err("the template has no subject".to_string()),
+	}
+    }
+
+    /// Fills in the template, giving its subject and message.
+    pub fn render(&self, values: &HashMap<&str, String>) -> (String, String) {
+	let subject = fill(&self.subject, values).unwrap_or_default();
+	let body: Vec<String> = self.body.iter().filter_map(|line| fill(line, values)).collect();
+
+	(subject, body.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_default_templates() {
+	let template = ReminderTemplate::parse(DEFAULT_OVERDUE_TEMPLATE).unwrap();
+
+	assert_eq!(template.subject, "Your hire is overdue (receipt {receipt_number})");
+	assert_eq!(template.body[0], "Hi {customer_name},");
+	assert_ne!(template.body.last().unwrap(), "");
+
+	for template in [DEFAULT_PICKUP_TEMPLATE, DEFAULT_RETURN_TEMPLATE] {
+	    assert!(ReminderTemplate::parse(template).is_ok());
+	}
+    }
+
+    #[test]
+    fn test_parse_errors() {
+	assert_eq!(ReminderTemplate::parse("Hi"), Err("line 1: the first line must be the Subject:".to_string()));
+	assert_eq!(
+	    ReminderTemplate::parse("Subject: Late\n{name}"),
+	    Err("line 2: {name} isn't a placeholder".to_string()),
+	);
+	assert!(ReminderTemplate::parse("# Nothing here").is_err());
+	assert!(ReminderTemplate::parse("Subject:").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+	let template = ReminderTemplate::parse("Subject: Hi {customer_name}\n\nCall {customer_phone}\nBye").unwrap();
+	let values = HashMap::from([
+	    ("customer_name", "Test Person".to_string()),
+	    ("customer_phone", String::new()),
+	]);
+
+	assert_eq!(template.render(&values), ("Hi Test Person".to_string(), "Bye".to_string()));
+    }
+}
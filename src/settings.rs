@@ -0,0 +1,398 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::error::Error;
+
+/// Describes a setting that is stored in the database, along with its default
+/// and the range of values it can have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SettingDef {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub default: i64,
+    pub min: i64,
+    pub max: i64,
+}
+
+/// The smallest raffle number given to an order.
+pub const RAFFLE_NUMBER_MIN: SettingDef = SettingDef {
+    key: "raffle_number_min",
+    label: "Lowest raffle number",
+    default: 0,
+    min: 0,
+    max: 1_000_000_000,
+};
+
+/// The biggest raffle number given to an order.
+pub const RAFFLE_NUMBER_MAX: SettingDef = SettingDef {
+    key: "raffle_number_max",
+    label: "Highest raffle number",
+    default: 1000,
+    min: 0,
+    max: 1_000_000_000,
+};
+
+/// How many of an item fit in a box, for items that aren't in the catalogue.
+pub const DEFAULT_BOX_CAPACITY: SettingDef = SettingDef {
+    key: "default_box_capacity",
+    label: "Default items per box",
+    default: 25,
+    min: 1,
+    max: 1000,
+};
+
+/// The most days an order can be hired out for.
+pub const MAX_HIRE_DAYS: SettingDef = SettingDef {
+    key: "max_hire_days",
+    label: "Longest hire (days)",
+    default: 30,
+    min: 1,
+    max: 365,
+};
+
+/// How many days before today a hire can start, for orders entered late.
+pub const MAX_DAYS_AGO: SettingDef = SettingDef {
+    key: "max_days_ago",
+    label: "Furthest back a hire can start (days)",
+    default: 30,
+    min: 0,
+    max: 3650,
+};
+
+/// How many days after today a hire can be booked for.
+pub const MAX_DAYS_AHEAD: SettingDef = SettingDef {
+    key: "max_days_ahead",
+    label: "Furthest ahead a hire can be booked (days)",
+    default: 365,
+    min: 0,
+    max: 3650,
+};
+
+/// The receipt number to start from when generating them, if no order has a
+/// higher one yet.
+pub const RECEIPT_NUMBER_START: SettingDef = SettingDef {
+    key: "receipt_number_start",
+    label: "First generated receipt number",
+    default: 1,
+    min: 1,
+    max: 999_999_999_999,
+};
+
+/// How long deleted orders can still be restored before they are purged.
+pub const DELETED_ORDER_RETENTION_DAYS: SettingDef = SettingDef {
+    key: "deleted_order_retention_days",
+    label: "Days to keep deleted orders",
+    default: 30,
+    min: 1,
+    max: 3650,
+};
+
+/// How often the app backs up the database while it is open.
+pub const BACKUP_INTERVAL_MINUTES: SettingDef = SettingDef {
+    key: "backup_interval_minutes",
+    label: "Minutes between automatic backups",
+    default: 60,
+    min: 5,
+    max: 1440,
+};
+
+/// How many days to keep the newest backup of.
+pub const BACKUP_KEEP_DAILY: SettingDef = SettingDef {
+    key: "backup_keep_daily",
+    label: "Daily backups to keep",
+    default: 7,
+    min: 1,
+    max: 365,
+};
+
+/// How many weeks to keep the newest backup of, on top of the daily ones.
+pub const BACKUP_KEEP_WEEKLY: SettingDef = SettingDef {
+    key: "backup_keep_weekly",
+    label: "Weekly backups to keep",
+    default: 4,
+    min: 0,
+    max: 520,
+};
+
+/// How many days to wait before reminding a customer about the same overdue
+/// order again.
+pub const REMINDER_INTERVAL_DAYS: SettingDef = SettingDef {
+    key: "reminder_interval_days",
+    label: "Days between reminders",
+    default: 7,
+    min: 1,
+    max: 365,
+};
+
+/// How many days before an order is picked up to email the customer a reminder.
+pub const PICKUP_REMINDER_DAYS: SettingDef = SettingDef {
+    key: "pickup_reminder_days",
+    label: "Days before pickup to email a reminder",
+    default: 1,
+    min: 0,
+    max: 30,
+};
+
+/// How many days before an order is due back to email the customer a reminder.
+pub const RETURN_REMINDER_DAYS: SettingDef = SettingDef {
+    key: "return_reminder_days",
+    label: "Days before the return date to email a reminder",
+    default: 1,
+    min: 0,
+    max: 30,
+};
+
+/// How many times to try sending an email before giving up on it.
+pub const EMAIL_MAX_ATTEMPTS: SettingDef = SettingDef {
+    key: "email_max_attempts",
+    label: "Tries to send an email before giving up",
+    default: 5,
+    min: 1,
+    max: 20,
+};
+
+/// What an order is charged for each day it comes back late, in cents.
+pub const LATE_FEE_PER_DAY: SettingDef = SettingDef {
+    key: "late_fee_per_day",
+    label: "Late fee per day (cents)",
+    default: 0,
+    min: 0,
+    max: 10_000_000,
+};
+
+/// What an order is charged for each item on it, for each day it comes back
+/// late, in cents.
+pub const LATE_FEE_PER_ITEM: SettingDef = SettingDef {
+    key: "late_fee_per_item",
+    label: "Late fee per item per day (cents)",
+    default: 0,
+    min: 0,
+    max: 1_000_000,
+};
+
+/// How many days late an order can come back before it is charged a late fee.
+pub const LATE_FEE_GRACE_DAYS: SettingDef = SettingDef {
+    key: "late_fee_grace_days",
+    label: "Days late before a late fee is charged",
+    default: 0,
+    min: 0,
+    max: 365,
+};
+
+/// The most late fee an order can be charged, in cents, or 0 for no limit.
+pub const LATE_FEE_CAP: SettingDef = SettingDef {
+    key: "late_fee_cap",
+    label: "Most late fee per order (cents, 0 for no limit)",
+    default: 0,
+    min: 0,
+    max: 10_000_000,
+};
+
+/// Every setting, in the order they are shown on the settings tab.
+pub const SETTINGS: &[SettingDef] = &[
+    RAFFLE_NUMBER_MIN,
+    RAFFLE_NUMBER_MAX,
+    DEFAULT_BOX_CAPACITY,
+    MAX_HIRE_DAYS,
+    MAX_DAYS_AGO,
+    MAX_DAYS_AHEAD,
+    RECEIPT_NUMBER_START,
+    DELETED_ORDER_RETENTION_DAYS,
+    BACKUP_INTERVAL_MINUTES,
+    BACKUP_KEEP_DAILY,
+    BACKUP_KEEP_WEEKLY,
+    REMINDER_INTERVAL_DAYS,
+    PICKUP_REMINDER_DAYS,
+    RETURN_REMINDER_DAYS,
+    EMAIL_MAX_ATTEMPTS,
+    LATE_FEE_PER_DAY,
+    LATE_FEE_PER_ITEM,
+    LATE_FEE_GRACE_DAYS,
+    LATE_FEE_CAP,
+];
+
+impl SettingDef {
+    /// Gets the value of the setting, or its default if it hasn't been set.
+    pub fn get(&self, connection: &Connection) -> Result<i64, Error> {
+	let value: Option<i64> = connection.query_row(
+	    "SELECT value FROM Setting WHERE key = ?1",
+	    [self.key],
+	    |row| row.get(0),
+	).optional()?;
+
+	Ok(value.unwrap_or(self.default))
+    }
+
+    /// Stores a new value for the setting.
+    pub fn set(&self, connection: &Connection, value: i64) -> Result<(), Error> {
+	connection.execute(
+	    "INSERT INTO Setting (key, value) VALUES (?1, ?2)
+	     ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+	    params![self.key, value],
+	)?;
+
+	Ok(())
+    }
+
+    /// Parses a value typed in by the user, or Err if it is invalid.
+    pub fn parse(&self, value: &str) -> Result<i64, String> {
+	match value.trim().parse::<i64>() {
+	    Ok(num) => {
+		if num < self.min {
+		    return Err(format!("{} must be at least {}", self.label, self.min));
+		} else if num > self.max {
+		    return Err(format!("{} must not be more than {}", self.label, self.max));
+		}
+
+		Ok(num)
+	    },
+	    Err(_) => {
+		Err(format!("{} must be an integer", self.label))
+	    },
+	}
+    }
+}
+
+/// Represents the form on the settings tab
+#[derive(Default)]
+pub struct SettingsForm {
+    // The text typed in for each setting, in the same order as SETTINGS
+    pub values: Vec<String>,
+    pub show_errors: bool,
+}
+
+impl SettingsForm {
+    /// Creates a form filled in with the current value of every setting.
+    pub fn load(connection: &Connection) -> Result<Self, Error> {
+	let values: Result<Vec<String>, Error> = SETTINGS.iter()
+	    .map(|setting| setting.get(connection).map(|v| v.to_string()))
+	    .collect();
+
+	Ok(Self {
+	    values: values?,
+	    show_errors: false,
+	})
+    }
+
+    /// Sets the text typed in for a setting.
+    pub fn set_value(&mut self, key: &str, value: String) {
+	if let Some(index) = SETTINGS.iter().position(|s| s.key == key) {
+	    self.values[index] = value;
+	    self.show_errors = true;
+	}
+    }
+
+    /// Gets the text typed in for a setting.
+    pub fn get_value(&self, key: &str) -> &str {
+	SETTINGS.iter()
+	    .position(|s| s.key == key)
+	    .map(|index| self.values[index].as_str())
+	    .unwrap_or("")
+    }
+
+    /// Gets the value of a setting in the form, or Err if it is invalid.
+    fn get_valid(&self, setting: &SettingDef) -> Result<i64, String> {
+	let value = setting.parse(self.get_value(setting.key))?;
+
+	// Settings that depend on each other are checked together
+	if setting.key == RAFFLE_NUMBER_MAX.key {
+	    if let Ok(min) = RAFFLE_NUMBER_MIN.parse(self.get_value(RAFFLE_NUMBER_MIN.key)) {
+		if value < min {
+		    return Err("Highest raffle number must not be less than the lowest".to_string());
+		}
+	    }
+	}
+
+	Ok(value)
+    }
+
+    /// Gets the error for a given setting if errors are being shown.
+    pub fn get_visible_field_error(&self, key: &str) -> Option<String> {
+	if !self.show_errors {
+	    return None;
+	}
+
+	SETTINGS.iter()
+	    .find(|s| s.key == key)
+	    .and_then(|setting| self.get_valid(setting).err())
+    }
+
+    /// Saves every setting if all of them are valid.
+    pub fn save(&self, connection: &Connection) -> Result<(), Error> {
+	let mut values = Vec::new();
+	for setting in SETTINGS {
+	    values.push((setting, self.get_valid(setting).map_err(Error::Validation)?));
+	}
+
+	for (setting, value) in values {
+	    setting.set(connection, value)?;
+	}
+
+	Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::init_db_tables;
+
+    use super::*;
+
+    #[test]
+    fn test_setting_default() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	assert_eq!(RAFFLE_NUMBER_MAX.get(&con).unwrap(), 1000);
+    }
+
+    #[test]
+    fn test_setting_set() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	RAFFLE_NUMBER_MAX.set(&con, 50).unwrap();
+	RAFFLE_NUMBER_MAX.set(&con, 60).unwrap();
+	assert_eq!(RAFFLE_NUMBER_MAX.get(&con).unwrap(), 60);
+    }
+
+    #[test]
+    fn test_setting_parse() {
+	assert_eq!(RAFFLE_NUMBER_MAX.parse(" 20 "), Ok(20));
+	assert!(RAFFLE_NUMBER_MAX.parse("-1").is_err());
+	assert!(RAFFLE_NUMBER_MAX.parse("2000000000").is_err());
+	assert!(RAFFLE_NUMBER_MAX.parse("lots").is_err());
+    }
+
+    #[test]
+    fn test_settings_form_save() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let mut form = SettingsForm::load(&con).unwrap();
+	assert_eq!(form.get_value(RAFFLE_NUMBER_MAX.key), "1000");
+
+	form.set_value(RAFFLE_NUMBER_MIN.key, "10".to_string());
+	form.set_value(RAFFLE_NUMBER_MAX.key, "99".to_string());
+
+	assert!(form.save(&con).is_ok());
+	assert_eq!(RAFFLE_NUMBER_MIN.get(&con).unwrap(), 10);
+	assert_eq!(RAFFLE_NUMBER_MAX.get(&con).unwrap(), 99);
+    }
+
+    #[test]
+    fn test_settings_form_raffle_range_backwards() {
+	let con = Connection::open_in_memory().unwrap();
+
+	init_db_tables(&con).unwrap();
+
+	let mut form = SettingsForm::load(&con).unwrap();
+	form.set_value(RAFFLE_NUMBER_MIN.key, "100".to_string());
+	form.set_value(RAFFLE_NUMBER_MAX.key, "99".to_string());
+
+	assert!(form.get_visible_field_error(RAFFLE_NUMBER_MAX.key).is_some());
+	assert!(form.save(&con).is_err());
+	assert_eq!(RAFFLE_NUMBER_MIN.get(&con).unwrap(), 0);
+    }
+}